@@ -0,0 +1,59 @@
+/* C interface to the loudness library, built with the "capi" feature:
+ *
+ *     cargo build --release --features capi
+ *
+ * and linked against target/release/libloudness.so (or .dylib / .dll).
+ * Written by hand, not generated by cbindgen: keep it in step with
+ * src/capi.rs. tests/capi.rs compiles smoke.c against it when a C compiler
+ * is found.
+ */
+
+#ifndef LOUDNESS_H
+#define LOUDNESS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define LOUDNESS_OK 0
+#define LOUDNESS_ERR_ARGUMENT 1
+#define LOUDNESS_ERR_OPEN 2
+#define LOUDNESS_ERR_FORMAT 3
+#define LOUDNESS_ERR_NO_AUDIO 4
+#define LOUDNESS_ERR_UNSUPPORTED 5
+#define LOUDNESS_ERR_PANIC 99
+
+typedef struct LoudnessResult {
+    /* integrated loudness in LUFS; -INFINITY for silence */
+    double loudness;
+    double energy;
+    /* seconds of audio, or NAN when unknown */
+    double duration;
+} LoudnessResult;
+
+/* Measures the file at the UTF-8 path `path`. */
+int loudness_measure_file(const char *path, LoudnessResult *out);
+
+/* Measures `frames` frames of interleaved samples, `channels` per frame. */
+int loudness_measure_samples(const float *samples, size_t frames, uint32_t channels,
+                             uint32_t rate, LoudnessResult *out);
+
+/* Copies the calling thread's last error message into the caller's `buf`,
+ * NUL-terminated and truncated to `len` bytes. Returns the size the whole
+ * message needs, including the NUL. */
+size_t loudness_last_error_message(char *buf, size_t len);
+
+/* Copies the calling thread's last successful measurement into `buf` as a
+ * JSON object with every field the cache has, NUL-terminated and truncated
+ * to `len` bytes. Silence's loudness is written as -1e999. Returns the size
+ * the whole object needs, including the NUL: 1 before any success. */
+size_t loudness_last_result_json(char *buf, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,48 @@
+/* Links against the cdylib and exercises each entry point:
+ *
+ *     cargo build --features capi
+ *     cc capi/smoke.c -Icapi -Ltarget/debug -lloudness -lm -o target/smoke
+ *     LD_LIBRARY_PATH=target/debug target/smoke
+ */
+
+#include <math.h>
+#include <stdio.h>
+#include <string.h>
+#include "loudness.h"
+
+int main(void) {
+    enum { RATE = 48000, FRAMES = RATE * 2 };
+    static float samples[FRAMES * 2];
+    for (size_t i = 0; i < FRAMES; i++) {
+        float s = 0.1f * sinf(2.0f * 3.14159265f * 997.0f * (float)i / RATE);
+        samples[2 * i] = samples[2 * i + 1] = s;
+    }
+
+    LoudnessResult result;
+    if (loudness_measure_samples(samples, FRAMES, 2, RATE, &result) != LOUDNESS_OK ||
+        fabs(result.loudness + 20.0) > 0.5) {
+        fprintf(stderr, "samples: unexpected result\n");
+        return 1;
+    }
+
+    char json[1024];
+    size_t needed = loudness_last_result_json(json, sizeof json);
+    if (needed <= 1 || needed > sizeof json || strstr(json, "\"momentary_max\":") == NULL) {
+        fprintf(stderr, "samples: expected the result as JSON\n");
+        return 1;
+    }
+
+    char message[256];
+    if (loudness_measure_file("/nonexistent.flac", &result) != LOUDNESS_ERR_OPEN ||
+        loudness_last_error_message(message, sizeof message) <= 1) {
+        fprintf(stderr, "file: expected an open error\n");
+        return 1;
+    }
+    if (loudness_measure_samples(samples, FRAMES, 0, RATE, &result) != LOUDNESS_ERR_UNSUPPORTED) {
+        fprintf(stderr, "samples: expected zero channels to be rejected\n");
+        return 1;
+    }
+
+    printf("ok (last error: %s)\n", message);
+    return 0;
+}
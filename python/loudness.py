@@ -0,0 +1,251 @@
+"""Python bindings to the loudness library, over its C API (the "capi" feature).
+
+    cargo build --release --features capi
+
+    >>> import loudness
+    >>> loudness.measure("track.flac")["loudness"]
+    -14.2
+
+`measure` and `measure_samples` return a dict with every field of the Rust
+`Measurement`, as the cache has them; silence has a loudness of -inf. Failures
+raise a `LoudnessError` subclass carrying the library's message.
+
+The library is found from the LOUDNESS_LIB environment variable, a path to
+libloudness.so (or .dylib / .dll), or else as the newer of this checkout's
+target/release and target/debug builds, or else wherever the system's loader
+looks.
+
+These use ctypes rather than pyo3 so they need nothing but the cdylib: no
+extension module to build per Python version, and no numpy, though numpy
+arrays are accepted.
+"""
+
+import array
+import ctypes
+import ctypes.util
+import json
+import os
+import sys
+from pathlib import Path
+
+__all__ = [
+    "measure",
+    "measure_samples",
+    "LoudnessError",
+    "ArgumentError",
+    "OpenError",
+    "FormatError",
+    "NoAudioError",
+    "UnsupportedError",
+    "InternalError",
+]
+
+
+class LoudnessError(Exception):
+    """A measurement failed; `code` is the C API's LOUDNESS_ERR_* value."""
+
+    code = None
+
+
+class ArgumentError(LoudnessError, ValueError):
+    """The arguments can't be measured, e.g. too few samples for the channels."""
+
+    code = 1
+
+
+class OpenError(LoudnessError):
+    """The file couldn't be opened or read."""
+
+    code = 2
+
+
+class FormatError(LoudnessError):
+    """The file isn't in a format that can be decoded, or is too damaged."""
+
+    code = 3
+
+
+class NoAudioError(LoudnessError):
+    """There's no audio to measure, e.g. an empty file or gated-out silence."""
+
+    code = 4
+
+
+class UnsupportedError(LoudnessError, ValueError):
+    """A channel count or sample rate the measurement doesn't support."""
+
+    code = 5
+
+
+class InternalError(LoudnessError):
+    """The library panicked or failed inside; a bug worth reporting."""
+
+    code = 99
+
+
+_ERRORS = {
+    error.code: error
+    for error in (
+        ArgumentError,
+        OpenError,
+        FormatError,
+        NoAudioError,
+        UnsupportedError,
+        InternalError,
+    )
+}
+
+
+class _Result(ctypes.Structure):
+    """Mirrors LoudnessResult; the dict comes from the JSON, which has more."""
+
+    _fields_ = [
+        ("loudness", ctypes.c_double),
+        ("energy", ctypes.c_double),
+        ("duration", ctypes.c_double),
+    ]
+
+
+def _library_names():
+    if sys.platform == "darwin":
+        return ["libloudness.dylib"]
+    if sys.platform == "win32":
+        return ["loudness.dll"]
+    return ["libloudness.so"]
+
+
+def _find_library():
+    explicit = os.environ.get("LOUDNESS_LIB")
+    if explicit:
+        return explicit
+    target = Path(__file__).resolve().parent.parent / "target"
+    built = [
+        target / profile / name
+        for profile in ("release", "debug")
+        for name in _library_names()
+        if (target / profile / name).is_file()
+    ]
+    if built:
+        # the one built last, so a stale release build doesn't shadow it
+        return str(max(built, key=lambda p: p.stat().st_mtime))
+    found = ctypes.util.find_library("loudness")
+    if found:
+        return found
+    raise ImportError(
+        "can't find the loudness library: build it with "
+        "`cargo build --release --features capi` or set LOUDNESS_LIB to its path"
+    )
+
+
+def _load():
+    lib = ctypes.CDLL(_find_library())
+    lib.loudness_measure_file.argtypes = [ctypes.c_char_p, ctypes.POINTER(_Result)]
+    lib.loudness_measure_file.restype = ctypes.c_int
+    lib.loudness_measure_samples.argtypes = [
+        ctypes.POINTER(ctypes.c_float),
+        ctypes.c_size_t,
+        ctypes.c_uint32,
+        ctypes.c_uint32,
+        ctypes.POINTER(_Result),
+    ]
+    lib.loudness_measure_samples.restype = ctypes.c_int
+    for name in ("loudness_last_error_message", "loudness_last_result_json"):
+        function = getattr(lib, name)
+        function.argtypes = [ctypes.c_char_p, ctypes.c_size_t]
+        function.restype = ctypes.c_size_t
+    return lib
+
+
+_lib = _load()
+
+
+def _text(function):
+    """The whole of a string the C API copies out, sized by asking first."""
+    needed = function(None, 0)
+    buf = ctypes.create_string_buffer(needed)
+    function(buf, needed)
+    return buf.value.decode("utf-8", errors="replace")
+
+
+def _finish(code):
+    if code != 0:
+        message = _text(_lib.loudness_last_error_message)
+        raise _ERRORS.get(code, LoudnessError)(message)
+    # -1e999 reads back as -inf, which is what it stands for
+    return json.loads(_text(_lib.loudness_last_result_json))
+
+
+def measure(path):
+    """Measures the audio file at `path` (a str or os.PathLike)."""
+    path = os.fsencode(os.fspath(path))
+    result = _Result()
+    return _finish(_lib.loudness_measure_file(path, ctypes.byref(result)))
+
+
+def _as_float32(samples):
+    """`samples` as a C-contiguous float32 buffer, and its shape."""
+    try:
+        view = memoryview(samples)
+    except TypeError:
+        # a plain sequence, possibly of per-frame sequences
+        rows = list(samples)
+        if rows and not isinstance(rows[0], (int, float)):
+            channels = len(rows[0])
+            flat = array.array("f", (float(s) for row in rows for s in row))
+            return flat, (len(rows), channels)
+        return array.array("f", (float(s) for s in rows)), (len(rows),)
+    if view.format == "f" and view.c_contiguous:
+        return view, view.shape
+    # e.g. float64, or a strided slice: copied once into float32
+    return array.array("f", _flatten(view.tolist())), view.shape
+
+
+def _flatten(values):
+    if isinstance(values, list):
+        for value in values:
+            yield from _flatten(value)
+    else:
+        yield float(values)
+
+
+def measure_samples(samples, rate, channels=None):
+    """Measures float samples at `rate` Hz.
+
+    `samples` is a numpy array, any float buffer, or a sequence: either
+    2-D as (frames, channels), or 1-D interleaved with `channels` per frame
+    (1 if not given). float32 is measured in place; anything else is copied.
+    """
+    buffer, shape = _as_float32(samples)
+    if len(shape) == 2:
+        frames, columns = shape
+        if channels is not None and channels != columns:
+            raise ArgumentError(
+                f"{columns} channels in the array, but channels={channels}"
+            )
+        channels = columns
+    elif len(shape) == 1:
+        channels = 1 if channels is None else channels
+        if channels <= 0:
+            raise ArgumentError(f"channels must be positive, got {channels}")
+        if shape[0] % channels:
+            raise ArgumentError(
+                f"{shape[0]} samples isn't a whole number of {channels}-channel frames"
+            )
+        frames = shape[0] // channels
+    else:
+        raise ArgumentError(f"expected a 1-D or 2-D array, got {len(shape)}-D")
+
+    if frames == 0:
+        pointer = None
+    elif isinstance(buffer, memoryview) and buffer.readonly:
+        copy = (ctypes.c_float * (frames * channels)).from_buffer_copy(buffer)
+        pointer = ctypes.cast(copy, ctypes.POINTER(ctypes.c_float))
+    else:
+        target = buffer.cast("B") if isinstance(buffer, memoryview) else buffer
+        holder = (ctypes.c_float * (frames * channels)).from_buffer(target)
+        pointer = ctypes.cast(holder, ctypes.POINTER(ctypes.c_float))
+    result = _Result()
+    code = _lib.loudness_measure_samples(
+        pointer, frames, channels, int(rate), ctypes.byref(result)
+    )
+    return _finish(code)
@@ -0,0 +1,208 @@
+"""Tests of the Python bindings, for pytest or run directly:
+
+    cargo build --features capi
+    python3 -m pytest python/        # or: python3 python/test_loudness.py
+
+The WAV files are generated here, so there are no fixtures to keep.
+"""
+
+import array
+import math
+import os
+import shutil
+import struct
+import sys
+import tempfile
+import wave
+
+sys.path.insert(0, os.path.dirname(os.path.abspath(__file__)))
+
+import loudness  # noqa: E402
+
+RATE = 48_000
+
+FIELDS = [
+    "loudness",
+    "energy",
+    "duration",
+    "path",
+    "decode_errors",
+    "short",
+    "duration_mismatch",
+    "source",
+    "start",
+    "end",
+    "channel_map",
+    "sample_rate_changes",
+    "channel_changes",
+    "true_peak",
+    "short_term_max",
+    "psr",
+    "psr_avg",
+    "momentary_max",
+    "momentary_max_at_secs",
+]
+
+
+def sine(amplitude=0.1, seconds=4.0, frequency=1000.0):
+    frames = int(seconds * RATE)
+    return array.array(
+        "f",
+        (
+            amplitude * math.sin(2 * math.pi * frequency * i / RATE)
+            for i in range(frames)
+        ),
+    )
+
+
+def write_wav(path, samples, channels=1):
+    with wave.open(str(path), "wb") as w:
+        w.setnchannels(channels)
+        w.setsampwidth(2)
+        w.setframerate(RATE)
+        w.writeframes(
+            b"".join(struct.pack("<h", round(s * 32767)) for s in samples)
+        )
+
+
+class TempDir:
+    def __enter__(self):
+        self.path = tempfile.mkdtemp(prefix="loudness-python-")
+        return self.path
+
+    def __exit__(self, *_):
+        shutil.rmtree(self.path)
+
+
+def test_measure_returns_every_field():
+    with TempDir() as tmp:
+        path = os.path.join(tmp, "sine.wav")
+        write_wav(path, sine())
+        m = loudness.measure(path)
+    assert list(m) == FIELDS
+    # a 1 kHz sine at -20 dBFS peak is about -23 LUFS in one channel
+    assert abs(m["loudness"] - -23.0) < 0.2
+    assert abs(m["duration"] - 4.0) < 1e-3
+    assert m["path"] == os.path.abspath(path)
+    assert m["decode_errors"] is None
+
+
+def test_measure_accepts_path_objects():
+    from pathlib import Path
+
+    with TempDir() as tmp:
+        path = Path(tmp) / "sine.wav"
+        write_wav(path, sine())
+        assert loudness.measure(path)["loudness"] < 0
+
+
+def test_measure_errors_are_exceptions_with_the_message():
+    with TempDir() as tmp:
+        missing = os.path.join(tmp, "missing.wav")
+        try:
+            loudness.measure(missing)
+        except loudness.OpenError as e:
+            assert "missing.wav" in str(e)
+            assert e.code == 2
+        else:
+            raise AssertionError("expected OpenError")
+
+        garbage = os.path.join(tmp, "garbage.wav")
+        with open(garbage, "wb") as f:
+            f.write(b"\x55" * 4096)
+        try:
+            loudness.measure(garbage)
+        except loudness.FormatError as e:
+            assert isinstance(e, loudness.LoudnessError)
+        else:
+            raise AssertionError("expected FormatError")
+
+
+def test_measure_samples_matches_the_file():
+    samples = sine()
+    with TempDir() as tmp:
+        path = os.path.join(tmp, "sine.wav")
+        write_wav(path, samples)
+        from_file = loudness.measure(path)
+    from_samples = loudness.measure_samples(samples, RATE)
+    assert list(from_samples) == FIELDS
+    assert from_samples["path"] is None
+    # only 16-bit quantization apart
+    assert abs(from_file["loudness"] - from_samples["loudness"]) < 0.01
+
+
+def test_measure_samples_interleaved_and_lists():
+    mono = sine()
+    stereo = array.array("f", (s for s in mono for _ in range(2)))
+    m = loudness.measure_samples(stereo, RATE, channels=2)
+    # the same tone in both channels is 3 dB louder than in one
+    assert abs(m["loudness"] - -20.0) < 0.2
+    # plain lists, flat and of frames, are converted
+    assert loudness.measure_samples(list(stereo), RATE, 2)["loudness"] == m["loudness"]
+    frames = [[s, s] for s in mono]
+    assert loudness.measure_samples(frames, RATE)["loudness"] == m["loudness"]
+
+
+def test_measure_samples_silence_is_negative_infinity():
+    m = loudness.measure_samples(array.array("f", [0.0] * RATE), RATE)
+    assert m["loudness"] == -math.inf
+    assert m["short"] is True
+
+
+def test_measure_samples_errors():
+    try:
+        loudness.measure_samples(array.array("f", [0.0] * 7), RATE, channels=2)
+    except loudness.ArgumentError as e:
+        assert "2-channel" in str(e)
+    else:
+        raise AssertionError("expected ArgumentError")
+    try:
+        loudness.measure_samples(sine(), 0)
+    except loudness.LoudnessError as e:
+        assert str(e)
+    else:
+        raise AssertionError("expected an error for a rate of 0")
+
+
+def test_measure_samples_buffers():
+    # the buffer protocol as numpy arrays speak it, without needing numpy
+    mono = sine()
+    stereo = array.array("f", (s for s in mono for _ in range(2)))
+    m = loudness.measure_samples(stereo, RATE, channels=2)
+    frames = memoryview(stereo.tobytes()).cast("f", shape=[len(mono), 2])
+    assert frames.readonly
+    assert loudness.measure_samples(frames, RATE)["loudness"] == m["loudness"]
+    doubles = array.array("d", stereo)
+    assert loudness.measure_samples(doubles, RATE, 2)["loudness"] == m["loudness"]
+    # every other sample, the left channel, isn't contiguous
+    left = memoryview(stereo)[::2]
+    assert not left.c_contiguous
+    assert abs(loudness.measure_samples(left, RATE)["loudness"] - -23.0) < 0.2
+
+
+def test_measure_samples_numpy():
+    try:
+        import numpy as np
+    except ImportError:
+        if "pytest" in sys.modules:
+            import pytest
+
+            pytest.skip("numpy isn't installed")
+        return
+    mono = np.array(sine(), dtype=np.float32)
+    stereo = np.stack([mono, mono], axis=1)
+    m = loudness.measure_samples(stereo, RATE)
+    assert abs(m["loudness"] - -20.0) < 0.2
+    # float64 and non-contiguous arrays are copied to float32
+    doubles = loudness.measure_samples(stereo.astype(np.float64), RATE)
+    assert abs(doubles["loudness"] - m["loudness"]) < 1e-6
+    left = loudness.measure_samples(stereo[:, 0], RATE)
+    assert abs(left["loudness"] - -23.0) < 0.2
+
+
+if __name__ == "__main__":
+    tests = [(n, f) for n, f in sorted(globals().items()) if n.startswith("test_")]
+    for name, test in tests:
+        test()
+        print(f"{name} ... ok")
+    print(f"{len(tests)} passed")
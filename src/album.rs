@@ -0,0 +1,100 @@
+//! Albums as the directories tracks were measured in, and their combined
+//! loudness.
+
+use loudness::Measurement;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The directory a measured file is in, which stands in for its album;
+/// `None` for entries without a recorded path.
+pub fn album_of(m: &Measurement) -> Option<PathBuf> {
+    Path::new(m.path.as_ref()?).parent().map(Path::to_path_buf)
+}
+
+/// `entries` grouped into albums by directory, each with its key, leaving
+/// out those without a recorded path.
+pub fn by_album<'a, K>(
+    entries: impl IntoIterator<Item = (K, &'a Measurement)>,
+) -> HashMap<PathBuf, Vec<(K, &'a Measurement)>> {
+    let mut albums: HashMap<PathBuf, Vec<(K, &Measurement)>> = HashMap::new();
+    for (key, m) in entries {
+        if let Some(album) = album_of(m) {
+            albums.entry(album).or_default().push((key, m));
+        }
+    }
+    albums
+}
+
+/// Loudness of the tracks played back to back: the mean energy of all
+/// their gating blocks together. Each entry's `energy` is its blocks'
+/// summed energy and `loudness` their mean, which gives how many there
+/// were. `None` when there's no audible track.
+pub fn album_loudness<'a>(tracks: impl IntoIterator<Item = &'a Measurement>) -> Option<f64> {
+    let (mut energy, mut blocks) = (0.0, 0.0);
+    for m in tracks {
+        if m.loudness.is_finite() && m.energy > 0.0 {
+            energy += m.energy;
+            blocks += m.energy / 10f64.powf((m.loudness + 0.691) / 10.0);
+        }
+    }
+    (blocks > 0.0).then(|| -0.691 + 10.0 * (energy / blocks).log10())
+}
+
+/// A true peak in dBTP as a linear amplitude, where 1 is full scale, as
+/// ReplayGain peaks are given.
+pub fn linear_peak(dbtp: f64) -> f64 {
+    10f64.powf(dbtp / 20.0)
+}
+
+/// A track's offset in dB from the loudness of its album of `tracks`
+/// tracks, positive when it's louder: where it sits within the album, which
+/// album gain keeps. `None` for a single, the only track in its directory,
+/// and for a silent track.
+pub fn track_vs_album(m: &Measurement, album: f64, tracks: usize) -> Option<f64> {
+    if tracks < 2 {
+        return None;
+    }
+    Some(m.loudness - album).filter(|d| d.is_finite())
+}
+
+/// An album's combined loudness, and how many tracks it was taken over.
+#[derive(Clone, Copy)]
+pub struct AlbumLoudness {
+    pub loudness: f64,
+    pub tracks: usize,
+    /// Highest true peak of its tracks, linear, when every one of them had
+    /// theirs measured.
+    pub peak: Option<f64>,
+}
+
+impl AlbumLoudness {
+    /// See [`track_vs_album`].
+    pub fn track_vs_album(&self, m: &Measurement) -> Option<f64> {
+        track_vs_album(m, self.loudness, self.tracks)
+    }
+}
+
+/// The loudness of every album in `cache`, by directory.
+pub fn albums<'a>(
+    cache: impl IntoIterator<Item = &'a Measurement>,
+) -> HashMap<PathBuf, AlbumLoudness> {
+    by_album(cache.into_iter().map(|m| ((), m)))
+        .into_iter()
+        .filter_map(|(album, tracks)| {
+            let count = tracks.len();
+            let peak = tracks
+                .iter()
+                .map(|(_, m)| m.true_peak.map(linear_peak))
+                .try_fold(0.0, |max, peak| Some(f64::max(max, peak?)));
+            let loudness = album_loudness(tracks.iter().map(|(_, m)| *m))?;
+            Some((
+                album,
+                AlbumLoudness {
+                    loudness,
+                    tracks: count,
+                    peak,
+                },
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,211 @@
+//! Albums of a cache with their tracks, and how far each track is from its
+//! album's loudness.
+
+use crate::album::{album_loudness, album_of, by_album, track_vs_album};
+use crate::cli::{AlbumSort, AlbumsArgs, Units};
+use crate::report::JsonNumber;
+use log::warn;
+use loudness::{load_cache, Measurement};
+use merde::json::JsonSerializer;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+struct Album<'a> {
+    dir: PathBuf,
+    /// Key and entry of each track, sorted by key.
+    tracks: Vec<(&'a str, &'a Measurement)>,
+    loudness: Option<f64>,
+    /// Audio files in the directory with the extensions its tracks have,
+    /// when it can be read.
+    files: Option<usize>,
+}
+
+impl Album<'_> {
+    /// A track's loudness relative to the album's, as in [`track_vs_album`].
+    fn delta(&self, m: &Measurement) -> Option<f64> {
+        track_vs_album(m, self.loudness?, self.tracks.len())
+    }
+
+    /// The largest difference between a track and the album.
+    fn spread(&self) -> Option<f64> {
+        self.tracks
+            .iter()
+            .filter_map(|(_, m)| self.delta(m))
+            .map(f64::abs)
+            .max_by(f64::total_cmp)
+    }
+
+    fn incomplete(&self) -> bool {
+        self.files.is_some_and(|files| files > self.tracks.len())
+    }
+}
+
+/// Counts the files in `dir` with one of `extensions`, ignoring case.
+fn count_files(dir: &Path, extensions: &HashSet<String>) -> Option<usize> {
+    let entries = std::fs::read_dir(dir).ok()?;
+    let count = entries
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|e| extensions.contains(&e.to_string_lossy().to_lowercase()))
+                && p.is_file()
+        })
+        .count();
+    Some(count)
+}
+
+fn albums_of(cache: &HashMap<String, Measurement>) -> (Vec<Album<'_>>, usize) {
+    let unknown_path = cache.values().filter(|m| album_of(m).is_none()).count();
+    let albums = by_album(cache.iter().map(|(key, m)| (key.as_str(), m)))
+        .into_iter()
+        .map(|(dir, mut tracks)| {
+            tracks.sort_by(|a, b| a.0.cmp(b.0));
+            let extensions: HashSet<String> = tracks
+                .iter()
+                .filter_map(|(_, m)| Path::new(m.path.as_ref()?).extension())
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .collect();
+            Album {
+                files: count_files(&dir, &extensions),
+                loudness: album_loudness(tracks.iter().map(|(_, m)| *m)),
+                dir,
+                tracks,
+            }
+        })
+        .collect();
+    (albums, unknown_path)
+}
+
+fn json(albums: &[Album]) -> String {
+    let mut s = JsonSerializer::new();
+    {
+        let mut arr = s.write_arr();
+        for album in albums {
+            arr.elem(&JsonAlbum(album));
+        }
+    }
+    String::from_utf8(s.into_inner()).unwrap()
+}
+
+struct JsonAlbum<'a>(&'a Album<'a>);
+
+impl merde::json::JsonSerialize for JsonAlbum<'_> {
+    fn json_serialize(&self, s: &mut JsonSerializer) {
+        let album = self.0;
+        let mut obj = s.write_obj();
+        obj.pair("album", &album.dir.to_string_lossy());
+        if let Some(loudness) = album.loudness {
+            obj.pair("loudness", &JsonNumber(loudness));
+        }
+        if let Some(spread) = album.spread() {
+            obj.pair("spread", &JsonNumber(spread));
+        }
+        obj.pair("track_count", &album.tracks.len());
+        if let Some(files) = album.files {
+            obj.pair("files", &files);
+        }
+        obj.pair("incomplete", &album.incomplete());
+        obj.pair("tracks", &JsonTracks(album));
+    }
+}
+
+struct JsonTracks<'a>(&'a Album<'a>);
+
+impl merde::json::JsonSerialize for JsonTracks<'_> {
+    fn json_serialize(&self, s: &mut JsonSerializer) {
+        let album = self.0;
+        let mut arr = s.write_arr();
+        for (key, m) in &album.tracks {
+            arr.elem(&JsonTrack(key, m, album.delta(m)));
+        }
+    }
+}
+
+struct JsonTrack<'a>(&'a str, &'a Measurement, Option<f64>);
+
+impl merde::json::JsonSerialize for JsonTrack<'_> {
+    fn json_serialize(&self, s: &mut JsonSerializer) {
+        let mut obj = s.write_obj();
+        obj.pair("key", &self.0);
+        obj.pair("loudness", &JsonNumber(self.1.loudness));
+        // null for a single, so every track has the field
+        obj.pair("track_vs_album_db", &self.2.map(JsonNumber));
+    }
+}
+
+/// Largest first, with albums without an audible track last.
+fn descending(a: Option<f64>, b: Option<f64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    }
+}
+
+/// Prints every album with its tracks, returning false if the cache
+/// couldn't be read.
+pub fn run(args: &AlbumsArgs) -> io::Result<bool> {
+    let cache = match load_cache(Path::new(&args.cache)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.cache);
+            return Ok(false);
+        }
+    };
+    let (mut albums, unknown_path) = albums_of(&cache);
+    if unknown_path > 0 {
+        warn!(
+            "skipped {unknown_path} entries measured before paths were recorded, re-measure them to include them"
+        );
+    }
+    albums.sort_by(|a, b| a.dir.cmp(&b.dir));
+    match args.sort {
+        AlbumSort::Name => {}
+        AlbumSort::Loudness => albums.sort_by(|a, b| descending(a.loudness, b.loudness)),
+        AlbumSort::Spread => albums.sort_by(|a, b| descending(a.spread(), b.spread())),
+    }
+
+    if args.json {
+        println!("{}", json(&albums));
+        return Ok(true);
+    }
+    for album in &albums {
+        let loudness = album
+            .loudness
+            .map_or("silent".to_string(), |l| Units::Lufs.format(l, None));
+        let spread = album
+            .spread()
+            .map_or(String::new(), |s| format!("  spread {s:.2} LU"));
+        let incomplete = match album.files {
+            Some(files) if album.incomplete() => {
+                format!("  incomplete: {} of {files} files", album.tracks.len())
+            }
+            _ => String::new(),
+        };
+        println!(
+            "{}  {loudness}{spread}  {} track(s){incomplete}",
+            album.dir.display(),
+            album.tracks.len()
+        );
+        let width = album.tracks.iter().map(|(k, _)| k.chars().count()).max();
+        for (key, m) in &album.tracks {
+            let delta = album
+                .delta(m)
+                .map_or(String::new(), |d| format!("  {d:>+7.2} LU"));
+            println!(
+                "  {key:<width$}  {:>11}{delta}",
+                Units::Lufs.format(m.loudness, None),
+                width = width.unwrap_or(0),
+            );
+        }
+    }
+    let incomplete = albums.iter().filter(|a| a.incomplete()).count();
+    eprintln!(
+        "{} album(s) of {} track(s), {incomplete} incomplete",
+        albums.len(),
+        albums.iter().map(|a| a.tracks.len()).sum::<usize>()
+    );
+    Ok(true)
+}
@@ -0,0 +1,519 @@
+//! Push-based measurement, for audio that arrives in chunks.
+
+use crate::{BlockHistogram, ChannelMap, ErrorKind, MeasureError, Measurement};
+use ebur128::{EbuR128, Mode};
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// What ebur128 computes: integrated loudness, and momentary and short-term
+/// loudness for the live readings. The histogram keeps memory constant on
+/// long inputs. Loudness range isn't reported, and true peak is expensive,
+/// so it's only added by [`Analyzer::measure_psr`].
+const MODES: Mode = Mode::I.union(Mode::S).union(Mode::HISTOGRAM);
+
+/// 100 ms steps in ebur128's 3 s short-term window.
+const WINDOW_STEPS: u64 = 30;
+
+/// Seconds below which integrated loudness is left out: with so few 400 ms
+/// gating blocks, the gates say more about where the file is quiet than how
+/// loud it is. Shorter audio is measured ungated, as one block, and marked
+/// [`short`](Measurement::short). It's also ebur128's short-term window, so
+/// the whole of such a file is still in its buffer.
+pub const SHORT_DURATION: f64 = 3.0;
+
+/// Measures audio pushed to it a chunk at a time, e.g. from a recording
+/// callback, with live momentary and short-term loudness along the way.
+///
+/// ```
+/// use loudness::Analyzer;
+///
+/// let mut analyzer = Analyzer::new(1, 48_000)?;
+/// for chunk in vec![0.1f32; 48_000 * 5].chunks(4800) {
+///     analyzer.push(chunk)?;
+///     let _live = analyzer.short_term()?;
+/// }
+/// let m = analyzer.finalize()?;
+/// assert_eq!(m.duration, Some(5.0));
+/// # Ok::<(), loudness::MeasureError>(())
+/// ```
+///
+/// `finalize` consumes the analyzer, so nothing can be pushed afterwards.
+/// It's `Send`, so it can be handed to an audio thread.
+pub struct Analyzer {
+    ebur128: EbuR128,
+    channels: u32,
+    sample_rate: u32,
+    frames: u64,
+    /// Frames pushed since the sample rate last changed.
+    frames_at_rate: u64,
+    /// Seconds pushed before it last changed.
+    earlier_seconds: f64,
+    /// Where each gating block's loudness is counted, if anywhere.
+    histogram: Option<BlockHistogram>,
+    /// Peaks and short-term loudness, once asked for.
+    psr: Option<Psr>,
+    /// Loudest momentary loudness so far, and the seconds pushed when its
+    /// window ended.
+    momentary_max: Option<(f64, f64)>,
+}
+
+/// What [`Analyzer::measure_psr`] keeps track of, a 100 ms step at a time.
+#[derive(Default)]
+struct Psr {
+    /// Highest true peak of any channel in the current step, linear.
+    step_peak: f64,
+    /// Highest true peak of each of the last [`WINDOW_STEPS`] steps: the
+    /// short-term window.
+    window: VecDeque<f64>,
+    /// Highest true peak of the steps before the current one.
+    peak: f64,
+    /// Loudest short-term loudness at the end of a step.
+    short_term_max: Option<f64>,
+    /// PSR summed over each whole window, and how many there were.
+    sum: f64,
+    windows: u64,
+}
+
+impl Psr {
+    /// Ends the `steps`th step since ebur128's window last started over,
+    /// with the short-term loudness then once a whole window has passed.
+    fn end_step(&mut self, steps: u64, short_term: Option<f64>) {
+        self.peak = self.peak.max(self.step_peak);
+        self.window.push_back(self.step_peak);
+        if self.window.len() > WINDOW_STEPS as usize {
+            self.window.pop_front();
+        }
+        self.step_peak = 0.0;
+        // silence has no loudness to compare the peak with
+        let Some(short_term) = short_term.filter(|l| l.is_finite()) else {
+            return;
+        };
+        self.short_term_max = Some(
+            self.short_term_max
+                .map_or(short_term, |m| m.max(short_term)),
+        );
+        if steps.is_multiple_of(WINDOW_STEPS) {
+            let peak = self.window.iter().copied().fold(0.0, f64::max);
+            if peak > 0.0 {
+                self.sum += 20.0 * peak.log10() - short_term;
+                self.windows += 1;
+            }
+        }
+    }
+
+    /// Starts the window over along with ebur128's.
+    fn restart(&mut self) {
+        self.peak = self.peak.max(self.step_peak);
+        self.step_peak = 0.0;
+        self.window.clear();
+    }
+
+    /// [`Measurement::true_peak`], [`short_term_max`](Measurement::short_term_max),
+    /// [`psr`](Measurement::psr) and [`psr_avg`](Measurement::psr_avg).
+    fn figures(&self) -> [Option<f64>; 4] {
+        let peak = self.peak.max(self.step_peak);
+        let true_peak = (peak > 0.0).then(|| 20.0 * peak.log10());
+        let psr = true_peak.zip(self.short_term_max).map(|(p, s)| p - s);
+        let psr_avg = (self.windows > 0).then(|| self.sum / self.windows as f64);
+        [true_peak, self.short_term_max, psr, psr_avg]
+    }
+}
+
+impl Analyzer {
+    /// An analyzer for interleaved audio with this layout.
+    pub fn new(channels: u32, sample_rate: u32) -> Result<Analyzer, MeasureError> {
+        // ebur128 only rejects a channel count or rate out of its range here
+        let ebur128 =
+            EbuR128::new(channels, sample_rate, MODES).map_err(|_| ErrorKind::Unsupported {
+                channels,
+                sample_rate,
+            })?;
+        Ok(Analyzer {
+            ebur128,
+            channels,
+            sample_rate,
+            frames: 0,
+            frames_at_rate: 0,
+            earlier_seconds: 0.0,
+            histogram: None,
+            psr: None,
+            momentary_max: None,
+        })
+    }
+
+    /// Also measures true peak and the loudest short-term loudness, which
+    /// [`finalize`](Self::finalize) gives as [`Measurement::psr`] along
+    /// with the rest. True peak is slow to measure, so it's left out
+    /// otherwise.
+    ///
+    /// ```
+    /// use loudness::Analyzer;
+    /// use std::f32::consts::PI;
+    ///
+    /// let mut analyzer = Analyzer::new(1, 48_000)?;
+    /// analyzer.measure_psr()?;
+    /// // a 1 kHz sine at -20 dBFS, which measures -23 LUFS
+    /// let sine: Vec<f32> = (0..48_000 * 10)
+    ///     .map(|i| (2.0 * PI * 1000.0 * i as f32 / 48_000.0).sin() * 0.1)
+    ///     .collect();
+    /// analyzer.push(&sine)?;
+    /// let m = analyzer.finalize()?;
+    /// assert!((m.true_peak.unwrap() - -20.0).abs() < 0.1);
+    /// assert!((m.psr.unwrap() - 3.0).abs() < 0.1);
+    /// assert!((m.psr_avg.unwrap() - 3.0).abs() < 0.1);
+    /// # Ok::<(), loudness::MeasureError>(())
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If anything has been pushed already.
+    pub fn measure_psr(&mut self) -> Result<(), MeasureError> {
+        assert_eq!(self.frames, 0, "measure_psr after audio was pushed");
+        let map = self.ebur128.channel_map().to_vec();
+        self.ebur128 = EbuR128::new(
+            self.channels,
+            self.sample_rate,
+            MODES.union(Mode::TRUE_PEAK),
+        )?;
+        self.ebur128.set_channel_map(&map)?;
+        self.psr = Some(Psr::default());
+        Ok(())
+    }
+
+    /// Counts the loudness of every gating block pushed from now on in
+    /// `histogram`, which [`take_block_histogram`](Self::take_block_histogram)
+    /// hands back.
+    ///
+    /// ```
+    /// use loudness::{Analyzer, BlockHistogram};
+    ///
+    /// let mut analyzer = Analyzer::new(1, 48_000)?;
+    /// analyzer.set_block_histogram(BlockHistogram::new(-70.0, 0.0));
+    /// // 1 s of silence, then 2 s of a square wave at 0.1
+    /// analyzer.push(&vec![0.0; 48_000])?;
+    /// let square: Vec<f32> = (0..96_000).map(|i| if i / 24 % 2 == 0 { 0.1 } else { -0.1 }).collect();
+    /// analyzer.push(&square)?;
+    /// let histogram = analyzer.take_block_histogram().unwrap();
+    /// // a block every 100 ms from 400 ms in
+    /// assert_eq!(histogram.blocks(), 27);
+    /// // the 7 ending in the first second are silent, the next 3 partly,
+    /// // each quieter than the square wave alone
+    /// assert_eq!(histogram.below(), 7);
+    /// assert_eq!(histogram.bins().iter().filter(|&&n| n > 0).count(), 4);
+    /// # Ok::<(), loudness::MeasureError>(())
+    /// ```
+    pub fn set_block_histogram(&mut self, histogram: BlockHistogram) {
+        self.histogram = Some(histogram);
+    }
+
+    /// The histogram given to
+    /// [`set_block_histogram`](Self::set_block_histogram), with the blocks
+    /// pushed since.
+    pub fn take_block_histogram(&mut self) -> Option<BlockHistogram> {
+        self.histogram.take()
+    }
+
+    /// Measures each channel as the speaker `map` gives for it, instead of
+    /// ebur128's default of L, R, C, LFE, Ls, Rs. Fails with
+    /// [`ErrorKind::ChannelMap`] unless it gives one for every channel.
+    pub fn set_channel_map(&mut self, map: &ChannelMap) -> Result<(), MeasureError> {
+        let speakers = map.speakers();
+        if speakers.len() != self.channels as usize {
+            return Err(ErrorKind::ChannelMap {
+                given: speakers.len(),
+                channels: self.channels,
+            }
+            .into());
+        }
+        for (i, speaker) in speakers.iter().enumerate() {
+            self.ebur128.set_channel(i as u32, speaker.channel())?;
+        }
+        Ok(())
+    }
+
+    /// Measures what's pushed from now on as audio at `sample_rate`, e.g.
+    /// where a chained stream switches rate. The gating blocks so far still
+    /// count, but the unfinished block at the switch, up to 400 ms of audio,
+    /// is dropped, and momentary and short-term loudness start over.
+    ///
+    /// ```
+    /// use loudness::Analyzer;
+    /// use std::f32::consts::PI;
+    ///
+    /// let sine = |rate: u32| -> Vec<f32> {
+    ///     (0..rate * 10).map(|i| (2.0 * PI * 1000.0 * i as f32 / rate as f32).sin() * 0.1).collect()
+    /// };
+    /// let mut analyzer = Analyzer::new(1, 48_000)?;
+    /// analyzer.push(&sine(48_000))?;
+    /// analyzer.set_sample_rate(44_100)?;
+    /// analyzer.push(&sine(44_100))?;
+    /// let m = analyzer.finalize()?;
+    /// assert_eq!(m.duration, Some(20.0));
+    /// // the same tone throughout, so the same loudness as either half alone
+    /// let mut half = Analyzer::new(1, 48_000)?;
+    /// half.push(&sine(48_000))?;
+    /// assert!((m.loudness - half.finalize()?.loudness).abs() < 0.01);
+    /// # Ok::<(), loudness::MeasureError>(())
+    /// ```
+    pub fn set_sample_rate(&mut self, sample_rate: u32) -> Result<(), MeasureError> {
+        if sample_rate == self.sample_rate {
+            return Ok(());
+        }
+        self.change_parameters(self.channels, sample_rate)?;
+        self.sample_rate = sample_rate;
+        Ok(())
+    }
+
+    /// Measures what's pushed from now on as `channels` channels, e.g. where
+    /// a chained stream goes from stereo to mono. As with
+    /// [`set_sample_rate`](Self::set_sample_rate), the gating blocks so far
+    /// still count but the unfinished one is dropped. The new channels get
+    /// ebur128's default layout, so a map set with
+    /// [`set_channel_map`](Self::set_channel_map) needs setting again.
+    ///
+    /// ```
+    /// use loudness::Analyzer;
+    /// use std::f32::consts::PI;
+    ///
+    /// let sine: Vec<f32> = (0..48_000 * 10)
+    ///     .map(|i| (2.0 * PI * 1000.0 * i as f32 / 48_000.0).sin() * 0.1)
+    ///     .collect();
+    /// let stereo: Vec<f32> = sine.iter().flat_map(|&s| [s, s]).collect();
+    /// let mut analyzer = Analyzer::new(2, 48_000)?;
+    /// analyzer.push(&stereo)?;
+    /// analyzer.set_channels(1)?;
+    /// analyzer.push(&sine)?;
+    /// let m = analyzer.finalize()?;
+    /// assert_eq!(m.duration, Some(20.0));
+    /// // half of it stereo, at -20 LUFS, and half mono, 3 dB quieter
+    /// assert!((m.loudness - -21.2).abs() < 0.1, "{}", m.loudness);
+    /// # Ok::<(), loudness::MeasureError>(())
+    /// ```
+    pub fn set_channels(&mut self, channels: u32) -> Result<(), MeasureError> {
+        if channels == self.channels {
+            return Ok(());
+        }
+        self.change_parameters(channels, self.sample_rate)?;
+        self.channels = channels;
+        Ok(())
+    }
+
+    /// Reconfigures ebur128, which empties its window.
+    fn change_parameters(&mut self, channels: u32, sample_rate: u32) -> Result<(), MeasureError> {
+        self.ebur128
+            .change_parameters(channels, sample_rate)
+            .map_err(|_| ErrorKind::Unsupported {
+                channels,
+                sample_rate,
+            })?;
+        self.earlier_seconds += self.frames_at_rate as f64 / self.sample_rate as f64;
+        self.frames_at_rate = 0;
+        if let Some(psr) = &mut self.psr {
+            psr.restart();
+        }
+        Ok(())
+    }
+
+    /// Adds interleaved samples; their count must be a multiple of the
+    /// channel count.
+    pub fn push(&mut self, samples: &[f32]) -> Result<(), MeasureError> {
+        self.count(samples.len())?;
+        let channels = self.channels as usize;
+        self.add_frames(samples.len() / channels, |ebur128, run| {
+            ebur128.add_frames_f32(&samples[run.start * channels..run.end * channels])
+        })
+    }
+
+    /// Like [`push`](Self::push), for `f64` samples.
+    pub fn push_f64(&mut self, samples: &[f64]) -> Result<(), MeasureError> {
+        self.count(samples.len())?;
+        let channels = self.channels as usize;
+        self.add_frames(samples.len() / channels, |ebur128, run| {
+            ebur128.add_frames_f64(&samples[run.start * channels..run.end * channels])
+        })
+    }
+
+    /// Adds one slice of samples per channel, all the same length, as
+    /// decoders that work channel by channel produce them.
+    pub fn push_planar(&mut self, planes: &[&[f32]]) -> Result<(), MeasureError> {
+        let frames = planes.first().map_or(0, |p| p.len());
+        if planes.len() != self.channels as usize || planes.iter().any(|p| p.len() != frames) {
+            return Err(ErrorKind::SampleCount {
+                samples: planes.iter().map(|p| p.len()).sum(),
+                channels: self.channels,
+            }
+            .into());
+        }
+        self.frames += frames as u64;
+        self.add_frames(frames, |ebur128, run| {
+            let planes: Vec<&[f32]> = planes.iter().map(|p| &p[run.clone()]).collect();
+            ebur128.add_frames_planar_f32(&planes)
+        })
+    }
+
+    /// Hands `frames` frames to `add` by their range, in runs that end
+    /// every 100 ms step, to read what ends with each.
+    fn add_frames(
+        &mut self,
+        frames: usize,
+        mut add: impl FnMut(&mut EbuR128, Range<usize>) -> Result<(), ebur128::Error>,
+    ) -> Result<(), MeasureError> {
+        // as ebur128 counts them, from when its window last started over
+        let step = (self.sample_rate as u64 + 5) / 10;
+        let mut done = 0;
+        while done < frames {
+            let to_step_end = step - self.frames_at_rate % step;
+            let run = (frames - done).min(to_step_end as usize);
+            add(&mut self.ebur128, done..done + run)?;
+            done += run;
+            self.frames_at_rate += run as u64;
+            if self.psr.is_some() {
+                let mut peak = 0.0;
+                for ch in 0..self.channels {
+                    peak = f64::max(peak, self.ebur128.prev_true_peak(ch)?);
+                }
+                if let Some(psr) = &mut self.psr {
+                    psr.step_peak = psr.step_peak.max(peak);
+                }
+            }
+            if run as u64 == to_step_end {
+                self.end_step(self.frames_at_rate / step)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads what ends with the `steps`th step: from the 4th on, a gating
+    /// block, as the last 400 ms, and from the 30th, a short-term window.
+    fn end_step(&mut self, steps: u64) -> Result<(), MeasureError> {
+        if steps >= 4 {
+            let loudness = self.momentary()?;
+            if let Some(histogram) = &mut self.histogram {
+                histogram.add(loudness);
+            }
+            // strictly louder, so a tie keeps the earliest
+            if loudness.is_finite() && self.momentary_max.is_none_or(|(max, _)| loudness > max) {
+                let at =
+                    self.earlier_seconds + self.frames_at_rate as f64 / self.sample_rate as f64;
+                self.momentary_max = Some((loudness, at));
+            }
+        }
+        if self.psr.is_some() {
+            let short_term = match steps >= WINDOW_STEPS {
+                true => Some(self.short_term()?),
+                false => None,
+            };
+            if let Some(psr) = &mut self.psr {
+                psr.end_step(steps, short_term);
+            }
+        }
+        Ok(())
+    }
+
+    fn count(&mut self, samples: usize) -> Result<(), MeasureError> {
+        if !samples.is_multiple_of(self.channels as usize) {
+            return Err(ErrorKind::SampleCount {
+                samples,
+                channels: self.channels,
+            }
+            .into());
+        }
+        self.frames += (samples / self.channels as usize) as u64;
+        Ok(())
+    }
+
+    /// Loudness of the last 400 ms in LUFS.
+    pub fn momentary(&self) -> Result<f64, MeasureError> {
+        Ok(self.ebur128.loudness_momentary()?)
+    }
+
+    /// Loudness of the last 3 s in LUFS.
+    pub fn short_term(&self) -> Result<f64, MeasureError> {
+        Ok(self.ebur128.loudness_shortterm()?)
+    }
+
+    /// Integrated loudness of everything pushed so far, in LUFS.
+    pub fn integrated(&self) -> Result<f64, MeasureError> {
+        Ok(self.ebur128.loudness_global()?)
+    }
+
+    /// Frames pushed so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// The integrated measurement of everything pushed, or for less than
+    /// [`SHORT_DURATION`], the ungated loudness of all of it; of all of it
+    /// since the sample rate last changed, if it did, as ebur128 starts its
+    /// window over then.
+    pub fn finalize(self) -> Result<Measurement, MeasureError> {
+        let at_rate = self.frames_at_rate as f64 / self.sample_rate as f64;
+        let duration = self.earlier_seconds + at_rate;
+        let [true_peak, short_term_max, psr, psr_avg] =
+            self.psr.as_ref().map_or([None; 4], Psr::figures);
+        let (momentary_max, momentary_max_at_secs) = self.momentary_max.unzip();
+        if self.frames > 0 && duration < SHORT_DURATION {
+            let loudness = self.ebur128.loudness_window((at_rate * 1000.0) as u32)?;
+            return Ok(Measurement {
+                loudness,
+                // as if it were a single gating block
+                energy: 10f64.powf((loudness + 0.691) / 10.0),
+                duration: Some(duration),
+                path: None,
+                decode_errors: None,
+                short: Some(true),
+                duration_mismatch: None,
+                source: None,
+                start: None,
+                end: None,
+                channel_map: None,
+                sample_rate_changes: None,
+                channel_changes: None,
+                true_peak,
+                short_term_max,
+                psr,
+                psr_avg,
+                momentary_max,
+                momentary_max_at_secs,
+            });
+        }
+
+        let global_loudness = self.integrated()?;
+
+        let Some((_, energy)) = self.ebur128.gating_block_count_and_energy() else {
+            return Err(ErrorKind::NoAudio.into());
+        };
+
+        // Convert dB difference to linear gain
+        // let target_loudness = -14.0;
+        // let gain = 10f32.powf(((target_loudness - global_loudness) / 20.0) as f32);
+
+        Ok(Measurement {
+            loudness: global_loudness,
+            energy,
+            duration: Some(duration),
+            path: None,
+            decode_errors: None,
+            short: None,
+            duration_mismatch: None,
+            source: None,
+            start: None,
+            end: None,
+            channel_map: None,
+            sample_rate_changes: None,
+            channel_changes: None,
+            true_peak,
+            short_term_max,
+            psr,
+            psr_avg,
+            momentary_max,
+            momentary_max_at_secs,
+        })
+    }
+}
+
+// audio threads hand analyzers around, so losing Send would be a breaking change
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Analyzer>();
+};
@@ -0,0 +1,187 @@
+//! Futures over the blocking measurement functions, for async applications.
+//!
+//! They don't depend on a particular runtime: the decoding still happens on
+//! a plain thread per measurement, and the futures only wait for it, so an
+//! executor's own threads are never blocked. Dropping a future before it
+//! completes cancels its measurement.
+
+use crate::{measure_file, CancelToken, ErrorKind, MeasureError, MeasureOptions, Measurement};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Results handed from worker threads to whichever task is waiting on them.
+struct Mailbox<T> {
+    ready: VecDeque<T>,
+    /// Workers still running; once 0 and `ready` is empty, nothing more comes.
+    running: usize,
+    waker: Option<Waker>,
+}
+
+impl<T> Mailbox<T> {
+    fn new(running: usize) -> Arc<Mutex<Mailbox<T>>> {
+        Arc::new(Mutex::new(Mailbox {
+            ready: VecDeque::new(),
+            running,
+            waker: None,
+        }))
+    }
+
+    fn push(mailbox: &Mutex<Mailbox<T>>, value: T) {
+        let mut mailbox = mailbox.lock().unwrap();
+        mailbox.ready.push_back(value);
+        if let Some(waker) = mailbox.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// The next value, `None` once every worker has finished, or pending.
+    fn poll(mailbox: &Mutex<Mailbox<T>>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut mailbox = mailbox.lock().unwrap();
+        if let Some(value) = mailbox.ready.pop_front() {
+            return Poll::Ready(Some(value));
+        }
+        if mailbox.running == 0 {
+            return Poll::Ready(None);
+        }
+        mailbox.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Tells the mailbox its worker is done when dropped, even by a panic, so
+/// waiters don't hang.
+struct Worker<T>(Arc<Mutex<Mailbox<T>>>);
+
+impl<T> Drop for Worker<T> {
+    fn drop(&mut self) {
+        let mut mailbox = self.0.lock().unwrap();
+        mailbox.running -= 1;
+        if let Some(waker) = mailbox.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A finished file from a [`MeasureSet`].
+type Finished = (PathBuf, Result<Measurement, MeasureError>);
+
+/// The measurement of one file, from [`measure_file_async`].
+pub struct Measuring {
+    mailbox: Arc<Mutex<Mailbox<Result<Measurement, MeasureError>>>>,
+    cancel: CancelToken,
+}
+
+/// Measures `path` on its own thread, completing when it's done.
+///
+/// A `cancel` token in `options` still works; dropping the future also
+/// cancels, without touching that token.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), loudness::MeasureError> {
+/// use loudness::{measure_file_async, MeasureOptions};
+///
+/// let m = measure_file_async("track.flac", &MeasureOptions::default()).await?;
+/// println!("{:.1} LUFS", m.loudness);
+/// # Ok(())
+/// # }
+/// ```
+pub fn measure_file_async(path: impl Into<PathBuf>, options: &MeasureOptions) -> Measuring {
+    let path = path.into();
+    let cancel = options.cancel.clone().unwrap_or_default().child();
+    let options = options.clone().with_cancel_token(cancel.clone());
+    let mailbox = Mailbox::new(1);
+    let worker = Worker(Arc::clone(&mailbox));
+    std::thread::spawn(move || {
+        Mailbox::push(&worker.0, measure_file(&path, &options));
+    });
+    Measuring { mailbox, cancel }
+}
+
+impl Future for Measuring {
+    type Output = Result<Measurement, MeasureError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Mailbox::poll(&self.mailbox, cx) {
+            Poll::Ready(Some(result)) => Poll::Ready(result),
+            // the worker always sends before finishing, unless it panicked
+            Poll::Ready(None) => Poll::Ready(Err(ErrorKind::Cancelled.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Measuring {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// Measures a set of files with at most `concurrency` running at once,
+/// yielding each result as it completes rather than in input order.
+///
+/// ```no_run
+/// # async fn example() {
+/// use loudness::{MeasureOptions, MeasureSet};
+///
+/// let mut set = MeasureSet::new(vec!["a.mp3", "b.flac"], &MeasureOptions::default(), 4);
+/// while let Some((path, result)) = set.next().await {
+///     println!("{}: {:?}", path.display(), result.map(|m| m.loudness));
+/// }
+/// # }
+/// ```
+///
+/// Dropping the set cancels the files still being measured and skips the
+/// rest.
+pub struct MeasureSet {
+    mailbox: Arc<Mutex<Mailbox<Finished>>>,
+    cancel: CancelToken,
+}
+
+impl MeasureSet {
+    pub fn new(
+        paths: impl IntoIterator<Item = impl Into<PathBuf>>,
+        options: &MeasureOptions,
+        concurrency: usize,
+    ) -> MeasureSet {
+        let queue: VecDeque<PathBuf> = paths.into_iter().map(Into::into).collect();
+        let workers = concurrency.clamp(1, queue.len().max(1));
+        let queue = Arc::new(Mutex::new(queue));
+        let cancel = options.cancel.clone().unwrap_or_default().child();
+        let options = options.clone().with_cancel_token(cancel.clone());
+        let mailbox = Mailbox::new(workers);
+        for _ in 0..workers {
+            let (queue, options) = (Arc::clone(&queue), options.clone());
+            let worker = Worker(Arc::clone(&mailbox));
+            std::thread::spawn(move || loop {
+                let Some(path) = queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+                if options
+                    .cancel
+                    .as_ref()
+                    .is_some_and(CancelToken::is_cancelled)
+                {
+                    break;
+                }
+                let result = measure_file(&path, &options);
+                Mailbox::push(&worker.0, (path, result));
+            });
+        }
+        MeasureSet { mailbox, cancel }
+    }
+
+    /// The next file to finish with its result, or `None` once all are done.
+    pub async fn next(&mut self) -> Option<Finished> {
+        std::future::poll_fn(|cx| Mailbox::poll(&self.mailbox, cx)).await
+    }
+}
+
+impl Drop for MeasureSet {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
@@ -0,0 +1,206 @@
+//! Decoded audio on its way to the analyzer, either on the decoding thread
+//! or, pipelined, on a thread of its own.
+
+use crate::{Analyzer, MeasureError};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::JoinHandle;
+
+/// Chunks that may wait for a pipelined analyzer before decoding blocks,
+/// which bounds memory to a few seconds of audio.
+const PIPELINE_DEPTH: usize = 2;
+
+/// One buffer per channel.
+type Planes = Vec<Vec<f32>>;
+
+/// Audio at one sample rate.
+struct Chunk {
+    planes: Planes,
+    rate: u32,
+}
+
+impl Chunk {
+    fn measure(&self, analyzer: &mut Analyzer) -> Result<(), MeasureError> {
+        analyzer.set_channels(self.planes.len() as u32)?;
+        analyzer.set_sample_rate(self.rate)?;
+        let planes: Vec<&[f32]> = self.planes.iter().map(Vec::as_slice).collect();
+        analyzer.push_planar(&planes)
+    }
+}
+
+/// Decoded audio collected into chunks before it goes to the analyzer, as
+/// ebur128 has a cost per call that adds up over thousands of small packets.
+pub(crate) struct Batch {
+    planes: Planes,
+    /// Sample rate of `planes`.
+    rate: u32,
+    /// Frames to collect before flushing.
+    size: usize,
+    /// Frames taken in so far, flushed or not.
+    frames: u64,
+    sink: Sink,
+}
+
+enum Sink {
+    Inline(Box<Analyzer>),
+    /// Full chunks go to an analyzer thread, which sends their buffers back
+    /// to be filled again.
+    Thread {
+        chunks: SyncSender<Chunk>,
+        spare: Receiver<Planes>,
+        /// Taken when it's joined.
+        analyzer: Option<JoinHandle<Result<Analyzer, MeasureError>>>,
+    },
+}
+
+impl Batch {
+    /// Batches of `size` frames at `rate` for `analyzer`, which runs on its
+    /// own thread when `pipelined`.
+    pub(crate) fn new(
+        analyzer: Analyzer,
+        channels: usize,
+        rate: u32,
+        size: usize,
+        pipelined: bool,
+    ) -> Batch {
+        let sink = if pipelined {
+            let (chunks, received) = mpsc::sync_channel::<Chunk>(PIPELINE_DEPTH);
+            let (returned, spare) = mpsc::channel();
+            let analyzer = std::thread::spawn(move || {
+                let mut analyzer = analyzer;
+                for chunk in received {
+                    chunk.measure(&mut analyzer)?;
+                    // the decoding side may be gone after an error
+                    let _ = returned.send(chunk.planes);
+                }
+                Ok(analyzer)
+            });
+            Sink::Thread {
+                chunks,
+                spare,
+                analyzer: Some(analyzer),
+            }
+        } else {
+            Sink::Inline(Box::new(analyzer))
+        };
+        Batch {
+            planes: vec![Vec::with_capacity(size); channels],
+            rate,
+            size,
+            frames: 0,
+            sink,
+        }
+    }
+
+    /// Frames pushed so far, whether or not the analyzer has them yet.
+    pub(crate) fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub(crate) fn channels(&self) -> usize {
+        self.planes.len()
+    }
+
+    /// Takes the audio pushed from now on to have `channels`, flushing what
+    /// came before with the old count.
+    pub(crate) fn set_channels(&mut self, channels: usize) -> Result<(), MeasureError> {
+        if channels != self.planes.len() {
+            self.flush()?;
+            self.planes = vec![Vec::with_capacity(self.size); channels];
+        }
+        Ok(())
+    }
+
+    pub(crate) fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Takes the audio pushed from now on to be at `rate`, flushing what
+    /// came before at the old one.
+    pub(crate) fn set_rate(&mut self, rate: u32) -> Result<(), MeasureError> {
+        if rate != self.rate {
+            self.flush()?;
+            self.rate = rate;
+        }
+        Ok(())
+    }
+
+    fn pending(&self) -> usize {
+        self.planes.first().map_or(0, Vec::len)
+    }
+
+    /// Adds a packet's planes, flushing once a full chunk has built up.
+    pub(crate) fn push(&mut self, planes: &[&[f32]]) -> Result<(), MeasureError> {
+        if planes.len() != self.planes.len() {
+            // let the analyzer reject the layout, after what came before it
+            self.flush()?;
+            let odd = Chunk {
+                planes: planes.iter().map(|p| p.to_vec()).collect(),
+                rate: self.rate,
+            };
+            return self.send(odd);
+        }
+        for (batched, plane) in self.planes.iter_mut().zip(planes) {
+            batched.extend_from_slice(plane);
+        }
+        self.frames += planes.first().map_or(0, |p| p.len() as u64);
+        if self.pending() >= self.size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), MeasureError> {
+        if self.pending() == 0 {
+            return Ok(());
+        }
+        let next = match &self.sink {
+            Sink::Thread { spare, .. } => spare.try_recv().ok(),
+            Sink::Inline(_) => None,
+        };
+        // one returned from before the channel count changed won't do
+        let next = next.filter(|planes| planes.len() == self.planes.len());
+        let next = next.unwrap_or_else(|| vec![Vec::with_capacity(self.size); self.planes.len()]);
+        let planes = std::mem::replace(&mut self.planes, next);
+        self.planes.iter_mut().for_each(Vec::clear);
+        let rate = self.rate;
+        self.send(Chunk { planes, rate })
+    }
+
+    fn send(&mut self, chunk: Chunk) -> Result<(), MeasureError> {
+        match &mut self.sink {
+            Sink::Inline(analyzer) => chunk.measure(analyzer),
+            Sink::Thread {
+                chunks, analyzer, ..
+            } => match chunks.send(chunk) {
+                Ok(()) => Ok(()),
+                // the analyzer stopped at an error, which its result carries
+                Err(_) => {
+                    let analyzer = analyzer.take().expect("analyzer already joined");
+                    wait(analyzer).map(|_| ())
+                }
+            },
+        }
+    }
+
+    /// Flushes what's left and hands back the analyzer with everything in it.
+    pub(crate) fn finish(mut self) -> Result<Analyzer, MeasureError> {
+        self.flush()?;
+        match self.sink {
+            Sink::Inline(analyzer) => Ok(*analyzer),
+            Sink::Thread {
+                chunks, analyzer, ..
+            } => {
+                // ends the analyzer's loop
+                drop(chunks);
+                wait(analyzer.expect("analyzer already joined"))
+            }
+        }
+    }
+}
+
+/// The pipelined analyzer's result, re-raising a panic on this thread.
+fn wait(analyzer: JoinHandle<Result<Analyzer, MeasureError>>) -> Result<Analyzer, MeasureError> {
+    analyzer
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
@@ -0,0 +1,90 @@
+//! The --bench table: where each file's time went, and the throughput of a
+//! worker and of the whole run.
+
+use crate::report::Row;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Renders the table for the rows timed under --bench; `wall` is the run's
+/// elapsed time.
+pub fn render(rows: &[Row], wall: Duration) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:<30} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}",
+        "bench", "io", "decode", "analysis", "wall", "audio", "realtime"
+    );
+    let (mut io, mut decode, mut analysis, mut busy) = (0.0, 0.0, 0.0, 0.0);
+    let mut audio = 0.0;
+    for row in rows {
+        let (Some(timing), Ok(m)) = (&row.timing, &row.outcome) else {
+            continue;
+        };
+        let Some(stages) = timing.stages else {
+            continue;
+        };
+        let duration = m.duration.unwrap_or(0.0);
+        let line = Line {
+            io: stages.io.as_secs_f64(),
+            decode: stages.decode.as_secs_f64(),
+            analysis: stages.analysis.as_secs_f64(),
+            wall: timing.wall.as_secs_f64(),
+            audio: duration,
+        };
+        line.write(&mut out, &row.key);
+        io += line.io;
+        decode += line.decode;
+        analysis += line.analysis;
+        busy += line.wall;
+        audio += duration;
+    }
+    let total = Line {
+        io,
+        decode,
+        analysis,
+        wall: busy,
+        audio,
+    };
+    total.write(&mut out, "total");
+    let _ = writeln!(
+        out,
+        "realtime: {:.1}x per worker, {:.1}x overall ({} workers)",
+        ratio(audio, busy),
+        ratio(audio, wall.as_secs_f64()),
+        rayon::current_num_threads()
+    );
+    out
+}
+
+/// One row of the table, in seconds.
+struct Line {
+    io: f64,
+    decode: f64,
+    analysis: f64,
+    wall: f64,
+    audio: f64,
+}
+
+impl Line {
+    fn write(&self, out: &mut String, key: &str) {
+        let key: String = key.chars().take(30).collect();
+        let _ = writeln!(
+            out,
+            "{key:<30} {:>8.3}s {:>8.3}s {:>8.3}s {:>8.3}s {:>8.1}s {:>8.1}x",
+            self.io,
+            self.decode,
+            self.analysis,
+            self.wall,
+            self.audio,
+            ratio(self.audio, self.wall)
+        );
+    }
+}
+
+fn ratio(audio: f64, wall: f64) -> f64 {
+    if wall > 0.0 {
+        audio / wall
+    } else {
+        0.0
+    }
+}
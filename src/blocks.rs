@@ -0,0 +1,98 @@
+//! The loudness of each 400 ms gating block, binned, for seeing how it's
+//! spread where integrated loudness gives one number.
+
+/// Width of a [`BlockHistogram`] bin in LU.
+pub const BIN_WIDTH: f64 = 0.5;
+
+/// How many gating blocks fell in each [`BIN_WIDTH`] bin of a fixed range
+/// of loudness, with those outside it only counted, so its size doesn't
+/// grow with the audio. A bimodal track, e.g. quiet verses and loud
+/// choruses, shows as two peaks.
+///
+/// ```
+/// use loudness::BlockHistogram;
+///
+/// let mut histogram = BlockHistogram::new(-70.0, 0.0);
+/// assert_eq!(histogram.bins().len(), 140);
+/// histogram.add(-23.2);
+/// histogram.add(-23.0);
+/// histogram.add(f64::NEG_INFINITY);
+/// assert_eq!(histogram.bins()[93], 1);
+/// assert_eq!(histogram.bins()[94], 1);
+/// assert_eq!((histogram.below(), histogram.above()), (1, 0));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockHistogram {
+    min: f64,
+    bins: Vec<u64>,
+    below: u64,
+    above: u64,
+}
+
+impl BlockHistogram {
+    /// An empty histogram from `min` to `max` LUFS, extended up to a whole
+    /// number of bins.
+    ///
+    /// # Panics
+    ///
+    /// If the range is empty or not finite.
+    pub fn new(min: f64, max: f64) -> BlockHistogram {
+        assert!(
+            min.is_finite() && max.is_finite() && min < max,
+            "invalid histogram range {min}:{max}"
+        );
+        let bins = ((max - min) / BIN_WIDTH).ceil() as usize;
+        BlockHistogram {
+            min,
+            bins: vec![0; bins],
+            below: 0,
+            above: 0,
+        }
+    }
+
+    /// Counts a block of `loudness` LUFS, where silence is -∞.
+    pub fn add(&mut self, loudness: f64) {
+        if loudness.is_nan() {
+            return;
+        }
+        let bin = ((loudness - self.min) / BIN_WIDTH).floor();
+        if bin < 0.0 {
+            self.below += 1;
+        } else if bin >= self.bins.len() as f64 {
+            self.above += 1;
+        } else {
+            self.bins[bin as usize] += 1;
+        }
+    }
+
+    /// Loudness the first bin starts at.
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    /// Loudness the last bin ends at.
+    pub fn max(&self) -> f64 {
+        self.min + self.bins.len() as f64 * BIN_WIDTH
+    }
+
+    /// Blocks in each bin, the first from [`min`](Self::min) to
+    /// [`BIN_WIDTH`] above it.
+    pub fn bins(&self) -> &[u64] {
+        &self.bins
+    }
+
+    /// Blocks quieter than [`min`](Self::min), silent ones included.
+    pub fn below(&self) -> u64 {
+        self.below
+    }
+
+    /// Blocks at or above [`max`](Self::max).
+    pub fn above(&self) -> u64 {
+        self.above
+    }
+
+    /// Every block counted.
+    pub fn blocks(&self) -> u64 {
+        self.below + self.above + self.bins.iter().sum::<u64>()
+    }
+}
@@ -0,0 +1,83 @@
+//! A read buffer in front of files, so symphonia's reads of at most 32 KiB
+//! become fewer, larger ones; on network filesystems each read can be a
+//! round trip.
+
+use log::debug;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use symphonia::core::io::MediaSource;
+
+/// Read buffer size when [`MeasureOptions::read_buffer`](crate::MeasureOptions::read_buffer)
+/// isn't set.
+pub const DEFAULT_READ_BUFFER: usize = 256 * 1024;
+
+/// `file` behind a buffer of `capacity` bytes, counting the reads that reach
+/// the file and logging them, at debug level, once it's dropped.
+pub(crate) struct BufferedFile {
+    inner: BufReader<Counted>,
+    seekable: bool,
+    len: Option<u64>,
+    name: String,
+}
+
+struct Counted {
+    file: File,
+    reads: u64,
+}
+
+impl Read for Counted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reads += 1;
+        self.file.read(buf)
+    }
+}
+
+impl Seek for Counted {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl BufferedFile {
+    pub(crate) fn new(file: File, capacity: usize, name: String) -> BufferedFile {
+        BufferedFile {
+            seekable: file.is_seekable(),
+            len: file.byte_len(),
+            inner: BufReader::with_capacity(capacity, Counted { file, reads: 0 }),
+            name,
+        }
+    }
+}
+
+impl Read for BufferedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Seek for BufferedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl MediaSource for BufferedFile {
+    fn is_seekable(&self) -> bool {
+        self.seekable
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.len
+    }
+}
+
+impl Drop for BufferedFile {
+    fn drop(&mut self) {
+        debug!(
+            "'{}': {} reads with a {} KiB buffer",
+            self.name,
+            self.inner.get_ref().reads,
+            self.inner.capacity() / 1024
+        );
+    }
+}
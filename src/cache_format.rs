@@ -0,0 +1,438 @@
+//! Formats a cache can be stored in besides JSON, and telling which one an
+//! existing file was written in.
+
+use crate::schema::FIELDS;
+use crate::{from_json, Measurement, Unbounded};
+use merde::{CowStr, Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How a cache file is laid out. Every format holds the same fields, so
+/// any of them can be read wherever a cache is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheFormat {
+    /// One object keyed by [`cache_key`](crate::cache_key), as
+    /// [`save_cache`](crate::save_cache) writes.
+    Json,
+    /// One object per line, with the key in its `key` field.
+    Ndjson,
+    /// A header of `key` and the fields of [`Measurement`], then a row per
+    /// entry with unknown values left empty.
+    Csv,
+    /// As CSV, separated by tabs, with tabs, newlines, carriage returns and
+    /// backslashes in values escaped as `\t`, `\n`, `\r` and `\\`.
+    Tsv,
+}
+
+const FORMATS: &[(&str, CacheFormat)] = &[
+    ("json", CacheFormat::Json),
+    ("ndjson", CacheFormat::Ndjson),
+    ("csv", CacheFormat::Csv),
+    ("tsv", CacheFormat::Tsv),
+];
+
+/// Formats asked for by name or extension that there's no support for.
+const UNSUPPORTED: &[(&str, &str)] = &[
+    ("sqlite", "SQLite"),
+    ("msgpack", "MessagePack"),
+    ("toml", "TOML"),
+];
+
+fn unsupported(what: &str) -> String {
+    let names: Vec<&str> = FORMATS.iter().map(|(n, _)| *n).collect();
+    format!(
+        "{what} caches aren't supported by this build, use one of {}",
+        names.join(", ")
+    )
+}
+
+impl CacheFormat {
+    pub fn name(self) -> &'static str {
+        FORMATS.iter().find(|(_, f)| *f == self).unwrap().0
+    }
+
+    /// The format the extension of `path` asks for: JSON for `.json`, no
+    /// extension or one that names no format, and an error for one that
+    /// names a format there's no support for.
+    ///
+    /// ```
+    /// use loudness::CacheFormat;
+    /// use std::path::Path;
+    ///
+    /// assert_eq!(CacheFormat::from_path(Path::new("a.TSV")), Ok(CacheFormat::Tsv));
+    /// assert_eq!(CacheFormat::from_path(Path::new("cache")), Ok(CacheFormat::Json));
+    /// assert!(CacheFormat::from_path(Path::new("a.sqlite")).is_err());
+    /// ```
+    pub fn from_path(path: &Path) -> Result<CacheFormat, String> {
+        let Some(extension) = path.extension() else {
+            return Ok(CacheFormat::Json);
+        };
+        let extension = extension.to_string_lossy().to_lowercase();
+        match extension.parse() {
+            Ok(format) => Ok(format),
+            Err(e) if UNSUPPORTED.iter().any(|(n, _)| *n == extension) => Err(e),
+            Err(_) => Ok(CacheFormat::Json),
+        }
+    }
+
+    /// The format of a cache starting with `bytes`, judged by its first line
+    /// that isn't blank rather than by its name. `None` if it's empty or in
+    /// no format known, and an error naming the format if it's one there's
+    /// no support for.
+    ///
+    /// ```
+    /// use loudness::CacheFormat;
+    ///
+    /// let detect = |s: &str| CacheFormat::detect(s.as_bytes());
+    /// assert_eq!(detect("{\n  \"a\": {\"loudness\": -9.0}\n}"), Ok(Some(CacheFormat::Json)));
+    /// assert_eq!(detect("{\"key\":\"a\",\"loudness\":-9.0}\n"), Ok(Some(CacheFormat::Ndjson)));
+    /// assert_eq!(detect("key,loudness,energy\n"), Ok(Some(CacheFormat::Csv)));
+    /// assert_eq!(detect("key\tloudness\tenergy\n"), Ok(Some(CacheFormat::Tsv)));
+    /// assert_eq!(detect(""), Ok(None));
+    /// assert!(detect("SQLite format 3\0").is_err());
+    /// ```
+    pub fn detect(bytes: &[u8]) -> Result<Option<CacheFormat>, String> {
+        if bytes.starts_with(b"SQLite format 3\0") {
+            return Err(unsupported("SQLite"));
+        }
+        // a map, which no text cache can start with as these aren't UTF-8 or
+        // JSON's first byte
+        if matches!(bytes.first(), Some(0x80..=0x8f | 0xde | 0xdf)) {
+            return Err(unsupported("MessagePack"));
+        }
+        let text = String::from_utf8_lossy(bytes);
+        let Some(line) = text
+            .trim_start_matches('\u{feff}')
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty())
+        else {
+            return Ok(None);
+        };
+        let format = if line.starts_with('[') {
+            return Err(unsupported("TOML"));
+        } else if line.starts_with('{') {
+            // a line that's a whole entry, rather than the start of an object
+            // of them; an entry named "key" has an object as its value
+            let entry = from_json::<Value>(line).ok();
+            let keyed = entry
+                .as_ref()
+                .and_then(|e| e.as_map().ok())
+                .and_then(|m| m.get(&CowStr::from("key")))
+                .is_some_and(|k| matches!(k, Value::Str(_)));
+            match keyed {
+                true => CacheFormat::Ndjson,
+                false => CacheFormat::Json,
+            }
+        } else if line.starts_with("key,") {
+            CacheFormat::Csv
+        } else if line.starts_with("key\t") {
+            CacheFormat::Tsv
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(format))
+    }
+
+    /// Writes `d` in this format, sorted by key in the line formats.
+    pub(crate) fn write(self, d: &HashMap<String, Measurement>) -> String {
+        let mut keys: Vec<&String> = d.keys().collect();
+        keys.sort();
+        match self {
+            CacheFormat::Json => merde::json::to_string(d),
+            CacheFormat::Ndjson => {
+                let mut out = String::new();
+                for key in keys {
+                    let entry = merde::json::to_string(&d[key]);
+                    // the entry's own fields after the key
+                    let fields = entry.strip_prefix('{').unwrap();
+                    out.push_str(&format!(
+                        "{{\"key\":{},{fields}\n",
+                        merde::json::to_string(key)
+                    ));
+                }
+                out
+            }
+            CacheFormat::Csv | CacheFormat::Tsv => {
+                let (delimiter, escape) = self.delimiter();
+                let mut out = String::from("key");
+                for field in FIELDS {
+                    out.push(delimiter);
+                    out.push_str(field.name);
+                }
+                out.push('\n');
+                for key in keys {
+                    let entry = merde::json::to_string(&d[key]);
+                    let entry: Value = from_json(&entry).expect("an entry reads back");
+                    let entry = entry.as_map().unwrap();
+                    out.push_str(&escape(key));
+                    for field in FIELDS {
+                        out.push(delimiter);
+                        let cell = match entry.get(&CowStr::from(field.name)) {
+                            None | Some(Value::Null) => String::new(),
+                            Some(Value::Float(f)) => f.to_string(),
+                            Some(Value::I64(n)) => n.to_string(),
+                            Some(Value::U64(n)) => n.to_string(),
+                            Some(Value::Bool(b)) => b.to_string(),
+                            Some(Value::Str(s)) => escape(s),
+                            Some(other) => unreachable!("a field of {other:?}"),
+                        };
+                        out.push_str(&cell);
+                    }
+                    out.push('\n');
+                }
+                out
+            }
+        }
+    }
+
+    /// Reads entries written in this format. With `lenient`, entries that
+    /// don't parse are skipped rather than failing the whole cache; the
+    /// JSON format has its own recovery in
+    /// [`salvage_cache`](crate::salvage_cache).
+    pub(crate) fn read(
+        self,
+        text: &str,
+        lenient: bool,
+    ) -> io::Result<HashMap<String, Measurement>> {
+        if self == CacheFormat::Json {
+            return from_json(text);
+        }
+        // every line is written with its newline, so a last line without one
+        // was cut short, and its values may be too
+        let mut text = text;
+        if !text.is_empty() && !text.ends_with('\n') {
+            if !lenient {
+                return Err(invalid("the last line is cut short".to_string()));
+            }
+            text = &text[..text.rfind('\n').map_or(0, |i| i + 1)];
+        }
+        let entries: Vec<io::Result<(String, Measurement)>> = match self {
+            CacheFormat::Json => unreachable!(),
+            CacheFormat::Ndjson => text
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(ndjson_entry)
+                .collect(),
+            CacheFormat::Csv | CacheFormat::Tsv => {
+                let mut rows = match self {
+                    CacheFormat::Csv => split_csv(text),
+                    _ => split_tsv(text),
+                }
+                .into_iter();
+                let header = rows
+                    .next()
+                    .ok_or_else(|| invalid("no header".to_string()))?;
+                let columns = columns(&header)?;
+                rows.map(|row| row_entry(&columns, row)).collect()
+            }
+        };
+        let mut d = HashMap::with_capacity(entries.len());
+        for entry in entries {
+            match entry {
+                Ok((key, m)) => {
+                    d.insert(key, m);
+                }
+                Err(_) if lenient => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(d)
+    }
+
+    /// Field separator and how values are escaped for it.
+    fn delimiter(self) -> (char, fn(&str) -> String) {
+        match self {
+            CacheFormat::Csv => (',', csv_field),
+            _ => ('\t', tsv_field),
+        }
+    }
+}
+
+impl fmt::Display for CacheFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A name of [`CacheFormat::name`], matched case-insensitively.
+impl FromStr for CacheFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CacheFormat, String> {
+        if let Some((_, format)) = FORMATS.iter().find(|(n, _)| n.eq_ignore_ascii_case(s)) {
+            return Ok(*format);
+        }
+        match UNSUPPORTED.iter().find(|(n, _)| n.eq_ignore_ascii_case(s)) {
+            Some((_, what)) => Err(unsupported(what)),
+            None => {
+                let names: Vec<&str> = FORMATS.iter().map(|(n, _)| *n).collect();
+                Err(format!(
+                    "unknown cache format '{s}', expected one of {}",
+                    names.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn ndjson_entry(line: &str) -> io::Result<(String, Measurement)> {
+    let entry: Value = from_json(line)?;
+    let Value::Map(mut entry) = entry else {
+        return Err(invalid(format!("not an object: {line}")));
+    };
+    let key = match entry.remove(&CowStr::from("key")) {
+        Some(Value::Str(key)) => key.to_string(),
+        _ => return Err(invalid(format!("no key: {line}"))),
+    };
+    Ok((key, measurement(entry)?))
+}
+
+/// The field of each column after the key.
+fn columns(header: &[String]) -> io::Result<Vec<&'static str>> {
+    if header.first().map(String::as_str) != Some("key") {
+        return Err(invalid("the first column isn't key".to_string()));
+    }
+    header[1..]
+        .iter()
+        .map(|name| {
+            FIELDS
+                .iter()
+                .find(|f| f.name == name)
+                .map(|f| f.name)
+                .ok_or_else(|| invalid(format!("unknown column '{name}'")))
+        })
+        .collect()
+}
+
+fn row_entry(columns: &[&'static str], row: Vec<String>) -> io::Result<(String, Measurement)> {
+    if row.len() != columns.len() + 1 {
+        return Err(invalid(format!(
+            "a row of {} columns, expected {}",
+            row.len(),
+            columns.len() + 1
+        )));
+    }
+    let mut row = row.into_iter();
+    let key = row.next().unwrap();
+    let mut entry = Map::new();
+    for (name, cell) in columns.iter().zip(row) {
+        if cell.is_empty() {
+            continue;
+        }
+        let field = FIELDS.iter().find(|f| f.name == *name).unwrap();
+        let unparsable = || invalid(format!("{name} of '{key}' is '{cell}'"));
+        let value = match field.kind {
+            "number" => Value::Float(cell.parse::<f64>().map_err(|_| unparsable())?.into()),
+            "integer" => Value::U64(cell.parse().map_err(|_| unparsable())?),
+            "boolean" => Value::Bool(cell.parse().map_err(|_| unparsable())?),
+            _ => Value::Str(CowStr::from(cell.clone())),
+        };
+        entry.insert(CowStr::from(*name), value);
+    }
+    Ok((key, measurement(entry)?))
+}
+
+/// An entry's fields read through the same derive as the JSON format, so
+/// every format accepts the same entries.
+fn measurement(entry: Map) -> io::Result<Measurement> {
+    let mut serializer = merde::json::JsonSerializer::new();
+    let mut object = serializer.write_obj();
+    for (name, value) in entry.iter() {
+        match value {
+            Value::Float(f) => object.pair(name, &Unbounded(f.into_inner())),
+            value => object.pair(name, value),
+        };
+    }
+    drop(object);
+    from_json(&String::from_utf8(serializer.into_inner()).expect("JSON is UTF-8"))
+}
+
+/// Quotes a CSV field when it contains a delimiter, quote or line break,
+/// doubling the quotes inside.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escapes the characters that would break a TSV row: tabs, newlines,
+/// carriage returns and backslashes, as `\t`, `\n`, `\r` and `\\`.
+pub fn tsv_field(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Rows of CSV with the quoting of [`csv_field`] undone, where a quoted
+/// value may span lines. Blank lines are skipped.
+pub fn split_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = vec![];
+    let (mut row, mut field) = (vec![], String::new());
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                if row.len() > 1 || !row[0].is_empty() {
+                    rows.push(std::mem::take(&mut row));
+                } else {
+                    row.clear();
+                }
+            }
+            _ => field.push(c),
+        }
+    }
+    if !row.is_empty() || !field.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Rows of TSV with the escapes of [`tsv_field`] undone. Blank lines are
+/// skipped.
+fn split_tsv(text: &str) -> Vec<Vec<String>> {
+    let unescape = |value: &str| {
+        let mut out = String::with_capacity(value.len());
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            match (c, chars.clone().next()) {
+                ('\\', Some(e @ ('\\' | 't' | 'n' | 'r'))) => {
+                    chars.next();
+                    out.push(match e {
+                        't' => '\t',
+                        'n' => '\n',
+                        'r' => '\r',
+                        _ => '\\',
+                    });
+                }
+                _ => out.push(c),
+            }
+        }
+        out
+    };
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.split('\t').map(unescape).collect())
+        .collect()
+}
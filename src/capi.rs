@@ -0,0 +1,178 @@
+//! The C ABI declared in `capi/loudness.h`.
+//!
+//! Every entry point returns a status code and never unwinds: panics are
+//! caught and reported as `LOUDNESS_ERR_PANIC`. The message for the last
+//! failure on the calling thread is kept for `loudness_last_error_message`,
+//! and the last measurement, with every field, for
+//! `loudness_last_result_json`.
+
+use crate::{measure_file, measure_samples, ErrorKind, MeasureError, MeasureOptions, Measurement};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+
+pub const LOUDNESS_OK: c_int = 0;
+pub const LOUDNESS_ERR_ARGUMENT: c_int = 1;
+pub const LOUDNESS_ERR_OPEN: c_int = 2;
+pub const LOUDNESS_ERR_FORMAT: c_int = 3;
+pub const LOUDNESS_ERR_NO_AUDIO: c_int = 4;
+pub const LOUDNESS_ERR_UNSUPPORTED: c_int = 5;
+pub const LOUDNESS_ERR_PANIC: c_int = 99;
+
+/// Mirrors `LoudnessResult` in the header.
+#[repr(C)]
+pub struct LoudnessResult {
+    pub loudness: f64,
+    pub energy: f64,
+    /// Seconds of audio, or NaN when unknown.
+    pub duration: f64,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+    static LAST_RESULT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn fail(code: c_int, message: String) -> c_int {
+    LAST_ERROR.with(|e| *e.borrow_mut() = message);
+    code
+}
+
+fn code(error: &MeasureError) -> c_int {
+    match error.kind() {
+        ErrorKind::Open(_) => LOUDNESS_ERR_OPEN,
+        ErrorKind::Probe(_)
+        | ErrorKind::NoTrack
+        | ErrorKind::Decoder(_)
+        | ErrorKind::TooCorrupt { .. }
+        | ErrorKind::ChannelsChanged { .. } => LOUDNESS_ERR_FORMAT,
+        ErrorKind::Empty | ErrorKind::NoAudio | ErrorKind::TooShort { .. } => LOUDNESS_ERR_NO_AUDIO,
+        ErrorKind::Unsupported { .. } => LOUDNESS_ERR_UNSUPPORTED,
+        ErrorKind::Panicked(_) | ErrorKind::Analysis(_) => LOUDNESS_ERR_PANIC,
+        ErrorKind::SampleCount { .. }
+        | ErrorKind::ChannelMap { .. }
+        | ErrorKind::Timeout(_)
+        | ErrorKind::Cancelled => LOUDNESS_ERR_ARGUMENT,
+    }
+}
+
+/// Runs `f`, storing its measurement in `out` or recording why it failed.
+fn run(
+    out: *mut LoudnessResult,
+    f: impl FnOnce() -> Result<Result<Measurement, MeasureError>, String>,
+) -> c_int {
+    if out.is_null() {
+        return fail(LOUDNESS_ERR_ARGUMENT, "out is NULL".to_string());
+    }
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(Ok(m))) => {
+            let result = LoudnessResult {
+                loudness: m.loudness,
+                energy: m.energy,
+                duration: m.duration.unwrap_or(f64::NAN),
+            };
+            // SAFETY: checked for NULL above; the caller guarantees it's writable
+            unsafe { out.write(result) };
+            LAST_RESULT.with(|r| *r.borrow_mut() = merde::json::to_string(&m));
+            LOUDNESS_OK
+        }
+        Ok(Ok(Err(e))) => fail(code(&e), e.to_string()),
+        Ok(Err(message)) => fail(LOUDNESS_ERR_ARGUMENT, message),
+        Err(_) => fail(LOUDNESS_ERR_PANIC, "internal error (panic)".to_string()),
+    }
+}
+
+/// Measures the file at the NUL-terminated UTF-8 `path`.
+///
+/// # Safety
+///
+/// `path` must be NULL or a valid C string, and `out` NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn loudness_measure_file(
+    path: *const c_char,
+    out: *mut LoudnessResult,
+) -> c_int {
+    run(out, || {
+        if path.is_null() {
+            return Err("path is NULL".to_string());
+        }
+        // SAFETY: the caller guarantees a valid C string
+        let path = unsafe { CStr::from_ptr(path) }
+            .to_str()
+            .map_err(|_| "path isn't valid UTF-8".to_string())?;
+        Ok(measure_file(Path::new(path), &MeasureOptions::default()))
+    })
+}
+
+/// Measures `frames` frames of interleaved samples.
+///
+/// # Safety
+///
+/// `samples` must point to `frames * channels` floats, and `out` be NULL or
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn loudness_measure_samples(
+    samples: *const f32,
+    frames: usize,
+    channels: u32,
+    rate: u32,
+    out: *mut LoudnessResult,
+) -> c_int {
+    run(out, || {
+        let Some(len) = frames.checked_mul(channels as usize) else {
+            return Err("frames * channels overflows".to_string());
+        };
+        let samples = match (samples.is_null(), len) {
+            (_, 0) => &[][..],
+            (true, _) => return Err("samples is NULL".to_string()),
+            // SAFETY: the caller guarantees `len` readable floats
+            (false, _) => unsafe { std::slice::from_raw_parts(samples, len) },
+        };
+        Ok(measure_samples(samples, channels, rate))
+    })
+}
+
+/// Copies the calling thread's last error message into `buf` as a
+/// NUL-terminated string, truncated to `len` bytes. Returns the length the
+/// full message needs, including the NUL, so callers can size a buffer.
+///
+/// # Safety
+///
+/// `buf` must be NULL or point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn loudness_last_error_message(buf: *mut c_char, len: usize) -> usize {
+    // SAFETY: passed on from the caller
+    LAST_ERROR.with(|e| unsafe { copy_out(&e.borrow(), buf, len) })
+}
+
+/// Copies the calling thread's last successful measurement into `buf` as a
+/// NUL-terminated JSON object with every field of [`Measurement`], as the
+/// cache writes it, truncated to `len` bytes. Returns the length the whole
+/// object needs, including the NUL; just 1 before any measurement succeeds.
+///
+/// # Safety
+///
+/// `buf` must be NULL or point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn loudness_last_result_json(buf: *mut c_char, len: usize) -> usize {
+    // SAFETY: passed on from the caller
+    LAST_RESULT.with(|r| unsafe { copy_out(&r.borrow(), buf, len) })
+}
+
+/// Copies `text` into `buf` as for `loudness_last_error_message`.
+///
+/// # Safety
+///
+/// `buf` must be NULL or point to `len` writable bytes.
+unsafe fn copy_out(text: &str, buf: *mut c_char, len: usize) -> usize {
+    if !buf.is_null() && len > 0 {
+        let n = text.len().min(len - 1);
+        // SAFETY: the caller guarantees `len` writable bytes, and n < len
+        unsafe {
+            std::ptr::copy_nonoverlapping(text.as_ptr().cast(), buf, n);
+            buf.add(n).write(0);
+        }
+    }
+    text.len() + 1
+}
@@ -0,0 +1,117 @@
+//! Channel layouts given by hand, for files whose own are wrong or missing.
+
+use ebur128::Channel;
+use std::fmt;
+use std::str::FromStr;
+
+/// Where one channel of a [`ChannelMap`] is played from, which decides how
+/// much it counts towards loudness.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Speaker {
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    /// Left out of the measurement, as BS.1770 says.
+    LowFrequency,
+    SideLeft,
+    SideRight,
+    BackLeft,
+    BackRight,
+    /// Left out of the measurement.
+    Unused,
+}
+
+const SPEAKERS: &[(&str, Speaker)] = &[
+    ("FL", Speaker::FrontLeft),
+    ("FR", Speaker::FrontRight),
+    ("FC", Speaker::FrontCenter),
+    ("LFE", Speaker::LowFrequency),
+    ("SL", Speaker::SideLeft),
+    ("SR", Speaker::SideRight),
+    ("BL", Speaker::BackLeft),
+    ("BR", Speaker::BackRight),
+    ("unused", Speaker::Unused),
+];
+
+impl Speaker {
+    /// Its name in a channel map, e.g. "FL".
+    pub fn name(self) -> &'static str {
+        SPEAKERS.iter().find(|(_, s)| *s == self).unwrap().0
+    }
+
+    /// How ebur128 weighs it: surrounds by +1.5 dB, backs at 135° by 0 dB.
+    pub(crate) fn channel(self) -> Channel {
+        match self {
+            Speaker::FrontLeft => Channel::Left,
+            Speaker::FrontRight => Channel::Right,
+            Speaker::FrontCenter => Channel::Center,
+            Speaker::SideLeft => Channel::LeftSurround,
+            Speaker::SideRight => Channel::RightSurround,
+            Speaker::BackLeft => Channel::Mp135,
+            Speaker::BackRight => Channel::Mm135,
+            Speaker::LowFrequency | Speaker::Unused => Channel::Unused,
+        }
+    }
+}
+
+/// The speaker of each decoded channel, in order, overriding the layout
+/// the file declares.
+///
+/// ```
+/// use loudness::{ChannelMap, Speaker};
+///
+/// let map: ChannelMap = "FL,FR,FC,LFE,SL,SR".parse().unwrap();
+/// assert_eq!(map.speakers()[3], Speaker::LowFrequency);
+/// assert_eq!(map.to_string(), "FL,FR,FC,LFE,SL,SR");
+/// assert!("FL,FR,XX".parse::<ChannelMap>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelMap(Vec<Speaker>);
+
+impl ChannelMap {
+    pub fn new(speakers: Vec<Speaker>) -> ChannelMap {
+        ChannelMap(speakers)
+    }
+
+    pub fn speakers(&self) -> &[Speaker] {
+        &self.0
+    }
+}
+
+/// Speaker names separated by commas, matched case-insensitively.
+impl FromStr for ChannelMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ChannelMap, String> {
+        let speakers = s
+            .split(',')
+            .map(|name| {
+                let name = name.trim();
+                SPEAKERS
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                    .map(|(_, speaker)| *speaker)
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = SPEAKERS.iter().map(|(n, _)| *n).collect();
+                        format!(
+                            "unknown channel '{name}', expected one of {}",
+                            names.join(", ")
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ChannelMap(speakers))
+    }
+}
+
+impl fmt::Display for ChannelMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, speaker) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(speaker.name())?;
+        }
+        Ok(())
+    }
+}
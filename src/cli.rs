@@ -0,0 +1,2024 @@
+use crate::config::{Config, Source, Value};
+use crate::output::ColorChoice;
+use crate::report::Field;
+use crate::template::Template;
+use log::LevelFilter;
+use loudness::{CacheFormat, ChannelMap, ShortFiles};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const USAGE: &str =
+    "usage: loudness [measure] <file/directory> [outfile] [--target LUFS] [--units lufs|lu] [-q|-qq] [-v|-vv]
+                                                     [--color always|never|auto] [--json|--ndjson|--csv|--tsv]
+                                                     [--include-failed] [--unordered] [--fail-fast] [--max-errors N]
+                                                     [--failed-out FILE]
+                                                     [--timeout SECS] [--limit N] [--max-decode-errors N]
+                                                     [--max-decode-error-fraction F] [--short-files ungated|fail]
+                                                     [--track N] [--language CODE] [--duration-tolerance PCT]
+                                                     [--start SECS] [--duration SECS] [--channel-map FL,FR,...]
+                                                     [--histogram-dir DIR] [--histogram-range MIN:MAX] [--psr]
+                                                     [--sort name|loudness|peak] [--reverse] [--timings]
+                                                     [--bench] [--precision N] [--tui] [--fields key,loudness,...]
+                                                     [--format TEMPLATE] [--extensions mp3,flac,...]
+                                                     [--threads N] [--io-jobs N] [--mmap] [--read-buffer BYTES]
+                                                     [--schedule size|name] [--nice] [--no-precount]
+                                                     [--allow-empty] [--strict-cache] [--force-outfile]
+                                                     [--cache-format json|ndjson|csv|tsv]
+                                                     [--config PATH]
+       loudness [measure] --files-from FILE [outfile] [options]
+       loudness config --print [--config PATH] [options]
+       loudness tag --from <cache.json> <directory> [--target LUFS] [--album] [--dry-run]
+       loudness untag <directory> [--dry-run]
+       loudness verify-tags <cache.json> <directory> [--target LUFS] [--tolerance DB]
+       loudness gains <cache.json> <out.json> [--target LUFS] [--format json] [--db] [--clamp]
+       loudness top <cache.json> --loudest N|--quietest N [--match GLOB] [--by loudness|peak|psr]
+       loudness stats <cache.json> [--match GLOB] [--weighted] [--json]
+       loudness hist <cache.json> [--bucket LU] [--range MIN:MAX] [--match GLOB]
+                                  [--metric loudness|gain] [--target LUFS]
+       loudness outliers <cache.json> [--sigma N|--delta LU]
+       loudness status <cache.json> --target LUFS [--tolerance LU]
+       loudness albums <cache.json> [--sort name|loudness|spread] [--json]
+       loudness compare <a> <b>|--target LUFS [--cache <cache.json>] [--tolerance LU]
+       loudness serve --cache <cache.json> [--listen ADDR] [--token TOKEN]
+       loudness daemon [--socket PATH] [--cache <cache.json>]
+       loudness client <path>... [--socket PATH]
+       loudness playlist <cache.json> --out <list.m3u8> [--sort name|loudness] [--reverse]
+                                      [--match GLOB] [--min LUFS] [--max LUFS]
+       loudness import --format loudnorm <file.json|directory> <cache.json> [--overwrite]
+       loudness export --format beets <cache.json> [--csv] [--tracks-only]
+       loudness validate <refs.csv> <directory> [--tolerance LU] [--json]
+       loudness selftest
+       loudness schema";
+
+/// The lines of [`USAGE`] for one subcommand, with their continuations, or
+/// `None` if there's no such subcommand. `measure` covers both its forms.
+pub fn usage_of(command: &str) -> Option<String> {
+    let name = if command == "measure" {
+        "[measure]"
+    } else {
+        command
+    };
+    let mut section = Vec::new();
+    let mut matched = false;
+    for line in USAGE.lines() {
+        // every line starts with "usage: " or as many spaces
+        let rest = &line[7..];
+        if let Some(command) = rest.strip_prefix("loudness ") {
+            matched = command.split(' ').next() == Some(name);
+        }
+        if matched {
+            section.push(rest);
+        }
+    }
+    let (first, rest) = section.split_first()?;
+    let mut usage = format!("usage: {first}");
+    for line in rest {
+        usage.push_str("\n       ");
+        usage.push_str(line);
+    }
+    Some(usage)
+}
+
+pub const HELP: &str = "Measures EBU R128 integrated loudness of audio files.
+
+If an outfile is given, measurements are cached there, as JSON unless its
+extension names another format, and files already in it are skipped on
+later runs.
+
+Results (lines, JSON, CSV/TSV) are written to stdout. Progress, skipped
+files, warnings, errors and the summary go to stderr, so stdout can be
+piped on its own.
+
+Exit codes:
+  0    all files measured (or nothing to do)
+  1    one or more files failed
+  2    usage or argument error
+  3    the outfile couldn't be read or written
+  4    no files to measure, e.g. none with a measured extension (unless
+       --allow-empty is given)
+  130  interrupted with Ctrl-C; what was measured is still saved
+
+An outfile with an audio extension, or that is an existing audio file or
+one of the files being measured, is refused (exit code 2) in case it was
+a typo for an input; --force-outfile writes it anyway.
+
+The outfile is written in the format its extension names: .json (and any
+extension naming no format), .ndjson, .csv or .tsv, each holding the same
+fields; --cache-format picks one for any name. .sqlite, .msgpack and .toml
+are refused (exit code 2), as this build can't write them. An existing
+outfile's format is told from its contents, and one written in another
+format is refused (exit code 3) rather than read as the new one; give its
+own format with --cache-format to keep adding to it. Every subcommand that
+reads a cache reads any of these formats.
+
+An outfile that can't be parsed, e.g. one cut short by a crash, is moved
+aside to <outfile>.corrupt-<unix time>, and the entries that can still be
+read from it are kept; the rest are measured again. With --strict-cache
+the run stops with exit code 3 instead.
+
+--format replaces each result line with a template such as
+'{key}\\t{loudness:.1}\\t{gain:+.1}'. Placeholders take a spec of
+[<|>][+][width][.precision]; {{ and }} are literal braces.
+
+Defaults for measuring can be set in ~/.config/loudness/config.toml (or the
+file given with --config), e.g. `target = -14` or `format = 'csv'`. Flags on
+the command line take precedence; unknown keys only warn. In between, the
+environment variables LOUDNESS_TARGET, LOUDNESS_CACHE (the outfile),
+LOUDNESS_EXTENSIONS, LOUDNESS_THREADS and LOUDNESS_QUIET (1 or 2) override
+the config file.
+
+--precision sets the decimal places of console and CSV/TSV numbers (console
+defaults to 2, CSV/TSV to full precision). Values are rounded to nearest, with
+exact ties going to the even digit. The outfile always keeps full precision.
+
+--nice lowers CPU priority and, on Linux, puts file reads in the idle I/O
+class, so a long run stays out of the way of other work. Unless --threads
+is given it also uses only half the cores. Where priorities can't be
+changed, a warning is printed and the run continues normally.
+
+--bench times each file's I/O and probing, decoding and analysis separately
+and prints a table of them after the summary, with the realtime factor of
+each file, of one worker and of the whole run. With --json or --ndjson the
+per-file times are also in the results.
+
+--mmap reads each file through a memory map, which can be faster for large
+files on fast local disks. Files that can't be mapped are read normally.
+It can't be combined with --io-jobs.
+
+--io-jobs caps how many files are read from at once, apart from --threads,
+so many workers don't swamp a slow disk or network mount. Each read of
+--read-buffer bytes waits its turn, while decoding goes on in parallel, so
+only a read buffer per file is held in memory. Without it every worker
+reads whenever it needs to.
+
+--read-buffer sets how many bytes are read from a file at a time (default
+256 KiB; 0 leaves it to the decoder's 32 KiB reads). Larger buffers mean
+fewer round trips on network filesystems. With -v the number of reads per
+file is logged.
+
+--schedule size (the default) starts the largest files first, so a long
+file isn't left running alone at the end of a run while the other workers
+sit idle. --schedule name starts them in input order instead. Either way,
+results are printed in input order unless --unordered is given.
+
+A file is failed as too corrupt to measure when more than
+--max-decode-errors packets fail to decode (no limit by default), or more
+than --max-decode-error-fraction of them do (default 0.5; 1 turns it off).
+Files with fewer errors are measured, and --json/--ndjson results and the
+outfile give their decode_errors count.
+
+Integrated loudness needs enough 400 ms gating blocks to mean anything, so
+audio shorter than 3 seconds is measured ungated, over the whole file, and
+marked short: \"(short)\" on the console, and `\"short\": true` in JSON and the
+outfile. --short-files fail fails such files instead.
+
+Each file's default track is measured if it's audio, and otherwise its
+first audio track, so video files and m4a files with chapter tracks work.
+--track N picks the Nth audio track instead, counting from 0, and
+--language CODE the first one tagged with that language, e.g. eng. Files
+without such a track fail with \"no audio track\".
+
+--start SECS and --duration SECS measure only that part of each file, e.g.
+a single movement of a live recording, seeking to the start where the
+format allows it. Either may be given alone. The range is recorded in the
+outfile as \"start\" and \"end\", and an entry only counts as already
+measured for the same range, so changing it measures the files again.
+
+--channel-map gives the speaker of each channel, in order, for files whose
+layout is wrong or missing: FL, FR, FC, LFE, SL, SR, BL and BR, or unused
+to leave a channel out. LFE is left out too, as BS.1770 says, and side
+channels weigh +1.5 dB. Files with a different number of channels fail.
+The map is recorded in the outfile as \"channel_map\", and an entry only
+counts as already measured with the same map.
+
+When a file decodes to a different length than its header declares, by
+more than --duration-tolerance percent (default 1) plus a few thousand
+samples of encoder padding, a warning is printed and its JSON and outfile
+entries get \"duration_mismatch\": true. That catches truncated files as
+well as wrong VBR headers on files that decoded fine.
+
+Audio that switches sample rate or channel count partway through, as
+chained Ogg streams can, is measured across the switch, dropping the
+unfinished 400 ms gating block at it. A warning gives where, and the entry
+gets \"sample_rate_changes\" or \"channel_changes\" with how many times it
+switched. With --channel-map a change of channel count fails the file
+instead, as the map only fits the count before it.
+
+--histogram-dir DIR writes the loudness of each measured file's 400 ms
+gating blocks, counted in 0.5 LU bins, to DIR/<key>.json, for seeing what
+a single figure hides, such as quiet verses and loud choruses. The bins
+span --histogram-range MIN:MAX LUFS (default -70:0, from the absolute gate
+to full scale); blocks outside it, silent ones included, are only counted
+as \"below\" or \"above\". Files skipped as already measured get none.
+
+--psr also measures each file's true peak and its loudest 3 s short-term
+loudness, and their difference, the peak-to-short-term loudness ratio
+(PSR): how much room limiting has left the peaks above the loudest
+passage. As one transient can make that single figure, \"psr_avg\" is
+the mean of each successive 3 s window's own PSR. They're written to the
+outfile and JSON as \"true_peak\", \"short_term_max\", \"psr\" and
+\"psr_avg\", the last two null for audio shorter than 3 s; --fields and
+--format take true_peak, psr and psr_avg, and -v adds PSR to each line.
+True peak is slow to measure, so it's left out without --psr, and files
+already in the outfile without one are measured again. `top --by psr`
+(or peak) ranks entries by it, and `stats` adds its distribution. --sort
+peak orders the report by true peak, highest first, and so needs --psr.
+
+Each file's loudest momentary (400 ms) loudness is recorded as
+\"momentary_max\", and the seconds into the file where that window ends as
+\"momentary_max_at_secs\", the earliest on a tie, for finding what sets
+off a limiter. It's counted from decoded samples, so it doesn't change
+between runs. -v adds both to each line, the time as mm:ss.mmm, and
+--fields and --format take momentary_max and momentary_max_at.
+
+The summary counts failed files by kind and lists the first few of each.
+--failed-out FILE writes all of them, with the stage that failed and why,
+as JSON. --files-from FILE measures the files listed in such a file, or in
+a text file with one path per line, instead of an input path, e.g. to
+retry just the failures after fixing whatever caused them.
+
+A .zip archive, given as the input or found in a directory, is measured
+without extracting it: each of its files with a measured extension is
+keyed as <archive name>/<path in the archive without its extension>, e.g.
+album/01 Intro, and skipped on later runs like any other file. Stored and
+deflated entries can be read; a password-protected archive fails as a
+whole.
+
+`import --format loudnorm` adds ffmpeg loudnorm first-pass results
+(print_format=json, the filter's log lines may be left in) to a cache,
+taking input_i as the loudness. Each is keyed by the media file named in
+a \"path\" or \"input_file\" field if the JSON has one, and otherwise by
+its own name with .json, .loudnorm and any audio extension removed, e.g.
+track.flac.loudnorm.json. Imported entries have \"source\": \"loudnorm\"
+and never replace measured ones unless --overwrite is given.
+
+`export --format beets` writes a JSON array (or CSV with --csv) with a
+record per measured file: its absolute \"path\" and the fields beets'
+replaygain plugin stores, rg_track_gain and rg_album_gain at the
+ReplayGain 2.0 reference of -18 LUFS, r128_track_gain and r128_album_gain
+at -23 LUFS. An album is the files measured in one directory, combined by
+the energy of their gating blocks; --tracks-only leaves the album fields out.
+track_vs_album_db is how far the track is from its album in dB, for
+normalizing albums as a whole without flattening them; a single has none.
+rg_track_peak is the track's true peak as a linear amplitude, and
+rg_album_peak its album's highest, only for files measured with --psr.
+For example, to load it with a few lines of Python against beets'
+library:
+
+    loudness export --format beets library.json > rg.json
+    # for r in json.load(open('rg.json')):
+    #     for item in lib.items(PathQuery('path', r['path'])):
+    #         item.update({k: v for k, v in r.items() if k != 'path'})
+    #         item.store()
+
+`tag --album` writes REPLAYGAIN_ALBUM_GAIN from the album's combined
+loudness alongside each track's own gain, and shows how far each track is
+from its album; that offset isn't written, as album gain already keeps it.
+
+`validate` measures the files in a directory named in a CSV of reference
+values, one `key,loudness` row per file (where the key is the file name
+without its extension, as in the outfile), and prints how far off each
+is, worst first. A header row is skipped. Further lra and peak columns are
+accepted, but only integrated loudness is measured, so only it is checked.
+A file passes within --tolerance LU of its reference (default 0.1).
+
+`selftest` measures signals generated in memory, without any audio files:
+the EBU Tech 3341 1 kHz sine at -23 LUFS, a -20 dBFS stereo sine, silence,
+and full-scale samples with inter-sample peaks, and checks each against
+its expected loudness.
+
+`schema` prints a JSON Schema (draft 2020-12) of the outfile, to validate
+caches against. Its $id, urn:loudness:cache:N, changes with the version
+of the cache format; optional fields may be absent or null.
+
+`albums` lists the albums in a cache, an album being the files measured
+in one directory, with their loudness combined by the energy of their
+gating blocks, and under each its tracks and how many LU each is from the
+album (\"track_vs_album_db\" with --json, null for a single, the only
+track in its directory). An album's spread is its largest such difference, so --sort spread
+puts compilations mastered to different levels first; --sort loudness
+puts the loudest first. An album whose directory has more files with its
+tracks' extensions than the cache has entries for is marked incomplete.
+
+`stats --weighted` adds two figures to the plain per-entry statistics,
+where a 90-second interlude counts as much as a 10-minute track: the mean
+weighted by each entry's duration, and the combined loudness of all of
+them played back to back, from their gating energies as for an album.
+Entries measured before durations were recorded are weighted as one
+second each, and counted on stderr.
+
+`status` classifies every entry of a cache against --target: OK within
+--tolerance LU of it (default 1), TOO LOUD or TOO QUIET outside it, or
+UNKNOWN without usable loudness, e.g. silent. It lists all but the OK ones
+by class, furthest off first, and counts each class on stderr. Only the
+cache is read. True peak isn't checked.
+
+For `outliers`, exit code 1 means at least one outlier was found. For
+`status`, it means an entry is too loud or too quiet, for
+`compare --tolerance`, that the loudness difference exceeded the
+tolerance, for `validate`, that a file failed or couldn't be measured, and
+for `selftest`, that a case failed.";
+
+/// ReplayGain 2.0 reference loudness, used when writing tags without --target.
+pub const REPLAYGAIN_REFERENCE: f64 = -18.0;
+
+/// Share of a file's packets that may fail to decode before it's failed,
+/// without --max-decode-error-fraction.
+pub const DEFAULT_MAX_DECODE_ERROR_FRACTION: f64 = 0.5;
+
+/// LUFS spanned by --histogram-dir histograms without --histogram-range:
+/// from the absolute gate to full scale.
+pub const DEFAULT_HISTOGRAM_RANGE: (f64, f64) = (-70.0, 0.0);
+
+/// Widest --histogram-range, which keeps a histogram to 400 bins.
+const MAX_HISTOGRAM_SPAN: f64 = 200.0;
+
+/// Decimal places shown on the console without --precision.
+pub const DEFAULT_PRECISION: usize = 2;
+
+/// Unit used when displaying a loudness value.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Units {
+    /// Absolute loudness, as stored in the cache.
+    Lufs,
+    /// Signed offset from the target.
+    Lu,
+}
+
+impl Units {
+    /// Converts an absolute loudness into the displayed unit.
+    pub fn value(self, loudness: f64, target: Option<f64>) -> f64 {
+        match (self, target) {
+            (Units::Lu, Some(target)) => loudness - target,
+            _ => loudness,
+        }
+    }
+
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Units::Lufs => "LUFS",
+            Units::Lu => "LU",
+        }
+    }
+
+    /// Column name for exports, e.g. "loudness_lufs".
+    pub fn column(self) -> &'static str {
+        match self {
+            Units::Lufs => "loudness_lufs",
+            Units::Lu => "loudness_lu",
+        }
+    }
+
+    /// Formats an absolute loudness for display, e.g. "-20.30 LUFS" or "+2.70 LU".
+    pub fn format(self, loudness: f64, target: Option<f64>) -> String {
+        self.format_with(loudness, target, DEFAULT_PRECISION)
+    }
+
+    /// Like `format`, with `precision` decimal places.
+    pub fn format_with(self, loudness: f64, target: Option<f64>, precision: usize) -> String {
+        let value = self.value(loudness, target);
+        match self {
+            Units::Lufs => format!("{:.precision$} {}", value, self.suffix()),
+            Units::Lu => format!("{:+.precision$} {}", value, self.suffix()),
+        }
+    }
+}
+
+/// How results are written to stdout.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One aligned line per file.
+    Human,
+    /// A single JSON document at the end of the run.
+    Json,
+    /// One JSON line per file as results complete.
+    Ndjson,
+    Csv,
+    Tsv,
+}
+
+impl OutputFormat {
+    /// Whether stdout carries machine-readable data only.
+    pub fn is_structured(self) -> bool {
+        self != OutputFormat::Human
+    }
+
+    /// Whether results are collected and written once the run has finished.
+    pub fn is_buffered(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Json | OutputFormat::Csv | OutputFormat::Tsv
+        )
+    }
+}
+
+/// Order of the final report.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Input order, i.e. by file name.
+    Name,
+    /// Loudest first.
+    Loudness,
+    /// Highest true peak first; only measured with --psr.
+    TruePeak,
+}
+
+/// Order in which pending files are handed to the workers.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// Largest first, so long files don't start last and hold up the end.
+    Size,
+    /// Input order.
+    Name,
+}
+
+pub enum Command {
+    /// The full usage and help, or the usage of the named subcommand.
+    Help(Option<&'static str>),
+    Measure(Args),
+    Tag(TagArgs),
+    Untag(UntagArgs),
+    VerifyTags(VerifyTagsArgs),
+    Gains(GainsArgs),
+    Top(TopArgs),
+    Stats(StatsArgs),
+    Hist(HistArgs),
+    Outliers(OutliersArgs),
+    Status(StatusArgs),
+    Albums(AlbumsArgs),
+    Compare(CompareArgs),
+    Serve(ServeArgs),
+    Daemon(DaemonArgs),
+    Client(ClientArgs),
+    Playlist(PlaylistArgs),
+    Import(ImportArgs),
+    Export(ExportArgs),
+    Validate(ValidateArgs),
+    SelfTest,
+    Schema,
+    /// Effective measure options, and the config file they were read from.
+    PrintConfig(Args, Option<PathBuf>),
+}
+
+/// The subcommand whose usage `<first> ... --help` shows: `None` for the
+/// whole of it, and `measure` when `first` is an input rather than a name.
+fn help_topic(first: &str) -> Option<&'static str> {
+    if matches!(first, "-h" | "--help" | "help") {
+        return None;
+    }
+    let named = USAGE.lines().find_map(|line| {
+        let name = line[7..].strip_prefix("loudness ")?.split(' ').next()?;
+        (name == first).then_some(name)
+    });
+    Some(named.unwrap_or("measure"))
+}
+
+impl Command {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+        let Some(first) = args.next() else {
+            return Err(USAGE.to_string());
+        };
+        // so `loudness tag --help` shows tag's usage rather than a usage error
+        let mut args: Vec<String> = args.collect();
+        if args.iter().any(|a| a == "-h" || a == "--help") {
+            return Ok(Command::Help(help_topic(&first)));
+        }
+        let config_path = match args.iter().position(|a| a == "--config") {
+            Some(i) if i + 1 < args.len() => Some(args.drain(i..i + 2).nth(1).unwrap()),
+            Some(_) => return Err("--config requires a value".to_string()),
+            None => None,
+        };
+        let config = || Config::load(config_path.as_deref());
+        let args = args.into_iter();
+        match first.as_str() {
+            "-h" | "--help" | "help" => Ok(Command::Help(None)),
+            "measure" => Ok(Command::Measure(Args::parse(args, &config()?, true)?)),
+            "config" => {
+                let mut args = args.peekable();
+                if args.next_if(|a| a == "--print").is_none() {
+                    return Err("usage: loudness config --print [--config PATH] [options]".into());
+                }
+                let config = config()?;
+                let args = Args::parse(args, &config, false)?;
+                Ok(Command::PrintConfig(args, config.path))
+            }
+            "tag" => Ok(Command::Tag(TagArgs::parse(args)?)),
+            "untag" => Ok(Command::Untag(UntagArgs::parse(args)?)),
+            "verify-tags" => Ok(Command::VerifyTags(VerifyTagsArgs::parse(args)?)),
+            "gains" => Ok(Command::Gains(GainsArgs::parse(args)?)),
+            "top" => Ok(Command::Top(TopArgs::parse(args)?)),
+            "stats" => Ok(Command::Stats(StatsArgs::parse(args)?)),
+            "hist" => Ok(Command::Hist(HistArgs::parse(args)?)),
+            "outliers" => Ok(Command::Outliers(OutliersArgs::parse(args)?)),
+            "status" => Ok(Command::Status(StatusArgs::parse(args)?)),
+            "albums" => Ok(Command::Albums(AlbumsArgs::parse(args)?)),
+            "compare" => Ok(Command::Compare(CompareArgs::parse(args)?)),
+            "serve" => Ok(Command::Serve(ServeArgs::parse(args)?)),
+            "daemon" => Ok(Command::Daemon(DaemonArgs::parse(args)?)),
+            "client" => Ok(Command::Client(ClientArgs::parse(args)?)),
+            "playlist" => Ok(Command::Playlist(PlaylistArgs::parse(args)?)),
+            "import" => Ok(Command::Import(ImportArgs::parse(args)?)),
+            "export" => Ok(Command::Export(ExportArgs::parse(args)?)),
+            "validate" => Ok(Command::Validate(ValidateArgs::parse(args)?)),
+            "selftest" | "schema" => match args.into_iter().next() {
+                Some(arg) => Err(format!("unexpected argument '{arg}'")),
+                None if first == "schema" => Ok(Command::Schema),
+                None => Ok(Command::SelfTest),
+            },
+            _ => Ok(Command::Measure(Args::parse(
+                std::iter::once(first).chain(args),
+                &config()?,
+                true,
+            )?)),
+        }
+    }
+}
+
+pub struct Args {
+    pub input: String,
+    pub outfile: Option<String>,
+    pub target: Option<f64>,
+    pub units: Units,
+    /// Log level from -v/-q, `None` to defer to RUST_LOG.
+    pub log_level: Option<LevelFilter>,
+    pub color: ColorChoice,
+    pub format: OutputFormat,
+    /// Emit failed files as rows with empty cells in CSV/TSV instead of omitting them.
+    pub include_failed: bool,
+    /// Print lines as files complete instead of in input order.
+    pub unordered: bool,
+    /// Stop starting new files after the first failure.
+    pub fail_fast: bool,
+    /// Stop starting new files once more than this many have failed.
+    pub max_errors: Option<usize>,
+    /// Write every failed file here as JSON.
+    pub failed_out: Option<String>,
+    /// Measure the files listed here rather than `input`.
+    pub files_from: Option<String>,
+    /// Give up on a single file after this long.
+    pub timeout: Option<Duration>,
+    /// Fail a file once more than this many of its packets fail to decode.
+    pub max_decode_errors: Option<u64>,
+    /// Fail a file when more than this share of its packets fail to decode.
+    pub max_decode_error_fraction: f64,
+    /// Measure at most this many uncached files; 0 for no limit.
+    pub limit: usize,
+    /// Skip stat-ing every file up front, so progress counts files, not bytes.
+    pub no_precount: bool,
+    pub sort: SortKey,
+    pub reverse: bool,
+    /// Record per-file wall time and bytes read.
+    pub timings: bool,
+    /// Like `timings`, split into I/O, decoding and analysis, with a table
+    /// at the end.
+    pub bench: bool,
+    /// Decimal places for console and CSV/TSV numbers.
+    pub precision: Option<usize>,
+    /// Show a full-screen dashboard on stderr instead of the progress bar.
+    pub tui: bool,
+    /// Columns for console and CSV/TSV output, in order.
+    pub fields: Option<Vec<Field>>,
+    /// Template for per-file result lines.
+    pub template: Option<Template>,
+    /// -q count, kept for printing the effective configuration.
+    pub quiet: u8,
+    /// File extensions measured when the input is a directory, lowercase.
+    pub extensions: Vec<String>,
+    /// Worker threads; 0 leaves it to rayon (RAYON_NUM_THREADS or one per core).
+    pub threads: usize,
+    /// Reads in flight at once. Files are streamed a read buffer at a time,
+    /// each read holding a permit while decoding goes on in parallel.
+    pub io_jobs: Option<usize>,
+    /// Read files through memory maps instead of read calls.
+    pub mmap: bool,
+    /// Bytes read from a file at a time; the library's default when unset.
+    pub read_buffer: Option<usize>,
+    /// Run at low CPU and I/O priority, on half the cores unless --threads is given.
+    pub nice: bool,
+    /// Exit successfully when there are no files to measure.
+    pub allow_empty: bool,
+    /// Stop on a malformed outfile rather than setting it aside.
+    pub strict_cache: bool,
+    /// Write the outfile even when it looks like audio.
+    pub force_outfile: bool,
+    /// Format to write the outfile in, instead of the one its extension
+    /// names.
+    pub cache_format: Option<CacheFormat>,
+    pub schedule: Schedule,
+    /// What to do with audio too short for integrated loudness.
+    pub short_files: ShortFiles,
+    /// Percent the decoded length may differ from the declared one by.
+    pub duration_tolerance: Option<f64>,
+    /// Audio track to measure, from 0, instead of the default one.
+    pub track: Option<usize>,
+    /// Measure the first audio track in this language instead.
+    pub language: Option<String>,
+    /// Seconds into each file to start measuring at.
+    pub start: Option<f64>,
+    /// Seconds of each file to measure, from `start`.
+    pub duration: Option<f64>,
+    /// Speaker of each channel, instead of the layout files declare.
+    pub channel_map: Option<ChannelMap>,
+    /// Where to write each measured file's gating-block histogram.
+    pub histogram_dir: Option<PathBuf>,
+    /// LUFS the histogram's bins span.
+    pub histogram_range: (f64, f64),
+    /// Also measure true peak and short-term loudness, for PSR.
+    pub psr: bool,
+    /// Where each option not left at its default came from.
+    pub sources: HashMap<&'static str, Source>,
+}
+
+impl Args {
+    /// Parses measure options on top of the defaults in `config`; the input
+    /// path is only required when `needs_input` is set.
+    fn parse(
+        args: impl Iterator<Item = String>,
+        config: &Config,
+        needs_input: bool,
+    ) -> Result<Args, String> {
+        // config settings go first as flags, then the environment's, so the
+        // command line overrides both
+        let mut args = config
+            .measure_flags()?
+            .into_iter()
+            .chain(std::iter::once(ENVIRONMENT.to_string()))
+            .chain(crate::config::env_flags()?)
+            .chain(std::iter::once(COMMAND_LINE.to_string()))
+            .chain(args);
+        let mut source = Source::Config;
+        let mut sources = HashMap::new();
+        let mut from_config = None;
+        let mut earlier_quiet = 0;
+        let mut extensions = vec!["mp3".to_string()];
+        let mut threads = 0;
+        let mut io_jobs = None;
+        let mut nice = false;
+        let mut allow_empty = false;
+        let mut strict_cache = false;
+        let mut force_outfile = false;
+        let mut cache_format = None;
+        let mut mmap = false;
+        let mut read_buffer = None;
+        let mut schedule = Schedule::Size;
+        let mut short_files = ShortFiles::Ungated;
+        let mut duration_tolerance = None;
+        let mut track = None;
+        let mut language = None;
+        let mut start = None;
+        let mut duration = None;
+        let mut channel_map = None;
+        let mut histogram_dir = None;
+        let mut histogram_range = DEFAULT_HISTOGRAM_RANGE;
+        let mut psr = false;
+        let mut positional = vec![];
+        let mut target = None;
+        let mut units = Units::Lufs;
+        let mut quiet = 0;
+        let mut verbose = 0;
+        let mut color = ColorChoice::Auto;
+        let mut format = OutputFormat::Human;
+        let mut include_failed = false;
+        let mut unordered = false;
+        let mut fail_fast = false;
+        let mut max_errors = None;
+        let mut failed_out = None;
+        let mut files_from = None;
+        let mut max_decode_errors = None;
+        let mut max_decode_error_fraction = DEFAULT_MAX_DECODE_ERROR_FRACTION;
+        let mut timeout = None;
+        let mut limit = 0;
+        let mut no_precount = false;
+        let mut sort = SortKey::Name;
+        let mut reverse = false;
+        let mut timings = false;
+        let mut bench = false;
+        let mut precision = None;
+        let mut tui = false;
+        let mut fields = None;
+        let mut template = None;
+
+        while let Some(arg) = args.next() {
+            let option = option_name(&arg);
+            match arg.as_str() {
+                ENVIRONMENT | COMMAND_LINE => {
+                    // -q counts up within a source, but a later source replaces it
+                    if quiet > 0 {
+                        earlier_quiet = quiet;
+                    }
+                    quiet = 0;
+                    source = if arg == ENVIRONMENT {
+                        Source::Environment
+                    } else {
+                        // output shape from the command line replaces the config's as a whole
+                        from_config = Some((format, template.take(), fields.take()));
+                        format = OutputFormat::Human;
+                        Source::CommandLine
+                    };
+                }
+                "-q" | "--quiet" => quiet += 1,
+                "-qq" => quiet += 2,
+                "-v" | "--verbose" => verbose += 1,
+                "-vv" => verbose += 2,
+                "--json" => set_format(&mut format, OutputFormat::Json)?,
+                "--ndjson" => set_format(&mut format, OutputFormat::Ndjson)?,
+                "--csv" => set_format(&mut format, OutputFormat::Csv)?,
+                "--tsv" => set_format(&mut format, OutputFormat::Tsv)?,
+                "--include-failed" => include_failed = true,
+                "--unordered" => unordered = true,
+                "--fail-fast" => fail_fast = true,
+                "--timeout" => {
+                    let value = args.next().ok_or("--timeout requires a value")?;
+                    timeout = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|secs: &f64| secs.is_finite() && *secs > 0.0)
+                            .map(Duration::from_secs_f64)
+                            .ok_or_else(|| {
+                                format!("invalid --timeout '{value}', expected seconds")
+                            })?,
+                    );
+                }
+                "--limit" => {
+                    let value = args.next().ok_or("--limit requires a value")?;
+                    limit = value
+                        .parse()
+                        .map_err(|_| format!("invalid --limit '{value}'"))?;
+                }
+                "--max-errors" => {
+                    let value = args.next().ok_or("--max-errors requires a value")?;
+                    max_errors = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --max-errors '{value}'"))?,
+                    );
+                }
+                "--failed-out" => {
+                    failed_out = Some(args.next().ok_or("--failed-out requires a value")?);
+                }
+                "--files-from" => {
+                    files_from = Some(args.next().ok_or("--files-from requires a value")?);
+                }
+                "--max-decode-errors" => {
+                    let value = args.next().ok_or("--max-decode-errors requires a value")?;
+                    max_decode_errors = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --max-decode-errors '{value}'"))?,
+                    );
+                }
+                "--max-decode-error-fraction" => {
+                    let value = args
+                        .next()
+                        .ok_or("--max-decode-error-fraction requires a value")?;
+                    max_decode_error_fraction = value
+                        .parse()
+                        .ok()
+                        .filter(|f| (0.0..=1.0).contains(f))
+                        .ok_or_else(|| {
+                            format!(
+                                "invalid --max-decode-error-fraction '{value}', expected 0 to 1"
+                            )
+                        })?;
+                }
+                "--reverse" => reverse = true,
+                "--timings" => timings = true,
+                "--bench" => bench = true,
+                "--tui" => tui = true,
+                "--format" => {
+                    let value = args.next().ok_or("--format requires a value")?;
+                    template = Some(Template::parse(&value)?);
+                }
+                "--fields" => {
+                    let value = args.next().ok_or("--fields requires a value")?;
+                    fields = Some(Field::parse_list(&value)?);
+                }
+                "--sort" => {
+                    let value = args.next().ok_or("--sort requires a value")?;
+                    sort = match value.as_str() {
+                        "name" => SortKey::Name,
+                        "loudness" => SortKey::Loudness,
+                        "peak" => SortKey::TruePeak,
+                        "lra" => return Err("--sort lra: lra isn't measured yet".to_string()),
+                        _ => {
+                            return Err(format!(
+                                "invalid --sort '{value}', expected name, loudness or peak"
+                            ))
+                        }
+                    };
+                }
+                "--duration-tolerance" => {
+                    let value = args.next().ok_or("--duration-tolerance requires a value")?;
+                    duration_tolerance = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|p: &f64| *p >= 0.0)
+                            .ok_or_else(|| format!("invalid --duration-tolerance '{value}'"))?,
+                    );
+                }
+                "--track" => {
+                    let value = args.next().ok_or("--track requires a value")?;
+                    track = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --track '{value}'"))?,
+                    );
+                }
+                "--language" => {
+                    language = Some(args.next().ok_or("--language requires a value")?);
+                }
+                "--start" => {
+                    let value = args.next().ok_or("--start requires a value")?;
+                    start = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|s: &f64| s.is_finite() && *s >= 0.0)
+                            .ok_or_else(|| format!("invalid --start '{value}'"))?,
+                    );
+                }
+                "--duration" => {
+                    let value = args.next().ok_or("--duration requires a value")?;
+                    duration = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|d: &f64| d.is_finite() && *d > 0.0)
+                            .ok_or_else(|| format!("invalid --duration '{value}'"))?,
+                    );
+                }
+                "--channel-map" => {
+                    let value = args.next().ok_or("--channel-map requires a value")?;
+                    channel_map = Some(
+                        value
+                            .parse()
+                            .map_err(|e| format!("invalid --channel-map '{value}': {e}"))?,
+                    );
+                }
+                "--cache-format" => {
+                    let value = args.next().ok_or("--cache-format requires a value")?;
+                    cache_format = Some(
+                        value
+                            .parse()
+                            .map_err(|e| format!("invalid --cache-format '{value}': {e}"))?,
+                    );
+                }
+                "--histogram-dir" => {
+                    let value = args.next().ok_or("--histogram-dir requires a value")?;
+                    histogram_dir = Some(PathBuf::from(value));
+                }
+                "--histogram-range" => {
+                    let value = args.next().ok_or("--histogram-range requires a value")?;
+                    histogram_range = value
+                        .split_once(':')
+                        .and_then(|(lo, hi)| Some((lo.parse().ok()?, hi.parse().ok()?)))
+                        .filter(|(lo, hi): &(f64, f64)| {
+                            lo.is_finite() && hi.is_finite() && lo < hi && hi - lo <= MAX_HISTOGRAM_SPAN
+                        })
+                        .ok_or_else(|| {
+                            format!(
+                                "invalid --histogram-range '{value}', expected MIN:MAX at most {MAX_HISTOGRAM_SPAN} LU apart"
+                            )
+                        })?;
+                }
+                "--short-files" => {
+                    let value = args.next().ok_or("--short-files requires a value")?;
+                    short_files = match value.as_str() {
+                        "ungated" => ShortFiles::Ungated,
+                        "fail" => ShortFiles::Fail,
+                        _ => {
+                            return Err(format!(
+                                "invalid --short-files '{value}', expected ungated or fail"
+                            ))
+                        }
+                    };
+                }
+                "--schedule" => {
+                    let value = args.next().ok_or("--schedule requires a value")?;
+                    schedule = match value.as_str() {
+                        "size" => Schedule::Size,
+                        "name" => Schedule::Name,
+                        _ => {
+                            return Err(format!(
+                                "invalid --schedule '{value}', expected size or name"
+                            ))
+                        }
+                    };
+                }
+                "--color" => {
+                    let value = args.next().ok_or("--color requires a value")?;
+                    color = match value.as_str() {
+                        "always" => ColorChoice::Always,
+                        "never" => ColorChoice::Never,
+                        "auto" => ColorChoice::Auto,
+                        _ => {
+                            return Err(format!(
+                                "invalid --color '{value}', expected always, never or auto"
+                            ))
+                        }
+                    };
+                }
+                "--target" => target = Some(parse_target(args.next())?),
+                "--precision" => {
+                    let value = args.next().ok_or("--precision requires a value")?;
+                    precision = Some(
+                        value
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|p| *p <= 17)
+                            .ok_or_else(|| {
+                                format!("invalid --precision '{value}', expected 0-17")
+                            })?,
+                    );
+                }
+                "--units" => {
+                    let value = args.next().ok_or("--units requires a value")?;
+                    units = match value.to_ascii_lowercase().as_str() {
+                        "lufs" => Units::Lufs,
+                        "lu" => Units::Lu,
+                        _ => return Err(format!("invalid --units '{value}', expected lufs or lu")),
+                    };
+                }
+                "--threads" => {
+                    let value = args.next().ok_or("--threads requires a value")?;
+                    threads = value
+                        .parse()
+                        .map_err(|_| format!("invalid --threads '{value}'"))?;
+                }
+                "--nice" => nice = true,
+                "--allow-empty" => allow_empty = true,
+                "--strict-cache" => strict_cache = true,
+                "--force-outfile" => force_outfile = true,
+                "--mmap" => mmap = true,
+                "--psr" => psr = true,
+                "--read-buffer" => {
+                    let value = args.next().ok_or("--read-buffer requires a value")?;
+                    read_buffer = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --read-buffer '{value}'"))?,
+                    );
+                }
+                "--no-precount" => no_precount = true,
+                "--io-jobs" => {
+                    let value = args.next().ok_or("--io-jobs requires a value")?;
+                    io_jobs = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|n| *n > 0)
+                            .ok_or_else(|| format!("invalid --io-jobs '{value}'"))?,
+                    );
+                }
+                "--extensions" => {
+                    let value = args.next().ok_or("--extensions requires a value")?;
+                    extensions = value
+                        .split(',')
+                        .map(|e| e.trim().trim_start_matches('.').to_ascii_lowercase())
+                        .filter(|e| !e.is_empty())
+                        .collect();
+                    if extensions.is_empty() {
+                        return Err(format!("invalid --extensions '{value}'"));
+                    }
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => positional.push(arg),
+            }
+            if let Some(option) = option {
+                sources.insert(option, source);
+            }
+        }
+        if quiet == 0 && verbose == 0 {
+            quiet = earlier_quiet;
+        }
+
+        if let Some(config) = from_config {
+            if format == OutputFormat::Human && template.is_none() && fields.is_none() {
+                (format, template, fields) = config;
+            }
+        }
+        if units == Units::Lu && target.is_none() {
+            return Err("--units lu requires --target".to_string());
+        }
+        if sort == SortKey::TruePeak && !psr {
+            return Err("--sort peak requires --psr, which measures true peak".to_string());
+        }
+        if template.is_some() && format.is_structured() {
+            return Err(
+                "--format applies to per-file lines, not --json/--ndjson/--csv/--tsv".into(),
+            );
+        }
+        if template.is_some() && fields.is_some() {
+            return Err("use only one of --format and --fields".to_string());
+        }
+
+        let mut positional = positional.into_iter();
+        // --files-from stands in for the input, so a lone path is the outfile
+        let input = match files_from {
+            Some(_) => String::new(),
+            None => match positional.next() {
+                Some(input) => input,
+                None if !needs_input => String::new(),
+                None => return Err(USAGE.to_string()),
+            },
+        };
+        // checked here so bad invocations fail before any cache is loaded
+        if needs_input && files_from.is_none() {
+            check_input(Path::new(&input))?;
+        }
+        let outfile = match (positional.next(), std::env::var("LOUDNESS_CACHE")) {
+            (Some(outfile), _) => {
+                sources.insert("outfile", Source::CommandLine);
+                Some(outfile)
+            }
+            (None, Ok(outfile)) if !outfile.is_empty() => {
+                sources.insert("outfile", Source::Environment);
+                Some(outfile)
+            }
+            _ => match config.measure_value("outfile") {
+                Some(Value::String(outfile)) => {
+                    sources.insert("outfile", Source::Config);
+                    Some(outfile.clone())
+                }
+                _ => None,
+            },
+        };
+        if let Some(extra) = positional.next() {
+            return Err(format!("unexpected argument '{extra}'"));
+        }
+        if mmap && io_jobs.is_some() {
+            // reads from a map are page faults, which no permit can hold back
+            return Err("--mmap and --io-jobs can't be combined".to_string());
+        }
+
+        Ok(Args {
+            input,
+            outfile,
+            target,
+            units,
+            log_level: crate::output::level_from_flags(verbose, quiet),
+            quiet,
+            color,
+            format,
+            include_failed,
+            unordered,
+            fail_fast,
+            max_errors,
+            failed_out,
+            files_from,
+            timeout,
+            max_decode_errors,
+            max_decode_error_fraction,
+            limit,
+            no_precount,
+            sort,
+            reverse,
+            timings,
+            bench,
+            precision,
+            tui,
+            fields,
+            template,
+            extensions,
+            threads,
+            io_jobs,
+            mmap,
+            read_buffer,
+            nice,
+            allow_empty,
+            strict_cache,
+            force_outfile,
+            cache_format,
+            schedule,
+            short_files,
+            duration_tolerance,
+            track,
+            language,
+            start,
+            duration,
+            channel_map,
+            histogram_dir,
+            histogram_range,
+            psr,
+            sources,
+        })
+    }
+}
+
+pub struct TagArgs {
+    pub from: String,
+    pub dir: String,
+    pub target: f64,
+    /// Also write album gain, from the album loudness of the cache.
+    pub album: bool,
+    pub dry_run: bool,
+}
+
+impl TagArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<TagArgs, String> {
+        let mut from = None;
+        let mut dir = None;
+        let mut target = REPLAYGAIN_REFERENCE;
+        let mut album = false;
+        let mut dry_run = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--from" => from = Some(args.next().ok_or("--from requires a value")?),
+                "--target" => target = parse_target(args.next())?,
+                "--album" => album = true,
+                "--dry-run" => dry_run = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ if dir.is_none() => dir = Some(arg),
+                _ => return Err(format!("unexpected argument '{arg}'")),
+            }
+        }
+
+        Ok(TagArgs {
+            from: from.ok_or("tag requires --from <cache.json>")?,
+            dir: dir.ok_or(USAGE)?,
+            target,
+            album,
+            dry_run,
+        })
+    }
+}
+
+pub struct UntagArgs {
+    pub dir: String,
+    pub dry_run: bool,
+}
+
+impl UntagArgs {
+    fn parse(args: impl Iterator<Item = String>) -> Result<UntagArgs, String> {
+        let mut dir = None;
+        let mut dry_run = false;
+
+        for arg in args {
+            match arg.as_str() {
+                "--dry-run" => dry_run = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ if dir.is_none() => dir = Some(arg),
+                _ => return Err(format!("unexpected argument '{arg}'")),
+            }
+        }
+
+        Ok(UntagArgs {
+            dir: dir.ok_or(USAGE)?,
+            dry_run,
+        })
+    }
+}
+
+pub struct VerifyTagsArgs {
+    pub cache: String,
+    pub dir: String,
+    pub target: f64,
+    pub tolerance: f64,
+}
+
+impl VerifyTagsArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<VerifyTagsArgs, String> {
+        let mut positional = vec![];
+        let mut target = REPLAYGAIN_REFERENCE;
+        let mut tolerance = 0.05;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--target" => target = parse_target(args.next())?,
+                "--tolerance" => {
+                    let value = args.next().ok_or("--tolerance requires a value")?;
+                    tolerance = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|t| *t >= 0.0)
+                        .ok_or_else(|| format!("invalid --tolerance '{value}'"))?;
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => positional.push(arg),
+            }
+        }
+
+        let [cache, dir] = <[String; 2]>::try_from(positional).map_err(|_| USAGE)?;
+        Ok(VerifyTagsArgs {
+            cache,
+            dir,
+            target,
+            tolerance,
+        })
+    }
+}
+
+pub struct GainsArgs {
+    pub cache: String,
+    pub out: String,
+    pub target: f64,
+    /// Write gains in dB instead of linear multipliers.
+    pub db: bool,
+    /// Never boost, only attenuate.
+    pub clamp: bool,
+}
+
+impl GainsArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<GainsArgs, String> {
+        let mut positional = vec![];
+        let mut target = REPLAYGAIN_REFERENCE;
+        let mut db = false;
+        let mut clamp = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--target" => target = parse_target(args.next())?,
+                "--format" => {
+                    let value = args.next().ok_or("--format requires a value")?;
+                    if value != "json" {
+                        return Err(format!("unsupported --format '{value}', expected json"));
+                    }
+                }
+                "--db" => db = true,
+                "--clamp" => clamp = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => positional.push(arg),
+            }
+        }
+
+        let [cache, out] = <[String; 2]>::try_from(positional).map_err(|_| USAGE)?;
+        Ok(GainsArgs {
+            cache,
+            out,
+            target,
+            db,
+            clamp,
+        })
+    }
+}
+
+pub struct TopArgs {
+    pub cache: String,
+    pub count: usize,
+    /// Rank quietest first instead of loudest first.
+    pub quietest: bool,
+    /// Only consider keys matching this glob.
+    pub pattern: Option<String>,
+    /// What entries are ranked by: loudness, true peak or PSR.
+    pub by: Field,
+}
+
+impl TopArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<TopArgs, String> {
+        let mut cache = None;
+        let mut ranking = None;
+        let mut pattern = None;
+        let mut by = Field::Loudness;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--loudest" | "--quietest" => {
+                    if ranking.is_some() {
+                        return Err("use only one of --loudest and --quietest".to_string());
+                    }
+                    let value = args.next().ok_or(format!("{arg} requires a value"))?;
+                    let count = value
+                        .parse::<usize>()
+                        .map_err(|_| format!("invalid {arg} '{value}'"))?;
+                    ranking = Some((count, arg == "--quietest"));
+                }
+                "--match" => pattern = Some(args.next().ok_or("--match requires a value")?),
+                "--by" => {
+                    let value = args.next().ok_or("--by requires a value")?;
+                    by = match value.as_str() {
+                        "loudness" => Field::Loudness,
+                        "peak" => Field::TruePeak,
+                        "psr" => Field::Psr,
+                        "lra" | "plr" => {
+                            return Err(format!("--by {value}: {value} isn't measured yet"))
+                        }
+                        _ => {
+                            return Err(format!(
+                                "invalid --by '{value}', expected loudness, peak or psr"
+                            ))
+                        }
+                    };
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ if cache.is_none() => cache = Some(arg),
+                _ => return Err(format!("unexpected argument '{arg}'")),
+            }
+        }
+
+        let (count, quietest) = ranking.ok_or("top requires --loudest N or --quietest N")?;
+        Ok(TopArgs {
+            cache: cache.ok_or(USAGE)?,
+            count,
+            quietest,
+            pattern,
+            by,
+        })
+    }
+}
+
+pub struct StatsArgs {
+    pub cache: String,
+    /// Only consider keys matching this glob.
+    pub pattern: Option<String>,
+    /// Also give the mean weighted by duration, and the combined loudness.
+    pub weighted: bool,
+    pub json: bool,
+}
+
+impl StatsArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<StatsArgs, String> {
+        let mut cache = None;
+        let mut pattern = None;
+        let mut weighted = false;
+        let mut json = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--match" => pattern = Some(args.next().ok_or("--match requires a value")?),
+                "--weighted" => weighted = true,
+                "--json" => json = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ if cache.is_none() => cache = Some(arg),
+                _ => return Err(format!("unexpected argument '{arg}'")),
+            }
+        }
+
+        Ok(StatsArgs {
+            cache: cache.ok_or(USAGE)?,
+            pattern,
+            weighted,
+            json,
+        })
+    }
+}
+
+/// Value bucketed by `hist`.
+#[derive(Clone, Copy)]
+pub enum HistMetric {
+    Loudness,
+    /// Gain in dB needed to reach the given target.
+    Gain(f64),
+}
+
+pub struct HistArgs {
+    pub cache: String,
+    pub metric: HistMetric,
+    /// Bucket width in LU (or dB for gains).
+    pub bucket: f64,
+    /// Fixed histogram range; derived from the data when absent.
+    pub range: Option<(f64, f64)>,
+    /// Only consider keys matching this glob.
+    pub pattern: Option<String>,
+}
+
+impl HistArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<HistArgs, String> {
+        let mut cache = None;
+        let mut gain = false;
+        let mut target = REPLAYGAIN_REFERENCE;
+        let mut bucket = 1.0;
+        let mut range = None;
+        let mut pattern = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--metric" => {
+                    let value = args.next().ok_or("--metric requires a value")?;
+                    gain = match value.as_str() {
+                        "loudness" => false,
+                        "gain" => true,
+                        "lra" => return Err("--metric lra: lra isn't measured yet".to_string()),
+                        _ => {
+                            return Err(format!(
+                                "invalid --metric '{value}', expected loudness or gain"
+                            ))
+                        }
+                    };
+                }
+                "--target" => target = parse_target(args.next())?,
+                "--bucket" => {
+                    let value = args.next().ok_or("--bucket requires a value")?;
+                    bucket = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|b| *b > 0.0)
+                        .ok_or_else(|| format!("invalid --bucket '{value}'"))?;
+                }
+                "--range" => {
+                    let value = args.next().ok_or("--range requires a value")?;
+                    range = Some(
+                        value
+                            .split_once(':')
+                            .and_then(|(lo, hi)| Some((lo.parse().ok()?, hi.parse().ok()?)))
+                            .filter(|(lo, hi): &(f64, f64)| lo < hi)
+                            .ok_or_else(|| {
+                                format!("invalid --range '{value}', expected MIN:MAX")
+                            })?,
+                    );
+                }
+                "--match" => pattern = Some(args.next().ok_or("--match requires a value")?),
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ if cache.is_none() => cache = Some(arg),
+                _ => return Err(format!("unexpected argument '{arg}'")),
+            }
+        }
+
+        Ok(HistArgs {
+            cache: cache.ok_or(USAGE)?,
+            metric: if gain {
+                HistMetric::Gain(target)
+            } else {
+                HistMetric::Loudness
+            },
+            bucket,
+            range,
+            pattern,
+        })
+    }
+}
+
+/// What makes an entry an outlier.
+#[derive(Clone, Copy)]
+pub enum OutlierRule {
+    /// More than this many standard deviations from the mean.
+    Sigma(f64),
+    /// More than this many LU from the median.
+    Delta(f64),
+}
+
+pub struct OutliersArgs {
+    pub cache: String,
+    pub rule: OutlierRule,
+}
+
+impl OutliersArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<OutliersArgs, String> {
+        let mut cache = None;
+        let mut rule = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--sigma" | "--delta" => {
+                    if rule.is_some() {
+                        return Err("use only one of --sigma and --delta".to_string());
+                    }
+                    let value = args.next().ok_or(format!("{arg} requires a value"))?;
+                    let limit = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|l| *l >= 0.0)
+                        .ok_or_else(|| format!("invalid {arg} '{value}'"))?;
+                    rule = Some(if arg == "--sigma" {
+                        OutlierRule::Sigma(limit)
+                    } else {
+                        OutlierRule::Delta(limit)
+                    });
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ if cache.is_none() => cache = Some(arg),
+                _ => return Err(format!("unexpected argument '{arg}'")),
+            }
+        }
+
+        Ok(OutliersArgs {
+            cache: cache.ok_or(USAGE)?,
+            rule: rule.unwrap_or(OutlierRule::Sigma(2.0)),
+        })
+    }
+}
+
+pub struct StatusArgs {
+    pub cache: String,
+    pub target: f64,
+    /// LU either side of the target an entry may be.
+    pub tolerance: f64,
+}
+
+impl StatusArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<StatusArgs, String> {
+        let mut target = None;
+        let mut tolerance = 1.0;
+        let mut positional = vec![];
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--target" => target = Some(parse_target(args.next())?),
+                "--tolerance" => {
+                    let value = args.next().ok_or("--tolerance requires a value")?;
+                    tolerance = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|t| *t >= 0.0)
+                        .ok_or_else(|| format!("invalid --tolerance '{value}'"))?;
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => positional.push(arg),
+            }
+        }
+
+        let [cache] = <[String; 1]>::try_from(positional).map_err(|_| USAGE)?;
+        Ok(StatusArgs {
+            cache,
+            target: target.ok_or("status requires --target LUFS")?,
+            tolerance,
+        })
+    }
+}
+
+pub struct CompareArgs {
+    pub a: String,
+    /// Second file; compared against `target` instead when absent.
+    pub b: Option<String>,
+    pub target: Option<f64>,
+    /// Cache to take existing measurements from.
+    pub cache: Option<String>,
+    /// Largest acceptable loudness difference in LU.
+    pub tolerance: Option<f64>,
+}
+
+impl CompareArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<CompareArgs, String> {
+        let mut positional = vec![];
+        let mut target = None;
+        let mut cache = None;
+        let mut tolerance = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--target" => target = Some(parse_target(args.next())?),
+                "--cache" => cache = Some(args.next().ok_or("--cache requires a value")?),
+                "--tolerance" => {
+                    let value = args.next().ok_or("--tolerance requires a value")?;
+                    tolerance = Some(
+                        value
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|t| *t >= 0.0)
+                            .ok_or_else(|| format!("invalid --tolerance '{value}'"))?,
+                    );
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => positional.push(arg),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let (Some(a), b, None) = (positional.next(), positional.next(), positional.next()) else {
+            return Err(USAGE.to_string());
+        };
+        match (&b, target) {
+            (Some(_), Some(_)) => {
+                return Err("compare takes a second file or --target, not both".to_string())
+            }
+            (None, None) => return Err("compare requires a second file or --target".to_string()),
+            _ => {}
+        }
+        Ok(CompareArgs {
+            a,
+            b,
+            target,
+            cache,
+            tolerance,
+        })
+    }
+}
+
+pub struct ServeArgs {
+    pub cache: String,
+    /// Address to listen on, e.g. "127.0.0.1:8123".
+    pub listen: String,
+    /// Bearer token required on every request, if set.
+    pub token: Option<String>,
+}
+
+impl ServeArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<ServeArgs, String> {
+        let mut cache = None;
+        let mut listen = "127.0.0.1:8123".to_string();
+        let mut token = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--cache" => cache = Some(args.next().ok_or("--cache requires a value")?),
+                "--listen" => listen = args.next().ok_or("--listen requires a value")?,
+                "--token" => token = Some(args.next().ok_or("--token requires a value")?),
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => return Err(format!("unexpected argument '{arg}'")),
+            }
+        }
+
+        Ok(ServeArgs {
+            cache: cache.ok_or("serve requires --cache <cache.json>")?,
+            listen,
+            token,
+        })
+    }
+}
+
+pub struct PlaylistArgs {
+    pub cache: String,
+    pub out: String,
+    /// Loudness orders quietest first, the opposite of the measure report.
+    pub sort: SortKey,
+    pub reverse: bool,
+    /// Only include keys matching this glob.
+    pub pattern: Option<String>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl PlaylistArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<PlaylistArgs, String> {
+        let mut cache = None;
+        let mut out = None;
+        let mut sort = SortKey::Name;
+        let mut reverse = false;
+        let mut pattern = None;
+        let mut min = None;
+        let mut max = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--out" => out = Some(args.next().ok_or("--out requires a value")?),
+                "--sort" => {
+                    let value = args.next().ok_or("--sort requires a value")?;
+                    sort = match value.as_str() {
+                        "name" => SortKey::Name,
+                        "loudness" => SortKey::Loudness,
+                        _ => {
+                            return Err(format!(
+                                "invalid --sort '{value}', expected name or loudness"
+                            ))
+                        }
+                    };
+                }
+                "--reverse" => reverse = true,
+                "--match" => pattern = Some(args.next().ok_or("--match requires a value")?),
+                "--min" | "--max" => {
+                    let value = args.next().ok_or(format!("{arg} requires a value"))?;
+                    let bound = value
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid {arg} '{value}'"))?;
+                    if arg == "--min" {
+                        min = Some(bound);
+                    } else {
+                        max = Some(bound);
+                    }
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ if cache.is_none() => cache = Some(arg),
+                _ => return Err(format!("unexpected argument '{arg}'")),
+            }
+        }
+
+        Ok(PlaylistArgs {
+            cache: cache.ok_or(USAGE)?,
+            out: out.ok_or("playlist requires --out <list.m3u8>")?,
+            sort,
+            reverse,
+            pattern,
+            min,
+            max,
+        })
+    }
+}
+
+/// Formats `import` reads.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// ffmpeg's loudnorm filter with print_format=json.
+    Loudnorm,
+}
+
+pub struct ImportArgs {
+    pub format: ImportFormat,
+    /// A JSON file, or a directory of them.
+    pub input: String,
+    pub cache: String,
+    /// Replace entries measured by this tool, not just earlier imports.
+    pub overwrite: bool,
+}
+
+impl ImportArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<ImportArgs, String> {
+        let mut format = None;
+        let mut overwrite = false;
+        let mut positional = vec![];
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => {
+                    let value = args.next().ok_or("--format requires a value")?;
+                    format = match value.as_str() {
+                        "loudnorm" => Some(ImportFormat::Loudnorm),
+                        _ => return Err(format!("invalid --format '{value}', expected loudnorm")),
+                    };
+                }
+                "--overwrite" => overwrite = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => positional.push(arg),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let (Some(input), Some(cache)) = (positional.next(), positional.next()) else {
+            return Err(USAGE.to_string());
+        };
+        if let Some(extra) = positional.next() {
+            return Err(format!("unexpected argument '{extra}'"));
+        }
+        Ok(ImportArgs {
+            format: format.ok_or("import requires --format loudnorm")?,
+            input,
+            cache,
+            overwrite,
+        })
+    }
+}
+
+pub struct ExportArgs {
+    pub cache: String,
+    pub csv: bool,
+    /// Leave out the album fields.
+    pub tracks_only: bool,
+}
+
+impl ExportArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<ExportArgs, String> {
+        let mut format = false;
+        let mut csv = false;
+        let mut tracks_only = false;
+        let mut positional = vec![];
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => {
+                    let value = args.next().ok_or("--format requires a value")?;
+                    if value != "beets" {
+                        return Err(format!("invalid --format '{value}', expected beets"));
+                    }
+                    format = true;
+                }
+                "--csv" => csv = true,
+                "--tracks-only" => tracks_only = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => positional.push(arg),
+            }
+        }
+
+        if !format {
+            return Err("export requires --format beets".to_string());
+        }
+        let [cache] = <[String; 1]>::try_from(positional).map_err(|_| USAGE)?;
+        Ok(ExportArgs {
+            cache,
+            csv,
+            tracks_only,
+        })
+    }
+}
+
+/// Order of the `albums` report.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AlbumSort {
+    /// By directory.
+    Name,
+    /// Loudest first.
+    Loudness,
+    /// Widest spread between tracks and album first.
+    Spread,
+}
+
+pub struct AlbumsArgs {
+    pub cache: String,
+    pub sort: AlbumSort,
+    pub json: bool,
+}
+
+impl AlbumsArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<AlbumsArgs, String> {
+        let mut sort = AlbumSort::Name;
+        let mut json = false;
+        let mut positional = vec![];
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--sort" => {
+                    let value = args.next().ok_or("--sort requires a value")?;
+                    sort = match value.as_str() {
+                        "name" => AlbumSort::Name,
+                        "loudness" => AlbumSort::Loudness,
+                        "spread" => AlbumSort::Spread,
+                        _ => {
+                            return Err(format!(
+                                "invalid --sort '{value}', expected name, loudness or spread"
+                            ))
+                        }
+                    };
+                }
+                "--json" => json = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => positional.push(arg),
+            }
+        }
+
+        let [cache] = <[String; 1]>::try_from(positional).map_err(|_| USAGE)?;
+        Ok(AlbumsArgs { cache, sort, json })
+    }
+}
+
+pub struct ValidateArgs {
+    /// CSV of reference loudness by key.
+    pub refs: String,
+    /// Directory of the files, or a single file.
+    pub input: String,
+    /// Largest acceptable difference from a reference in LU.
+    pub tolerance: f64,
+    pub json: bool,
+}
+
+impl ValidateArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<ValidateArgs, String> {
+        let mut tolerance = 0.1;
+        let mut json = false;
+        let mut positional = vec![];
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--tolerance" => {
+                    let value = args.next().ok_or("--tolerance requires a value")?;
+                    tolerance = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|t| *t >= 0.0)
+                        .ok_or_else(|| format!("invalid --tolerance '{value}'"))?;
+                }
+                "--json" => json = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => positional.push(arg),
+            }
+        }
+
+        let [refs, input] = <[String; 2]>::try_from(positional).map_err(|_| USAGE)?;
+        check_input(Path::new(&input))?;
+        Ok(ValidateArgs {
+            refs,
+            input,
+            tolerance,
+            json,
+        })
+    }
+}
+
+/// Socket used by `daemon` and `client` without --socket.
+fn default_socket() -> String {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => format!("{dir}/loudness.sock"),
+        _ => "/tmp/loudness.sock".to_string(),
+    }
+}
+
+pub struct DaemonArgs {
+    pub socket: String,
+    /// Cache to answer from and add new measurements to.
+    pub cache: Option<String>,
+}
+
+impl DaemonArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<DaemonArgs, String> {
+        let mut socket = None;
+        let mut cache = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--socket" => socket = Some(args.next().ok_or("--socket requires a value")?),
+                "--cache" => cache = Some(args.next().ok_or("--cache requires a value")?),
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => return Err(format!("unexpected argument '{arg}'")),
+            }
+        }
+
+        Ok(DaemonArgs {
+            socket: socket.unwrap_or_else(default_socket),
+            cache,
+        })
+    }
+}
+
+pub struct ClientArgs {
+    pub socket: String,
+    pub paths: Vec<String>,
+}
+
+impl ClientArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<ClientArgs, String> {
+        let mut socket = None;
+        let mut paths = vec![];
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--socket" => socket = Some(args.next().ok_or("--socket requires a value")?),
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ => paths.push(arg),
+            }
+        }
+
+        if paths.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        Ok(ClientArgs {
+            socket: socket.unwrap_or_else(default_socket),
+            paths,
+        })
+    }
+}
+
+/// Checks that `input` is a file or directory that can be read, saying why
+/// not otherwise.
+fn check_input(input: &Path) -> Result<(), String> {
+    let shown = input.display();
+    let readable = match std::fs::metadata(input) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!("'{shown}' does not exist"));
+        }
+        Err(e) => Err(e),
+        Ok(meta) if meta.is_dir() => std::fs::read_dir(input).map(drop),
+        Ok(meta) if meta.is_file() => std::fs::File::open(input).map(drop),
+        Ok(_) => return Err(format!("'{shown}' is neither a file nor a directory")),
+    };
+    match readable {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            Err(format!("'{shown}' can't be read: permission denied"))
+        }
+        Err(e) => Err(format!("'{shown}' can't be read: {e}")),
+    }
+}
+
+/// Mark the start of flags generated from the environment, and of those
+/// actually on the command line.
+const ENVIRONMENT: &str = "\0environment";
+const COMMAND_LINE: &str = "\0command-line";
+
+/// Config key set by a measure flag, for tracking where options came from.
+fn option_name(flag: &str) -> Option<&'static str> {
+    Some(match flag {
+        "-q" | "--quiet" | "-qq" => "quiet",
+        "--json" | "--ndjson" | "--csv" | "--tsv" => "format",
+        "--format" => "template",
+        "--target" => "target",
+        "--units" => "units",
+        "--color" => "color",
+        "--precision" => "precision",
+        "--sort" => "sort",
+        "--fields" => "fields",
+        "--extensions" => "extensions",
+        "--threads" => "threads",
+        "--io-jobs" => "io_jobs",
+        "--nice" => "nice",
+        "--mmap" => "mmap",
+        "--psr" => "psr",
+        "--read-buffer" => "read_buffer",
+        "--schedule" => "schedule",
+        "--short-files" => "short_files",
+        "--duration-tolerance" => "duration_tolerance",
+        "--track" => "track",
+        "--language" => "language",
+        "--allow-empty" => "allow_empty",
+        "--strict-cache" => "strict_cache",
+        "--cache-format" => "cache_format",
+        "--reverse" => "reverse",
+        "--include-failed" => "include_failed",
+        "--unordered" => "unordered",
+        "--fail-fast" => "fail_fast",
+        "--max-errors" => "max_errors",
+        "--failed-out" => "failed_out",
+        "--max-decode-errors" => "max_decode_errors",
+        "--max-decode-error-fraction" => "max_decode_error_fraction",
+        "--timeout" => "timeout",
+        "--limit" => "limit",
+        "--no-precount" => "no_precount",
+        "--timings" => "timings",
+        "--bench" => "bench",
+        _ => return None,
+    })
+}
+
+fn set_format(format: &mut OutputFormat, f: OutputFormat) -> Result<(), String> {
+    if *format != OutputFormat::Human && *format != f {
+        return Err("only one of --json, --ndjson, --csv and --tsv may be given".to_string());
+    }
+    *format = f;
+    Ok(())
+}
+
+fn parse_target(value: Option<String>) -> Result<f64, String> {
+    let value = value.ok_or("--target requires a value")?;
+    value
+        .parse::<f64>()
+        .map_err(|_| format!("invalid --target '{value}'"))
+}
@@ -0,0 +1,93 @@
+//! Side-by-side measurements of two files, or of one file against a target.
+
+use crate::cli::{CompareArgs, Units};
+use crate::gains::gain_db;
+use crate::measure;
+use crate::report::loudness_cell;
+use loudness::{cache_key, load_cache, Measurement};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Uses the cached entry for `path` when there is one, measuring it otherwise.
+fn lookup(path: &str, cache: &HashMap<String, Measurement>) -> Option<Measurement> {
+    let path = PathBuf::from(path);
+    if let Some(m) = cache.get(&cache_key(&path)) {
+        return Some(m.clone());
+    }
+    match measure(&path) {
+        Ok(m) => Some(m),
+        Err(e) => {
+            eprintln!("failed to measure '{}': {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Prints the comparison, returning false if a file couldn't be measured or
+/// the loudness difference exceeds the tolerance.
+pub fn run(args: &CompareArgs) -> io::Result<bool> {
+    let cache = match &args.cache {
+        Some(cache) => match load_cache(Path::new(cache)) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("failed to read cache '{cache}': {e}");
+                return Ok(false);
+            }
+        },
+        None => HashMap::new(),
+    };
+
+    let Some(a) = lookup(&args.a, &cache) else {
+        return Ok(false);
+    };
+
+    let delta = match (&args.b, args.target) {
+        (Some(b_path), _) => {
+            let Some(b) = lookup(b_path, &cache) else {
+                return Ok(false);
+            };
+            let delta = b.loudness - a.loudness;
+            let width = args.a.len().max(b_path.len()).max(11);
+            println!("{:<9} {:>width$}  {:>width$}  delta", "", args.a, b_path);
+            println!(
+                "{:<9} {:>width$}  {:>width$}  {:+.2} LU",
+                "loudness",
+                loudness_cell(a.loudness),
+                loudness_cell(b.loudness),
+                delta
+            );
+            if let (Some(da), Some(db)) = (a.duration, b.duration) {
+                println!(
+                    "{:<9} {:>width$}  {:>width$}  {:+.2} s",
+                    "duration",
+                    format!("{da:.2} s"),
+                    format!("{db:.2} s"),
+                    db - da
+                );
+            }
+            delta
+        }
+        (None, Some(target)) => {
+            let delta = a.loudness - target;
+            println!("loudness  {}", loudness_cell(a.loudness));
+            println!("target    {}", Units::Lufs.format(target, None));
+            println!("distance  {delta:+.2} LU");
+            println!("gain      {:+.2} dB", gain_db(a.loudness, target, false));
+            if let Some(duration) = a.duration {
+                println!("duration  {duration:.2} s");
+            }
+            delta
+        }
+        (None, None) => unreachable!("compare requires a second file or --target"),
+    };
+
+    match args.tolerance {
+        // a silent file is never within tolerance
+        Some(tolerance) if delta.is_nan() || delta.abs() > tolerance => {
+            eprintln!("loudness differs by more than {tolerance} LU");
+            Ok(false)
+        }
+        _ => Ok(true),
+    }
+}
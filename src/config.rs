@@ -0,0 +1,388 @@
+//! Defaults read from a small TOML configuration file.
+//!
+//! Only the subset of TOML the options need is understood: `[section]`
+//! headers, and `key = value` with basic or literal strings, numbers and
+//! booleans. Keys at the
+//! top level or in `[measure]` become defaults for `loudness measure`, by
+//! turning into the equivalent flags placed before the command line ones.
+
+use crate::cli::{Args, OutputFormat, Schedule, SortKey, Units};
+use crate::output::ColorChoice;
+use loudness::ShortFiles;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+}
+
+impl Value {
+    /// The value as it would be passed to a flag.
+    fn as_arg(&self) -> String {
+        match self {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+        }
+    }
+}
+
+/// Where an option's value came from, in increasing precedence.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Source {
+    Config,
+    Environment,
+    CommandLine,
+}
+
+impl Source {
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Config => "config",
+            Source::Environment => "env",
+            Source::CommandLine => "command line",
+        }
+    }
+}
+
+/// Flags equivalent to the LOUDNESS_* environment variables, which sit
+/// between the config file and the command line. LOUDNESS_CACHE isn't a flag
+/// and is read where the outfile is chosen.
+pub fn env_flags() -> Result<Vec<String>, String> {
+    let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+    let mut flags = vec![];
+    if let Some(target) = var("LOUDNESS_TARGET") {
+        if target.trim().parse::<f64>().is_err() {
+            return Err(format!(
+                "LOUDNESS_TARGET: expected a loudness in LUFS, got '{target}'"
+            ));
+        }
+        flags.extend(["--target".to_string(), target.trim().to_string()]);
+    }
+    if let Some(threads) = var("LOUDNESS_THREADS") {
+        if threads.trim().parse::<usize>().is_err() {
+            return Err(format!(
+                "LOUDNESS_THREADS: expected a number of threads, got '{threads}'"
+            ));
+        }
+        flags.extend(["--threads".to_string(), threads.trim().to_string()]);
+    }
+    if let Some(extensions) = var("LOUDNESS_EXTENSIONS") {
+        flags.extend(["--extensions".to_string(), extensions]);
+    }
+    if let Some(quiet) = var("LOUDNESS_QUIET") {
+        match quiet.trim() {
+            "0" | "false" => {}
+            "1" | "true" => flags.push("-q".to_string()),
+            "2" => flags.push("-qq".to_string()),
+            _ => return Err(format!("LOUDNESS_QUIET: expected 0, 1 or 2, got '{quiet}'")),
+        }
+    }
+    Ok(flags)
+}
+
+#[derive(Default)]
+pub struct Config {
+    /// Where the configuration was read from, if a file was found.
+    pub path: Option<PathBuf>,
+    /// `(section, key, value)` in file order; the top level is section "".
+    entries: Vec<(String, String, Value)>,
+}
+
+/// `$XDG_CONFIG_HOME/loudness/config.toml`, or under `~/.config` without it.
+pub fn default_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("loudness").join("config.toml"))
+}
+
+/// Parses a single value: a quoted string, a number or a boolean.
+fn parse_value(raw: &str) -> Option<Value> {
+    // literal strings have no escapes
+    if let Some(inner) = raw.strip_prefix('\'') {
+        let (value, rest) = inner.split_once('\'')?;
+        let rest = rest.trim();
+        return (rest.is_empty() || rest.starts_with('#')).then(|| Value::String(value.into()));
+    }
+    if let Some(inner) = raw.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = inner.chars();
+        loop {
+            match chars.next()? {
+                '"' => break,
+                '\\' => out.push(match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    c => c,
+                }),
+                c => out.push(c),
+            }
+        }
+        // anything after the closing quote must be a comment
+        let rest = chars.as_str().trim();
+        return (rest.is_empty() || rest.starts_with('#')).then_some(Value::String(out));
+    }
+    let raw = raw.split('#').next().unwrap_or_default().trim();
+    match raw {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => raw.parse().ok().map(Value::Number),
+    }
+}
+
+impl Config {
+    /// Reads `path`, or the default location when it's `None`. A missing
+    /// default file is an empty configuration; a missing explicit one is an error.
+    pub fn load(path: Option<&str>) -> Result<Config, String> {
+        let (path, explicit) = match path {
+            Some(p) => (PathBuf::from(p), true),
+            None => match default_path() {
+                Some(p) => (p, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(t) => t,
+            Err(e) if !explicit && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(format!("failed to read config '{}': {e}", path.display())),
+        };
+        let mut config = Config::parse(&text, &path)?;
+        config.path = Some(path);
+        Ok(config)
+    }
+
+    fn parse(text: &str, path: &Path) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut section = String::new();
+        for (n, line) in text.lines().enumerate() {
+            let error = |what: &str| format!("{}:{}: {what}", path.display(), n + 1);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name.split('#').next().unwrap_or_default().trim();
+                section = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| error("malformed section header"))?
+                    .trim()
+                    .to_string();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected 'key = value'"))?;
+            let value = parse_value(value.trim())
+                .ok_or_else(|| error("expected a string, number or boolean"))?;
+            config
+                .entries
+                .push((section.clone(), key.trim().to_string(), value));
+        }
+        Ok(config)
+    }
+
+    /// Value of `key` for `loudness measure`, if set.
+    pub fn measure_value(&self, key: &str) -> Option<&Value> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(s, k, _)| (s.is_empty() || s == "measure") && k == key)
+            .map(|(_, _, v)| v)
+    }
+
+    /// Flags equivalent to the `measure` settings, warning about unknown keys
+    /// rather than failing so configs survive version skew.
+    pub fn measure_flags(&self) -> Result<Vec<String>, String> {
+        let mut flags = vec![];
+        for (section, key, value) in &self.entries {
+            if !section.is_empty() && section != "measure" {
+                eprintln!("warning: config: unknown section [{section}]");
+                continue;
+            }
+            let bad = || format!("config: invalid value {value:?} for '{key}'");
+            match key.as_str() {
+                "target" | "units" | "color" | "precision" | "sort" | "fields" | "extensions"
+                | "threads" | "timeout" | "limit" | "schedule" | "track" | "language" => {
+                    flags.push(format!("--{key}"));
+                    flags.push(value.as_arg());
+                }
+                "io_jobs"
+                | "max_errors"
+                | "failed_out"
+                | "short_files"
+                | "cache_format"
+                | "read_buffer"
+                | "max_decode_errors"
+                | "max_decode_error_fraction"
+                | "duration_tolerance" => {
+                    flags.push(format!("--{}", key.replace('_', "-")));
+                    flags.push(value.as_arg());
+                }
+                "template" => {
+                    flags.push("--format".to_string());
+                    flags.push(value.as_arg());
+                }
+                "format" => match value {
+                    Value::String(f) if matches!(f.as_str(), "json" | "ndjson" | "csv" | "tsv") => {
+                        flags.push(format!("--{f}"))
+                    }
+                    Value::String(f) if f == "human" => {}
+                    _ => return Err(bad()),
+                },
+                "quiet" => match value {
+                    Value::Number(n) if *n == 0.0 => {}
+                    Value::Number(n) if *n == 1.0 => flags.push("-q".to_string()),
+                    Value::Number(n) if *n == 2.0 => flags.push("-qq".to_string()),
+                    _ => return Err(bad()),
+                },
+                "include_failed" | "unordered" | "fail_fast" | "reverse" | "timings" | "nice"
+                | "no_precount" | "bench" | "mmap" | "allow_empty" | "strict_cache" | "psr" => {
+                    match value {
+                        Value::Bool(true) => flags.push(format!("--{}", key.replace('_', "-"))),
+                        Value::Bool(false) => {}
+                        _ => return Err(bad()),
+                    }
+                }
+                // not a flag, used when no outfile is given
+                "outfile" => {
+                    if !matches!(value, Value::String(_)) {
+                        return Err(bad());
+                    }
+                }
+                _ => eprintln!("warning: config: unknown key '{key}'"),
+            }
+        }
+        Ok(flags)
+    }
+}
+
+/// Quotes a string as a TOML basic string.
+fn quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{escaped}\"")
+}
+
+/// Prints the effective measure options in config file syntax, noting where
+/// each value that isn't a built-in default came from.
+pub fn print(args: &Args, path: Option<&Path>) {
+    match path {
+        Some(path) => println!("# config file: {}", path.display()),
+        None => println!("# no config file found"),
+    }
+    println!("[measure]");
+    let show = |key: &str, value: String| match args.sources.get(key) {
+        Some(source) => println!("{key} = {value}  # {}", source.name()),
+        None => println!("{key} = {value}"),
+    };
+    if let Some(target) = args.target {
+        show("target", target.to_string());
+    }
+    let units = match args.units {
+        Units::Lufs => "lufs",
+        Units::Lu => "lu",
+    };
+    show("units", quote(units));
+    let color = match args.color {
+        ColorChoice::Always => "always",
+        ColorChoice::Never => "never",
+        ColorChoice::Auto => "auto",
+    };
+    show("color", quote(color));
+    let format = match args.format {
+        OutputFormat::Human => "human",
+        OutputFormat::Json => "json",
+        OutputFormat::Ndjson => "ndjson",
+        OutputFormat::Csv => "csv",
+        OutputFormat::Tsv => "tsv",
+    };
+    show("format", quote(format));
+    if let Some(template) = &args.template {
+        show("template", quote(&template.source));
+    }
+    if let Some(fields) = &args.fields {
+        let names: Vec<_> = fields.iter().map(|f| f.name()).collect();
+        show("fields", quote(&names.join(",")));
+    }
+    if let Some(precision) = args.precision {
+        show("precision", precision.to_string());
+    }
+    let sort = match args.sort {
+        SortKey::Name => "name",
+        SortKey::Loudness => "loudness",
+        SortKey::TruePeak => "peak",
+    };
+    show("sort", quote(sort));
+    show("extensions", quote(&args.extensions.join(",")));
+    show("threads", args.threads.to_string());
+    if let Some(io_jobs) = args.io_jobs {
+        show("io_jobs", io_jobs.to_string());
+    }
+    show("quiet", args.quiet.to_string());
+    show("reverse", args.reverse.to_string());
+    show("include_failed", args.include_failed.to_string());
+    show("unordered", args.unordered.to_string());
+    show("fail_fast", args.fail_fast.to_string());
+    if let Some(max_errors) = args.max_errors {
+        show("max_errors", max_errors.to_string());
+    }
+    if let Some(failed_out) = &args.failed_out {
+        show("failed_out", failed_out.clone());
+    }
+    if let Some(timeout) = args.timeout {
+        show("timeout", timeout.as_secs_f64().to_string());
+    }
+    if let Some(max) = args.max_decode_errors {
+        show("max_decode_errors", max.to_string());
+    }
+    show(
+        "max_decode_error_fraction",
+        args.max_decode_error_fraction.to_string(),
+    );
+    show("limit", args.limit.to_string());
+    show("timings", args.timings.to_string());
+    show("bench", args.bench.to_string());
+    let schedule = match args.schedule {
+        Schedule::Size => "size",
+        Schedule::Name => "name",
+    };
+    show("schedule", quote(schedule));
+    let short_files = match args.short_files {
+        ShortFiles::Ungated => "ungated",
+        ShortFiles::Fail => "fail",
+    };
+    show("short_files", quote(short_files));
+    if let Some(tolerance) = args.duration_tolerance {
+        show("duration_tolerance", tolerance.to_string());
+    }
+    if let Some(track) = args.track {
+        show("track", track.to_string());
+    }
+    if let Some(language) = &args.language {
+        show("language", quote(language));
+    }
+    show("nice", args.nice.to_string());
+    show("allow_empty", args.allow_empty.to_string());
+    show("strict_cache", args.strict_cache.to_string());
+    if let Some(format) = args.cache_format {
+        show("cache_format", quote(format.name()));
+    }
+    show("mmap", args.mmap.to_string());
+    show("psr", args.psr.to_string());
+    if let Some(read_buffer) = args.read_buffer {
+        show("read_buffer", read_buffer.to_string());
+    }
+    show("no_precount", args.no_precount.to_string());
+    if let Some(outfile) = &args.outfile {
+        show("outfile", quote(outfile));
+    }
+}
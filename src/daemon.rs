@@ -0,0 +1,148 @@
+//! A long-running process answering measurement requests on a unix socket,
+//! and the client that talks to it.
+//!
+//! Clients send one path per line; each gets one NDJSON result line back.
+
+use crate::cli::{ClientArgs, DaemonArgs};
+use crate::measure;
+use crate::report::{self, Outcome};
+use crate::serve::open_cache;
+use log::{error, info, warn};
+use loudness::{cache_key, save_cache_as, CacheFormat, Measurement};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+struct Daemon {
+    cache: RwLock<HashMap<String, Measurement>>,
+    /// Where the cache is saved after each new measurement, if anywhere.
+    cache_path: Option<PathBuf>,
+    cache_format: CacheFormat,
+}
+
+impl Daemon {
+    /// Looks `path` up in the cache, measuring and saving it if it's new.
+    fn lookup(&self, path: &Path) -> (Outcome, bool) {
+        let key = cache_key(path);
+        if let Some(m) = self.cache.read().unwrap().get(&key) {
+            return (Ok(m.clone()), true);
+        }
+        let outcome = measure(path);
+        if let (Ok(m), Some(cache_path)) = (&outcome, &self.cache_path) {
+            let mut cache = self.cache.write().unwrap();
+            cache.insert(key, m.clone());
+            if let Err(e) = save_cache_as(&cache, cache_path, self.cache_format) {
+                error!("failed to write cache '{}': {e}", cache_path.display());
+            }
+        }
+        (outcome, false)
+    }
+
+    fn serve(&self, stream: UnixStream) -> io::Result<()> {
+        let mut writer = &stream;
+        for (seq, line) in BufReader::new(&stream).lines().enumerate() {
+            let path = PathBuf::from(line?.trim());
+            let (outcome, cached) = self.lookup(&path);
+            info!(
+                "{}: {}",
+                path.display(),
+                if outcome.is_ok() { "ok" } else { "failed" }
+            );
+            let reply = report::ndjson_line(seq, &cache_key(&path), &outcome, None, cached);
+            writeln!(writer, "{reply}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Serves until the process is killed; only returns early on startup errors.
+pub fn run(args: &DaemonArgs) -> io::Result<bool> {
+    let (cache, cache_format) = match args.cache.as_deref().map(Path::new).map(open_cache) {
+        None => (HashMap::new(), CacheFormat::Json),
+        Some(Ok(opened)) => opened,
+        Some(Err(e)) => {
+            error!("{e}");
+            return Ok(false);
+        }
+    };
+
+    let socket = Path::new(&args.socket);
+    if let Ok(metadata) = socket.symlink_metadata() {
+        if !metadata.file_type().is_socket() {
+            error!(
+                "'{}' exists and isn't a socket, not replacing it",
+                socket.display()
+            );
+            return Ok(false);
+        }
+        if UnixStream::connect(socket).is_ok() {
+            error!("a daemon is already listening on '{}'", socket.display());
+            return Ok(false);
+        }
+        // left behind by a daemon that didn't shut down cleanly
+        std::fs::remove_file(socket)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+    info!("listening on '{}'", socket.display());
+
+    let daemon = Arc::new(Daemon {
+        cache: RwLock::new(cache),
+        cache_path: args.cache.as_ref().map(PathBuf::from),
+        cache_format,
+    });
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let daemon = Arc::clone(&daemon);
+        rayon::spawn(move || {
+            if let Err(e) = daemon.serve(stream) {
+                warn!("connection failed: {e}");
+            }
+        });
+    }
+    Ok(true)
+}
+
+/// Sends paths to the daemon and prints its replies, returning false if any
+/// measurement failed or went unanswered.
+pub fn client(args: &ClientArgs) -> io::Result<bool> {
+    let stream = UnixStream::connect(&args.socket).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("can't reach the daemon at '{}': {e}", args.socket),
+        )
+    })?;
+    let mut writer = &stream;
+    for path in &args.paths {
+        // the daemon may run elsewhere, so send absolute paths
+        let path = std::path::absolute(path).unwrap_or_else(|_| PathBuf::from(path));
+        writeln!(writer, "{}", path.display())?;
+    }
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut ok = true;
+    let mut replies = 0;
+    for line in BufReader::new(&stream).lines() {
+        let line = line?;
+        // keys are JSON-escaped, so an unescaped `,"error":` can only be the field
+        ok &= !line.contains(",\"error\":");
+        replies += 1;
+        println!("{line}");
+    }
+    if replies < args.paths.len() {
+        eprintln!(
+            "error: the daemon answered {replies} of {} paths",
+            args.paths.len()
+        );
+        return Ok(false);
+    }
+    Ok(ok)
+}
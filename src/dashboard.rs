@@ -0,0 +1,382 @@
+//! The full-screen --tui dashboard: a gauge, the files being measured, a
+//! table of results that can be scrolled and sorted, and a running summary.
+//!
+//! It's drawn on a thread of its own from events the workers send, so they
+//! never wait on the terminal.
+
+use crate::progress::{Active, Gauge, REDRAW_INTERVAL};
+use crate::report::loudness_cell;
+use crate::summary::format_duration;
+use crate::terminal;
+use std::cmp::Ordering;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+/// What the dashboard is told about.
+pub enum Event {
+    Start {
+        name: String,
+        bytes: u64,
+    },
+    Update {
+        name: String,
+        fraction: f64,
+    },
+    Finish {
+        name: String,
+    },
+    Result {
+        key: String,
+        status: Status,
+    },
+    /// A byte read from stdin.
+    Key(u8),
+    Close,
+}
+
+/// How a file's result came about, with its loudness if it has one.
+#[derive(Clone, Copy)]
+pub enum Status {
+    Measured(f64),
+    Cached(f64),
+    Failed,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Measured(_) => "measured",
+            Status::Cached(_) => "cached",
+            Status::Failed => "failed",
+        }
+    }
+
+    /// Loudness to sort by; none for failed and silent files.
+    fn loudness(self) -> Option<f64> {
+        match self {
+            Status::Measured(l) | Status::Cached(l) => Some(l).filter(|l| l.is_finite()),
+            Status::Failed => None,
+        }
+    }
+
+    fn cell(self) -> String {
+        match self {
+            Status::Measured(l) | Status::Cached(l) => loudness_cell(l),
+            Status::Failed => String::new(),
+        }
+    }
+}
+
+/// Order of the results table, cycled with `s`.
+#[derive(Clone, Copy, PartialEq)]
+enum Order {
+    Completed,
+    Key,
+    /// Loudest first, then silent and failed files.
+    Loudness,
+}
+
+impl Order {
+    fn next(self) -> Order {
+        match self {
+            Order::Completed => Order::Key,
+            Order::Key => Order::Loudness,
+            Order::Loudness => Order::Completed,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Order::Completed => "completion",
+            Order::Key => "key",
+            Order::Loudness => "loudness",
+        }
+    }
+}
+
+struct Row {
+    key: String,
+    status: Status,
+}
+
+pub struct Dashboard {
+    events: Sender<Event>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Dashboard {
+    /// Takes over the terminal and starts drawing; `on_quit` is called the
+    /// first time q is pressed.
+    pub fn open(gauge: Gauge, on_quit: impl FnOnce() + Send + 'static) -> Dashboard {
+        let keys = terminal::take();
+        let (events, received) = mpsc::channel();
+        let key_events = events.clone();
+        terminal::on_keys(move |key| key_events.send(Event::Key(key)).is_ok());
+        let screen = Screen {
+            gauge,
+            keys,
+            done: 0,
+            done_bytes: 0,
+            active: vec![],
+            rows: vec![],
+            order: Order::Completed,
+            view: vec![],
+            scroll: None,
+            page: 1,
+            escape: vec![],
+            stopping: false,
+        };
+        let thread = std::thread::spawn(move || screen.run(received, on_quit));
+        Dashboard {
+            events,
+            thread: Mutex::new(Some(thread)),
+        }
+    }
+
+    pub fn send(&self, event: Event) {
+        // nothing to tell once it's closed
+        let _ = self.events.send(event);
+    }
+
+    /// Stops drawing and gives the terminal back.
+    pub fn close(&self) {
+        self.send(Event::Close);
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+        terminal::give_back();
+    }
+}
+
+/// The dashboard's view of the run, owned by its thread.
+struct Screen {
+    gauge: Gauge,
+    /// Whether keys arrive as they're pressed, rather than after Enter.
+    keys: bool,
+    done: usize,
+    done_bytes: u64,
+    active: Vec<Active>,
+    /// In the order they completed.
+    rows: Vec<Row>,
+    order: Order,
+    /// Indices into `rows` in `order`, rebuilt when either changes, which
+    /// empties it.
+    view: Vec<usize>,
+    /// First row shown, or `None` to follow the end of the table.
+    scroll: Option<usize>,
+    /// Table rows that fit on the screen, as of the last draw.
+    page: usize,
+    /// A partly read escape sequence, such as an arrow key.
+    escape: Vec<u8>,
+    stopping: bool,
+}
+
+impl Screen {
+    fn run(mut self, events: Receiver<Event>, on_quit: impl FnOnce()) {
+        let mut on_quit = Some(on_quit);
+        let mut last_draw: Option<Instant> = None;
+        loop {
+            // keys are answered at once, everything else at most every interval
+            let mut now = false;
+            match events.recv_timeout(REDRAW_INTERVAL) {
+                Ok(Event::Close) | Err(RecvTimeoutError::Disconnected) => return,
+                Ok(Event::Key(key)) => {
+                    now = true;
+                    if self.key(key) {
+                        self.stopping = true;
+                        if let Some(on_quit) = on_quit.take() {
+                            on_quit();
+                        }
+                    }
+                }
+                Ok(event) => self.apply(event),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            if now || last_draw.is_none_or(|t| t.elapsed() >= REDRAW_INTERVAL) {
+                self.draw();
+                last_draw = Some(Instant::now());
+            }
+        }
+    }
+
+    fn apply(&mut self, event: Event) {
+        match event {
+            Event::Start { name, bytes } => self.active.push(Active {
+                name,
+                bytes,
+                fraction: 0.0,
+            }),
+            Event::Update { name, fraction } => {
+                if let Some(active) = self.active.iter_mut().find(|a| a.name == name) {
+                    active.fraction = fraction;
+                }
+            }
+            Event::Finish { name } => {
+                if let Some(i) = self.active.iter().position(|a| a.name == name) {
+                    self.done_bytes += self.active.remove(i).bytes;
+                }
+                self.done += 1;
+            }
+            Event::Result { key, status } => self.rows.push(Row { key, status }),
+            Event::Key(_) | Event::Close => {}
+        }
+    }
+
+    /// Handles a byte of input, returning true when it asks to quit.
+    fn key(&mut self, key: u8) -> bool {
+        if key == 0x1b || !self.escape.is_empty() {
+            self.escape.push(key);
+            // ESC [ or ESC O, parameters, then a final byte from @ to ~
+            let complete = match self.escape[..] {
+                [_] | [_, b'[' | b'O'] => false,
+                [_, b'[' | b'O', .., last] => (0x40..=0x7e).contains(&last),
+                _ => true,
+            };
+            if complete || self.escape.len() > 8 {
+                let page = self.page as isize;
+                match &std::mem::take(&mut self.escape)[1..] {
+                    b"[A" | b"OA" => self.scroll_by(-1),
+                    b"[B" | b"OB" => self.scroll_by(1),
+                    b"[5~" => self.scroll_by(-page),
+                    b"[6~" => self.scroll_by(page),
+                    b"[H" | b"OH" | b"[1~" => self.scroll_to(0),
+                    b"[F" | b"OF" | b"[4~" => self.scroll = None,
+                    _ => {}
+                }
+            }
+            return false;
+        }
+        match key {
+            b'q' => return true,
+            b's' => {
+                self.order = self.order.next();
+                self.view.clear();
+                // the newest results are at the end in completion order,
+                // the loudest or first by key at the top otherwise
+                self.scroll = (self.order != Order::Completed).then_some(0);
+            }
+            b'k' => self.scroll_by(-1),
+            b'j' => self.scroll_by(1),
+            b'g' => self.scroll_to(0),
+            b'G' => self.scroll = None,
+            _ => {}
+        }
+        false
+    }
+
+    /// First row that still fills the table.
+    fn last_top(&self) -> usize {
+        self.rows.len().saturating_sub(self.page)
+    }
+
+    fn scroll_by(&mut self, rows: isize) {
+        let top = self.scroll.unwrap_or(self.last_top());
+        self.scroll_to(top.saturating_add_signed(rows));
+    }
+
+    /// Shows the table from row `top`, following the end once it's reached.
+    fn scroll_to(&mut self, top: usize) {
+        let last = self.last_top();
+        self.scroll = (top < last).then_some(top);
+    }
+
+    fn sort(&mut self) {
+        if self.view.len() == self.rows.len() {
+            return;
+        }
+        let rows = &self.rows;
+        self.view = (0..rows.len()).collect();
+        match self.order {
+            Order::Completed => {}
+            Order::Key => self.view.sort_by(|&a, &b| rows[a].key.cmp(&rows[b].key)),
+            Order::Loudness => self.view.sort_by(|&a, &b| {
+                let (a, b) = (&rows[a], &rows[b]);
+                let by_loudness = match (a.status.loudness(), b.status.loudness()) {
+                    (Some(a), Some(b)) => b.total_cmp(&a),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                };
+                by_loudness.then_with(|| a.key.cmp(&b.key))
+            }),
+        }
+    }
+
+    /// Redraws the whole screen: the gauge, files in progress, the results
+    /// table, a summary of the results so far, and the keys at the bottom.
+    fn draw(&mut self) {
+        let (height, width) = terminal::size();
+        let height = height.max(8);
+        // the last column is left free, so full rows don't wrap
+        let width = width.max(20) - 1;
+        let clip = |s: &str| s.chars().take(width).collect::<String>() + "\n";
+
+        let fraction = self
+            .gauge
+            .fraction(self.done, self.done_bytes, &self.active);
+        let bar = (width / 3).clamp(10, 50);
+        let filled = (bar as f64 * fraction) as usize;
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        screen += &clip(&format!(
+            "[{}{}] {:>3.0}%  {}/{} files{}  {} elapsed  ETA {}",
+            "=".repeat(filled),
+            " ".repeat(bar - filled),
+            fraction * 100.0,
+            self.done,
+            self.gauge.total(),
+            self.gauge.size(self.done_bytes),
+            format_duration(self.gauge.elapsed()),
+            self.gauge.eta(fraction),
+        ));
+
+        let workers = self.active.len().min(height / 4);
+        screen += &format!("measuring ({}):\n", self.active.len());
+        for active in self.active.iter().take(workers) {
+            screen += &clip(&format!(
+                "  {:>3.0}% {}",
+                active.fraction * 100.0,
+                active.name
+            ));
+        }
+
+        // everything but the gauge, the two headings, the footer and the keys
+        self.page = height.saturating_sub(workers + 5).max(1);
+        self.sort();
+        let top = self.scroll.unwrap_or(self.last_top()).min(self.last_top());
+        screen += &format!("results ({}, by {}):\n", self.rows.len(), self.order.name());
+        for &i in self.view.iter().skip(top).take(self.page) {
+            let row = &self.rows[i];
+            // the key last, so a narrow terminal cuts it rather than the numbers
+            screen += &clip(&format!(
+                "  {:>12}  {:<8}  {}",
+                row.status.cell(),
+                row.status.name(),
+                row.key
+            ));
+        }
+
+        let count = |name| self.rows.iter().filter(|r| r.status.name() == name).count();
+        let mut footer = format!(
+            "measured: {}  cached: {}  failed: {}",
+            count("measured"),
+            count("cached"),
+            count("failed")
+        );
+        if self.stopping {
+            footer += "  stopping after the current files…";
+        }
+        let keys = if self.keys {
+            "q: stop and save  s: sort  ↑↓/jk: scroll  PgUp/PgDn: page  g/G: top/end"
+        } else {
+            "q+Enter: stop after the current files and save"
+        };
+        screen += &format!("\x1b[{};1H{}", height - 1, clip(&footer));
+        screen += &format!(
+            "\x1b[{height};1H{}",
+            keys.chars().take(width).collect::<String>()
+        );
+        eprint!("{screen}");
+    }
+}
@@ -0,0 +1,211 @@
+//! Why a measurement failed, at which stage, and for which file.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use symphonia::core::errors::Error as DecodeError;
+
+/// A failed measurement: what went wrong and, for files, which one.
+///
+/// `Display` gives a one-line summary; the underlying I/O or decoder error,
+/// if any, is available through `source()`.
+#[derive(Debug)]
+pub struct MeasureError {
+    kind: ErrorKind,
+    path: Option<PathBuf>,
+}
+
+/// What went wrong in a [`MeasureError`].
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The file couldn't be opened or read.
+    Open(io::Error),
+    /// The data isn't in a format that can be decoded.
+    Probe(DecodeError),
+    /// The file has no data at all; found before probing.
+    Empty,
+    /// The container has no audio tracks, or none matching
+    /// `MeasureOptions::track` or `language`.
+    NoTrack,
+    /// No decoder could be created for the track's codec.
+    Decoder(DecodeError),
+    /// More packets failed to decode than `MeasureOptions` allows.
+    TooCorrupt { errors: u64, packets: u64 },
+    /// Shorter than `SHORT_DURATION`, with `ShortFiles::Fail`.
+    TooShort { seconds: f64 },
+    /// Decoding ended without a single gating block of audio.
+    NoAudio,
+    /// `MeasureOptions::timeout` passed before decoding finished.
+    Timeout(Duration),
+    /// The `MeasureOptions::cancel` token was cancelled.
+    Cancelled,
+    /// The channel count or sample rate is outside what can be measured.
+    Unsupported { channels: u32, sample_rate: u32 },
+    /// Interleaved samples whose count isn't a multiple of the channel count.
+    SampleCount { samples: usize, channels: u32 },
+    /// A `MeasureOptions::channel_map` of a different number of channels
+    /// than were decoded.
+    ChannelMap { given: usize, channels: u32 },
+    /// The channel count changed `seconds` into the file, with a
+    /// `MeasureOptions::channel_map` for the old count only.
+    ChannelsChanged { from: u32, to: u32, seconds: f64 },
+    /// ebur128 refused a call after the audio's layout was accepted: a bug
+    /// rather than anything about the audio.
+    Analysis(ebur128::Error),
+    /// Measuring panicked, with the panic's message. Never returned by the
+    /// measurement functions themselves; for callers that catch panics and
+    /// report them alongside other failures.
+    Panicked(String),
+}
+
+/// The step of a measurement that failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    Open,
+    Probe,
+    Decoder,
+    Decode,
+    /// Setting up or finishing the EBU R128 measurement itself.
+    Measure,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Open => "open",
+            Stage::Probe => "probe",
+            Stage::Decoder => "decoder",
+            Stage::Decode => "decode",
+            Stage::Measure => "measure",
+        }
+    }
+}
+
+impl MeasureError {
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// The file being measured, when there was one.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn stage(&self) -> Stage {
+        match self.kind {
+            ErrorKind::Open(_) => Stage::Open,
+            ErrorKind::Empty | ErrorKind::Probe(_) | ErrorKind::NoTrack => Stage::Probe,
+            ErrorKind::Decoder(_) => Stage::Decoder,
+            ErrorKind::TooCorrupt { .. }
+            | ErrorKind::Timeout(_)
+            | ErrorKind::Cancelled
+            | ErrorKind::ChannelsChanged { .. }
+            | ErrorKind::Panicked(_) => Stage::Decode,
+            ErrorKind::TooShort { .. }
+            | ErrorKind::NoAudio
+            | ErrorKind::Unsupported { .. }
+            | ErrorKind::SampleCount { .. }
+            | ErrorKind::ChannelMap { .. }
+            | ErrorKind::Analysis(_) => Stage::Measure,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.kind, ErrorKind::Cancelled)
+    }
+
+    /// The same error, attributed to the file at `path`.
+    pub fn in_file(self, path: &Path) -> MeasureError {
+        MeasureError {
+            path: Some(path.to_path_buf()),
+            ..self
+        }
+    }
+}
+
+impl From<ebur128::Error> for MeasureError {
+    fn from(e: ebur128::Error) -> MeasureError {
+        ErrorKind::Analysis(e).into()
+    }
+}
+
+impl From<ErrorKind> for MeasureError {
+    fn from(kind: ErrorKind) -> MeasureError {
+        MeasureError { kind, path: None }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Open(_) => write!(f, "failed to open file"),
+            ErrorKind::Probe(_) => write!(f, "unrecognized format"),
+            ErrorKind::Empty => write!(f, "empty file"),
+            ErrorKind::NoTrack => write!(f, "no audio track"),
+            ErrorKind::Decoder(_) => write!(f, "failed to create decoder"),
+            ErrorKind::TooCorrupt { errors, packets } => write!(
+                f,
+                "too corrupt to measure: {errors} of {packets} packets failed to decode"
+            ),
+            ErrorKind::TooShort { seconds } => {
+                write!(f, "too short to measure: {seconds:.2}s of audio")
+            }
+            ErrorKind::NoAudio => write!(f, "no audio could be measured"),
+            ErrorKind::Timeout(t) => write!(f, "timeout: gave up after {}s", t.as_secs_f64()),
+            ErrorKind::Cancelled => write!(f, "cancelled"),
+            ErrorKind::Unsupported {
+                channels,
+                sample_rate,
+            } => write!(
+                f,
+                "unsupported audio: {channels} channel(s) at {sample_rate} Hz"
+            ),
+            ErrorKind::SampleCount { samples, channels } => write!(
+                f,
+                "{samples} samples don't divide into frames of {channels} channel(s)"
+            ),
+            ErrorKind::ChannelMap { given, channels } => write!(
+                f,
+                "channel map has {given} channel(s), the audio has {channels}"
+            ),
+            ErrorKind::ChannelsChanged { from, to, seconds } => write!(
+                f,
+                "channel count changes from {from} to {to} at {seconds:.1}s, past the channel map"
+            ),
+            ErrorKind::Analysis(_) => write!(f, "measurement failed"),
+            ErrorKind::Panicked(message) => write!(f, "panicked: {message}"),
+        }
+    }
+}
+
+impl fmt::Display for MeasureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path.display(), self.kind),
+            None => self.kind.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MeasureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Open(e) => Some(e),
+            ErrorKind::Probe(e) | ErrorKind::Decoder(e) => Some(e),
+            ErrorKind::Analysis(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// `error` followed by each of its sources, separated by colons.
+pub fn chain(error: &dyn std::error::Error) -> String {
+    let mut out = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        out += &format!(": {e}");
+        source = e.source();
+    }
+    out
+}
@@ -0,0 +1,119 @@
+//! Cache entries reshaped for other tools' databases.
+
+use crate::album::{album_of, albums, linear_peak, AlbumLoudness};
+use crate::cli::{ExportArgs, REPLAYGAIN_REFERENCE};
+use crate::report::JsonNumber;
+use log::warn;
+use loudness::{csv_field, load_cache, Measurement};
+use merde::json::JsonSerializer;
+use std::io;
+use std::path::Path;
+
+/// Reference loudness of R128_*_GAIN tags (EBU R128 itself).
+const R128_REFERENCE: f64 = -23.0;
+
+/// Columns of a beets record, after "path", in the names beets' replaygain
+/// plugin stores them under.
+const BEETS_FIELDS: &[&str] = &[
+    "rg_track_gain",
+    "rg_track_peak",
+    "rg_album_gain",
+    "rg_album_peak",
+    "r128_track_gain",
+    "r128_album_gain",
+    "track_vs_album_db",
+];
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// The beets fields for one track, in `BEETS_FIELDS` order; peaks are
+/// `None` unless measured with --psr, as are album fields without an album
+/// and the offset from the album of a single.
+fn beets_record(m: &Measurement, album: Option<AlbumLoudness>) -> [Option<f64>; 7] {
+    let gain = |loudness: f64, reference: f64| round2(reference - loudness);
+    [
+        Some(gain(m.loudness, REPLAYGAIN_REFERENCE)),
+        m.true_peak.map(linear_peak),
+        album.map(|a| gain(a.loudness, REPLAYGAIN_REFERENCE)),
+        album.and_then(|a| a.peak),
+        Some(gain(m.loudness, R128_REFERENCE)),
+        album.map(|a| gain(a.loudness, R128_REFERENCE)),
+        album.and_then(|a| a.track_vs_album(m)).map(round2),
+    ]
+}
+
+/// Writes the export to stdout, returning false if the cache couldn't be read.
+pub fn run(args: &ExportArgs) -> io::Result<bool> {
+    let cache = match load_cache(Path::new(&args.cache)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.cache);
+            return Ok(false);
+        }
+    };
+    let albums = match args.tracks_only {
+        true => Default::default(),
+        false => albums(cache.values()),
+    };
+
+    let mut tracks: Vec<(&str, [Option<f64>; 7])> = vec![];
+    let (mut unknown_path, mut silent) = (0, 0);
+    for m in cache.values() {
+        let Some(path) = &m.path else {
+            unknown_path += 1;
+            continue;
+        };
+        if !m.loudness.is_finite() {
+            silent += 1;
+            continue;
+        }
+        let album = album_of(m).and_then(|a| albums.get(&a).copied());
+        tracks.push((path, beets_record(m, album)));
+    }
+    tracks.sort_by(|a, b| a.0.cmp(b.0));
+    if unknown_path > 0 {
+        warn!(
+            "skipped {unknown_path} entries measured before paths were recorded, re-measure them to include them"
+        );
+    }
+    if silent > 0 {
+        warn!("skipped {silent} entries without usable loudness");
+    }
+
+    if args.csv {
+        println!("path,{}", BEETS_FIELDS.join(","));
+        for (path, record) in &tracks {
+            let values: Vec<String> = record
+                .iter()
+                .map(|v| v.map_or(String::new(), |v| v.to_string()))
+                .collect();
+            println!("{},{}", csv_field(path), values.join(","));
+        }
+    } else {
+        let mut s = JsonSerializer::new();
+        {
+            let mut arr = s.write_arr();
+            for (path, record) in &tracks {
+                arr.elem(&BeetsRecord(path, record));
+            }
+        }
+        println!("{}", String::from_utf8(s.into_inner()).unwrap());
+    }
+    Ok(true)
+}
+
+struct BeetsRecord<'a>(&'a str, &'a [Option<f64>; 7]);
+
+impl merde::json::JsonSerialize for BeetsRecord<'_> {
+    fn json_serialize(&self, s: &mut JsonSerializer) {
+        let mut obj = s.write_obj();
+        obj.pair("path", &self.0);
+        for (field, value) in BEETS_FIELDS.iter().zip(self.1) {
+            if let Some(value) = value {
+                obj.pair(field, &JsonNumber(*value));
+            }
+        }
+    }
+}
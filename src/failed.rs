@@ -0,0 +1,63 @@
+//! The list of failed files written by --failed-out, which --files-from
+//! reads back to retry just those.
+
+use crate::summary::Failure;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One failed file, as written to the list.
+struct Entry {
+    path: String,
+    stage: String,
+    kind: String,
+    error: String,
+}
+merde::derive! {
+    impl (JsonSerialize) for struct Entry { path, stage, kind, error }
+}
+
+/// Writes every failure to `to` as a JSON array, replacing an older list.
+pub fn write(failures: &[Failure], to: &Path) -> io::Result<()> {
+    let entries: Vec<Entry> = failures
+        .iter()
+        .map(|f| Entry {
+            path: f.path.to_string_lossy().into_owned(),
+            stage: f.stage.to_string(),
+            kind: f.class.to_string(),
+            error: f.reason.clone(),
+        })
+        .collect();
+    std::fs::write(to, merde::json::to_string(&entries))
+}
+
+/// The files listed in `from`: a list written by [`write`], or plain text
+/// with one path per line.
+pub fn read(from: &Path) -> io::Result<Vec<PathBuf>> {
+    let text = std::fs::read_to_string(from)?;
+    if text.trim_start().starts_with('[') {
+        // read as a plain value: merde's Vec<T> prints a backtrace in debug builds
+        let list: merde::Value = merde::json::from_str(&text).map_err(invalid)?;
+        let mut files = vec![];
+        for entry in list.as_array().map_err(invalid)?.iter() {
+            let path = entry
+                .as_map()
+                .map_err(invalid)?
+                .get(&merde::CowStr::from("path"))
+                .ok_or_else(|| invalid("entry without a path"))?
+                .as_str()
+                .map_err(invalid)?;
+            files.push(PathBuf::from(path.as_ref()));
+        }
+        return Ok(files);
+    }
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+fn invalid(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
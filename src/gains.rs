@@ -0,0 +1,70 @@
+//! Flat key → gain maps for players that just want a multiplier per track.
+
+use crate::cli::GainsArgs;
+use loudness::load_cache;
+use merde::json::JsonSerializer;
+use std::io;
+use std::path::Path;
+
+/// Gain in dB that brings `loudness` to `target`, limited to attenuation when `clamp` is set.
+pub fn gain_db(loudness: f64, target: f64, clamp: bool) -> f64 {
+    let gain = target - loudness;
+    if clamp {
+        gain.min(0.0)
+    } else {
+        gain
+    }
+}
+
+/// Converts a gain in dB to a linear amplitude multiplier.
+pub fn db_to_linear(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+fn round_to(value: f64, decimals: i32) -> f64 {
+    let scale = 10f64.powi(decimals);
+    (value * scale).round() / scale
+}
+
+/// Writes the gains file, returning false if the cache couldn't be read.
+pub fn run(args: &GainsArgs) -> io::Result<bool> {
+    let cache = match load_cache(Path::new(&args.cache)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.cache);
+            return Ok(false);
+        }
+    };
+
+    let mut keys: Vec<_> = cache.keys().collect();
+    keys.sort();
+
+    let mut serializer = JsonSerializer::new();
+    let mut omitted = 0;
+    {
+        let mut obj = serializer.write_obj();
+        for key in keys {
+            let loudness = cache[key].loudness;
+            if !loudness.is_finite() {
+                // silent or failed measurement, there's no meaningful gain
+                omitted += 1;
+                continue;
+            }
+            let db = gain_db(loudness, args.target, args.clamp);
+            let value = if args.db {
+                round_to(db, 2)
+            } else {
+                round_to(db_to_linear(db), 3)
+            };
+            obj.pair(key, &value);
+        }
+    }
+
+    std::fs::write(&args.out, serializer.into_inner())?;
+    eprintln!(
+        "wrote {} gain(s) to '{}', omitted {omitted} without usable loudness",
+        cache.len() - omitted,
+        args.out
+    );
+    Ok(true)
+}
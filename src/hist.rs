@@ -0,0 +1,99 @@
+//! Text histogram of a cache, without touching any audio.
+
+use crate::cli::{HistArgs, HistMetric};
+use crate::gains::gain_db;
+use crate::progress::terminal_width;
+use crate::top::glob_match;
+use loudness::load_cache;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+/// Prints the histogram, returning false if the cache couldn't be read.
+pub fn run(args: &HistArgs) -> io::Result<bool> {
+    let cache = match load_cache(Path::new(&args.cache)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.cache);
+            return Ok(false);
+        }
+    };
+
+    let mut values = vec![];
+    let mut unusable = 0;
+    for (key, m) in &cache {
+        if args.pattern.as_ref().is_some_and(|p| !glob_match(p, key)) {
+            continue;
+        }
+        if !m.loudness.is_finite() {
+            unusable += 1;
+            continue;
+        }
+        values.push(match args.metric {
+            HistMetric::Loudness => m.loudness,
+            HistMetric::Gain(target) => gain_db(m.loudness, target, false),
+        });
+    }
+
+    let width = args.bucket;
+    let (lo, hi) = match args.range {
+        Some(range) => range,
+        None if values.is_empty() => (0.0, 0.0),
+        None => {
+            let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            (
+                (min / width).floor() * width,
+                ((max / width).floor() + 1.0) * width,
+            )
+        }
+    };
+    let buckets = ((hi - lo) / width).ceil().max(0.0) as usize;
+
+    let mut counts = vec![0; buckets];
+    let (mut below, mut above) = (0, 0);
+    for v in values {
+        if v < lo {
+            below += 1;
+        } else if v >= hi {
+            above += 1;
+        } else {
+            counts[(((v - lo) / width) as usize).min(buckets - 1)] += 1;
+        }
+    }
+
+    let mut rows: Vec<(String, usize)> = vec![];
+    if below > 0 {
+        rows.push((format!("< {lo:.1}"), below));
+    }
+    for (i, count) in counts.iter().enumerate() {
+        let start = lo + i as f64 * width;
+        rows.push((format!("{start:.1} .. {:.1}", start + width), *count));
+    }
+    if above > 0 {
+        rows.push((format!(">= {hi:.1}"), above));
+    }
+    if unusable > 0 {
+        rows.push(("silent/failed".to_string(), unusable));
+    }
+
+    if !io::stdout().is_terminal() {
+        for (label, count) in &rows {
+            println!("{label}\t{count}");
+        }
+        return Ok(true);
+    }
+
+    let label_width = rows.iter().map(|(l, _)| l.len()).max().unwrap_or(0);
+    let max = rows.iter().map(|(_, c)| *c).max().unwrap_or(0);
+    let count_width = max.to_string().len();
+    let bar_width = terminal_width().saturating_sub(label_width + count_width + 4);
+    for (label, count) in &rows {
+        // round up so a non-empty bucket never looks empty
+        let bar = (count * bar_width).div_ceil(max.max(1));
+        println!(
+            "{label:>label_width$}  {count:>count_width$}  {}",
+            "#".repeat(bar)
+        );
+    }
+    Ok(true)
+}
@@ -0,0 +1,51 @@
+//! Gating-block histograms of measured files, written one JSON file each
+//! under --histogram-dir.
+
+use loudness::{BlockHistogram, BIN_WIDTH};
+use merde::json::{JsonSerialize, JsonSerializer};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where the histogram of the entry keyed `key` goes: `<key>.json` under
+/// `dir`, in subdirectories for keys of files in archives. Parts of a key
+/// that would lead out of `dir` are replaced with `_`.
+pub fn path_for(dir: &Path, key: &str) -> PathBuf {
+    let mut path = dir.to_path_buf();
+    path.extend(key.split(['/', '\\']).map(|part| match part {
+        "" | "." | ".." => "_",
+        part => part,
+    }));
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".json");
+    path.set_file_name(name);
+    path
+}
+
+struct Json<'a>(&'a str, &'a BlockHistogram);
+
+impl JsonSerialize for Json<'_> {
+    fn json_serialize(&self, s: &mut JsonSerializer) {
+        let Json(key, histogram) = self;
+        let mut obj = s.write_obj();
+        obj.pair("key", key);
+        obj.pair("bin_width", &BIN_WIDTH);
+        obj.pair("min", &histogram.min());
+        obj.pair("max", &histogram.max());
+        obj.pair("blocks", &histogram.blocks());
+        obj.pair("below", &histogram.below());
+        obj.pair("above", &histogram.above());
+        obj.pair("bins", &histogram.bins());
+    }
+}
+
+/// Writes the histogram of `key` under `dir`, creating directories as
+/// needed.
+pub fn write(dir: &Path, key: &str, histogram: &BlockHistogram) -> io::Result<()> {
+    let path = path_for(dir, key);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut s = JsonSerializer::new();
+    Json(key, histogram).json_serialize(&mut s);
+    std::fs::write(path, s.into_inner())
+}
@@ -0,0 +1,170 @@
+//! Cache entries from other tools' measurements, so files already measured
+//! elsewhere needn't be decoded again.
+
+use crate::cli::{ImportArgs, ImportFormat};
+use crate::AUDIO_EXTENSIONS;
+use log::{info, warn};
+use loudness::{
+    cache_format_for, cache_key, load_cache, normalize_key, save_cache_as, Measurement,
+};
+use merde::{CowStr, Value};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Value of `Measurement::source` for entries read from loudnorm output.
+const LOUDNORM: &str = "loudnorm";
+
+/// Imports every JSON file in `args.input`, returning false if any couldn't
+/// be read.
+pub fn run(args: &ImportArgs) -> io::Result<bool> {
+    let input = Path::new(&args.input);
+    let files = if input.is_dir() {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(input)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.is_file()
+                    && p.extension()
+                        .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+            })
+            .collect();
+        files.sort();
+        files
+    } else {
+        vec![input.to_path_buf()]
+    };
+
+    let cache_path = Path::new(&args.cache);
+    let cache_format = match cache_format_for(cache_path) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.cache);
+            return Ok(false);
+        }
+    };
+    let mut cache = if cache_path.exists() {
+        match load_cache(cache_path) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("failed to read cache '{}': {e}", args.cache);
+                return Ok(false);
+            }
+        }
+    } else {
+        HashMap::new()
+    };
+
+    let (mut imported, mut kept, mut failed) = (0, 0, 0);
+    for file in &files {
+        let entry = match args.format {
+            ImportFormat::Loudnorm => read_loudnorm(file),
+        };
+        let (key, measurement) = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("{}: {e}", file.display());
+                failed += 1;
+                continue;
+            }
+        };
+        // re-importing updates earlier imports, but measured entries stay
+        let measured = cache.get(&key).is_some_and(|m| m.source.is_none());
+        if measured && !args.overwrite {
+            kept += 1;
+            continue;
+        }
+        cache.insert(key, measurement);
+        imported += 1;
+    }
+
+    if imported > 0 {
+        save_cache_as(&cache, cache_path, cache_format)?;
+    }
+    info!(
+        "imported {imported} of {} file(s) into '{}'",
+        files.len(),
+        args.cache
+    );
+    if kept > 0 {
+        info!("kept {kept} measured entries (--overwrite replaces them)");
+    }
+    Ok(failed == 0)
+}
+
+/// The cache entry for a loudnorm first-pass JSON file.
+fn read_loudnorm(file: &Path) -> Result<(String, Measurement), String> {
+    let text = std::fs::read_to_string(file).map_err(|e| e.to_string())?;
+    // ffmpeg prints the object after its log lines, which may be kept
+    let object = text
+        .rfind('{')
+        .and_then(|start| Some(&text[start..start + text[start..].find('}')? + 1]))
+        .ok_or("no JSON object")?;
+    let value: Value = merde::json::from_str(object).map_err(|e| format!("{e}"))?;
+    let fields = value.as_map().map_err(|e| format!("{e}"))?;
+    let field = |name: &str| fields.get(&CowStr::from(name.to_string()));
+
+    // loudnorm writes its numbers as strings
+    let loudness = match field("input_i") {
+        Some(Value::Str(s)) => s.trim().parse::<f64>().ok(),
+        Some(Value::Float(f)) => Some(f.into_inner()),
+        Some(Value::I64(i)) => Some(*i as f64),
+        _ => None,
+    }
+    .ok_or("no input_i")?;
+    // as measuring it would have failed
+    if !loudness.is_finite() {
+        return Err(format!("input_i is {loudness}, no audio to import"));
+    }
+
+    let media = ["path", "input_file"]
+        .into_iter()
+        .find_map(|name| match field(name) {
+            Some(Value::Str(s)) => Some(PathBuf::from(s.as_ref())),
+            _ => None,
+        });
+    let key = match &media {
+        Some(media) => cache_key(media),
+        None => sidecar_key(file),
+    };
+    Ok((
+        key,
+        Measurement {
+            loudness,
+            // as if it were a single gating block; loudnorm doesn't say how many
+            energy: 10f64.powf((loudness + 0.691) / 10.0),
+            duration: None,
+            path: media.map(|m| m.to_string_lossy().into_owned()),
+            decode_errors: None,
+            short: None,
+            duration_mismatch: None,
+            source: Some(LOUDNORM.to_string()),
+            start: None,
+            end: None,
+            channel_map: None,
+            sample_rate_changes: None,
+            channel_changes: None,
+            true_peak: None,
+            short_term_max: None,
+            psr: None,
+            psr_avg: None,
+            momentary_max: None,
+            momentary_max_at_secs: None,
+        },
+    ))
+}
+
+/// The key for a JSON file named after its media: `track.json`,
+/// `track.loudnorm.json` or `track.flac.loudnorm.json` all give "track".
+fn sidecar_key(file: &Path) -> String {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let mut name = name.as_ref();
+    for suffix in [".json", ".JSON", ".loudnorm"] {
+        name = name.strip_suffix(suffix).unwrap_or(name);
+    }
+    if let Some((stem, extension)) = name.rsplit_once('.') {
+        if AUDIO_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()) {
+            name = stem;
+        }
+    }
+    normalize_key(name)
+}
@@ -0,0 +1,73 @@
+//! Ctrl-C handling for measure runs: the first press cancels the files in
+//! progress and stops new ones, so what's done can still be saved; a second
+//! press kills the process as usual, giving the terminal back first if the
+//! dashboard has it.
+
+use crate::terminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::c_int;
+
+    pub const SIGINT: c_int = 2;
+    /// `SIG_DFL`, the default disposition.
+    pub const DEFAULT: usize = 0;
+    /// `SIG_IGN`, set by e.g. `nohup` or a shell running us in the background.
+    pub const IGNORE: usize = 1;
+    /// `SIG_ERR`.
+    pub const ERROR: usize = usize::MAX;
+
+    extern "C" {
+        pub fn signal(signum: c_int, handler: usize) -> usize;
+        pub fn raise(signum: c_int) -> c_int;
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: std::ffi::c_int) {
+    let again = INTERRUPTED.swap(true, Ordering::Relaxed);
+    if !again && terminal::is_taken() {
+        // stays installed, so the second press can restore the terminal
+        // before terminating
+        return;
+    }
+    terminal::give_back();
+    // SAFETY: signal and raise are async-signal-safe; restoring the default
+    // makes the next Ctrl-C, or this one raised again, terminate
+    unsafe {
+        sys::signal(sys::SIGINT, sys::DEFAULT);
+        if again {
+            sys::raise(sys::SIGINT);
+        }
+    }
+}
+
+/// Starts catching Ctrl-C; returns false where that isn't supported or
+/// SIGINT was already being ignored.
+pub fn install() -> bool {
+    #[cfg(unix)]
+    {
+        let handler = on_interrupt as extern "C" fn(std::ffi::c_int) as usize;
+        // SAFETY: the handler only touches atomics and makes
+        // async-signal-safe calls
+        match unsafe { sys::signal(sys::SIGINT, handler) } {
+            sys::ERROR => false,
+            sys::IGNORE => {
+                // SAFETY: as above
+                unsafe { sys::signal(sys::SIGINT, sys::IGNORE) };
+                false
+            }
+            _ => true,
+        }
+    }
+    #[cfg(not(unix))]
+    false
+}
+
+/// Whether Ctrl-C has been pressed since `install`.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,1551 @@
+//! EBU R128 integrated loudness measurement of audio files, and the JSON
+//! cache the `loudness` tool keeps measurements in.
+//!
+//! ```no_run
+//! use loudness::{measure_file, MeasureOptions};
+//! use std::path::Path;
+//!
+//! let m = measure_file(Path::new("track.flac"), &MeasureOptions::default())?;
+//! println!("{:.1} LUFS", m.loudness);
+//! # Ok::<(), loudness::MeasureError>(())
+//! ```
+//!
+//! Nothing here prints; problems that don't stop a measurement, such as
+//! occasional decode errors, are reported through the `log` facade.
+
+use batch::Batch;
+use buffered::BufferedFile;
+use log::{debug, warn};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use symphonia::core::audio::{AudioBuffer, AudioBufferRef};
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo, Track};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{Limit, MetadataOptions};
+use symphonia::core::units::Time;
+
+// part of the signature of measure_source, so embedders needn't depend on
+// symphonia themselves
+pub use symphonia::core::io::MediaSource;
+pub use symphonia::core::probe::Hint;
+
+mod analyzer;
+#[cfg(feature = "async")]
+mod asynchronous;
+mod batch;
+mod blocks;
+mod buffered;
+mod cache_format;
+#[cfg(feature = "capi")]
+mod capi;
+mod channels;
+mod error;
+mod nfc;
+mod schema;
+#[cfg(feature = "serde")]
+mod serde_support;
+
+pub use analyzer::{Analyzer, SHORT_DURATION};
+#[cfg(feature = "async")]
+pub use asynchronous::{measure_file_async, MeasureSet, Measuring};
+pub use blocks::{BlockHistogram, BIN_WIDTH};
+pub use buffered::DEFAULT_READ_BUFFER;
+pub use cache_format::{csv_field, split_csv, tsv_field, CacheFormat};
+pub use channels::{ChannelMap, Speaker};
+pub use error::{chain, ErrorKind, MeasureError, Stage};
+pub use schema::{cache_schema, CACHE_FORMAT_VERSION};
+
+/// Share of a track's declared length its decoded length may differ by
+/// without `MeasureOptions::duration_tolerance`.
+pub const DEFAULT_DURATION_TOLERANCE: f64 = 0.01;
+
+/// Frames a decoded length may differ from the declared one by on top of
+/// the tolerance, for encoder delay and padding, which headers don't always
+/// account for.
+const DURATION_SLACK_FRAMES: u64 = 8192;
+
+/// How many packets are decoded between timeout checks; a few hundred
+/// milliseconds of audio for typical codecs.
+const TIMEOUT_CHECK_PACKETS: u64 = 16;
+
+/// The loudness of one file, as stored in the cache.
+///
+/// With the `serde` feature it also implements serde's `Serialize` and
+/// `Deserialize`, producing the same JSON fields as the cache.
+#[derive(Clone, Debug)]
+pub struct Measurement {
+    /// Integrated loudness in LUFS; negative infinity for silence, which the
+    /// cache writes as `-1e999`.
+    pub loudness: f64,
+    /// Summed energy of the gating blocks that counted towards `loudness`
+    /// (which is their mean), so entries can be combined into an album.
+    pub energy: f64,
+    /// Seconds of audio measured; absent in entries written by older versions.
+    pub duration: Option<f64>,
+    /// Absolute path of the file measured; absent in entries written by older versions.
+    pub path: Option<String>,
+    /// Packets that failed to decode and were skipped; absent when none did.
+    pub decode_errors: Option<u64>,
+    /// Set when the audio was shorter than [`SHORT_DURATION`], so `loudness`
+    /// is ungated rather than integrated; absent otherwise.
+    pub short: Option<bool>,
+    /// Set when the decoded length differed from the one the file declares
+    /// by more than `MeasureOptions::duration_tolerance`; absent otherwise.
+    pub duration_mismatch: Option<bool>,
+    /// Where an entry that wasn't measured by this crate came from, e.g.
+    /// "loudnorm" for one imported from ffmpeg; absent for measured ones.
+    pub source: Option<String>,
+    /// Seconds into the file the measured range started at, when only part
+    /// of it was measured ([`MeasureOptions::start`] or
+    /// [`MeasureOptions::duration`]); absent for the whole file.
+    pub start: Option<f64>,
+    /// Seconds into the file the measured range was asked to end at; absent
+    /// when it ran to the end. `duration` is how much was actually measured.
+    pub end: Option<f64>,
+    /// The [`ChannelMap`] measured with instead of the file's own layout,
+    /// e.g. "FL,FR,FC,LFE,SL,SR"; absent when the file's was used.
+    pub channel_map: Option<String>,
+    /// Times the sample rate changed partway through, each dropping up to
+    /// 400 ms of audio from the measurement; absent when it never did.
+    pub sample_rate_changes: Option<u64>,
+    /// Times the channel count changed partway through, each dropping up
+    /// to 400 ms of audio like a rate change; absent when it never did.
+    pub channel_changes: Option<u64>,
+    /// Highest true peak of any channel in dBTP, when measured with
+    /// [`MeasureOptions::psr`]; absent otherwise, and for silence.
+    pub true_peak: Option<f64>,
+    /// Loudest short-term (3 s) loudness in LUFS, when measured with
+    /// [`MeasureOptions::psr`]; absent otherwise, and for audio shorter
+    /// than one short-term window.
+    pub short_term_max: Option<f64>,
+    /// Peak-to-short-term loudness ratio in dB, `true_peak` minus
+    /// `short_term_max`: how far the peaks stand above the loudest
+    /// passage, which heavy limiting shrinks. Absent without both.
+    pub psr: Option<f64>,
+    /// PSR of each successive 3 s window, its own true peak over its
+    /// short-term loudness, averaged over the audio, so a single transient
+    /// doesn't decide it. Silent windows are left out; absent like `psr`.
+    pub psr_avg: Option<f64>,
+    /// Loudest momentary (400 ms) loudness in LUFS; absent for audio
+    /// shorter than 400 ms, silence, and entries written by older versions.
+    pub momentary_max: Option<f64>,
+    /// Seconds into the file where the 400 ms window of `momentary_max`
+    /// ends, the earliest one on a tie. It's counted in decoded samples, so
+    /// it's the same on every run.
+    pub momentary_max_at_secs: Option<f64>,
+}
+merde::derive! {
+    impl (Deserialize) for struct Measurement {
+        loudness, energy, duration, path, decode_errors, short, duration_mismatch, source,
+        start, end, channel_map, sample_rate_changes, channel_changes, true_peak,
+        short_term_max, psr, psr_avg, momentary_max, momentary_max_at_secs
+    }
+}
+
+/// Written as the derive would, except that silence's loudness goes through
+/// [`Unbounded`] so the cache reads back.
+impl merde::json::JsonSerialize for Measurement {
+    fn json_serialize(&self, serializer: &mut merde::json::JsonSerializer) {
+        serializer
+            .write_obj()
+            .pair("loudness", &Unbounded(self.loudness))
+            .pair("energy", &self.energy)
+            .pair("duration", &self.duration)
+            .pair("path", &self.path)
+            .pair("decode_errors", &self.decode_errors)
+            .pair("short", &self.short)
+            .pair("duration_mismatch", &self.duration_mismatch)
+            .pair("source", &self.source)
+            .pair("start", &self.start)
+            .pair("end", &self.end)
+            .pair("channel_map", &self.channel_map)
+            .pair("sample_rate_changes", &self.sample_rate_changes)
+            .pair("channel_changes", &self.channel_changes)
+            .pair("true_peak", &self.true_peak)
+            .pair("short_term_max", &self.short_term_max)
+            .pair("psr", &self.psr)
+            .pair("psr_avg", &self.psr_avg)
+            .pair("momentary_max", &self.momentary_max)
+            .pair("momentary_max_at_secs", &self.momentary_max_at_secs);
+    }
+}
+
+/// A number that may be infinite, written as one too large for a double so
+/// JSON readers take it back as infinity, where `-inf` wouldn't parse.
+pub(crate) struct Unbounded(pub(crate) f64);
+
+impl merde::json::JsonSerialize for Unbounded {
+    fn json_serialize(&self, serializer: &mut merde::json::JsonSerializer) {
+        match self.0 {
+            f64::INFINITY => serializer.as_mut_vec().extend_from_slice(b"1e999"),
+            f64::NEG_INFINITY => serializer.as_mut_vec().extend_from_slice(b"-1e999"),
+            value => serializer.write_f64(value),
+        }
+    }
+}
+
+impl Measurement {
+    /// Whether this measurement is of what `options` asks for: the same
+    /// range and channel map, and a true peak if [`psr`](MeasureOptions::psr)
+    /// is set. So a cached entry of the whole file isn't taken for one of a
+    /// part of it, or the other way around.
+    pub fn matches(&self, options: &MeasureOptions) -> bool {
+        self.start == options.range_start()
+            && self.end == options.range_end()
+            && self.channel_map == options.channel_map.as_ref().map(ToString::to_string)
+            && (!options.psr || self.true_peak.is_some())
+    }
+}
+
+/// Settings for a single measurement; the default measures the whole file.
+///
+/// Built up from [`MeasureOptions::new`] with the `with_*` methods:
+///
+/// ```no_run
+/// use loudness::{measure_file, CancelToken, MeasureOptions};
+/// use std::path::Path;
+/// use std::time::Duration;
+///
+/// let cancel = CancelToken::new();
+/// let options = MeasureOptions::new()
+///     .with_timeout(Duration::from_secs(60))
+///     .with_progress(|p| {
+///         if let Some(fraction) = p.fraction() {
+///             eprintln!("{:.0}%", fraction * 100.0);
+///         }
+///     })
+///     .with_cancel_token(cancel.clone());
+/// let m = measure_file(Path::new("track.flac"), &options)?;
+/// # Ok::<(), loudness::MeasureError>(())
+/// ```
+#[derive(Clone, Default)]
+pub struct MeasureOptions {
+    /// Give up once decoding has taken this long.
+    pub timeout: Option<Duration>,
+    /// Called from the decode loop about every half second of audio.
+    ///
+    /// A callback that panics isn't called again; the measurement carries on.
+    pub on_progress: Option<ProgressCallback>,
+    /// Checked between packets; once cancelled, measuring stops with
+    /// [`ErrorKind::Cancelled`].
+    pub cancel: Option<CancelToken>,
+    /// Called once a measurement succeeds, with where its time went. Without
+    /// it nothing is timed.
+    pub on_breakdown: Option<BreakdownCallback>,
+    /// Analyze on a second thread while this one decodes, which speeds up
+    /// a single long file when there are cores to spare. The results are
+    /// the same either way.
+    pub pipeline: bool,
+    /// Bytes to read from a file at a time in [`measure_file`];
+    /// [`DEFAULT_READ_BUFFER`] when unset, and 0 to leave reads to symphonia's
+    /// own buffering.
+    pub read_buffer: Option<usize>,
+    /// Give up with [`ErrorKind::TooCorrupt`] once more than this many
+    /// packets have failed to decode. Unlimited when unset.
+    pub max_decode_errors: Option<u64>,
+    /// Fail with [`ErrorKind::TooCorrupt`] when more than this fraction of
+    /// the track's packets failed to decode, rather than measuring what
+    /// little audio was left. Unchecked when unset.
+    pub max_decode_error_fraction: Option<f64>,
+    /// How audio shorter than [`SHORT_DURATION`] is handled.
+    pub short_files: ShortFiles,
+    /// Share of the declared length the decoded length may differ by
+    /// before the measurement is marked
+    /// [`duration_mismatch`](Measurement::duration_mismatch);
+    /// [`DEFAULT_DURATION_TOLERANCE`] when unset.
+    pub duration_tolerance: Option<f64>,
+    /// Measure this audio track, counting from 0 among a file's audio
+    /// tracks only, instead of the default one.
+    pub track: Option<usize>,
+    /// Measure the first audio track tagged with this language, e.g. "eng",
+    /// instead of the default one. Ignored when `track` is set.
+    pub language: Option<String>,
+    /// Seconds into the track to start measuring at. The reader seeks there
+    /// when it can; otherwise the audio before it is decoded and discarded.
+    pub start: Option<f64>,
+    /// Seconds to measure, from `start`; to the end of the track when unset.
+    pub duration: Option<f64>,
+    /// Measure with this layout instead of the one the file declares.
+    /// Fails with [`ErrorKind::ChannelMap`] for a file with a different
+    /// number of channels.
+    pub channel_map: Option<ChannelMap>,
+    /// Called once a measurement succeeds, with the loudness of each of its
+    /// gating blocks counted in the empty histogram given with it. Without
+    /// it no block is looked at on its own.
+    pub on_block_histogram: Option<(BlockHistogram, BlockHistogramCallback)>,
+    /// Also measure true peak and the loudest short-term loudness, for
+    /// [`Measurement::psr`]. Off by default, as true peak is slow to
+    /// measure.
+    pub psr: bool,
+}
+
+/// See [`MeasureOptions::short_files`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShortFiles {
+    /// Measure it ungated and mark it [`short`](Measurement::short).
+    #[default]
+    Ungated,
+    /// Fail with [`ErrorKind::TooShort`].
+    Fail,
+}
+
+impl MeasureOptions {
+    /// The defaults: no timeout, no progress reports, not cancellable.
+    pub fn new() -> MeasureOptions {
+        MeasureOptions::default()
+    }
+
+    /// Sets [`timeout`](Self::timeout).
+    pub fn with_timeout(self, timeout: Duration) -> MeasureOptions {
+        MeasureOptions {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Sets [`on_progress`](Self::on_progress), replacing any callback
+    /// already set.
+    pub fn with_progress(
+        self,
+        callback: impl Fn(Progress) + Send + Sync + 'static,
+    ) -> MeasureOptions {
+        MeasureOptions {
+            on_progress: Some(Arc::new(callback)),
+            ..self
+        }
+    }
+
+    /// Sets [`on_breakdown`](Self::on_breakdown).
+    pub fn with_breakdown(
+        self,
+        callback: impl Fn(Breakdown) + Send + Sync + 'static,
+    ) -> MeasureOptions {
+        MeasureOptions {
+            on_breakdown: Some(Arc::new(callback)),
+            ..self
+        }
+    }
+
+    /// Sets [`pipeline`](Self::pipeline).
+    pub fn with_pipeline(self, pipeline: bool) -> MeasureOptions {
+        MeasureOptions { pipeline, ..self }
+    }
+
+    /// Sets [`read_buffer`](Self::read_buffer).
+    pub fn with_read_buffer(self, bytes: usize) -> MeasureOptions {
+        MeasureOptions {
+            read_buffer: Some(bytes),
+            ..self
+        }
+    }
+
+    /// Sets [`max_decode_errors`](Self::max_decode_errors).
+    pub fn with_max_decode_errors(self, max: u64) -> MeasureOptions {
+        MeasureOptions {
+            max_decode_errors: Some(max),
+            ..self
+        }
+    }
+
+    /// Sets [`max_decode_error_fraction`](Self::max_decode_error_fraction).
+    pub fn with_max_decode_error_fraction(self, fraction: f64) -> MeasureOptions {
+        MeasureOptions {
+            max_decode_error_fraction: Some(fraction),
+            ..self
+        }
+    }
+
+    /// Sets [`short_files`](Self::short_files).
+    pub fn with_short_files(self, short_files: ShortFiles) -> MeasureOptions {
+        MeasureOptions {
+            short_files,
+            ..self
+        }
+    }
+
+    /// Sets [`duration_tolerance`](Self::duration_tolerance).
+    pub fn with_duration_tolerance(self, tolerance: f64) -> MeasureOptions {
+        MeasureOptions {
+            duration_tolerance: Some(tolerance),
+            ..self
+        }
+    }
+
+    /// Sets [`track`](Self::track).
+    pub fn with_track(self, track: usize) -> MeasureOptions {
+        MeasureOptions {
+            track: Some(track),
+            ..self
+        }
+    }
+
+    /// Sets [`language`](Self::language).
+    pub fn with_language(self, language: impl Into<String>) -> MeasureOptions {
+        MeasureOptions {
+            language: Some(language.into()),
+            ..self
+        }
+    }
+
+    /// Sets [`start`](Self::start).
+    pub fn with_start(self, seconds: f64) -> MeasureOptions {
+        MeasureOptions {
+            start: Some(seconds),
+            ..self
+        }
+    }
+
+    /// Sets [`duration`](Self::duration).
+    pub fn with_duration(self, seconds: f64) -> MeasureOptions {
+        MeasureOptions {
+            duration: Some(seconds),
+            ..self
+        }
+    }
+
+    /// Sets [`channel_map`](Self::channel_map).
+    pub fn with_channel_map(self, map: ChannelMap) -> MeasureOptions {
+        MeasureOptions {
+            channel_map: Some(map),
+            ..self
+        }
+    }
+
+    /// Sets [`on_block_histogram`](Self::on_block_histogram), counting
+    /// blocks in `histogram`, which should be empty.
+    pub fn with_block_histogram(
+        self,
+        histogram: BlockHistogram,
+        callback: impl Fn(BlockHistogram) + Send + Sync + 'static,
+    ) -> MeasureOptions {
+        MeasureOptions {
+            on_block_histogram: Some((histogram, Arc::new(callback))),
+            ..self
+        }
+    }
+
+    /// Sets [`psr`](Self::psr).
+    pub fn with_psr(self, psr: bool) -> MeasureOptions {
+        MeasureOptions { psr, ..self }
+    }
+
+    /// The [`Measurement::start`] these options give: where the range
+    /// starts, if only part of the track is measured.
+    fn range_start(&self) -> Option<f64> {
+        (self.start.is_some() || self.duration.is_some()).then(|| self.start.unwrap_or(0.0))
+    }
+
+    /// The [`Measurement::end`] these options give.
+    fn range_end(&self) -> Option<f64> {
+        self.duration.map(|d| self.start.unwrap_or(0.0) + d)
+    }
+
+    /// Sets [`cancel`](Self::cancel).
+    pub fn with_cancel_token(self, cancel: CancelToken) -> MeasureOptions {
+        MeasureOptions {
+            cancel: Some(cancel),
+            ..self
+        }
+    }
+}
+
+/// A flag for stopping measurements from another thread. Clones share it.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+    /// Also cancels this token; see `child`.
+    parent: Option<Arc<AtomicBool>>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Stops every measurement using this token, or a clone of it.
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|p| p.load(Ordering::Relaxed))
+    }
+
+    /// A token cancelled along with this one, that can also be cancelled on
+    /// its own without affecting this one.
+    #[cfg_attr(not(feature = "async"), allow(dead_code))]
+    fn child(&self) -> CancelToken {
+        CancelToken {
+            flag: Arc::default(),
+            parent: Some(Arc::clone(&self.flag)),
+        }
+    }
+}
+
+impl fmt::Debug for MeasureOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MeasureOptions")
+            .field("timeout", &self.timeout)
+            .field("on_progress", &self.on_progress.as_ref().map(|_| ".."))
+            .field("cancel", &self.cancel)
+            .field("on_breakdown", &self.on_breakdown.as_ref().map(|_| ".."))
+            .field("pipeline", &self.pipeline)
+            .field("read_buffer", &self.read_buffer)
+            .field("max_decode_errors", &self.max_decode_errors)
+            .field("max_decode_error_fraction", &self.max_decode_error_fraction)
+            .field("short_files", &self.short_files)
+            .field("duration_tolerance", &self.duration_tolerance)
+            .field("track", &self.track)
+            .field("language", &self.language)
+            .field("start", &self.start)
+            .field("duration", &self.duration)
+            .field("channel_map", &self.channel_map)
+            .field(
+                "on_block_histogram",
+                &self.on_block_histogram.as_ref().map(|_| ".."),
+            )
+            .field("psr", &self.psr)
+            .finish()
+    }
+}
+
+/// See [`MeasureOptions::on_progress`].
+pub type ProgressCallback = Arc<dyn Fn(Progress) + Send + Sync>;
+
+/// See [`MeasureOptions::on_breakdown`].
+pub type BreakdownCallback = Arc<dyn Fn(Breakdown) + Send + Sync>;
+
+/// See [`MeasureOptions::on_block_histogram`].
+pub type BlockHistogramCallback = Arc<dyn Fn(BlockHistogram) + Send + Sync>;
+
+/// Where a measurement's time went, roughly: each stage is timed around the
+/// calls that do its work, and anything in between counts towards `io`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Breakdown {
+    /// Opening and probing the file, and reading packets.
+    pub io: Duration,
+    /// Decoding packets to samples.
+    pub decode: Duration,
+    /// The EBU R128 analysis.
+    pub analysis: Duration,
+}
+
+/// Adds the time since `mark` to `total` and restarts `mark`; does nothing,
+/// not even reading the clock, when `mark` is `None`.
+fn lap(mark: &mut Option<Instant>, total: &mut Duration) {
+    if let Some(mark) = mark {
+        let now = Instant::now();
+        *total += now - *mark;
+        *mark = now;
+    }
+}
+
+/// How far a measurement has got, as passed to [`MeasureOptions::on_progress`].
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    /// Frames decoded and measured so far.
+    pub frames_done: u64,
+    /// Frames in the track, when the container declares it.
+    pub total_frames: Option<u64>,
+    /// Time since the measurement started.
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Fraction of the track measured, when its length is known.
+    pub fn fraction(&self) -> Option<f64> {
+        self.total_frames
+            .filter(|total| *total > 0)
+            .map(|total| (self.frames_done as f64 / total as f64).min(1.0))
+    }
+}
+
+/// Measures the file at `path`, recording its absolute path in the result.
+/// Errors carry the path too.
+///
+/// The file's extension is passed on to the format probe as a hint.
+pub fn measure_file(path: &Path, options: &MeasureOptions) -> Result<Measurement, MeasureError> {
+    let measured = File::open(path)
+        .map_err(|e| ErrorKind::Open(e).into())
+        .and_then(|file| {
+            // with a capacity of 0 every read goes straight to the file
+            let size = options.read_buffer.unwrap_or(DEFAULT_READ_BUFFER);
+            let source = BufferedFile::new(file, size, path.display().to_string());
+            measure_stream(Box::new(source), hint_for(path), Some(path), options)
+        });
+    let mut measurement = measured.map_err(|e| e.in_file(path))?;
+    measurement.path = std::path::absolute(path)
+        .ok()
+        .map(|p| p.to_string_lossy().into_owned());
+    Ok(measurement)
+}
+
+/// Measures audio read from `source`, such as a file that's already open,
+/// an archive member or a network stream. The result has no `path`.
+///
+/// `hint` helps the probe pick a format, e.g. from a file extension or MIME
+/// type; an empty `Hint::new()` works for any supported format. The source
+/// is only seeked if its `is_seekable` says it can be.
+///
+/// ```no_run
+/// use loudness::{measure_source, Hint, MeasureOptions};
+/// use std::io::Cursor;
+///
+/// let bytes: Vec<u8> = std::fs::read("track.mp3")?;
+/// let mut hint = Hint::new();
+/// hint.with_extension("mp3");
+/// let m = measure_source(Box::new(Cursor::new(bytes)), hint, &MeasureOptions::default())?;
+/// println!("{:.1} LUFS", m.loudness);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn measure_source(
+    source: Box<dyn MediaSource>,
+    hint: Hint,
+    options: &MeasureOptions,
+) -> Result<Measurement, MeasureError> {
+    measure_stream(source, hint, None, options)
+}
+
+/// A probe hint carrying `path`'s extension, if it has one.
+pub fn hint_for(path: &Path) -> Hint {
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    hint
+}
+
+/// Does the work for both entry points; `path` only names the file in logs.
+fn measure_stream(
+    source: Box<dyn MediaSource>,
+    hint: Hint,
+    path: Option<&Path>,
+    options: &MeasureOptions,
+) -> Result<Measurement, MeasureError> {
+    let started = Instant::now();
+    let name = path.map_or("<source>".into(), |p| p.display().to_string());
+    let format = open_format(source, hint, &name)?;
+    measure_format(format, &name, started, options)
+}
+
+/// Measures what `format` reads, timing out from `started`; `name` names
+/// the source in logs.
+fn measure_format(
+    mut format: Box<dyn FormatReader>,
+    name: &str,
+    started: Instant,
+    options: &MeasureOptions,
+) -> Result<Measurement, MeasureError> {
+    let deadline = options.timeout.map(|t| (started + t, t));
+    let decoder_opts: DecoderOptions = Default::default();
+
+    let mut track = select_track(&*format, options)
+        .ok_or(ErrorKind::NoTrack)?
+        .clone();
+
+    // Create a decoder for the track.
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &decoder_opts)
+        .map_err(ErrorKind::Decoder)?;
+
+    // Store the track identifier, we'll use it to filter packets.
+    let mut track_id = track.id;
+
+    // seconds of decoded audio before the range, to discard
+    let mut skip_seconds = options.start.unwrap_or(0.0);
+    if skip_seconds > 0.0 {
+        let to = SeekTo::Time {
+            time: Time::from(skip_seconds),
+            track_id: Some(track_id),
+        };
+        match format.seek(SeekMode::Accurate, to) {
+            Ok(seeked) => {
+                decoder.reset();
+                // an accurate seek lands on or before the time asked for
+                let before = seeked.required_ts.saturating_sub(seeked.actual_ts);
+                let params = &track.codec_params;
+                skip_seconds = match (params.time_base, params.sample_rate) {
+                    (Some(time_base), _) => {
+                        let time = time_base.calc_time(before);
+                        time.seconds as f64 + time.frac
+                    }
+                    (None, Some(rate)) => before as f64 / f64::from(rate),
+                    (None, None) => 0.0,
+                };
+            }
+            Err(e) => debug!("'{name}': can't seek ({e}), decoding up to the start instead"),
+        }
+    }
+    // seconds into the file of the next decoded buffer, to say where the
+    // layout changed
+    let mut position = options.start.unwrap_or(0.0) - skip_seconds;
+    // frames to discard before the range and to measure in it, once the
+    // rate is known
+    let mut skip_frames = 0u64;
+    let mut take_frames: Option<u64> = None;
+    let mut total_frames = track.codec_params.n_frames;
+
+    // created from the first decoded buffer: some AAC and Vorbis streams only
+    // tell their channel layout or sample rate once a packet has been decoded
+    let mut batch: Option<Batch> = None;
+
+    let mut breakdown = Breakdown::default();
+    let mut clock = options.on_breakdown.is_some().then_some(started);
+    lap(&mut clock, &mut breakdown.io);
+
+    // decode errors can fire for nearly every packet of a damaged file, so only
+    // the first one is surfaced by default along with a count of the rest
+    let mut first_decode_error = None;
+    let mut decode_errors = 0u64;
+    let mut rate_changes = 0u64;
+    let mut channel_changes = 0u64;
+    // chained streams restart with new tracks for each segment
+    let mut segments = 1u64;
+    let mut packets = 0u64;
+    // of the measured track only, for the share that failed
+    let mut track_packets = 0u64;
+    let mut on_progress = options.on_progress.as_deref();
+    // both set once the rate is known
+    let mut report_every = 0;
+    let mut next_report = 0;
+    // reused for every packet that isn't f32 already, and only replaced when
+    // one doesn't fit
+    let mut converted: Option<AudioBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // how symphonia ends every stream; one that ends early is caught
+            // by the length check below
+            Err(Error::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            // the next segment of a chained stream, e.g. Ogg, whose layout
+            // may differ; its tracks replace the last one's
+            Err(Error::ResetRequired) => {
+                track = select_track(&*format, options)
+                    .ok_or(ErrorKind::NoTrack)?
+                    .clone();
+                decoder = symphonia::default::get_codecs()
+                    .make(&track.codec_params, &decoder_opts)
+                    .map_err(ErrorKind::Decoder)?;
+                track_id = track.id;
+                segments += 1;
+                debug!("'{name}': segment {segments} starts at {position:.1}s");
+                continue;
+            }
+            Err(e) => {
+                warn!("error while reading '{name}': {e}");
+                break;
+            }
+        };
+        if options
+            .cancel
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+        {
+            return Err(ErrorKind::Cancelled.into());
+        }
+        packets += 1;
+        if let Some((deadline, timeout)) = deadline {
+            if packets.is_multiple_of(TIMEOUT_CHECK_PACKETS) && Instant::now() > deadline {
+                // nothing partial is returned, so nothing partial gets cached
+                return Err(ErrorKind::Timeout(timeout).into());
+            }
+        }
+
+        // If the packet does not belong to the selected track, skip it.
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        track_packets += 1;
+        lap(&mut clock, &mut breakdown.io);
+        // Decode the packet into audio samples, ignoring any decode errors.
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                if decoded.frames() > 0 {
+                    if batch.is_none() {
+                        let spec = decoded.spec();
+                        let (channels, rate) = (spec.channels.count(), spec.rate);
+                        report_every = u64::from(rate / 2).max(1);
+                        next_report = report_every;
+                        let to_frames = |seconds: f64| (seconds * f64::from(rate)).round() as u64;
+                        skip_frames = to_frames(skip_seconds);
+                        take_frames = options.duration.map(to_frames);
+                        if options.start.is_some() || options.duration.is_some() {
+                            let start = to_frames(options.start.unwrap_or(0.0));
+                            let left = total_frames.map(|n| n.saturating_sub(start));
+                            total_frames = match (take_frames, left) {
+                                (Some(take), Some(left)) => Some(take.min(left)),
+                                (take, left) => take.or(left),
+                            };
+                        }
+                        // fails for layouts or rates ebur128 can't measure
+                        let mut analyzer = Analyzer::new(channels as u32, rate)?;
+                        if options.psr {
+                            analyzer.measure_psr()?;
+                        }
+                        if let Some(map) = &options.channel_map {
+                            analyzer.set_channel_map(map)?;
+                        }
+                        if let Some((histogram, _)) = &options.on_block_histogram {
+                            analyzer.set_block_histogram(histogram.clone());
+                        }
+                        // 1s
+                        batch = Some(Batch::new(
+                            analyzer,
+                            channels,
+                            rate,
+                            rate as usize,
+                            options.pipeline,
+                        ));
+                    }
+                    let batch = batch.as_mut().unwrap();
+                    // chained Ogg streams and broadcast captures can switch
+                    // layout between segments
+                    let spec = decoded.spec();
+                    let (channels, rate) = (spec.channels.count(), spec.rate);
+                    let old = batch.channels();
+                    if channels != old {
+                        // the map only says where the old channels go
+                        if options.channel_map.is_some() {
+                            return Err(ErrorKind::ChannelsChanged {
+                                from: old as u32,
+                                to: channels as u32,
+                                seconds: position,
+                            }
+                            .into());
+                        }
+                        warn!("'{name}' changes from {old} to {channels} channel(s) at {position:.1}s");
+                        channel_changes += 1;
+                        batch.set_channels(channels)?;
+                    }
+                    let old = batch.rate();
+                    if rate != old {
+                        warn!("'{name}' changes sample rate from {old} to {rate} Hz at {position:.1}s");
+                        rate_changes += 1;
+                        // the range left, counted in frames at the new rate
+                        let rescale = |frames: u64| {
+                            (frames as f64 * f64::from(rate) / f64::from(old)).round() as u64
+                        };
+                        take_frames = take_frames.map(rescale);
+                        skip_frames = rescale(skip_frames);
+                        report_every = u64::from(rate / 2).max(1);
+                        batch.set_rate(rate)?;
+                    }
+                    let planes = match &decoded {
+                        AudioBufferRef::F32(buffer) => buffer.planes(),
+                        _ => {
+                            let fits = converted.as_ref().is_some_and(|c| {
+                                c.spec() == decoded.spec() && c.capacity() >= decoded.capacity()
+                            });
+                            if !fits {
+                                converted = Some(decoded.make_equivalent());
+                            }
+                            let converted = converted.as_mut().unwrap();
+                            decoded.convert(converted);
+                            converted.planes()
+                        }
+                    };
+                    lap(&mut clock, &mut breakdown.decode);
+                    let planes = planes.planes();
+                    // the part of this buffer inside the range
+                    let len = decoded.frames() as u64;
+                    position += len as f64 / f64::from(rate);
+                    let from = skip_frames.min(len);
+                    skip_frames -= from;
+                    let to = take_frames.map_or(len, |take| len.min(from + take));
+                    if let Some(take) = &mut take_frames {
+                        *take -= to - from;
+                    }
+                    if from == 0 && to == len {
+                        batch.push(planes)?;
+                    } else if to > from {
+                        let (from, to) = (from as usize, to as usize);
+                        let trimmed: Vec<&[f32]> = planes.iter().map(|p| &p[from..to]).collect();
+                        batch.push(&trimmed)?;
+                    }
+                    lap(&mut clock, &mut breakdown.analysis);
+                    if batch.frames() >= next_report {
+                        next_report = batch.frames() + report_every;
+                        let progress = Progress {
+                            frames_done: batch.frames(),
+                            total_frames,
+                            elapsed: started.elapsed(),
+                        };
+                        if let Some(callback) = on_progress {
+                            let call = AssertUnwindSafe(|| callback(progress));
+                            if std::panic::catch_unwind(call).is_err() {
+                                warn!("progress callback panicked; no longer calling it");
+                                on_progress = None;
+                            }
+                        }
+                    }
+                    // the rest of the file is after the range
+                    if take_frames == Some(0) {
+                        break;
+                    }
+                }
+                // packets without frames, e.g. metadata only, are skipped
+            }
+            Err(Error::DecodeError(e)) => {
+                debug!("decode error in '{name}': {e}");
+                first_decode_error.get_or_insert(e);
+                decode_errors += 1;
+                if options
+                    .max_decode_errors
+                    .is_some_and(|max| decode_errors > max)
+                {
+                    return Err(ErrorKind::TooCorrupt {
+                        errors: decode_errors,
+                        packets: track_packets,
+                    }
+                    .into());
+                }
+            }
+            Err(Error::IoError(e)) => {
+                if e.kind() != io::ErrorKind::UnexpectedEof {
+                    warn!("io error while decoding '{name}': {e}");
+                }
+                break;
+            }
+            Err(e) => {
+                warn!("error while decoding '{name}': {e}");
+                break;
+            }
+        }
+    }
+
+    // a truncated file, or a header that's wrong, e.g. a stale Xing header;
+    // only the whole file can be compared with what the header declares, and
+    // only at the one rate it declares it at, and for its one segment
+    let whole = options.range_start().is_none() && rate_changes == 0 && segments == 1;
+    let mismatch = track
+        .codec_params
+        .n_frames
+        .filter(|_| whole)
+        .and_then(|declared| {
+            let frames = batch.as_ref().map_or(0, Batch::frames);
+            let tolerance = options
+                .duration_tolerance
+                .unwrap_or(DEFAULT_DURATION_TOLERANCE);
+            let slack = (declared as f64 * tolerance) as u64 + DURATION_SLACK_FRAMES;
+            (frames.abs_diff(declared) > slack).then_some((frames, declared))
+        });
+    match mismatch {
+        Some((frames, declared)) if frames < declared => {
+            warn!("'{name}' ended early, after {frames} of {declared} frames")
+        }
+        Some((frames, declared)) => {
+            warn!("'{name}' has {frames} frames, more than the {declared} its header declares")
+        }
+        None => {}
+    }
+    if let Some(fraction) = options.max_decode_error_fraction {
+        if decode_errors as f64 > fraction * track_packets as f64 {
+            return Err(ErrorKind::TooCorrupt {
+                errors: decode_errors,
+                packets: track_packets,
+            }
+            .into());
+        }
+    }
+    if let Some(e) = first_decode_error {
+        match decode_errors {
+            1 => warn!("decode error in '{name}': {e}"),
+            n => warn!(
+                "decode error in '{name}': {e} (...and {} more decode errors)",
+                n - 1
+            ),
+        }
+    }
+
+    lap(&mut clock, &mut breakdown.io);
+    // nothing decoded, so there was never a layout to measure with
+    let Some(batch) = batch else {
+        return Err(ErrorKind::NoAudio.into());
+    };
+    let mut analyzer = batch.finish()?;
+    let histogram = analyzer.take_block_histogram();
+    let mut measurement = analyzer.finalize()?;
+    if measurement.short == Some(true) && options.short_files == ShortFiles::Fail {
+        return Err(ErrorKind::TooShort {
+            seconds: measurement.duration.unwrap_or_default(),
+        }
+        .into());
+    }
+    measurement.decode_errors = (decode_errors > 0).then_some(decode_errors);
+    measurement.duration_mismatch = mismatch.map(|_| true);
+    measurement.start = options.range_start();
+    measurement.end = options.range_end();
+    measurement.channel_map = options.channel_map.as_ref().map(ToString::to_string);
+    measurement.sample_rate_changes = (rate_changes > 0).then_some(rate_changes);
+    measurement.channel_changes = (channel_changes > 0).then_some(channel_changes);
+    // into the file, not the range
+    measurement.momentary_max_at_secs = measurement
+        .momentary_max_at_secs
+        .map(|at| at + options.start.unwrap_or(0.0));
+    lap(&mut clock, &mut breakdown.analysis);
+    if let Some(callback) = &options.on_breakdown {
+        callback(breakdown);
+    }
+    if let (Some((_, callback)), Some(histogram)) = (&options.on_block_histogram, histogram) {
+        callback(histogram);
+    }
+    Ok(measurement)
+}
+
+/// Probes `source` for a container format, returning its reader; `name`
+/// only names it in logs.
+fn open_format(
+    source: Box<dyn MediaSource>,
+    hint: Hint,
+    name: &str,
+) -> Result<Box<dyn FormatReader>, MeasureError> {
+    // probing zero bytes would only report an unrecognized format
+    if source.byte_len() == Some(0) {
+        return Err(ErrorKind::Empty.into());
+    }
+    let mut source = source;
+    if source.is_seekable() {
+        let skipped = skip_id3v2(&mut source).map_err(ErrorKind::Open)?;
+        if skipped > 0 {
+            debug!("'{name}': skipped {skipped} bytes of ID3v2 tags");
+            // demuxers seek to positions they count from where they started
+            source = Box::new(Offset {
+                inner: source,
+                offset: skipped,
+            });
+        }
+    }
+    let mss = MediaSourceStream::new(source, Default::default());
+
+    // Use the default options when reading.
+    let format_opts: FormatOptions = Default::default();
+    // tags and pictures are never used; symphonia 0.5 doesn't enforce these
+    // limits yet, hence skip_id3v2
+    let metadata_opts = MetadataOptions {
+        limit_metadata_bytes: Limit::Maximum(0),
+        limit_visual_bytes: Limit::Maximum(0),
+    };
+
+    // Probe the media source stream for a format.
+    let probed = symphonia::default::get_probe()
+        .format(&hint, mss, &format_opts, &metadata_opts)
+        .map_err(ErrorKind::Probe)?;
+    Ok(probed.format)
+}
+
+/// Whether the file at `path` is in a format that can be measured and has
+/// an audio track, found by probing it without decoding anything.
+pub fn is_audio_file(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let name = path.display().to_string();
+    open_format(Box::new(file), hint_for(path), &name)
+        .is_ok_and(|format| format.tracks().iter().any(is_audio))
+}
+
+/// The track to measure: the one `options` asks for, otherwise the default
+/// track if it's audio, otherwise the first audio track. Video files and
+/// chaptered m4a files can have a default track that isn't audio.
+fn select_track<'a>(format: &'a dyn FormatReader, options: &MeasureOptions) -> Option<&'a Track> {
+    let mut audio = format.tracks().iter().filter(|t| is_audio(t));
+    if let Some(n) = options.track {
+        return audio.nth(n);
+    }
+    if let Some(language) = &options.language {
+        return audio.find(|t| {
+            t.language
+                .as_deref()
+                .is_some_and(|l| l.eq_ignore_ascii_case(language))
+        });
+    }
+    format
+        .default_track()
+        .filter(|t| is_audio(t))
+        .or_else(|| audio.next())
+}
+
+/// Whether `track` looks like audio: it has a sample rate or channels, or a
+/// codec there's a decoder for. Video, subtitle and chapter tracks don't.
+fn is_audio(track: &Track) -> bool {
+    let params = &track.codec_params;
+    params.codec != CODEC_TYPE_NULL
+        && (params.sample_rate.is_some()
+            || params.channels.is_some()
+            || symphonia::default::get_codecs()
+                .get_codec(params.codec)
+                .is_some())
+}
+
+/// Seeks `source` past any ID3v2 tags at its start, returning how many bytes
+/// that skipped, so the probe doesn't read and keep their contents, which
+/// can be megabytes of cover art. Leaves it at the start if there are none.
+fn skip_id3v2(source: &mut Box<dyn MediaSource>) -> io::Result<u64> {
+    let mut offset = 0;
+    loop {
+        let mut header = [0u8; 10];
+        let complete = match source.read_exact(&mut header) {
+            Ok(()) => true,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => false,
+            Err(e) => return Err(e),
+        };
+        // "ID3", version, flags, then a 28-bit size in 7-bit bytes
+        let size = &header[6..10];
+        if !complete || &header[..3] != b"ID3" || size.iter().any(|b| b & 0x80 != 0) {
+            source.seek(SeekFrom::Start(offset))?;
+            return Ok(offset);
+        }
+        let size = size.iter().fold(0u64, |n, b| n << 7 | u64::from(*b));
+        // a footer repeats the header at the end
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        offset += 10 + size + footer;
+        source.seek(SeekFrom::Start(offset))?;
+    }
+}
+
+/// A source seen from `offset` bytes in, such as past the ID3v2 tags
+/// [`skip_id3v2`] skipped, so that a seek to a position the demuxer read
+/// lands where it meant rather than inside the tags.
+struct Offset {
+    inner: Box<dyn MediaSource>,
+    offset: u64,
+}
+
+impl Read for Offset {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Seek for Offset {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(n) => {
+                SeekFrom::Start(n.checked_add(self.offset).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "seek past the end")
+                })?)
+            }
+            pos => pos,
+        };
+        let at = self.inner.seek(pos)?;
+        if at < self.offset {
+            self.inner.seek(SeekFrom::Start(self.offset))?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the audio",
+            ));
+        }
+        Ok(at - self.offset)
+    }
+}
+
+impl MediaSource for Offset {
+    fn is_seekable(&self) -> bool {
+        self.inner.is_seekable()
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.inner
+            .byte_len()
+            .map(|len| len.saturating_sub(self.offset))
+    }
+}
+
+/// Measures interleaved PCM, e.g. from another decoder or a plugin host.
+///
+/// `samples.len()` must be a multiple of `channels`. The result is the same
+/// as for a file with that audio in it, minus the `path`.
+///
+/// ```
+/// // one second of a 997 Hz sine at -20 dBFS, in stereo
+/// let samples: Vec<f32> = (0..48_000)
+///     .flat_map(|i| {
+///         let s = 0.1 * (2.0 * std::f32::consts::PI * 997.0 * i as f32 / 48_000.0).sin();
+///         [s, s]
+///     })
+///     .collect();
+/// let m = loudness::measure_samples(&samples, 2, 48_000)?;
+/// assert!((m.loudness - -20.0).abs() < 0.5);
+/// # Ok::<(), loudness::MeasureError>(())
+/// ```
+pub fn measure_samples(
+    samples: &[f32],
+    channels: u32,
+    sample_rate: u32,
+) -> Result<Measurement, MeasureError> {
+    let mut analyzer = Analyzer::new(channels, sample_rate)?;
+    analyzer.push(samples)?;
+    analyzer.finalize()
+}
+
+/// Like [`measure_samples`], for `f64` samples.
+pub fn measure_samples_f64(
+    samples: &[f64],
+    channels: u32,
+    sample_rate: u32,
+) -> Result<Measurement, MeasureError> {
+    let mut analyzer = Analyzer::new(channels, sample_rate)?;
+    analyzer.push_f64(samples)?;
+    analyzer.finalize()
+}
+
+/// Key under which a file's measurement is stored in the cache: its file
+/// name without the extension, in Unicode NFC (see [`normalize_key`]).
+///
+/// Never panics: a path without a stem falls back to its whole file name
+/// (or the path itself), and a name that isn't UTF-8 is converted lossily,
+/// with a warning since two such names can end up with the same key.
+///
+/// ```
+/// use loudness::cache_key;
+/// use std::path::Path;
+///
+/// assert_eq!(cache_key(Path::new("music/track.flac")), "track");
+/// assert_eq!(cache_key(Path::new("music/.hidden")), ".hidden");
+/// assert_eq!(cache_key(Path::new("..")), "..");
+/// // as macOS names it, and as Linux usually does
+/// assert_eq!(cache_key(Path::new("Cafe\u{301}.mp3")), "Caf\u{e9}");
+/// assert_eq!(cache_key(Path::new("Caf\u{e9}.mp3")), "Caf\u{e9}");
+/// # #[cfg(unix)]
+/// # {
+/// use std::ffi::OsStr;
+/// use std::os::unix::ffi::OsStrExt;
+///
+/// let name = OsStr::from_bytes(b"caf\xe9.mp3");
+/// assert_eq!(cache_key(Path::new(name)), "caf\u{FFFD}");
+/// # }
+/// ```
+pub fn cache_key(path: &Path) -> String {
+    let name = path
+        .file_stem()
+        .or_else(|| path.file_name())
+        .unwrap_or(path.as_os_str());
+    match name.to_str() {
+        Some(key) => normalize_key(key),
+        None => {
+            let key = normalize_key(&name.to_string_lossy());
+            warn!(
+                "'{}' isn't valid UTF-8; its cache key '{key}' is lossy",
+                path.display()
+            );
+            key
+        }
+    }
+}
+
+/// `key` in Unicode NFC, the form every cache key is stored in, so the same
+/// name gives the same key whether the filesystem composes accents (Linux,
+/// Windows) or stores them as combining marks (macOS).
+///
+/// ```
+/// assert_eq!(loudness::normalize_key("Ha\u{308}nde\u{301}l"), "H\u{e4}nd\u{e9}l");
+/// assert_eq!(loudness::normalize_key("\u{1100}\u{1161}"), "\u{ac00}");
+/// ```
+pub fn normalize_key(key: &str) -> String {
+    nfc::nfc(key).into_owned()
+}
+
+/// Reads a cache written by [`save_cache`] or [`save_cache_as`], in the
+/// format [`CacheFormat::detect`] finds, or JSON if it finds none. Malformed
+/// entries are reported as [`io::ErrorKind::InvalidData`], and a file in a
+/// format there's no support for as [`io::ErrorKind::Unsupported`].
+///
+/// Keys are brought to NFC as in [`cache_key`], so caches from before keys
+/// were normalized still match. Where that makes two entries one, the entry
+/// whose key was already NFC is kept.
+pub fn load_cache(from: &Path) -> io::Result<HashMap<String, Measurement>> {
+    let bytes = std::fs::read(from)?;
+    let format = detect_format(&bytes)?.unwrap_or(CacheFormat::Json);
+    let serialized = String::from_utf8(bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    Ok(normalize_keys(format.read(&serialized, false)?))
+}
+
+/// Bytes read to tell a cache's format by; its first line is enough.
+const DETECT_LIMIT: u64 = 64 * 1024;
+
+/// The format the cache at `from` was written in, found from its contents
+/// as in [`CacheFormat::detect`]; `None` if it's empty or in no format known.
+pub fn cache_format_of(from: &Path) -> io::Result<Option<CacheFormat>> {
+    let mut start = Vec::new();
+    File::open(from)?
+        .take(DETECT_LIMIT)
+        .read_to_end(&mut start)?;
+    detect_format(&start)
+}
+
+/// The format to write the cache at `to` in, so updating it keeps its
+/// format: the one it's already in, or else the one its extension names.
+pub fn cache_format_for(to: &Path) -> io::Result<CacheFormat> {
+    let found = match to.exists() {
+        true => cache_format_of(to)?,
+        false => None,
+    };
+    match found {
+        Some(format) => Ok(format),
+        None => {
+            CacheFormat::from_path(to).map_err(|e| io::Error::new(io::ErrorKind::Unsupported, e))
+        }
+    }
+}
+
+fn detect_format(bytes: &[u8]) -> io::Result<Option<CacheFormat>> {
+    CacheFormat::detect(bytes).map_err(|e| io::Error::new(io::ErrorKind::Unsupported, e))
+}
+
+fn normalize_keys(cache: HashMap<String, Measurement>) -> HashMap<String, Measurement> {
+    let mut normalized = HashMap::with_capacity(cache.len());
+    for (key, measurement) in cache {
+        match nfc::nfc(&key) {
+            Cow::Borrowed(_) => {
+                normalized.insert(key, measurement);
+            }
+            Cow::Owned(key) => {
+                if normalized.contains_key(&key) {
+                    debug!("dropped a duplicate of '{key}' with a decomposed key");
+                }
+                normalized.entry(key).or_insert(measurement);
+            }
+        }
+    }
+    normalized
+}
+
+/// Parses `text` as JSON, with parse errors as [`io::ErrorKind::InvalidData`].
+/// merde panics on some invalid input rather than returning an error, so
+/// that's caught and reported the same way.
+fn from_json<'s, T: merde::Deserialize<'s>>(text: &'s str) -> io::Result<T> {
+    let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+    match std::panic::catch_unwind(|| merde::json::from_str(text)) {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(invalid(format!("{e:?}"))),
+        Err(_) => Err(invalid("invalid JSON".to_string())),
+    }
+}
+
+/// Reads every entry that still parses from a damaged cache, e.g. one cut
+/// short by a crash while it was being written. JSON entries are found by
+/// matching braces and the other formats' by line, so one broken entry
+/// doesn't lose the rest.
+pub fn salvage_cache(from: &Path) -> io::Result<HashMap<String, Measurement>> {
+    let bytes = std::fs::read(from)?;
+    let text = String::from_utf8_lossy(&bytes);
+    match detect_format(&bytes)? {
+        None | Some(CacheFormat::Json) => {}
+        // an entry to a line, so each line is kept or not on its own
+        Some(format) => return Ok(normalize_keys(format.read(&text, true)?)),
+    }
+    let mut salvaged = HashMap::new();
+    let (mut depth, mut in_string, mut escaped) = (0, false, false);
+    // where the current top-level key and its value start
+    let (mut key, mut value) = (None, None);
+    let mut key_end = 0;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    in_string = false;
+                    if depth == 1 {
+                        key_end = i + 1;
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                if depth == 1 {
+                    key = Some(i);
+                }
+            }
+            '{' => {
+                depth += 1;
+                if depth == 2 {
+                    value = Some(i);
+                }
+            }
+            '}' => {
+                depth -= 1;
+                if depth == 1 {
+                    if let (Some(k), Some(v)) = (key.take(), value.take()) {
+                        let entry = from_json::<String>(&text[k..key_end])
+                            .ok()
+                            .zip(from_json::<Measurement>(&text[v..=i]).ok());
+                        if let Some((k, m)) = entry {
+                            salvaged.insert(k, m);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(normalize_keys(salvaged))
+}
+
+/// Writes measurements to `to` as a JSON object keyed by [`cache_key`].
+pub fn save_cache(d: &HashMap<String, Measurement>, to: &Path) -> io::Result<()> {
+    save_cache_as(d, to, CacheFormat::Json)
+}
+
+/// Writes measurements to `to` in `format`, which [`load_cache`] reads
+/// back.
+pub fn save_cache_as(
+    d: &HashMap<String, Measurement>,
+    to: &Path,
+    format: CacheFormat,
+) -> io::Result<()> {
+    let mut file = File::create(to)?;
+    file.write_all(format.write(d).as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia::core::formats::{Cue, Packet, SeekedTo};
+    use symphonia::core::meta::Metadata;
+
+    /// Reads as `inner` does, but with no sample rate in the track's
+    /// parameters, like streams that only tell it once a packet is decoded.
+    struct NoRate {
+        inner: Box<dyn FormatReader>,
+        tracks: Vec<Track>,
+    }
+
+    impl NoRate {
+        fn new(inner: Box<dyn FormatReader>) -> NoRate {
+            let mut tracks = inner.tracks().to_vec();
+            for track in &mut tracks {
+                track.codec_params.sample_rate = None;
+            }
+            NoRate { inner, tracks }
+        }
+    }
+
+    impl FormatReader for NoRate {
+        fn try_new(
+            _: MediaSourceStream,
+            _: &FormatOptions,
+        ) -> symphonia::core::errors::Result<Self> {
+            unimplemented!("only wraps a reader that's already open")
+        }
+
+        fn cues(&self) -> &[Cue] {
+            self.inner.cues()
+        }
+
+        fn metadata(&mut self) -> Metadata<'_> {
+            self.inner.metadata()
+        }
+
+        fn seek(
+            &mut self,
+            mode: SeekMode,
+            to: SeekTo,
+        ) -> symphonia::core::errors::Result<SeekedTo> {
+            self.inner.seek(mode, to)
+        }
+
+        fn tracks(&self) -> &[Track] {
+            &self.tracks
+        }
+
+        fn next_packet(&mut self) -> symphonia::core::errors::Result<Packet> {
+            self.inner.next_packet()
+        }
+
+        fn into_inner(self: Box<Self>) -> MediaSourceStream {
+            self.inner.into_inner()
+        }
+    }
+
+    /// CRC-8 of a FLAC frame header: polynomial 0x07, MSB first, from zero.
+    fn crc8(bytes: &[u8]) -> u8 {
+        bytes.iter().fold(0, |crc, &byte| {
+            (0..8).fold(crc ^ byte, |crc, _| {
+                (crc << 1) ^ if crc & 0x80 != 0 { 0x07 } else { 0 }
+            })
+        })
+    }
+
+    /// CRC-16 of a FLAC frame: polynomial 0x8005, MSB first, from zero.
+    fn crc16(bytes: &[u8]) -> u16 {
+        bytes.iter().fold(0, |crc, &byte| {
+            (0..8).fold(crc ^ u16::from(byte) << 8, |crc, _| {
+                (crc << 1) ^ if crc & 0x8000 != 0 { 0x8005 } else { 0 }
+            })
+        })
+    }
+
+    /// About two seconds of a 1 kHz sine as 16-bit mono FLAC at 44.1 kHz,
+    /// in verbatim blocks of 4096 samples. Its rate is in the STREAMINFO the
+    /// decoder reads, not only in the track's parameters.
+    fn sine_flac() -> Vec<u8> {
+        const BLOCK: u64 = 4096;
+        let (rate, blocks) = (44_100u64, 22u64);
+        let sample = |i: u64| {
+            let t = i as f64 / rate as f64;
+            (3000.0 * (2.0 * std::f64::consts::PI * 1000.0 * t).sin()) as i16
+        };
+        let mut flac = b"fLaC".to_vec();
+        // the only metadata block, STREAMINFO: block sizes, unknown frame
+        // sizes, rate, channels, bits per sample and length, no MD5
+        flac.extend_from_slice(&[0x80, 0, 0, 34]);
+        flac.extend_from_slice(&[0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+        let format = rate << 44 | (16 - 1) << 36 | (blocks * BLOCK);
+        flac.extend_from_slice(&format.to_be_bytes());
+        flac.extend_from_slice(&[0; 16]);
+        for n in 0..blocks {
+            // sync, 4096 samples at 44.1 kHz, mono, 16 bits, frame number
+            let mut frame = vec![0xff, 0xf8, 0xc9, 0x08, n as u8];
+            frame.push(crc8(&frame));
+            // a verbatim subframe
+            frame.push(0x02);
+            for i in n * BLOCK..(n + 1) * BLOCK {
+                frame.extend_from_slice(&sample(i).to_be_bytes());
+            }
+            let crc = crc16(&frame);
+            frame.extend_from_slice(&crc.to_be_bytes());
+            flac.extend_from_slice(&frame);
+        }
+        flac
+    }
+
+    fn open(flac: Vec<u8>) -> Box<dyn FormatReader> {
+        open_format(Box::new(io::Cursor::new(flac)), Hint::new(), "sine").unwrap()
+    }
+
+    #[test]
+    fn measure_format_takes_the_rate_from_the_decoded_audio() {
+        let options = MeasureOptions::default();
+        let declared = measure_format(open(sine_flac()), "sine", Instant::now(), &options).unwrap();
+        let reader = NoRate::new(open(sine_flac()));
+        assert_eq!(reader.tracks()[0].codec_params.sample_rate, None);
+        let decoded = measure_format(Box::new(reader), "sine", Instant::now(), &options).unwrap();
+
+        assert!(declared.loudness.is_finite());
+        assert_eq!(decoded.loudness, declared.loudness);
+        assert_eq!(decoded.energy, declared.energy);
+    }
+}
@@ -0,0 +1,103 @@
+//! Caps how many files are read at once, independently of decode threads.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use symphonia::core::io::MediaSource;
+
+/// A counting semaphore handing out read permits.
+pub struct IoLimit {
+    available: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl IoLimit {
+    pub fn new(jobs: usize) -> IoLimit {
+        IoLimit {
+            available: Mutex::new(jobs.max(1)),
+            freed: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) {
+        let mut available = self.available.lock().unwrap();
+        while *available == 0 {
+            available = self.freed.wait(available).unwrap();
+        }
+        *available -= 1;
+    }
+
+    fn release(&self) {
+        *self.available.lock().unwrap() += 1;
+        self.freed.notify_one();
+    }
+
+    /// Opens `path` to be read `buffer` bytes at a time, each read holding a
+    /// permit, so at most `jobs` reads are in flight at any time; decoding
+    /// between them happens without one, and only a buffer per file is kept
+    /// in memory.
+    pub fn open(self: &Arc<Self>, path: &Path, buffer: usize) -> io::Result<Box<dyn MediaSource>> {
+        let file = File::open(path)?;
+        Ok(Box::new(Limited {
+            seekable: file.is_seekable(),
+            len: file.byte_len(),
+            inner: BufReader::with_capacity(
+                buffer,
+                Permitted {
+                    file,
+                    limit: Arc::clone(self),
+                },
+            ),
+        }))
+    }
+}
+
+/// A file whose every read waits for a permit.
+struct Permitted {
+    file: File,
+    limit: Arc<IoLimit>,
+}
+
+impl Read for Permitted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.limit.acquire();
+        let read = self.file.read(buf);
+        self.limit.release();
+        read
+    }
+}
+
+impl Seek for Permitted {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+struct Limited {
+    inner: BufReader<Permitted>,
+    seekable: bool,
+    len: Option<u64>,
+}
+
+impl Read for Limited {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Seek for Limited {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl MediaSource for Limited {
+    fn is_seekable(&self) -> bool {
+        self.seekable
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.len
+    }
+}
@@ -1,51 +1,240 @@
-use ebur128::{EbuR128, Mode};
+mod album;
+mod albums;
+mod bench;
+mod cli;
+mod compare;
+mod config;
+mod daemon;
+mod dashboard;
+mod export;
+mod failed;
+mod gains;
+mod hist;
+mod histogram;
+mod import;
+mod interrupt;
+mod limit;
+mod mmap;
+mod nice;
+mod outliers;
+mod output;
+mod playlist;
+mod progress;
+mod report;
+mod selftest;
+mod serve;
+mod stats;
+mod status;
+mod summary;
+mod tag;
+mod template;
+mod terminal;
+mod top;
+mod validate;
+mod zip;
+
+use cli::{Args, Command, OutputFormat, Schedule, SortKey};
+use limit::IoLimit;
+use log::{error, info, log_enabled, warn, Level};
+use loudness::{
+    cache_format_of, cache_key, load_cache, save_cache_as, BlockHistogram, CacheFormat,
+    CancelToken, ErrorKind, MeasureError, MeasureOptions, Measurement,
+};
+use progress::{InOrder, Progress};
 use rayon::prelude::*;
+use report::{Field, Outcome, Row, Timing};
+use std::any::Any;
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::error::Error as _;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use std::sync::RwLock;
-use std::time::SystemTime;
-use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::DecoderOptions;
-use symphonia::core::errors::Error;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
-
-#[derive(Clone, Copy)]
-struct Measurement {
-    loudness: f64,
-    energy: f64,
-}
-merde::derive! {
-    impl (Deserialize, JsonSerialize) for struct Measurement { loudness, energy }
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use summary::{Failure, Summary};
+
+/// Exit codes, documented in `cli::HELP`.
+const EXIT_FAILED: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+
+const EXIT_OUTFILE: u8 = 3;
+const EXIT_NO_FILES: u8 = 4;
+/// 128 + SIGINT, as shells report a process killed by Ctrl-C.
+const EXIT_INTERRUPTED: u8 = 130;
+
+fn main() -> ExitCode {
+    let command = match Command::parse(std::env::args().skip(1)) {
+        Ok(c) => c,
+        // the full usage when nothing was given, otherwise what was wrong
+        Err(e) if e.starts_with("usage:") => {
+            eprintln!("{e}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n(run `loudness --help` for usage)");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let result = match command {
+        Command::PrintConfig(args, path) => {
+            config::print(&args, path.as_deref());
+            return ExitCode::SUCCESS;
+        }
+        Command::Help(None) => {
+            println!("{}\n\n{}", cli::USAGE, cli::HELP);
+            return ExitCode::SUCCESS;
+        }
+        Command::Help(Some("measure")) => {
+            println!("{}\n\n{}", cli::usage_of("measure").unwrap(), cli::HELP);
+            return ExitCode::SUCCESS;
+        }
+        Command::Help(Some(command)) => {
+            println!("{}", cli::usage_of(command).unwrap());
+            println!("\n(run `loudness --help` for the other subcommands and more detail)");
+            return ExitCode::SUCCESS;
+        }
+        Command::Measure(args) => {
+            output::init(args.log_level, args.color);
+            return match run(args) {
+                Ok(code) => code,
+                Err(e) => {
+                    error!("{e}");
+                    ExitCode::from(EXIT_FAILED)
+                }
+            };
+        }
+        Command::Tag(args) => tag::tag(&args),
+        Command::Untag(args) => tag::untag(&args),
+        Command::VerifyTags(args) => tag::verify(&args),
+        Command::Gains(args) => gains::run(&args),
+        Command::Top(args) => top::run(&args),
+        Command::Stats(args) => stats::run(&args),
+        Command::Hist(args) => hist::run(&args),
+        Command::Outliers(args) => outliers::run(&args),
+        Command::Status(args) => status::run(&args),
+        Command::Serve(args) => {
+            output::init(None, output::ColorChoice::Auto);
+            serve::run(&args)
+        }
+        Command::Daemon(args) => {
+            output::init(None, output::ColorChoice::Auto);
+            daemon::run(&args)
+        }
+        Command::Client(args) => daemon::client(&args),
+        Command::Playlist(args) => {
+            output::init(None, output::ColorChoice::Auto);
+            playlist::run(&args)
+        }
+        Command::Import(args) => {
+            output::init(None, output::ColorChoice::Auto);
+            import::run(&args)
+        }
+        Command::Export(args) => {
+            output::init(None, output::ColorChoice::Auto);
+            export::run(&args)
+        }
+        Command::Compare(args) => {
+            output::init(None, output::ColorChoice::Auto);
+            compare::run(&args)
+        }
+        Command::Schema => {
+            println!("{}", loudness::cache_schema());
+            return ExitCode::SUCCESS;
+        }
+        Command::SelfTest => selftest::run(),
+        Command::Validate(args) => {
+            output::init(None, output::ColorChoice::Auto);
+            validate::run(&args)
+        }
+        Command::Albums(args) => {
+            output::init(None, output::ColorChoice::Auto);
+            albums::run(&args)
+        }
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_FAILED),
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(EXIT_FAILED)
+        }
+    }
 }
 
-fn main() -> std::io::Result<()> {
-    let mut args = std::env::args();
-    let input = args
-        .nth(1)
-        .expect("usage: loudness <file/directory> [outfile]");
-    let maybe_outfile = args.next();
+fn run(args: Args) -> std::io::Result<ExitCode> {
+    let input = args.input;
+    let maybe_outfile = args.outfile;
+
+    let cache_format = match (&maybe_outfile, args.cache_format) {
+        (_, Some(format)) => format,
+        (Some(outfile), None) => match CacheFormat::from_path(Path::new(outfile)) {
+            Ok(format) => format,
+            Err(e) => {
+                error!("can't write the outfile '{outfile}': {e}, or pick one with --cache-format");
+                return Ok(ExitCode::from(EXIT_USAGE));
+            }
+        },
+        (None, None) => CacheFormat::Json,
+    };
+
+    if let Some(outfile) = &maybe_outfile {
+        if let Some(why) = audio_outfile(Path::new(outfile), &args.extensions) {
+            if !args.force_outfile {
+                error!("refusing to write the outfile '{outfile}': {why} (--force-outfile to write it anyway)");
+                return Ok(ExitCode::from(EXIT_USAGE));
+            }
+        }
+        // found now rather than when saving after hours of measuring
+        if let Err(e) = check_writable(Path::new(outfile)) {
+            error!("can't write the outfile '{outfile}': {e}");
+            return Ok(ExitCode::from(EXIT_OUTFILE));
+        }
+    }
 
     let data = if let Some(outfile) = &maybe_outfile {
         let outfile = Path::new(&outfile);
         if outfile.exists() {
+            // told from what's in it, so a file is never read as another format
+            match cache_format_of(outfile) {
+                Ok(Some(found)) if found != cache_format => {
+                    error!(
+                        "refusing to write the outfile '{}' as {cache_format}: it holds a {found} \
+                         cache (--cache-format {found} keeps adding to it as {found})",
+                        outfile.display()
+                    );
+                    return Ok(ExitCode::from(EXIT_OUTFILE));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    error!("failed to read outfile '{}': {e}", outfile.display());
+                    return Ok(ExitCode::from(EXIT_OUTFILE));
+                }
+            }
             // load existing items
-            let mut serialized = String::new();
-            File::open(outfile)?
-                .read_to_string(&mut serialized)
-                .expect("failed to read outfile");
-            let deserialized: HashMap<String, Measurement> =
-                match merde::json::from_str(&serialized) {
-                    Ok(d) => d,
-                    Err(e) => {
-                        eprintln!("malformed outfile: {e:?}");
-                        return Ok(());
+            let deserialized = match load_cache(outfile) {
+                Ok(d) => d,
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData && args.strict_cache => {
+                    error!("malformed outfile: {e}");
+                    return Ok(ExitCode::from(EXIT_OUTFILE));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    match recover_cache(outfile) {
+                        Ok(d) => d,
+                        Err(e) => {
+                            error!("failed to set aside malformed outfile: {e}");
+                            return Ok(ExitCode::from(EXIT_OUTFILE));
+                        }
                     }
-                };
+                }
+                Err(e) => {
+                    error!("failed to read outfile '{}': {e}", outfile.display());
+                    return Ok(ExitCode::from(EXIT_OUTFILE));
+                }
+            };
             Some(RwLock::new(deserialized))
         } else {
             // create empty
@@ -56,12 +245,45 @@ fn main() -> std::io::Result<()> {
         None
     };
 
-    let path = Path::new(&input);
-    if !path.exists() {
-        eprintln!("Path '{}' does not exist.", path.display());
-        return Ok(());
+    // before any worker exists, since priorities are inherited by new threads
+    let mut threads = args.threads;
+    if args.nice {
+        nice::lower_priority();
+        if threads == 0 && std::env::var_os("RAYON_NUM_THREADS").is_none() {
+            threads = nice::default_threads();
+        }
     }
-    let files = if path.is_dir() {
+    if threads > 0 {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+        {
+            warn!("couldn't set up {threads} worker threads: {e}");
+        }
+    }
+
+    let path = Path::new(&input);
+    let mut summary = Summary::new();
+    let wanted = |path: &Path| {
+        path.extension().is_some_and(|e| {
+            let e = e.to_string_lossy().to_ascii_lowercase();
+            args.extensions.contains(&e)
+        })
+    };
+    // why entries were left out, for when none are left
+    let (mut not_files, mut other_extensions) = (0, 0);
+    let mut files = if let Some(list) = &args.files_from {
+        match failed::read(Path::new(list)) {
+            Ok(files) => {
+                summary.scanned = files.len();
+                files
+            }
+            Err(e) => {
+                error!("failed to read --files-from '{list}': {e}");
+                return Ok(ExitCode::from(EXIT_USAGE));
+            }
+        }
+    } else if path.is_dir() {
         // multi-file
         let mut tmp = vec![];
         let contents = std::fs::read_dir(path)?;
@@ -69,199 +291,723 @@ fn main() -> std::io::Result<()> {
         for entry in contents {
             let entry = entry?;
             let path = entry.path();
-            if path.is_file() && path.extension().is_some_and(|p| p == "mp3") {
+            summary.scanned += 1;
+            if !path.is_file() {
+                not_files += 1;
+            } else if zip::is_archive(&path) {
+                let entries = archive_files(&path, wanted);
+                if !entries.is_empty() {
+                    tmp.extend(entries);
+                    continue;
+                }
+                other_extensions += 1;
+            } else if !wanted(&path) {
+                other_extensions += 1;
+            } else {
                 tmp.push(path);
+                continue;
             }
+            summary.filtered += 1;
         }
         tmp
     } else {
+        summary.scanned = 1;
         // single file
-        vec![path.to_path_buf()]
+        let files = if zip::is_archive(path) {
+            archive_files(path, wanted)
+        } else if wanted(path) {
+            vec![path.to_path_buf()]
+        } else {
+            vec![]
+        };
+        if !files.is_empty() {
+            files
+        } else {
+            other_extensions = 1;
+            summary.filtered = 1;
+            vec![]
+        }
     };
-
-    let ts = SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_millis();
+    files.sort();
+    if files.is_empty() {
+        let why = if let Some(list) = &args.files_from {
+            format!("'{list}' lists no files")
+        } else if path.is_dir() {
+            format!(
+                "'{input}' has {} entries: {other_extensions} with other extensions, \
+                 {not_files} not regular files",
+                summary.scanned
+            )
+        } else {
+            format!("'{input}' doesn't have one of the measured extensions")
+        };
+        let message = format!(
+            "no files to measure: {why} (measuring {}; see --extensions)",
+            args.extensions.join(",")
+        );
+        if !args.allow_empty {
+            error!("{message}");
+            return Ok(ExitCode::from(EXIT_NO_FILES));
+        }
+        warn!("{message}");
+    }
 
     let maybe_outfile_path = maybe_outfile.as_ref().map(Path::new);
+    if let Some(outfile) = maybe_outfile_path.and_then(|o| std::path::absolute(o).ok()) {
+        let measured = files
+            .iter()
+            .any(|f| std::path::absolute(f).is_ok_and(|f| f == outfile));
+        if measured && !args.force_outfile {
+            error!(
+                "refusing to write the outfile '{}': it's one of the files to measure \
+                 (--force-outfile to write it anyway)",
+                outfile.display()
+            );
+            return Ok(ExitCode::from(EXIT_USAGE));
+        }
+    }
+    // a single file's JSON result isn't keyed by name
+    let single = args.files_from.is_none() && !path.is_dir();
+
+    // derived once, so a lossy name is only warned about once
+    let mut keys: Vec<String> = files
+        .iter()
+        .map(|f| zip::key(f).unwrap_or_else(|| cache_key(f)))
+        .collect();
+    // cancels the files in progress too, on Ctrl-C
+    let cancel = CancelToken::new();
+    let mut options = MeasureOptions::new().with_cancel_token(cancel.clone());
+    if let Some(timeout) = args.timeout {
+        options = options.with_timeout(timeout);
+    }
+    if let Some(bytes) = args.read_buffer {
+        options = options.with_read_buffer(bytes);
+    }
+    if let Some(max) = args.max_decode_errors {
+        options = options.with_max_decode_errors(max);
+    }
+    options = options.with_short_files(args.short_files);
+    if let Some(percent) = args.duration_tolerance {
+        options = options.with_duration_tolerance(percent / 100.0);
+    }
+    if let Some(track) = args.track {
+        options = options.with_track(track);
+    }
+    if let Some(language) = &args.language {
+        options = options.with_language(language);
+    }
+    if args.max_decode_error_fraction < 1.0 {
+        options = options.with_max_decode_error_fraction(args.max_decode_error_fraction);
+    }
+    if let Some(start) = args.start {
+        options = options.with_start(start);
+    }
+    if let Some(duration) = args.duration {
+        options = options.with_duration(duration);
+    }
+    if let Some(map) = &args.channel_map {
+        options = options.with_channel_map(map.clone());
+    }
+    options = options.with_psr(args.psr);
+    // checked against the cache once, up front, so the workers only get new files
+    let mut cached: Vec<bool> = match &data {
+        Some(d) => {
+            let d = d.read().unwrap();
+            keys.iter()
+                .map(|key| d.get(key).is_some_and(|m| m.matches(&options)))
+                .collect()
+        }
+        None => vec![false; files.len()],
+    };
+    let mut pending = cached.iter().filter(|&&c| !c).count();
+    if args.limit > 0 && pending > args.limit {
+        // cached files are kept; they're only reported, not measured
+        let mut allowed = args.limit;
+        let keep: Vec<bool> = cached
+            .iter()
+            .map(|&cached| {
+                if cached {
+                    return true;
+                }
+                if allowed == 0 {
+                    return false;
+                }
+                allowed -= 1;
+                true
+            })
+            .collect();
+        retain_marked(&mut files, &keep);
+        retain_marked(&mut keys, &keep);
+        retain_marked(&mut cached, &keep);
+        summary.limited(args.limit, pending - args.limit);
+        pending = args.limit;
+    }
+    if pending < files.len() {
+        info!(
+            "{} already measured, {pending} to do",
+            files.len() - pending
+        );
+    }
+    // sizes of the files to measure, from metadata alone; cached files stay 0
+    let sizes: Option<Vec<u64>> = (!args.no_precount).then(|| {
+        files
+            .par_iter()
+            .zip(&cached)
+            .map(|(f, &cached)| if cached { 0 } else { file_size(f) })
+            .collect()
+    });
+    // set on the first failure under --fail-fast, or when the run is
+    // stopped; files already being measured finish, nothing new starts
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut progress = Progress::new(pending, args.tui || log_enabled!(Level::Warn));
+    if let Some(sizes) = &sizes {
+        progress.count_bytes(sizes.iter().sum());
+    }
+    if args.tui {
+        // q stops the run the way Ctrl-C does
+        let stop = Arc::clone(&stop);
+        let cancel = cancel.clone();
+        progress.open_dashboard(move || {
+            stop.store(true, Ordering::Relaxed);
+            cancel.cancel();
+        });
+    }
+    // shared with the per-file progress callbacks
+    let progress = Arc::new(progress);
+    let size_of = |i: usize, f: &Path| match &sizes {
+        Some(sizes) => sizes[i],
+        None => file_size(f),
+    };
+    // in structured modes stdout carries only data
+    let show_results = log_enabled!(Level::Info) && !args.format.is_structured();
+    // any order other than input order needs every result before printing
+    let sorted = args.sort != SortKey::Name || args.reverse;
+    let collected: Mutex<Vec<Row>> = Mutex::new(vec![]);
+    let held_lines: Mutex<Vec<Option<String>>> = Mutex::new(vec![None; files.len()]);
+    let collect = |i: usize, name: &str, outcome: Outcome, timing: Option<Timing>, cached: bool| {
+        if args.format == OutputFormat::Ndjson {
+            let line = report::ndjson_line(i, name, &outcome, timing.as_ref(), cached);
+            progress.println(&line);
+        }
+        progress.result(name, &outcome, cached);
+        if args.format.is_buffered() || sorted || args.bench {
+            collected.lock().unwrap().push(Row {
+                index: i,
+                key: name.to_string(),
+                outcome,
+                timing,
+            });
+        }
+    };
+
+    let in_order = InOrder::new(&progress, !args.unordered);
 
-    files.par_iter().enumerate().for_each(|(i, f)| {
-        //let name = &f.to_str().unwrap().to_string();
-        let name = &f.file_stem().unwrap().to_str().unwrap().to_string();
+    // measures one file, returning the line to print for it, if any
+    // counted separately from the summary so the check stays lock-free
+    let errors = AtomicUsize::new(0);
+    // without --io-jobs every worker streams its own file, as before
+    let io_limit = args.io_jobs.map(|jobs| Arc::new(IoLimit::new(jobs)));
+    // a lone file would leave the other workers idle, so it gets two threads
+    if pending == 1 && rayon::current_num_threads() > 1 {
+        options = options.with_pipeline(true);
+    }
+    if interrupt::install() {
+        let stop = Arc::clone(&stop);
+        let cancel = cancel.clone();
+        std::thread::spawn(move || {
+            while !interrupt::interrupted() {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            warn!("interrupted, saving what's been measured (Ctrl-C again to quit now)");
+            stop.store(true, Ordering::Relaxed);
+            cancel.cancel();
+        });
+    }
+    let process = |i: usize, f: &PathBuf, name: &String| -> Option<String> {
+        if stop.load(Ordering::Relaxed) {
+            summary.not_started();
+            return None;
+        }
+        // a notice, not a result, so it goes to stderr with the diagnostics
+        let skip = || {
+            if show_results {
+                info!("[{}] {}: skipping", i, name);
+            }
+            None
+        };
+        // already settled before the workers started; this catches files
+        // another worker measured under the same key since
         if let Some(d) = &data {
-            if d.read().unwrap().contains_key(name) {
-                println!("[{}] {}: skipping", i, name);
-                return;
+            let d = d.read().unwrap();
+            if let Some(cached) = d.get(name).filter(|m| m.matches(&options)) {
+                summary.cached();
+                collect(i, name, Ok(cached.clone()), None, true);
+                return skip();
             }
         }
-        if let Ok(measurement) = measure(f) {
-            if let Some(d) = &data {
-                if d.read().unwrap().contains_key(name) {
-                    println!("[{}] {}: skipping", i, name);
-                    return;
+        progress.start(name, sizes.as_ref().map_or(0, |s| s[i]));
+        let options = {
+            let progress = Arc::clone(&progress);
+            let name = name.clone();
+            options.clone().with_progress(move |p| {
+                if let Some(fraction) = p.fraction() {
+                    progress.update(&name, fraction);
                 }
-                d.write()
-                    .expect("failed to acquire lock")
-                    .insert(name.clone(), measurement);
-
-                // only save sometimes
-                if i % 10 == 0 {
-                    save(&d.read().unwrap(), maybe_outfile_path.unwrap()).unwrap();
+            })
+        };
+        // filled in by the measurement under --bench
+        let stages = args.bench.then(|| Arc::new(Mutex::new(None)));
+        let options = match &stages {
+            Some(stages) => {
+                let stages = Arc::clone(stages);
+                options.with_breakdown(move |b| *stages.lock().unwrap() = Some(b))
+            }
+            None => options,
+        };
+        // filled in by the measurement under --histogram-dir
+        let histogram = args
+            .histogram_dir
+            .is_some()
+            .then(|| Arc::new(Mutex::new(None)));
+        let options = match &histogram {
+            Some(slot) => {
+                let slot = Arc::clone(slot);
+                let (min, max) = args.histogram_range;
+                options.with_block_histogram(BlockHistogram::new(min, max), move |h| {
+                    *slot.lock().unwrap() = Some(h)
+                })
+            }
+            None => options,
+        };
+        // only timed when asked for, to keep the normal path free of syscalls
+        let started = (args.timings || args.bench).then(Instant::now);
+        // a panic deep in a decoder fails this file rather than the whole run
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            measure_with(f, io_limit.as_ref(), args.mmap, &options)
+        }))
+        .unwrap_or_else(|panic| {
+            Err(MeasureError::from(ErrorKind::Panicked(panic_message(&*panic))).in_file(f))
+        });
+        let timing = started.map(|started| Timing {
+            wall: started.elapsed(),
+            bytes: size_of(i, f),
+            stages: stages.and_then(|s| s.lock().unwrap().take()),
+        });
+        progress.finish(name);
+        if result.as_ref().is_err_and(MeasureError::is_cancelled) {
+            summary.not_started();
+            return None;
+        }
+        let failure = result
+            .as_ref()
+            .err()
+            .map(|e| (failure_class(e.kind()), e.stage().name()));
+        let result = result.map_err(|e| reason(&e));
+        if let Some(timing) = &timing {
+            summary.timed(timing);
+        }
+        collect(i, name, result.clone(), timing, false);
+        let measurement = match result {
+            Ok(m) => m,
+            Err(e) => {
+                warn!("{}: {e}", f.display());
+                let (class, stage) = failure.unwrap_or_default();
+                summary.failed(Failure {
+                    path: f.clone(),
+                    class,
+                    stage,
+                    reason: e,
+                });
+                let failures = errors.fetch_add(1, Ordering::Relaxed) + 1;
+                if args.fail_fast && !stop.swap(true, Ordering::Relaxed) {
+                    error!("stopping after '{}' failed (--fail-fast)", f.display());
+                }
+                if let Some(max) = args.max_errors {
+                    if failures > max && !stop.swap(true, Ordering::Relaxed) {
+                        error!("stopping after {failures} failed file(s) (--max-errors {max})");
+                    }
+                }
+                return None;
+            }
+        };
+        if let Some(d) = &data {
+            let measured = d
+                .read()
+                .unwrap()
+                .get(name)
+                .is_some_and(|m| m.matches(&options));
+            if measured {
+                summary.cached();
+                return skip();
+            }
+            d.write()
+                .expect("failed to acquire lock")
+                .insert(name.clone(), measurement.clone());
+
+            // only save sometimes
+            if i.is_multiple_of(10) {
+                let outfile = maybe_outfile_path.unwrap();
+                if let Err(e) = save_cache_as(&d.read().unwrap(), outfile, cache_format) {
+                    error!("failed to write outfile '{}': {e}", outfile.display());
                 }
             }
-            println!(
-                "[{}] {}: \t{:.2} LUFS\t{:.2} energy",
-                i, name, measurement.loudness, measurement.energy
-            )
         }
-    });
-
-    if let Some(d) = &data {
-        // data only exists if an outfile is specified
-        // this seems kinda mid
-        save(&d.read().unwrap(), maybe_outfile_path.unwrap())?;
-    }
-
-    Ok(())
-}
-
-fn save(d: &HashMap<String, Measurement>, to: &Path) -> std::io::Result<()> {
-    let mut file = File::create(to)?;
-    let serialized = merde::json::to_string(d);
-    file.write_all(serialized.as_bytes())?;
-    Ok(())
-}
-
-fn measure(path: &PathBuf) -> Result<Measurement, ()> {
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(e) => {
-            eprintln!(
-                "failed to open file '{}' for measurement: {e:?}",
-                path.display()
+        let histogram = histogram.and_then(|h| h.lock().unwrap().take());
+        if let (Some(dir), Some(histogram)) = (&args.histogram_dir, histogram) {
+            if let Err(e) = histogram::write(dir, name, &histogram) {
+                warn!(
+                    "{}: failed to write histogram '{}': {e}",
+                    f.display(),
+                    histogram::path_for(dir, name).display()
+                );
+            }
+        }
+        summary.measured(measurement.duration);
+        if measurement.short == Some(true) {
+            summary.short();
+        }
+        if !show_results {
+            return None;
+        }
+        let precision = args.precision.unwrap_or(cli::DEFAULT_PRECISION);
+        let mut loudness = args
+            .units
+            .format_with(measurement.loudness, args.target, precision);
+        if let Some(target) = args.target {
+            loudness = output::color(
+                &loudness,
+                output::distance_color(measurement.loudness, target),
             );
-            return Err(());
         }
+        // a template controls the whole line
+        if let Some(template) = &args.template {
+            let line = template.render(name, &measurement, args.units, args.target, precision);
+            return Some(line);
+        }
+        let mut line = match &args.fields {
+            None => format!(
+                "[{}] {}: \t{}\t{:.precision$} energy",
+                i, name, loudness, measurement.energy
+            ),
+            Some(fields) => {
+                let cells: Vec<_> = fields
+                    .iter()
+                    .map(|f| match f {
+                        Field::Loudness => loudness.clone(),
+                        f => f.display(name, &measurement, args.units, args.target, precision),
+                    })
+                    .collect();
+                cells.join("\t")
+            }
+        };
+        if measurement.short == Some(true) {
+            line += "\t(short)";
+        }
+        if args.fields.is_none() && log_enabled!(Level::Debug) {
+            if let Some(psr) = measurement.psr {
+                line += &format!("\tPSR {psr:.precision$} dB");
+            }
+            if let Some(psr_avg) = measurement.psr_avg {
+                line += &format!(" (mean {psr_avg:.precision$} dB)");
+            }
+            if let (Some(max), Some(at)) =
+                (measurement.momentary_max, measurement.momentary_max_at_secs)
+            {
+                line += &format!(
+                    "\tloudest {max:.precision$} LUFS at {}",
+                    report::timestamp(at)
+                );
+            }
+        }
+        if let Some(realtime) = timing.and_then(|t| t.realtime(&measurement)) {
+            line += &format!("\tx{realtime:.1} realtime");
+        }
+        Some(line)
     };
-    let file = Box::new(file);
-    let mss = MediaSourceStream::new(file, Default::default());
-    let hint = Hint::new();
-
-    // Use the default options when reading and decoding.
-    let format_opts: FormatOptions = Default::default();
-    let metadata_opts: MetadataOptions = Default::default();
-    let decoder_opts: DecoderOptions = Default::default();
-
-    // Probe the media source stream for a format.
-    let Ok(probed) =
-        symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts)
-    else {
-        eprintln!("failed to get probe for file '{}'", path.display());
-        return Err(());
-    };
-
-    // Get the format reader yielded by the probe operation.
-    let mut format = probed.format;
 
-    // Get the default track.
-    let track = match format.default_track() {
-        None => {
-            eprintln!("file '{}' has no tracks?", path.display());
-            return Err(());
+    // cached files are reported here without a task, or a "skipping" line, each
+    if let Some(d) = &data {
+        let d = d.read().unwrap();
+        for (i, name) in keys.iter().enumerate().filter(|&(i, _)| cached[i]) {
+            summary.cached();
+            collect(i, name, Ok(d[name].clone()), None, true);
+            if !sorted {
+                in_order.complete(i, None);
+            }
+        }
+    }
+    let mut todo: Vec<usize> = (0..files.len()).filter(|&i| !cached[i]).collect();
+    if args.schedule == Schedule::Size {
+        // stable, so files of equal size keep their input order
+        todo.sort_by_cached_key(|&i| Reverse(size_of(i, &files[i])));
+    }
+    let run = |&i: &usize| {
+        let line = process(i, &files[i], &keys[i]);
+        if sorted {
+            held_lines.lock().unwrap()[i] = line;
+        } else {
+            in_order.complete(i, line);
         }
-        Some(t) => t.clone(),
     };
+    match args.schedule {
+        // par_iter would split the list into ranges and start several in
+        // parallel, small ones among them; this hands them out in order
+        Schedule::Size => todo.iter().par_bridge().for_each(run),
+        Schedule::Name => todo.par_iter().for_each(run),
+    }
+    progress.clear();
+
+    let mut results = collected.into_inner().unwrap();
+    results.sort_by_key(|row| row.index);
+    if sorted {
+        report::sort(&mut results, args.sort, args.reverse);
+        let mut held_lines = held_lines.into_inner().unwrap();
+        for row in &results {
+            if let Some(line) = held_lines[row.index].take() {
+                println!("{line}");
+            }
+        }
+    }
 
-    // Create a decoder for the track.
-    let mut decoder =
-        match symphonia::default::get_codecs().make(&track.codec_params, &decoder_opts) {
-            Ok(d) => d,
-            Err(e) => {
-                eprintln!(
-                    "failed to create decoder for file '{}' - {e:?}",
-                    path.display()
+    if args.format.is_buffered() {
+        match args.format {
+            OutputFormat::Json => println!("{}", report::json(&results, single)),
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                let delimiter = if args.format == OutputFormat::Csv {
+                    ','
+                } else {
+                    '\t'
+                };
+                print!(
+                    "{}",
+                    report::delimited(
+                        &results,
+                        delimiter,
+                        args.fields.as_deref().unwrap_or(&Field::DEFAULT),
+                        args.units,
+                        args.target,
+                        args.precision,
+                        args.include_failed
+                    )
                 );
-                return Err(());
             }
-        };
+            _ => {}
+        }
+    }
 
-    // Store the track identifier, we'll use it to filter packets.
-    let track_id = track.id;
+    let mut written = None;
+    if let Some(d) = &data {
+        // data only exists if an outfile is specified
+        // this seems kinda mid
+        let d = d.read().unwrap();
+        let outfile = maybe_outfile_path.unwrap();
+        if let Err(e) = save_cache_as(&d, outfile, cache_format) {
+            error!("failed to write outfile '{}': {e}", outfile.display());
+            return Ok(ExitCode::from(EXIT_OUTFILE));
+        }
+        written = Some((outfile, d.len()));
+    }
 
-    let channels = track.codec_params.channels.unwrap().count();
+    if let Some(failed_out) = &args.failed_out {
+        // written even when empty, so retrying from it doesn't repeat old failures
+        if let Err(e) = failed::write(&summary.failures(), Path::new(failed_out)) {
+            error!("failed to write --failed-out '{failed_out}': {e}");
+            return Ok(ExitCode::from(EXIT_OUTFILE));
+        }
+    }
 
-    let rate = track
-        .codec_params
-        .sample_rate
-        .expect("has no sample rate??");
+    if log_enabled!(Level::Warn) {
+        eprint!("{}", summary.render(written));
+    }
+    if args.bench {
+        eprint!("{}", bench::render(&results, summary.elapsed()));
+    }
+    if let Some(max) = args.max_errors {
+        if summary.failed_count() > max {
+            error!(
+                "more than {max} files failed; check that '{}' is the right input",
+                input
+            );
+        }
+    }
 
-    let mut ebur128 =
-        EbuR128::new(channels as u32, rate, Mode::all()).expect("Failed to create ebur128");
+    if interrupt::interrupted() {
+        return Ok(ExitCode::from(EXIT_INTERRUPTED));
+    }
+    if summary.failed_count() > 0 {
+        return Ok(ExitCode::from(EXIT_FAILED));
+    }
+    Ok(ExitCode::SUCCESS)
+}
 
-    let chunk_size = rate; // 1s
+/// Keeps the items of `items` whose entry in `keep` is set.
+fn retain_marked<T>(items: &mut Vec<T>, keep: &[bool]) {
+    let mut keep = keep.iter();
+    items.retain(|_| *keep.next().unwrap());
+}
 
-    //println!("{:?}", samples.samples().chunks(100).nth(5).unwrap())
+/// Measures `path` with the default options for the daemon, server and
+/// `compare`, failing it rather than the process if a decoder panics, as
+/// these run on rayon's pool, which aborts on a panic.
+fn measure(path: &Path) -> Result<Measurement, String> {
+    catch_unwind(AssertUnwindSafe(|| {
+        measure_with(path, None, false, &MeasureOptions::default())
+    }))
+    .unwrap_or_else(|panic| {
+        Err(MeasureError::from(ErrorKind::Panicked(panic_message(&*panic))).in_file(path))
+    })
+    .map_err(|e| reason(&e))
+}
 
-    while let Ok(packet) = format.next_packet() {
-        // If the packet does not belong to the selected track, skip it.
-        if packet.track_id() != track_id {
-            continue;
-        }
+/// What kind of failure `kind` is, for counting failures by kind.
+fn failure_class(kind: &ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::Open(_) => "unreadable",
+        ErrorKind::Empty => "empty",
+        ErrorKind::Probe(_) => "unrecognized format",
+        ErrorKind::NoTrack => "no audio track",
+        ErrorKind::Decoder(_) => "no decoder",
+        ErrorKind::TooCorrupt { .. } => "too corrupt",
+        ErrorKind::TooShort { .. } => "too short",
+        ErrorKind::NoAudio => "no audio decoded",
+        ErrorKind::Unsupported { .. } => "unsupported channels or rate",
+        ErrorKind::Timeout(_) => "timed out",
+        ErrorKind::Cancelled => "cancelled",
+        ErrorKind::SampleCount { .. } => "bad sample count",
+        ErrorKind::ChannelMap { .. } => "channel map mismatch",
+        ErrorKind::ChannelsChanged { .. } => "channel count changed",
+        ErrorKind::Analysis(_) => "measurement failed",
+        ErrorKind::Panicked(_) => "panicked",
+    }
+}
 
-        // Decode the packet into audio samples, ignoring any decode errors.
-        match decoder.decode(&packet) {
-            Ok(decoded) => {
-                let spec = *decoded.spec();
+/// The entries of a zip archive to measure, or the archive itself if it
+/// can't be read, so it fails with the reason when it's measured.
+fn archive_files(archive: &Path, wanted: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    zip::expand(archive, wanted).unwrap_or_else(|_| vec![archive.to_path_buf()])
+}
 
-                if decoded.frames() > 0 {
-                    let mut sample_buffer: SampleBuffer<f32> =
-                        SampleBuffer::new(decoded.frames() as u64, spec);
+/// Size of a file, or of an archive entry uncompressed; 0 if unknown.
+fn file_size(path: &Path) -> u64 {
+    zip::size(path)
+        .or_else(|| std::fs::metadata(path).ok().map(|m| m.len()))
+        .unwrap_or(0)
+}
 
-                    sample_buffer.copy_interleaved_ref(decoded);
-                    ebur128
-                        .add_frames_f32(sample_buffer.samples())
-                        .expect("Failed to add frames");
-                    ebur128
-                        .loudness_global()
-                        .expect("Failed to get global loudness");
-                } else {
-                    eprintln!("Empty packet encountered while loading song!");
-                }
-            }
-            Err(Error::DecodeError(e)) => {
-                eprintln!("decode error... {e:?}");
-            }
-            Err(Error::IoError(e)) => {
-                if matches!(e.kind(), std::io::ErrorKind::UnexpectedEof) {
-                    // end of stream
-                    eprintln!("end of stream during decode!");
-                } else {
-                    eprintln!("io error.... {e:?}");
-                }
-                break;
-            }
-            Err(e) => {
-                eprintln!("other error... {e:?}");
-                break;
-            }
-        }
+/// Extensions of audio files, refused as an outfile along with `--extensions`.
+pub(crate) const AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "flac", "wav", "ogg", "oga", "opus", "m4a", "m4b", "mp4", "aac", "alac", "aif", "aiff",
+    "wma", "wv", "ape", "mka", "webm", "caf",
+];
+
+/// Why `outfile` looks like audio rather than a cache, if it does: an audio
+/// extension, or an existing file that probes as audio.
+fn audio_outfile(outfile: &Path, extensions: &[String]) -> Option<&'static str> {
+    let extension = outfile
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    if extension.is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.as_str()) || extensions.contains(&e))
+    {
+        return Some("it has an audio file extension");
+    }
+    if outfile.is_file() && loudness::is_audio_file(outfile) {
+        return Some("it's an audio file");
+    }
+    None
+}
+
+/// Checks that `outfile` can be written without changing it: opened for
+/// writing if it exists, or created and removed again if not.
+fn check_writable(outfile: &Path) -> std::io::Result<()> {
+    if outfile.exists() {
+        return std::fs::OpenOptions::new()
+            .append(true)
+            .open(outfile)
+            .map(drop);
     }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(outfile)?;
+    std::fs::remove_file(outfile)
+}
 
-    let global_loudness = ebur128
-        .loudness_global()
-        .expect("Failed to get global loudness");
+/// Moves a malformed outfile aside to `<outfile>.corrupt-<unix time>` and
+/// returns the entries that could still be read from it.
+fn recover_cache(outfile: &Path) -> std::io::Result<HashMap<String, Measurement>> {
+    let salvaged = loudness::salvage_cache(outfile)?;
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut aside = outfile.as_os_str().to_owned();
+    aside.push(format!(".corrupt-{secs}"));
+    std::fs::rename(outfile, &aside)?;
+    warn!(
+        "outfile '{}' is malformed; moved it to '{}' and kept the entries that could \
+         be read ({}), the rest will be measured again (--strict-cache stops instead)",
+        outfile.display(),
+        Path::new(&aside).display(),
+        salvaged.len()
+    );
+    Ok(salvaged)
+}
 
-    let Some((_, energy)) = ebur128.gating_block_count_and_energy() else {
-        return Err(());
-    };
+/// The message a panic was raised with, when it was a string.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => payload
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "(no message)".to_string()),
+    }
+}
 
-    // Convert dB difference to linear gain
-    // let target_loudness = -14.0;
-    // let gain = 10f32.powf(((target_loudness - global_loudness) / 20.0) as f32);
+/// Why a file failed, without its path: what went wrong and its immediate
+/// cause, or every cause down the chain with -v.
+fn reason(e: &MeasureError) -> String {
+    let cause = if log_enabled!(Level::Debug) {
+        e.source().map(loudness::chain)
+    } else {
+        e.source().map(|s| s.to_string())
+    };
+    match cause {
+        Some(cause) => format!("{}: {cause}", e.kind()),
+        None => e.kind().to_string(),
+    }
+}
 
-    Ok(Measurement {
-        loudness: global_loudness,
-        energy,
-    })
+/// Measures `path`, reading it from its zip archive if it's in one, through
+/// `io_limit` when one is given, from a memory map with `mmap`, and
+/// streaming it from disk otherwise.
+fn measure_with(
+    path: &Path,
+    io_limit: Option<&Arc<IoLimit>>,
+    mmap: bool,
+    options: &MeasureOptions,
+) -> Result<Measurement, MeasureError> {
+    // lets the tests check that a panicking decoder only fails its own file
+    #[cfg(debug_assertions)]
+    if std::env::var_os("LOUDNESS_TEST_PANIC").is_some_and(|name| path.ends_with(name)) {
+        panic!("LOUDNESS_TEST_PANIC");
+    }
+    let source = match (zip::open(path), io_limit, mmap) {
+        (Some(entry), _, _) => entry,
+        (None, Some(limit), _) => limit.open(
+            path,
+            options.read_buffer.unwrap_or(loudness::DEFAULT_READ_BUFFER),
+        ),
+        (None, None, true) => mmap::open(path),
+        (None, None, false) => return loudness::measure_file(path, options),
+    };
+    source
+        .map_err(|e| ErrorKind::Open(e).into())
+        .and_then(|source| loudness::measure_source(source, loudness::hint_for(path), options))
+        .map(|mut measurement| {
+            measurement.path = std::path::absolute(path)
+                .ok()
+                .map(|p| p.to_string_lossy().into_owned());
+            measurement
+        })
+        .map_err(|e| e.in_file(path))
 }
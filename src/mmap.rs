@@ -0,0 +1,111 @@
+//! Memory-mapped input for --mmap.
+//!
+//! Decoding then reads straight from the page cache instead of copying
+//! through read calls. Files that can't be mapped are read normally.
+
+use log::debug;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use symphonia::core::io::MediaSource;
+
+/// Largest file mapped where the address space is 32 bits wide; bigger ones
+/// are read normally rather than risk running out of room.
+#[cfg(unix)]
+const MAX_MAPPED_32: u64 = 512 << 20;
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{c_int, c_long, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+    pub const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+    extern "C" {
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: c_long,
+        ) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+/// A read-only mapping of a whole file, unmapped on drop.
+#[cfg(unix)]
+struct Mapping {
+    ptr: *mut std::ffi::c_void,
+    len: usize,
+}
+
+// SAFETY: the mapping is private and read-only, and only unmapped on drop
+#[cfg(unix)]
+unsafe impl Send for Mapping {}
+#[cfg(unix)]
+unsafe impl Sync for Mapping {}
+
+#[cfg(unix)]
+impl AsRef<[u8]> for Mapping {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: `ptr` points to `len` mapped, readable bytes until drop
+        unsafe { std::slice::from_raw_parts(self.ptr.cast(), self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` and `len` are exactly what mmap returned and was given
+        unsafe { sys::munmap(self.ptr, self.len) };
+    }
+}
+
+#[cfg(unix)]
+fn map(file: &File) -> io::Result<Option<Mapping>> {
+    use std::os::fd::AsRawFd;
+
+    let len = file.metadata()?.len();
+    // mmap rejects empty mappings, and huge ones may not fit on 32 bits
+    if len == 0 || (usize::BITS < 64 && len > MAX_MAPPED_32) {
+        return Ok(None);
+    }
+    let len = len as usize;
+    // SAFETY: a fresh private read-only mapping of an open file; nothing
+    // else refers to the returned address
+    let ptr = unsafe {
+        sys::mmap(
+            std::ptr::null_mut(),
+            len,
+            sys::PROT_READ,
+            sys::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if ptr == sys::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some(Mapping { ptr, len }))
+}
+
+/// Opens `path` as a mapped source, falling back to the plain file when it
+/// can't be mapped.
+pub fn open(path: &Path) -> io::Result<Box<dyn MediaSource>> {
+    let file = File::open(path)?;
+    #[cfg(unix)]
+    match map(&file) {
+        Ok(Some(mapping)) => return Ok(Box::new(io::Cursor::new(mapping))),
+        Ok(None) => debug!("{}: not mapping, reading normally", path.display()),
+        Err(e) => debug!("{}: mapping failed, reading normally: {e}", path.display()),
+    }
+    #[cfg(not(unix))]
+    debug!(
+        "{}: --mmap isn't supported here, reading normally",
+        path.display()
+    );
+    Ok(Box::new(file))
+}
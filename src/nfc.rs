@@ -0,0 +1,140 @@
+//! Unicode NFC normalization for cache keys, so a name decomposed by one
+//! filesystem (macOS stores accents as separate combining marks) and
+//! composed by another give the same key.
+
+mod tables;
+
+use std::borrow::Cow;
+use tables::{COMBINING_CLASSES, COMPOSITIONS, DECOMPOSITIONS};
+
+// Hangul syllables are composed and decomposed arithmetically rather than
+// through the tables.
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+/// `s` in Unicode Normalization Form C, borrowed when it already is, as
+/// ASCII always is.
+pub fn nfc(s: &str) -> Cow<'_, str> {
+    if s.is_ascii() {
+        return Cow::Borrowed(s);
+    }
+    let mut chars = vec![];
+    for c in s.chars() {
+        decompose(c, &mut chars);
+    }
+    reorder(&mut chars);
+    let composed: String = compose(chars).into_iter().collect();
+    match composed == s {
+        true => Cow::Borrowed(s),
+        false => Cow::Owned(composed),
+    }
+}
+
+/// Appends the full canonical decomposition of `c` to `out`.
+fn decompose(c: char, out: &mut Vec<char>) {
+    let s = c as u32;
+    if (S_BASE..S_BASE + S_COUNT).contains(&s) {
+        let index = s - S_BASE;
+        let l = L_BASE + index / N_COUNT;
+        let v = V_BASE + index % N_COUNT / T_COUNT;
+        let t = T_BASE + index % T_COUNT;
+        out.extend([l, v].into_iter().filter_map(char::from_u32));
+        if t != T_BASE {
+            out.extend(char::from_u32(t));
+        }
+        return;
+    }
+    match DECOMPOSITIONS.binary_search_by_key(&c, |&(from, _, _)| from) {
+        Ok(i) => {
+            let (_, first, second) = DECOMPOSITIONS[i];
+            decompose(first, out);
+            if second != '\0' {
+                decompose(second, out);
+            }
+        }
+        Err(_) => out.push(c),
+    }
+}
+
+fn combining_class(c: char) -> u8 {
+    let found = COMBINING_CLASSES.binary_search_by(|&(start, end, _)| {
+        if end < c {
+            std::cmp::Ordering::Less
+        } else if start > c {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    });
+    found.map_or(0, |i| COMBINING_CLASSES[i].2)
+}
+
+/// Puts each run of combining marks in canonical order: by combining class,
+/// keeping the original order within a class.
+fn reorder(chars: &mut [char]) {
+    let mut start = 0;
+    while start < chars.len() {
+        let len = chars[start..]
+            .iter()
+            .take_while(|&&c| combining_class(c) != 0)
+            .count();
+        chars[start..start + len].sort_by_key(|&c| combining_class(c));
+        start += len.max(1);
+    }
+}
+
+/// The character `first` and `second` canonically compose to, if any.
+fn compose_pair(first: char, second: char) -> Option<char> {
+    let (l, v) = (first as u32, second as u32);
+    if (L_BASE..L_BASE + L_COUNT).contains(&l) && (V_BASE..V_BASE + V_COUNT).contains(&v) {
+        let index = (l - L_BASE) * N_COUNT + (v - V_BASE) * T_COUNT;
+        return char::from_u32(S_BASE + index);
+    }
+    let (s, t) = (l, v);
+    if (S_BASE..S_BASE + S_COUNT).contains(&s)
+        && (s - S_BASE).is_multiple_of(T_COUNT)
+        && (T_BASE + 1..T_BASE + T_COUNT).contains(&t)
+    {
+        return char::from_u32(s + t - T_BASE);
+    }
+    COMPOSITIONS
+        .binary_search_by_key(&(first, second), |&(a, b, _)| (a, b))
+        .ok()
+        .map(|i| COMPOSITIONS[i].2)
+}
+
+/// Composes decomposed, reordered `chars`: each mark joins the last starter
+/// unless another character in between blocks it.
+fn compose(chars: Vec<char>) -> Vec<char> {
+    let mut out: Vec<char> = Vec::with_capacity(chars.len());
+    let mut starter = None;
+    // class of the last character kept since the starter, if any was
+    let mut last_class = None;
+    for c in chars {
+        let class = combining_class(c);
+        if let Some(at) = starter {
+            let blocked = last_class.is_some_and(|last| last == 0 || last >= class);
+            if !blocked {
+                if let Some(composed) = compose_pair(out[at], c) {
+                    out[at] = composed;
+                    continue;
+                }
+            }
+        }
+        if class == 0 {
+            starter = Some(out.len());
+            last_class = None;
+        } else {
+            last_class = Some(class);
+        }
+        out.push(c);
+    }
+    out
+}
@@ -0,0 +1,84 @@
+//! Lowering scheduling priority for --nice.
+//!
+//! On Linux both values are per thread and inherited by threads created
+//! later, so this has to run before the worker pool is built.
+
+use log::{debug, warn};
+
+/// Niceness applied with --nice, the same as `nice` uses by default.
+const NICENESS: i32 = 10;
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{c_int, c_long, c_uint};
+
+    pub const PRIO_PROCESS: c_int = 0;
+
+    extern "C" {
+        pub fn setpriority(which: c_int, who: c_uint, prio: c_int) -> c_int;
+        #[cfg(target_os = "linux")]
+        pub fn syscall(number: c_long, ...) -> c_long;
+    }
+
+    /// `ioprio_set` isn't wrapped by libc, so it's called by number.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    pub const SYS_IOPRIO_SET: Option<c_long> = Some(251);
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    pub const SYS_IOPRIO_SET: Option<c_long> = Some(30);
+    #[cfg(all(
+        target_os = "linux",
+        not(any(target_arch = "x86_64", target_arch = "aarch64"))
+    ))]
+    pub const SYS_IOPRIO_SET: Option<c_long> = None;
+
+    pub const IOPRIO_WHO_PROCESS: c_long = 1;
+    /// Idle class: only gets disk time when nothing else wants it.
+    pub const IOPRIO_CLASS_IDLE: c_long = 3;
+    pub const IOPRIO_CLASS_SHIFT: c_long = 13;
+}
+
+/// Lowers CPU and, where possible, I/O priority, warning about what couldn't be done.
+pub fn lower_priority() {
+    #[cfg(unix)]
+    {
+        // SAFETY: setpriority only reads its integer arguments
+        let result = unsafe { sys::setpriority(sys::PRIO_PROCESS, 0, NICENESS) };
+        if result == 0 {
+            debug!("--nice: CPU niceness set to {NICENESS}");
+        } else {
+            warn!(
+                "--nice: couldn't lower CPU priority: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    #[cfg(not(unix))]
+    warn!("--nice: lowering CPU priority isn't supported on this platform");
+
+    #[cfg(target_os = "linux")]
+    match sys::SYS_IOPRIO_SET {
+        Some(number) => {
+            let priority = sys::IOPRIO_CLASS_IDLE << sys::IOPRIO_CLASS_SHIFT;
+            // SAFETY: ioprio_set only reads its integer arguments
+            let result = unsafe { sys::syscall(number, sys::IOPRIO_WHO_PROCESS, 0, priority) };
+            if result == 0 {
+                debug!("--nice: I/O priority set to idle");
+            } else {
+                warn!(
+                    "--nice: couldn't lower I/O priority: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+        None => warn!("--nice: lowering I/O priority isn't supported on this architecture"),
+    }
+    #[cfg(not(target_os = "linux"))]
+    warn!("--nice: lowering I/O priority isn't supported on this platform");
+}
+
+/// Worker count used under --nice when --threads isn't given: half the cores.
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| (n.get() / 2).max(1))
+        .unwrap_or(1)
+}
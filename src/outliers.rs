@@ -0,0 +1,62 @@
+//! Entries whose loudness sticks out from the rest of the cache.
+
+use crate::cli::{OutlierRule, OutliersArgs, Units};
+use crate::stats::Distribution;
+use loudness::load_cache;
+use std::io;
+use std::path::Path;
+
+/// Lists outliers, returning false if any were found or the cache couldn't be read.
+pub fn run(args: &OutliersArgs) -> io::Result<bool> {
+    let cache = match load_cache(Path::new(&args.cache)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.cache);
+            return Ok(false);
+        }
+    };
+
+    let usable: Vec<(&str, f64)> = cache
+        .iter()
+        .filter(|(_, m)| m.loudness.is_finite())
+        .map(|(k, m)| (k.as_str(), m.loudness))
+        .collect();
+    let excluded = cache.len() - usable.len();
+    let library = Distribution::of(usable.iter().map(|(_, l)| *l).collect());
+
+    // deviation is measured from the mean for --sigma and the median for --delta
+    let (center, limit) = match args.rule {
+        OutlierRule::Sigma(sigma) => (library.mean, sigma * library.stddev),
+        OutlierRule::Delta(delta) => (library.median, delta),
+    };
+    let mut outliers: Vec<_> = usable
+        .into_iter()
+        .map(|(key, loudness)| (key, loudness, loudness - center))
+        .filter(|(_, _, deviation)| deviation.abs() > limit)
+        .collect();
+    outliers.sort_by(|(ka, _, a), (kb, _, b)| b.abs().total_cmp(&a.abs()).then(ka.cmp(kb)));
+
+    let width = outliers.iter().map(|(k, _, _)| k.chars().count()).max();
+    for (key, loudness, deviation) in &outliers {
+        println!(
+            "{key:<width$}  {:>11}  {:>+7.2} LU  gain {:>+7.2} dB",
+            Units::Lufs.format(*loudness, None),
+            deviation,
+            library.median - loudness,
+            width = width.unwrap_or(0),
+        );
+    }
+
+    eprintln!(
+        "{} outlier(s) among {} entries (mean {:.2} LUFS, median {:.2} LUFS, stddev {:.2} LU)",
+        outliers.len(),
+        library.count,
+        library.mean,
+        library.median,
+        library.stddev
+    );
+    if excluded > 0 {
+        eprintln!("excluded {excluded} without usable loudness");
+    }
+    Ok(outliers.is_empty())
+}
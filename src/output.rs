@@ -0,0 +1,167 @@
+//! Diagnostics on stderr through the `log` facade.
+//!
+//! Results go to stdout and are printed by the caller; everything logged goes
+//! to stderr. The level comes from -v/-q when given, otherwise from RUST_LOG,
+//! otherwise defaults to info. Only this crate's own messages follow -v;
+//! dependencies are silent unless RUST_LOG says otherwise, since the failures
+//! they log are reported by this crate anyway.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// When to emit ANSI colors, from --color.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Always,
+    Never,
+    /// Only when the stream is a terminal and NO_COLOR isn't set.
+    Auto,
+}
+
+impl ColorChoice {
+    fn enabled(self, tty: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => tty && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+        }
+    }
+}
+
+static STDOUT_COLOR: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+}
+
+fn paint(text: &str, color: Color) -> String {
+    let code = match color {
+        Color::Red => 31,
+        Color::Green => 32,
+        Color::Yellow => 33,
+    };
+    format!("\x1b[{code}m{text}\x1b[0m")
+}
+
+/// Colors text bound for stdout, if colors are enabled there.
+pub fn color(text: &str, color: Color) -> String {
+    if STDOUT_COLOR.load(Ordering::Relaxed) {
+        paint(text, color)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Color for a loudness reading by its distance from the target.
+pub fn distance_color(loudness: f64, target: f64) -> Color {
+    match (loudness - target).abs() {
+        d if d <= 1.0 => Color::Green,
+        d if d <= 3.0 => Color::Yellow,
+        _ => Color::Red,
+    }
+}
+
+struct Logger {
+    /// (target prefix, level) pairs; the longest matching prefix wins.
+    directives: Vec<(String, LevelFilter)>,
+    tty: bool,
+    color: bool,
+}
+
+impl Logger {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(LevelFilter::Off, |(_, level)| *level)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // clear a progress bar that may be drawn on this line
+        let clear = if self.tty { "\r\x1b[2K" } else { "" };
+        let prefix = |label: &str, color| match self.color {
+            true => paint(label, color),
+            false => label.to_string(),
+        };
+        match record.level() {
+            Level::Error => eprintln!("{clear}{}: {}", prefix("error", Color::Red), record.args()),
+            Level::Warn => eprintln!(
+                "{clear}{}: {}",
+                prefix("warning", Color::Yellow),
+                record.args()
+            ),
+            _ => eprintln!("{clear}{}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Maps -v/-q counts to a level; `None` when neither was given.
+pub fn level_from_flags(verbose: u8, quiet: u8) -> Option<LevelFilter> {
+    match verbose as i16 - quiet as i16 {
+        _ if verbose == 0 && quiet == 0 => None,
+        i16::MIN..=-2 => Some(LevelFilter::Error),
+        -1 => Some(LevelFilter::Warn),
+        0 => Some(LevelFilter::Info),
+        1 => Some(LevelFilter::Debug),
+        _ => Some(LevelFilter::Trace),
+    }
+}
+
+/// Parses RUST_LOG-style directives such as "debug" or "warn,loudness=trace".
+fn parse_directives(spec: &str) -> Vec<(String, LevelFilter)> {
+    spec.split(',')
+        .filter_map(|d| {
+            let d = d.trim();
+            match d.split_once('=') {
+                Some((target, level)) => Some((target.to_string(), level.parse().ok()?)),
+                None => Some((String::new(), d.parse().ok()?)),
+            }
+        })
+        .collect()
+}
+
+pub fn init(level: Option<LevelFilter>, color: ColorChoice) {
+    STDOUT_COLOR.store(
+        color.enabled(std::io::stdout().is_terminal()),
+        Ordering::Relaxed,
+    );
+
+    let crate_name = env!("CARGO_CRATE_NAME").to_string();
+    let directives = match (level, std::env::var("RUST_LOG")) {
+        (Some(level), _) => vec![(crate_name, level)],
+        (None, Ok(spec)) => parse_directives(&spec),
+        (None, Err(_)) => vec![(crate_name, LevelFilter::Info)],
+    };
+    let max = directives
+        .iter()
+        .map(|(_, l)| *l)
+        .max()
+        .unwrap_or(LevelFilter::Error)
+        .max(LevelFilter::Error);
+
+    let tty = std::io::stderr().is_terminal();
+    let logger = Logger {
+        directives,
+        tty,
+        color: color.enabled(tty),
+    };
+    if log::set_logger(Box::leak(Box::new(logger))).is_ok() {
+        log::set_max_level(max);
+    }
+}
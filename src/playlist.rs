@@ -0,0 +1,105 @@
+//! Extended M3U playlists built from the cache, ordered by a metric.
+
+use crate::cli::{PlaylistArgs, SortKey};
+use crate::report::{self, Row};
+use crate::top::glob_match;
+use log::warn;
+use loudness::load_cache;
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// `to` relative to the directory `from`; both must be absolute.
+fn relative_to(to: &Path, from: &Path) -> PathBuf {
+    let to: Vec<Component> = to.components().collect();
+    let from: Vec<Component> = from.components().collect();
+    let common = to.iter().zip(&from).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    relative.extend(&to[common..]);
+    relative
+}
+
+/// Percent-encodes a path for use as a URI reference, leaving unreserved
+/// characters and separators readable.
+fn encode(path: &Path) -> String {
+    let mut out = String::new();
+    for b in path.to_string_lossy().bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(b as char)
+            }
+            _ => {
+                let _ = write!(out, "%{b:02X}");
+            }
+        }
+    }
+    out
+}
+
+/// Writes the playlist, returning false if the cache couldn't be read.
+pub fn run(args: &PlaylistArgs) -> io::Result<bool> {
+    let cache = match load_cache(Path::new(&args.cache)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.cache);
+            return Ok(false);
+        }
+    };
+
+    let in_range = |loudness: f64| {
+        args.min.is_none_or(|min| loudness >= min) && args.max.is_none_or(|max| loudness <= max)
+    };
+    let mut unknown_path = 0;
+    let mut rows: Vec<Row> = vec![];
+    for (key, m) in cache {
+        if args.pattern.as_ref().is_some_and(|p| !glob_match(p, &key))
+            || (args.min.is_some() || args.max.is_some()) && !in_range(m.loudness)
+        {
+            continue;
+        }
+        if m.path.is_none() {
+            unknown_path += 1;
+            continue;
+        }
+        rows.push(Row {
+            index: 0,
+            key,
+            outcome: Ok(m),
+            timing: None,
+        });
+    }
+    if unknown_path > 0 {
+        warn!("skipped {unknown_path} entries measured before paths were recorded, re-measure them to include them");
+    }
+    rows.sort_by(|a, b| a.key.cmp(&b.key));
+    // playlists ramp up, so loudness runs quietest first unless reversed
+    let reverse = match args.sort {
+        SortKey::Loudness | SortKey::TruePeak => !args.reverse,
+        SortKey::Name => args.reverse,
+    };
+    report::sort(&mut rows, args.sort, reverse);
+
+    let out = std::path::absolute(&args.out)?;
+    let base = out.parent().unwrap_or(Path::new("/"));
+    let mut playlist = String::from("#EXTM3U\n");
+    let mut written = 0;
+    for row in &rows {
+        let Ok(m) = &row.outcome else { continue };
+        let path = Path::new(m.path.as_deref().unwrap());
+        if !path.exists() {
+            warn!("'{}' no longer exists, skipping", path.display());
+            continue;
+        }
+        let duration = m.duration.map_or(-1, |d| d.round() as i64);
+        let _ = writeln!(playlist, "#EXTINF:{duration},{}", row.key);
+        let _ = writeln!(playlist, "{}", encode(&relative_to(path, base)));
+        written += 1;
+    }
+
+    std::fs::write(&out, playlist)?;
+    eprintln!("wrote {written} entries to '{}'", args.out);
+    Ok(true)
+}
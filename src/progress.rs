@@ -0,0 +1,325 @@
+//! A progress bar on stderr, drawn only when stderr is a terminal, or with
+//! --tui a full-screen dashboard in its place.
+
+use crate::dashboard::{Dashboard, Event, Status};
+use crate::report::Outcome;
+use crate::summary::format_duration;
+use crate::terminal;
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Minimum time between redraws, so fast runs don't spend their time drawing.
+pub const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct Progress {
+    enabled: bool,
+    gauge: Gauge,
+    state: Mutex<State>,
+    /// Drawn instead of the bar, with --tui.
+    dashboard: Option<Dashboard>,
+}
+
+/// How much there is to do, and since when it's being done.
+#[derive(Clone, Copy)]
+pub struct Gauge {
+    total: usize,
+    /// Combined size of the files to measure, when known; drives the bar and
+    /// ETA so a few long files don't throw them off.
+    total_bytes: Option<u64>,
+    started: Instant,
+}
+
+/// A file being measured.
+pub struct Active {
+    pub name: String,
+    pub bytes: u64,
+    /// How much of it is done, as last reported by the measurement.
+    pub fraction: f64,
+}
+
+struct State {
+    done: usize,
+    done_bytes: u64,
+    active: Vec<Active>,
+    last_draw: Option<Instant>,
+    /// Lines for stdout held back while the dashboard owns the screen
+    /// they'd be printed on.
+    held: Vec<String>,
+}
+
+impl Gauge {
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Fraction of the work done, by size when the sizes are known, counting
+    /// what's done of the files in progress.
+    pub fn fraction(&self, done: usize, done_bytes: u64, active: &[Active]) -> f64 {
+        match self.total_bytes {
+            Some(total) => {
+                let partial: f64 = active.iter().map(|a| a.bytes as f64 * a.fraction).sum();
+                ((done_bytes as f64 + partial) / total as f64).min(1.0)
+            }
+            None => {
+                let partial: f64 = active.iter().map(|a| a.fraction).sum();
+                ((done as f64 + partial) / self.total as f64).min(1.0)
+            }
+        }
+    }
+
+    /// Time left at the rate so far, once anything is done.
+    pub fn eta(&self, fraction: f64) -> String {
+        if fraction > 0.0 {
+            format_duration(self.elapsed().mul_f64((1.0 - fraction) / fraction))
+        } else {
+            "--:--:--".to_string()
+        }
+    }
+
+    /// " done/total MB", when the sizes are known.
+    pub fn size(&self, done_bytes: u64) -> String {
+        match self.total_bytes {
+            Some(total) => format!(
+                " {:.1}/{:.1} MB",
+                done_bytes as f64 / 1e6,
+                total as f64 / 1e6
+            ),
+            None => String::new(),
+        }
+    }
+}
+
+impl Progress {
+    pub fn new(total: usize, visible: bool) -> Progress {
+        Progress {
+            enabled: visible && std::io::stderr().is_terminal(),
+            gauge: Gauge {
+                total,
+                total_bytes: None,
+                started: Instant::now(),
+            },
+            state: Mutex::new(State {
+                done: 0,
+                done_bytes: 0,
+                active: vec![],
+                last_draw: None,
+                held: vec![],
+            }),
+            dashboard: None,
+        }
+    }
+
+    /// Measures progress in bytes out of `total` rather than in files.
+    pub fn count_bytes(&mut self, total: u64) {
+        // nothing to divide by for a run of empty files
+        self.gauge.total_bytes = Some(total).filter(|t| *t > 0);
+    }
+
+    /// Takes over the terminal with a live dashboard in place of the bar,
+    /// once the bytes are counted. `on_quit` is called when q is pressed.
+    ///
+    /// Printed lines go to stdout as they come when it's redirected, and
+    /// when it's the terminal, are held back until `clear`, so the output
+    /// ends up the same as without it. Stays the plain bar when stderr isn't
+    /// a terminal.
+    pub fn open_dashboard(&mut self, on_quit: impl FnOnce() + Send + 'static) {
+        if self.enabled {
+            self.dashboard = Some(Dashboard::open(self.gauge, on_quit));
+        }
+    }
+
+    /// Marks `name`, `bytes` long, as being processed.
+    pub fn start(&self, name: &str, bytes: u64) {
+        if let Some(dashboard) = &self.dashboard {
+            let name = name.to_string();
+            return dashboard.send(Event::Start { name, bytes });
+        }
+        let mut state = self.state.lock().unwrap();
+        state.active.push(Active {
+            name: name.to_string(),
+            bytes,
+            fraction: 0.0,
+        });
+        self.draw(&mut state, false);
+    }
+
+    /// Records that `fraction` of `name` has been measured.
+    pub fn update(&self, name: &str, fraction: f64) {
+        if let Some(dashboard) = &self.dashboard {
+            let name = name.to_string();
+            return dashboard.send(Event::Update { name, fraction });
+        }
+        let mut state = self.state.lock().unwrap();
+        if let Some(active) = state.active.iter_mut().find(|a| a.name == name) {
+            active.fraction = fraction;
+        }
+        self.draw(&mut state, false);
+    }
+
+    /// Marks `name` as completed, successfully or not.
+    pub fn finish(&self, name: &str) {
+        if let Some(dashboard) = &self.dashboard {
+            let name = name.to_string();
+            return dashboard.send(Event::Finish { name });
+        }
+        let mut state = self.state.lock().unwrap();
+        let mut bytes = 0;
+        if let Some(i) = state.active.iter().position(|a| a.name == name) {
+            bytes = state.active.remove(i).bytes;
+        }
+        state.done += 1;
+        state.done_bytes += bytes;
+        let last = state.done == self.gauge.total;
+        self.draw(&mut state, last);
+    }
+
+    /// Adds the outcome for `key` to the dashboard's results, if it's up.
+    pub fn result(&self, key: &str, outcome: &Outcome, cached: bool) {
+        if let Some(dashboard) = &self.dashboard {
+            let status = match outcome {
+                Ok(m) if cached => Status::Cached(m.loudness),
+                Ok(m) => Status::Measured(m.loudness),
+                Err(_) => Status::Failed,
+            };
+            let key = key.to_string();
+            dashboard.send(Event::Result { key, status });
+        }
+    }
+
+    /// Prints a line to stdout above the bar.
+    pub fn println(&self, line: &str) {
+        let mut state = self.state.lock().unwrap();
+        if self.dashboard.is_some() {
+            // a pipe or file still gets each line as soon as it's ready
+            if std::io::stdout().is_terminal() {
+                state.held.push(line.to_string());
+            } else {
+                println!("{line}");
+            }
+            return;
+        }
+        if self.enabled {
+            eprint!("\r\x1b[2K");
+        }
+        println!("{line}");
+        if self.enabled {
+            self.draw(&mut state, true);
+        }
+    }
+
+    /// Removes the bar, leaving the cursor at the start of an empty line.
+    ///
+    /// The dashboard is closed and the lines it held are printed.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.close();
+            for line in state.held.drain(..) {
+                println!("{line}");
+            }
+        } else if self.enabled {
+            eprint!("\r\x1b[2K");
+            let _ = std::io::stderr().flush();
+        }
+    }
+
+    fn draw(&self, state: &mut State, force: bool) {
+        if !self.enabled || self.gauge.total == 0 {
+            return;
+        }
+        let now = Instant::now();
+        if !force && state.last_draw.is_some_and(|t| now - t < REDRAW_INTERVAL) {
+            return;
+        }
+        state.last_draw = Some(now);
+
+        let fraction = self
+            .gauge
+            .fraction(state.done, state.done_bytes, &state.active);
+        const WIDTH: usize = 30;
+        let filled = (WIDTH as f64 * fraction) as usize;
+        let line = format!(
+            "[{}{}] {}/{}{} {} ETA {} {}",
+            "=".repeat(filled),
+            " ".repeat(WIDTH - filled),
+            state.done,
+            self.gauge.total,
+            self.gauge.size(state.done_bytes),
+            format_duration(self.gauge.elapsed()),
+            self.gauge.eta(fraction),
+            state
+                .active
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let line: String = line.chars().take(terminal_width()).collect();
+        eprint!("\r\x1b[2K{line}");
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// Usable columns of the terminal, leaving the last one free so a full
+/// line doesn't wrap.
+pub fn terminal_width() -> usize {
+    terminal::size().1.max(20) - 1
+}
+
+/// Prints per-file lines in input order even though files complete out of order.
+///
+/// Every index must be completed exactly once, with or without a line, so the
+/// frontier can advance past it. Only finished lines are held back, never audio.
+pub struct InOrder<'a> {
+    progress: &'a Progress,
+    ordered: bool,
+    state: Mutex<Frontier>,
+}
+
+struct Frontier {
+    /// Index of the first file not yet printed.
+    next: usize,
+    pending: BTreeMap<usize, Option<String>>,
+}
+
+impl<'a> InOrder<'a> {
+    pub fn new(progress: &'a Progress, ordered: bool) -> InOrder<'a> {
+        InOrder {
+            progress,
+            ordered,
+            state: Mutex::new(Frontier {
+                next: 0,
+                pending: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Records the line for file `index`, printing it and any held-back
+    /// successors once everything before it has been printed.
+    pub fn complete(&self, index: usize, line: Option<String>) {
+        if !self.ordered {
+            if let Some(line) = line {
+                self.progress.println(&line);
+            }
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.pending.insert(index, line);
+        while let Some(line) = {
+            let next = state.next;
+            state.pending.remove(&next)
+        } {
+            state.next += 1;
+            if let Some(line) = line {
+                self.progress.println(&line);
+            }
+        }
+    }
+}
@@ -0,0 +1,412 @@
+//! Machine-readable renderings of measurement results.
+
+use crate::cli::{SortKey, Units, REPLAYGAIN_REFERENCE};
+use crate::gains::gain_db;
+use loudness::{csv_field, tsv_field, Breakdown, Measurement};
+use merde::json::{JsonSerialize, JsonSerializer, ObjectGuard};
+use std::cmp::Ordering;
+use std::time::Duration;
+
+/// Outcome of measuring a single file: its measurement, or why it failed.
+pub type Outcome = Result<Measurement, String>;
+
+/// A loudness for a table cell, in LUFS, or "silent" when there's none.
+pub fn loudness_cell(loudness: f64) -> String {
+    if loudness.is_finite() {
+        Units::Lufs.format(loudness, None)
+    } else {
+        "silent".to_string()
+    }
+}
+
+/// A selectable output column. `Field::ALL` is the single list of what
+/// --fields accepts, so new metrics only need adding here.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Key,
+    Loudness,
+    Energy,
+    Duration,
+    /// Gain in dB to the target, or the ReplayGain reference without one.
+    Gain,
+    /// Only measured with --psr, like the two after it.
+    TruePeak,
+    Psr,
+    PsrAvg,
+    MomentaryMax,
+    /// Where the loudest momentary loudness was, shown as mm:ss.mmm.
+    MomentaryMaxAt,
+}
+
+impl Field {
+    pub const ALL: [Field; 10] = [
+        Field::Key,
+        Field::Loudness,
+        Field::Energy,
+        Field::Duration,
+        Field::Gain,
+        Field::TruePeak,
+        Field::Psr,
+        Field::PsrAvg,
+        Field::MomentaryMax,
+        Field::MomentaryMaxAt,
+    ];
+
+    /// Columns shown when --fields isn't given.
+    pub const DEFAULT: [Field; 3] = [Field::Key, Field::Loudness, Field::Energy];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Key => "key",
+            Field::Loudness => "loudness",
+            Field::Energy => "energy",
+            Field::Duration => "duration",
+            Field::Gain => "gain",
+            Field::TruePeak => "true_peak",
+            Field::Psr => "psr",
+            Field::PsrAvg => "psr_avg",
+            Field::MomentaryMax => "momentary_max",
+            Field::MomentaryMaxAt => "momentary_max_at",
+        }
+    }
+
+    /// Parses a comma-separated list such as "key,gain".
+    pub fn parse_list(spec: &str) -> Result<Vec<Field>, String> {
+        spec.split(',')
+            .map(|name| {
+                Field::ALL
+                    .into_iter()
+                    .find(|f| f.name() == name.trim())
+                    .ok_or_else(|| {
+                        let names: Vec<_> = Field::ALL.iter().map(|f| f.name()).collect();
+                        format!(
+                            "unknown field '{name}', expected one of: {}",
+                            names.join(", ")
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Column header in exports.
+    pub fn column(self, units: Units) -> &'static str {
+        match self {
+            Field::Loudness => units.column(),
+            Field::Duration => "duration_s",
+            Field::Gain => "gain_db",
+            Field::TruePeak => "true_peak_dbtp",
+            Field::Psr => "psr_db",
+            Field::PsrAvg => "psr_avg_db",
+            Field::MomentaryMax => "momentary_max_lufs",
+            Field::MomentaryMaxAt => "momentary_max_at_secs",
+            _ => self.name(),
+        }
+    }
+
+    /// Numeric value of the field, if it was measured (never for `Key`).
+    pub fn value(self, m: &Measurement, units: Units, target: Option<f64>) -> Option<f64> {
+        let value = match self {
+            Field::Key => return None,
+            Field::Loudness => units.value(m.loudness, target),
+            Field::Energy => m.energy,
+            Field::Duration => m.duration?,
+            Field::Gain => gain_db(m.loudness, target.unwrap_or(REPLAYGAIN_REFERENCE), false),
+            Field::TruePeak => m.true_peak?,
+            Field::Psr => m.psr?,
+            Field::PsrAvg => m.psr_avg?,
+            Field::MomentaryMax => m.momentary_max?,
+            Field::MomentaryMaxAt => m.momentary_max_at_secs?,
+        };
+        Some(value).filter(|v| v.is_finite())
+    }
+
+    /// Human-readable value with its unit, or empty if it wasn't measured.
+    pub fn display(
+        self,
+        key: &str,
+        m: &Measurement,
+        units: Units,
+        target: Option<f64>,
+        precision: usize,
+    ) -> String {
+        if self == Field::Key {
+            return key.to_string();
+        }
+        let Some(value) = self.value(m, units, target) else {
+            return String::new();
+        };
+        match self {
+            Field::Loudness => units.format_with(m.loudness, target, precision),
+            Field::Energy => format!("{value:.precision$} energy"),
+            Field::Duration => format!("{value:.precision$} s"),
+            Field::Gain => format!("{value:+.precision$} dB"),
+            Field::TruePeak => format!("{value:.precision$} dBTP"),
+            Field::Psr => format!("{value:.precision$} dB PSR"),
+            Field::PsrAvg => format!("{value:.precision$} dB mean PSR"),
+            Field::MomentaryMax => format!("{value:.precision$} LUFS max"),
+            Field::MomentaryMaxAt => format!("at {}", timestamp(value)),
+            Field::Key => unreachable!(),
+        }
+    }
+}
+
+/// Seconds as mm:ss.mmm, e.g. "01:23.456", with minutes past the hour
+/// carrying on from 60.
+pub fn timestamp(secs: f64) -> String {
+    let ms = (secs * 1000.0).round() as u64;
+    format!("{:02}:{:02}.{:03}", ms / 60_000, ms / 1000 % 60, ms % 1000)
+}
+
+/// Cost of measuring one file, recorded under --timings or --bench.
+#[derive(Clone, Copy)]
+pub struct Timing {
+    /// Wall time spent decoding and analysing.
+    pub wall: Duration,
+    /// Size of the file read.
+    pub bytes: u64,
+    /// How `wall` splits up; only under --bench, and only for files that
+    /// measured successfully.
+    pub stages: Option<Breakdown>,
+}
+
+impl Timing {
+    /// Seconds of audio processed per second of wall time.
+    pub fn realtime(&self, m: &Measurement) -> Option<f64> {
+        let wall = self.wall.as_secs_f64();
+        m.duration.filter(|_| wall > 0.0).map(|d| d / wall)
+    }
+}
+
+/// One file's entry in the final report.
+pub struct Row {
+    /// Position in input order.
+    pub index: usize,
+    pub key: String,
+    pub outcome: Outcome,
+    /// Only set for files measured in this run with --timings.
+    pub timing: Option<Timing>,
+}
+
+/// Sorts indexed results for the final report.
+///
+/// Loudness sorts loudest first and true peak highest first; files without
+/// a usable value always come last. Ties fall back to key order so the
+/// output is deterministic.
+pub fn sort(results: &mut [Row], key: SortKey, reverse: bool) {
+    let value = |o: &Outcome| {
+        let m = o.as_ref().ok()?;
+        match key {
+            SortKey::Name | SortKey::Loudness => Some(m.loudness),
+            SortKey::TruePeak => m.true_peak,
+        }
+        .filter(|v| v.is_finite())
+    };
+    results.sort_by(|a, b| {
+        let (ka, kb) = (&a.key, &b.key);
+        let primary = match key {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Loudness | SortKey::TruePeak => match (value(&a.outcome), value(&b.outcome)) {
+                (Some(a), Some(b)) => {
+                    let o = b.total_cmp(&a);
+                    if reverse {
+                        o.reverse()
+                    } else {
+                        o
+                    }
+                }
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        };
+        primary.then_with(|| match (key, reverse) {
+            (SortKey::Name, true) => kb.cmp(ka),
+            _ => ka.cmp(kb),
+        })
+    });
+}
+
+/// Writes a number, or null when it can't be represented in JSON (e.g. the
+/// -inf loudness of a silent file).
+fn write_number(s: &mut JsonSerializer, value: f64) {
+    if value.is_finite() {
+        s.write_f64(value);
+    } else {
+        s.write_null();
+    }
+}
+
+/// Writes a result's fields into an object, with an "error" field on failure.
+fn write_fields(obj: &mut ObjectGuard, outcome: &Outcome, timing: Option<&Timing>) {
+    match outcome {
+        Ok(m) => {
+            obj.pair("loudness", &JsonNumber(m.loudness));
+            obj.pair("energy", &JsonNumber(m.energy));
+            if let Some(duration) = m.duration {
+                obj.pair("duration", &JsonNumber(duration));
+            }
+            if let Some(errors) = m.decode_errors {
+                obj.pair("decode_errors", &errors);
+            }
+            if m.short == Some(true) {
+                obj.pair("short", &true);
+            }
+            if m.duration_mismatch == Some(true) {
+                obj.pair("duration_mismatch", &true);
+            }
+            if let Some(source) = &m.source {
+                obj.pair("source", source);
+            }
+            if let Some(start) = m.start {
+                obj.pair("start", &JsonNumber(start));
+            }
+            if let Some(end) = m.end {
+                obj.pair("end", &JsonNumber(end));
+            }
+            if let Some(map) = &m.channel_map {
+                obj.pair("channel_map", map);
+            }
+            if let Some(changes) = m.sample_rate_changes {
+                obj.pair("sample_rate_changes", &changes);
+            }
+            if let Some(changes) = m.channel_changes {
+                obj.pair("channel_changes", &changes);
+            }
+            if let Some(peak) = m.true_peak {
+                obj.pair("true_peak", &JsonNumber(peak));
+            }
+            if let Some(loudness) = m.short_term_max {
+                obj.pair("short_term_max", &JsonNumber(loudness));
+            }
+            // null when measured but too short for a short-term window
+            if m.true_peak.is_some() {
+                obj.pair("psr", &m.psr.map(JsonNumber));
+                obj.pair("psr_avg", &m.psr_avg.map(JsonNumber));
+            }
+            if let Some(loudness) = m.momentary_max {
+                obj.pair("momentary_max", &JsonNumber(loudness));
+            }
+            if let Some(at) = m.momentary_max_at_secs {
+                obj.pair("momentary_max_at_secs", &JsonNumber(at));
+            }
+        }
+        Err(e) => {
+            obj.pair("error", e);
+        }
+    }
+    if let Some(timing) = timing {
+        obj.pair("wall_time", &JsonNumber(timing.wall.as_secs_f64()));
+        obj.pair("bytes", &timing.bytes);
+        if let Some(realtime) = outcome.as_ref().ok().and_then(|m| timing.realtime(m)) {
+            obj.pair("realtime", &JsonNumber(realtime));
+        }
+        if let Some(stages) = timing.stages {
+            obj.pair("io_time", &JsonNumber(stages.io.as_secs_f64()));
+            obj.pair("decode_time", &JsonNumber(stages.decode.as_secs_f64()));
+            obj.pair("analysis_time", &JsonNumber(stages.analysis.as_secs_f64()));
+        }
+    }
+}
+
+pub struct JsonNumber(pub f64);
+
+impl merde::json::JsonSerialize for JsonNumber {
+    fn json_serialize(&self, s: &mut JsonSerializer) {
+        write_number(s, self.0);
+    }
+}
+
+struct RowRef<'a>(&'a Row);
+
+impl merde::json::JsonSerialize for RowRef<'_> {
+    fn json_serialize(&self, s: &mut JsonSerializer) {
+        write_fields(&mut s.write_obj(), &self.0.outcome, self.0.timing.as_ref());
+    }
+}
+
+/// Renders results as a single object for one file, or a key → result map otherwise.
+pub fn json(results: &[Row], single: bool) -> String {
+    let mut s = JsonSerializer::new();
+    match results {
+        [row] if single => RowRef(row).json_serialize(&mut s),
+        _ => {
+            let mut obj = s.write_obj();
+            for row in results {
+                obj.pair(&row.key, &RowRef(row));
+            }
+        }
+    }
+    String::from_utf8(s.into_inner()).unwrap()
+}
+
+/// Renders one result as a self-describing NDJSON line (without the newline).
+///
+/// `seq` is the file's position in input order, since lines are written in
+/// completion order.
+pub fn ndjson_line(
+    seq: usize,
+    key: &str,
+    outcome: &Outcome,
+    timing: Option<&Timing>,
+    cached: bool,
+) -> String {
+    let mut s = JsonSerializer::new();
+    {
+        let mut obj = s.write_obj();
+        obj.pair("key", &key);
+        obj.pair("seq", &seq);
+        write_fields(&mut obj, outcome, timing);
+        if cached {
+            obj.pair("cached", &true);
+        }
+    }
+    String::from_utf8(s.into_inner()).unwrap()
+}
+
+/// Renders results as CSV (or TSV with `delimiter == '\t'`), including a header row.
+///
+/// The loudness column is named after the unit it's in. Numbers are rounded to
+/// `precision` decimal places if given, and written in full otherwise. Failed files are
+/// omitted unless `include_failed` is set, in which case their numeric cells
+/// are left empty, as are cells for values that weren't measured.
+pub fn delimited(
+    results: &[Row],
+    delimiter: char,
+    fields: &[Field],
+    units: Units,
+    target: Option<f64>,
+    precision: Option<usize>,
+    include_failed: bool,
+) -> String {
+    let field = |v: &str| match delimiter {
+        '\t' => tsv_field(v),
+        _ => csv_field(v),
+    };
+    let number = |v: Option<f64>| match (v, precision) {
+        (Some(v), Some(p)) => format!("{v:.p$}"),
+        (Some(v), None) => v.to_string(),
+        (None, _) => String::new(),
+    };
+
+    let header: Vec<_> = fields.iter().map(|f| f.column(units)).collect();
+    let mut out = header.join(&delimiter.to_string());
+    out.push('\n');
+    for Row { key, outcome, .. } in results {
+        let measurement = match outcome {
+            Ok(m) => Some(m),
+            Err(_) if include_failed => None,
+            Err(_) => continue,
+        };
+        let cells: Vec<_> = fields
+            .iter()
+            .map(|f| match (f, measurement) {
+                (Field::Key, _) => field(key),
+                (_, Some(m)) => number(f.value(m, units, target)),
+                (_, None) => String::new(),
+            })
+            .collect();
+        out.push_str(&cells.join(&delimiter.to_string()));
+        out.push('\n');
+    }
+    out
+}
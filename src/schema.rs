@@ -0,0 +1,265 @@
+//! A JSON Schema of the cache, for tools that read it.
+
+use merde::json::{JsonSerialize, JsonSerializer};
+
+/// Version of the cache's JSON layout, which the schema is versioned with.
+/// Bumped when a field is removed or changes meaning; adding an optional
+/// field doesn't, as readers ignore fields they don't know.
+pub const CACHE_FORMAT_VERSION: u32 = 1;
+
+/// A field of a cache entry, in the order they're serialized.
+pub(crate) struct Field {
+    pub(crate) name: &'static str,
+    /// JSON Schema type; optional fields may also be null.
+    pub(crate) kind: &'static str,
+    /// Written by every version; the rest are absent or null when unknown.
+    required: bool,
+    description: &'static str,
+}
+
+/// The fields of [`Measurement`](crate::Measurement), as written by its
+/// merde derive. The doctest on [`cache_schema`] checks them against it.
+pub(crate) const FIELDS: &[Field] = &[
+    Field {
+        name: "loudness",
+        kind: "number",
+        required: true,
+        description: "Integrated loudness in LUFS; ungated loudness when short is set.",
+    },
+    Field {
+        name: "energy",
+        kind: "number",
+        required: true,
+        description: "Summed energy of the gating blocks that counted towards loudness.",
+    },
+    Field {
+        name: "duration",
+        kind: "number",
+        required: false,
+        description: "Seconds of audio measured.",
+    },
+    Field {
+        name: "path",
+        kind: "string",
+        required: false,
+        description: "Absolute path of the file measured.",
+    },
+    Field {
+        name: "decode_errors",
+        kind: "integer",
+        required: false,
+        description: "Packets that failed to decode and were skipped.",
+    },
+    Field {
+        name: "short",
+        kind: "boolean",
+        required: false,
+        description: "Set when the audio was too short to gate.",
+    },
+    Field {
+        name: "duration_mismatch",
+        kind: "boolean",
+        required: false,
+        description: "Set when the decoded length differed from the declared one.",
+    },
+    Field {
+        name: "source",
+        kind: "string",
+        required: false,
+        description: "Where an imported entry came from, e.g. \"loudnorm\".",
+    },
+    Field {
+        name: "start",
+        kind: "number",
+        required: false,
+        description:
+            "Seconds into the file a measured range started at; absent for the whole file.",
+    },
+    Field {
+        name: "end",
+        kind: "number",
+        required: false,
+        description: "Seconds into the file a measured range was to end at; absent to the end.",
+    },
+    Field {
+        name: "channel_map",
+        kind: "string",
+        required: false,
+        description: "Speaker of each channel measured with instead of the file's layout, e.g. \"FL,FR,FC,LFE,SL,SR\".",
+    },
+    Field {
+        name: "sample_rate_changes",
+        kind: "integer",
+        required: false,
+        description: "Times the sample rate changed partway through the audio.",
+    },
+    Field {
+        name: "channel_changes",
+        kind: "integer",
+        required: false,
+        description: "Times the channel count changed partway through the audio.",
+    },
+    Field {
+        name: "true_peak",
+        kind: "number",
+        required: false,
+        description: "Highest true peak of any channel in dBTP, when measured.",
+    },
+    Field {
+        name: "short_term_max",
+        kind: "number",
+        required: false,
+        description: "Loudest short-term (3 s) loudness in LUFS, when measured.",
+    },
+    Field {
+        name: "psr",
+        kind: "number",
+        required: false,
+        description: "Peak-to-short-term loudness ratio in dB: true_peak minus short_term_max.",
+    },
+    Field {
+        name: "psr_avg",
+        kind: "number",
+        required: false,
+        description: "Mean PSR of each successive 3 s window.",
+    },
+    Field {
+        name: "momentary_max",
+        kind: "number",
+        required: false,
+        description: "Loudest momentary (400 ms) loudness in LUFS.",
+    },
+    Field {
+        name: "momentary_max_at_secs",
+        kind: "number",
+        required: false,
+        description: "Seconds into the file where the window of momentary_max ends.",
+    },
+];
+
+/// Serializes with a closure, for nesting objects without a type for each.
+struct Json<F>(F);
+
+impl<F: Fn(&mut JsonSerializer)> JsonSerialize for Json<F> {
+    fn json_serialize(&self, s: &mut JsonSerializer) {
+        (self.0)(s)
+    }
+}
+
+fn measurement(s: &mut JsonSerializer) {
+    let mut obj = s.write_obj();
+    obj.pair("type", &"object");
+    obj.pair(
+        "properties",
+        &Json(|s: &mut JsonSerializer| {
+            let mut properties = s.write_obj();
+            for field in FIELDS {
+                properties.pair(
+                    field.name,
+                    &Json(|s: &mut JsonSerializer| {
+                        let mut property = s.write_obj();
+                        match field.required {
+                            true => property.pair("type", &field.kind),
+                            false => property.pair("type", &&[field.kind, "null"][..]),
+                        };
+                        property.pair("description", &field.description);
+                    }),
+                );
+            }
+        }),
+    );
+    let required: Vec<&str> = FIELDS
+        .iter()
+        .filter(|f| f.required)
+        .map(|f| f.name)
+        .collect();
+    obj.pair("required", &required);
+}
+
+/// A JSON Schema (draft 2020-12) of a cache written by
+/// [`save_cache`](crate::save_cache): an object of entries keyed by
+/// [`cache_key`](crate::cache_key). Its `$id` ends in
+/// [`CACHE_FORMAT_VERSION`].
+///
+/// ```
+/// use loudness::{cache_schema, Measurement};
+/// use merde::{CowStr, Value};
+///
+/// // every field set, so none is written as null
+/// let m = Measurement {
+///     loudness: -23.0,
+///     energy: 1.5,
+///     duration: Some(2.5),
+///     path: Some("/music/a.flac".to_string()),
+///     decode_errors: Some(1),
+///     short: Some(true),
+///     duration_mismatch: Some(true),
+///     source: Some("loudnorm".to_string()),
+///     start: Some(30.5),
+///     end: Some(90.5),
+///     channel_map: Some("FL,FR".to_string()),
+///     sample_rate_changes: Some(1),
+///     channel_changes: Some(1),
+///     true_peak: Some(-1.0),
+///     short_term_max: Some(-9.5),
+///     psr: Some(8.5),
+///     psr_avg: Some(10.5),
+///     momentary_max: Some(-8.5),
+///     momentary_max_at_secs: Some(61.5),
+/// };
+/// let entry = merde::json::to_string(&m);
+/// let entry: Value = merde::json::from_str(&entry).unwrap();
+/// let schema = cache_schema();
+/// let schema: Value = merde::json::from_str(&schema).unwrap();
+///
+/// fn get<'s>(value: &Value<'s>, key: &str) -> Value<'s> {
+///     value.as_map().unwrap()[&CowStr::from(key.to_string())].clone()
+/// }
+/// let properties = get(&get(&get(&schema, "$defs"), "measurement"), "properties");
+/// let properties = properties.as_map().unwrap();
+/// let entry = entry.as_map().unwrap();
+/// assert_eq!(entry.len(), properties.len());
+/// for (name, value) in entry.iter() {
+///     let types: Vec<String> = match &properties[name].as_map().unwrap()[&CowStr::from("type")] {
+///         Value::Array(types) => types.iter().map(|t| t.as_str().unwrap().to_string()).collect(),
+///         t => vec![t.as_str().unwrap().to_string()],
+///     };
+///     let allowed = |kind: &str| types.iter().any(|t| t == kind);
+///     let valid = match value {
+///         // whole numbers are read back as integers
+///         Value::I64(_) | Value::U64(_) => allowed("integer") || allowed("number"),
+///         Value::Float(_) => allowed("number"),
+///         Value::Str(_) => allowed("string"),
+///         Value::Bool(_) => allowed("boolean"),
+///         _ => false,
+///     };
+///     assert!(valid, "{name} is {value:?}, the schema allows {types:?}");
+/// }
+/// ```
+pub fn cache_schema() -> String {
+    let mut s = JsonSerializer::new();
+    {
+        let mut obj = s.write_obj();
+        obj.pair("$schema", &"https://json-schema.org/draft/2020-12/schema");
+        obj.pair("$id", &format!("urn:loudness:cache:{CACHE_FORMAT_VERSION}"));
+        obj.pair("title", &"loudness cache");
+        obj.pair(
+            "description",
+            &"Measurements keyed by file name without its extension, in Unicode NFC.",
+        );
+        obj.pair("type", &"object");
+        obj.pair(
+            "additionalProperties",
+            &Json(|s: &mut JsonSerializer| {
+                s.write_obj().pair("$ref", &"#/$defs/measurement");
+            }),
+        );
+        obj.pair(
+            "$defs",
+            &Json(|s: &mut JsonSerializer| {
+                s.write_obj().pair("measurement", &Json(measurement));
+            }),
+        );
+    }
+    String::from_utf8(s.into_inner()).unwrap()
+}
@@ -0,0 +1,99 @@
+//! Known signals generated in memory and measured through the same path as
+//! decoded files, to check a build without any audio at hand.
+
+use loudness::{measure_samples, ErrorKind, Measurement};
+use std::f64::consts::PI;
+use std::io;
+
+const SAMPLE_RATE: u32 = 48_000;
+/// Seconds of each signal, as in the EBU Tech 3341 test cases.
+const SECONDS: u32 = 20;
+
+/// A stereo sine at `frequency` Hz with a peak of `dbfs` in both channels,
+/// starting at `phase` radians.
+fn stereo_sine(frequency: f64, dbfs: f64, phase: f64) -> Vec<f32> {
+    let amplitude = 10f64.powf(dbfs / 20.0);
+    (0..SAMPLE_RATE * SECONDS)
+        .flat_map(|i| {
+            let t = f64::from(i) / f64::from(SAMPLE_RATE);
+            let s = (amplitude * (2.0 * PI * frequency * t + phase).sin()) as f32;
+            [s, s]
+        })
+        .collect()
+}
+
+fn measure(samples: &[f32]) -> Result<Measurement, String> {
+    measure_samples(samples, 2, SAMPLE_RATE).map_err(|e| e.to_string())
+}
+
+/// A case: what was measured, or why it's not what was expected.
+type Check = fn() -> Result<String, String>;
+
+/// EBU Tech 3341 case 1: a 1 kHz sine at -23 dBFS in both channels of a
+/// stereo signal is -23 LUFS, to within ±0.1 LU.
+fn ebu_reference() -> Result<String, String> {
+    let m = measure(&stereo_sine(1000.0, -23.0, 0.0))?;
+    within(m.loudness, -23.0, 0.1)
+}
+
+/// As case 1, 3 dB louder than Tech 3341 case 2.
+fn stereo_sine_20() -> Result<String, String> {
+    let m = measure(&stereo_sine(1000.0, -20.0, 0.0))?;
+    within(m.loudness, -20.0, 0.1)
+}
+
+/// Digital silence has no gating block above the absolute gate, so there's
+/// nothing to measure, as for a silent file.
+fn silence() -> Result<String, String> {
+    let samples = vec![0.0; (SAMPLE_RATE * SECONDS * 2) as usize];
+    match measure_samples(&samples, 2, SAMPLE_RATE) {
+        Err(e) if matches!(e.kind(), ErrorKind::NoAudio) => Ok(e.kind().to_string()),
+        Err(e) => Err(e.to_string()),
+        Ok(m) => Err(format!("{:.3}, expected no audio", m.loudness)),
+    }
+}
+
+/// A sine at a quarter of the sample rate, phased so every sample is at
+/// exactly full scale and the peaks in between reach +3 dBTP. Measured
+/// loudness must scale with it: the same signal 20 dB down measures 20 LU
+/// quieter, so nothing clipped or saturated on the way.
+fn inter_sample_peaks() -> Result<String, String> {
+    let frequency = f64::from(SAMPLE_RATE) / 4.0;
+    // +3.01 dBFS, with samples at ±0.707 of it
+    let peak = 20.0 * 2f64.sqrt().log10();
+    let full = measure(&stereo_sine(frequency, peak, PI / 4.0))?;
+    let quiet = measure(&stereo_sine(frequency, peak - 20.0, PI / 4.0))?;
+    within(full.loudness - quiet.loudness, 20.0, 0.01)
+}
+
+fn within(value: f64, expected: f64, tolerance: f64) -> Result<String, String> {
+    if (value - expected).abs() <= tolerance {
+        Ok(format!("{value:.3}"))
+    } else {
+        Err(format!("{value:.3}, expected {expected} ± {tolerance}"))
+    }
+}
+
+const CASES: &[(&str, Check)] = &[
+    ("1 kHz stereo sine at -23 dBFS is -23 LUFS", ebu_reference),
+    ("1 kHz stereo sine at -20 dBFS is -20 LUFS", stereo_sine_20),
+    ("silence has nothing to measure", silence),
+    ("full-scale inter-sample peaks scale", inter_sample_peaks),
+];
+
+/// Runs every case and prints pass or FAIL for each, returning false if any
+/// failed.
+pub fn run() -> io::Result<bool> {
+    let mut failed = 0;
+    for (name, check) in CASES {
+        match check() {
+            Ok(measured) => println!("pass  {name} ({measured})"),
+            Err(e) => {
+                failed += 1;
+                println!("FAIL  {name}: {e}");
+            }
+        }
+    }
+    eprintln!("{} of {} cases passed", CASES.len() - failed, CASES.len());
+    Ok(failed == 0)
+}
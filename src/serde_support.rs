@@ -0,0 +1,134 @@
+//! `Serialize` and `Deserialize` for [`Measurement`], matching the JSON the
+//! merde path writes field for field, so either side can read the other's
+//! caches. A cache is a `HashMap<String, Measurement>`, which serde already
+//! handles.
+//!
+//! Like merde, `None` is written as `null`, and missing or `null` optional
+//! fields read back as `None`. Unknown fields are ignored, so entries from
+//! newer versions still load. Silence has a loudness of negative infinity,
+//! which JSON can't represent; how that's written is up to the serializer.
+
+use crate::Measurement;
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+const FIELDS: &[&str] = &[
+    "loudness",
+    "energy",
+    "duration",
+    "path",
+    "decode_errors",
+    "short",
+    "duration_mismatch",
+    "source",
+    "start",
+    "end",
+    "channel_map",
+    "sample_rate_changes",
+    "channel_changes",
+    "true_peak",
+    "short_term_max",
+    "psr",
+    "psr_avg",
+    "momentary_max",
+    "momentary_max_at_secs",
+];
+
+impl Serialize for Measurement {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Measurement", FIELDS.len())?;
+        s.serialize_field("loudness", &self.loudness)?;
+        s.serialize_field("energy", &self.energy)?;
+        s.serialize_field("duration", &self.duration)?;
+        s.serialize_field("path", &self.path)?;
+        s.serialize_field("decode_errors", &self.decode_errors)?;
+        s.serialize_field("short", &self.short)?;
+        s.serialize_field("duration_mismatch", &self.duration_mismatch)?;
+        s.serialize_field("source", &self.source)?;
+        s.serialize_field("start", &self.start)?;
+        s.serialize_field("end", &self.end)?;
+        s.serialize_field("channel_map", &self.channel_map)?;
+        s.serialize_field("sample_rate_changes", &self.sample_rate_changes)?;
+        s.serialize_field("channel_changes", &self.channel_changes)?;
+        s.serialize_field("true_peak", &self.true_peak)?;
+        s.serialize_field("short_term_max", &self.short_term_max)?;
+        s.serialize_field("psr", &self.psr)?;
+        s.serialize_field("psr_avg", &self.psr_avg)?;
+        s.serialize_field("momentary_max", &self.momentary_max)?;
+        s.serialize_field("momentary_max_at_secs", &self.momentary_max_at_secs)?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Measurement {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Measurement, D::Error> {
+        deserializer.deserialize_struct("Measurement", FIELDS, MeasurementVisitor)
+    }
+}
+
+struct MeasurementVisitor;
+
+impl<'de> Visitor<'de> for MeasurementVisitor {
+    type Value = Measurement;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a measurement")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Measurement, A::Error> {
+        let (mut loudness, mut energy, mut duration, mut path) = (None, None, None, None);
+        let (mut decode_errors, mut short, mut duration_mismatch) = (None, None, None);
+        let (mut source, mut start, mut end, mut channel_map) = (None, None, None, None);
+        let (mut sample_rate_changes, mut channel_changes) = (None, None);
+        let (mut true_peak, mut short_term_max, mut psr, mut psr_avg) = (None, None, None, None);
+        let (mut momentary_max, mut momentary_max_at_secs) = (None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "loudness" => loudness = Some(map.next_value()?),
+                "energy" => energy = Some(map.next_value()?),
+                "duration" => duration = map.next_value()?,
+                "path" => path = map.next_value()?,
+                "decode_errors" => decode_errors = map.next_value()?,
+                "short" => short = map.next_value()?,
+                "duration_mismatch" => duration_mismatch = map.next_value()?,
+                "source" => source = map.next_value()?,
+                "start" => start = map.next_value()?,
+                "end" => end = map.next_value()?,
+                "channel_map" => channel_map = map.next_value()?,
+                "sample_rate_changes" => sample_rate_changes = map.next_value()?,
+                "channel_changes" => channel_changes = map.next_value()?,
+                "true_peak" => true_peak = map.next_value()?,
+                "short_term_max" => short_term_max = map.next_value()?,
+                "psr" => psr = map.next_value()?,
+                "psr_avg" => psr_avg = map.next_value()?,
+                "momentary_max" => momentary_max = map.next_value()?,
+                "momentary_max_at_secs" => momentary_max_at_secs = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(Measurement {
+            loudness: loudness.ok_or_else(|| de::Error::missing_field("loudness"))?,
+            energy: energy.ok_or_else(|| de::Error::missing_field("energy"))?,
+            duration,
+            path,
+            decode_errors,
+            short,
+            duration_mismatch,
+            source,
+            start,
+            end,
+            channel_map,
+            sample_rate_changes,
+            channel_changes,
+            true_peak,
+            short_term_max,
+            psr,
+            psr_avg,
+            momentary_max,
+            momentary_max_at_secs,
+        })
+    }
+}
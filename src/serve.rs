@@ -0,0 +1,293 @@
+//! A small HTTP/1.1 server exposing the cache, built on std::net alone.
+//!
+//! Every connection gets its own thread and is closed after one response.
+
+use crate::cli::ServeArgs;
+use crate::measure;
+use crate::report::{self, Row};
+use crate::stats::{self, Distribution, Psr};
+use log::{error, info, warn};
+use loudness::{cache_format_for, cache_key, load_cache, save_cache_as, CacheFormat, Measurement};
+use merde::json::JsonSerializer;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Largest request body accepted, which only ever holds a path.
+const MAX_BODY: usize = 64 * 1024;
+
+struct Server {
+    cache: RwLock<HashMap<String, Measurement>>,
+    cache_path: PathBuf,
+    cache_format: CacheFormat,
+    token: Option<String>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: String) -> Response {
+        Response { status, body }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        let mut s = JsonSerializer::new();
+        s.write_obj().pair("error", &message);
+        Response::json(status, String::from_utf8(s.into_inner()).unwrap())
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Decodes `%XX` escapes in a path segment.
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = s.bytes();
+    while let Some(b) = rest.next() {
+        if b == b'%' {
+            let hex = [rest.next()?, rest.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn read_request(stream: &mut BufReader<&TcpStream>) -> Result<Request, Response> {
+    let bad = |message: &str| Response::error(400, message);
+    let mut line = String::new();
+    stream
+        .read_line(&mut line)
+        .map_err(|_| bad("unreadable request"))?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(bad("malformed request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut length = 0;
+    let mut authorization = None;
+    loop {
+        let mut header = String::new();
+        stream
+            .read_line(&mut header)
+            .map_err(|_| bad("unreadable headers"))?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(bad("malformed header"));
+        };
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => {
+                length = value.parse().map_err(|_| bad("invalid Content-Length"))?;
+            }
+            "authorization" => authorization = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    if length > MAX_BODY {
+        return Err(Response::error(413, "request body too large"));
+    }
+    let mut body = vec![0; length];
+    stream
+        .read_exact(&mut body)
+        .map_err(|_| bad("truncated body"))?;
+
+    Ok(Request {
+        method,
+        path,
+        authorization,
+        body,
+    })
+}
+
+/// Renders cache entries as a key → measurement map, or a single object.
+fn entries_json<'a>(
+    entries: impl Iterator<Item = (&'a String, &'a Measurement)>,
+    single: bool,
+) -> String {
+    let mut rows: Vec<Row> = entries
+        .map(|(key, m)| Row {
+            index: 0,
+            key: key.clone(),
+            outcome: Ok(m.clone()),
+            timing: None,
+        })
+        .collect();
+    rows.sort_by(|a, b| a.key.cmp(&b.key));
+    report::json(&rows, single)
+}
+
+impl Server {
+    fn handle(&self, request: &Request) -> Response {
+        if let Some(token) = &self.token {
+            let expected = format!("Bearer {token}");
+            if request.authorization.as_deref() != Some(expected.as_str()) {
+                return Response::error(401, "missing or wrong bearer token");
+            }
+        }
+
+        let path = request.path.split('?').next().unwrap_or_default();
+        match (request.method.as_str(), path) {
+            ("GET", "/entries") => {
+                let cache = self.cache.read().unwrap();
+                Response::json(200, entries_json(cache.iter(), false))
+            }
+            ("GET", "/stats") => {
+                let cache = self.cache.read().unwrap();
+                let (loudness, excluded) =
+                    Distribution::of_loudness(cache.values().map(|m| m.loudness).collect());
+                let psr = Psr::of(cache.values());
+                Response::json(200, stats::json(&loudness, excluded, None, psr.as_ref()))
+            }
+            ("POST", "/measure") => self.measure(&request.body),
+            ("GET", path) if path.starts_with("/entries/") => {
+                let Some(key) = percent_decode(&path["/entries/".len()..]) else {
+                    return Response::error(400, "malformed key");
+                };
+                let key = loudness::normalize_key(&key);
+                let cache = self.cache.read().unwrap();
+                match cache.get_key_value(&key) {
+                    Some(entry) => Response::json(200, entries_json(std::iter::once(entry), true)),
+                    None => Response::error(404, "no such entry"),
+                }
+            }
+            (_, "/entries" | "/stats" | "/measure") => Response::error(405, "method not allowed"),
+            _ => Response::error(404, "not found"),
+        }
+    }
+
+    /// Measures the file named in the body, adds it to the cache and saves.
+    fn measure(&self, body: &[u8]) -> Response {
+        let Ok(path) = std::str::from_utf8(body) else {
+            return Response::error(400, "body must be a UTF-8 path");
+        };
+        let path = PathBuf::from(path.trim());
+        if !path.is_file() {
+            return Response::error(422, "no such file");
+        }
+        let measurement = match measure(&path) {
+            Ok(m) => m,
+            Err(e) => return Response::error(422, &e),
+        };
+        let key = cache_key(&path);
+
+        // held across the save so concurrent requests don't interleave writes
+        let mut cache = self.cache.write().unwrap();
+        cache.insert(key.clone(), measurement.clone());
+        if let Err(e) = save_cache_as(&cache, &self.cache_path, self.cache_format) {
+            error!("failed to write cache '{}': {e}", self.cache_path.display());
+            return Response::error(500, "measured, but the cache couldn't be saved");
+        }
+        Response::json(
+            200,
+            entries_json(std::iter::once((&key, &measurement)), false),
+        )
+    }
+}
+
+fn serve_connection(server: &Server, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let (request_line, response) = match read_request(&mut reader) {
+        Ok(request) => {
+            let line = format!("{} {}", request.method, request.path);
+            (line, server.handle(&request))
+        }
+        Err(response) => ("-".to_string(), response),
+    };
+    info!("{request_line} {}", response.status);
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+/// The cache a long-running process answers from and adds to, empty if
+/// there's no file yet, with the format it's saved back in: the file's own.
+pub fn open_cache(path: &Path) -> Result<(HashMap<String, Measurement>, CacheFormat), String> {
+    let failed =
+        |e: &dyn std::fmt::Display| format!("failed to read cache '{}': {e}", path.display());
+    let format = cache_format_for(path).map_err(|e| failed(&e))?;
+    let cache = match path.exists() {
+        true => load_cache(path).map_err(|e| failed(&e))?,
+        false => HashMap::new(),
+    };
+    Ok((cache, format))
+}
+
+/// Serves until the process is killed; only returns early on startup errors.
+pub fn run(args: &ServeArgs) -> io::Result<bool> {
+    let cache_path = Path::new(&args.cache);
+    let (cache, cache_format) = match open_cache(cache_path) {
+        Ok(opened) => opened,
+        Err(e) => {
+            error!("{e}");
+            return Ok(false);
+        }
+    };
+
+    let listener = TcpListener::bind(&args.listen)?;
+    info!(
+        "serving '{}' ({} entries) on http://{}",
+        args.cache,
+        cache.len(),
+        listener.local_addr()?
+    );
+    let server = Arc::new(Server {
+        cache: RwLock::new(cache),
+        cache_path: cache_path.to_path_buf(),
+        cache_format,
+        token: args.token.clone(),
+    });
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let server = Arc::clone(&server);
+        std::thread::spawn(move || {
+            if let Err(e) = serve_connection(&server, stream) {
+                warn!("failed to respond: {e}");
+            }
+        });
+    }
+    Ok(true)
+}
@@ -0,0 +1,252 @@
+//! Distribution of integrated loudness across a cache, without touching any audio.
+
+use crate::album::album_loudness;
+use crate::cli::StatsArgs;
+use crate::report::JsonNumber;
+use crate::top::glob_match;
+use loudness::{load_cache, Measurement};
+use merde::json::{JsonSerialize, JsonSerializer};
+use std::io;
+use std::path::Path;
+
+/// Summary statistics of a set of values; NaN where the set is empty.
+pub struct Distribution {
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64,
+    /// Population standard deviation.
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p5: f64,
+    pub p25: f64,
+    pub p75: f64,
+    pub p95: f64,
+}
+
+/// Percentile `p` (0–100) of sorted values, interpolating linearly between
+/// the two closest ranks.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+impl Distribution {
+    pub fn of(mut values: Vec<f64>) -> Distribution {
+        values.sort_by(f64::total_cmp);
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+        Distribution {
+            count: values.len(),
+            mean,
+            median: percentile(&values, 50.0),
+            stddev: variance.sqrt(),
+            min: values.first().copied().unwrap_or(f64::NAN),
+            max: values.last().copied().unwrap_or(f64::NAN),
+            p5: percentile(&values, 5.0),
+            p25: percentile(&values, 25.0),
+            p75: percentile(&values, 75.0),
+            p95: percentile(&values, 95.0),
+        }
+    }
+
+    /// Distribution of usable loudness values, and how many were left out.
+    pub fn of_loudness(values: Vec<f64>) -> (Distribution, usize) {
+        let total = values.len();
+        // silent or failed measurements would drag every statistic to -inf
+        let usable = Distribution::of(values.into_iter().filter(|l| l.is_finite()).collect());
+        let excluded = total - usable.count;
+        (usable, excluded)
+    }
+
+    fn fields(&self) -> [(&'static str, f64); 9] {
+        [
+            ("mean", self.mean),
+            ("median", self.median),
+            ("stddev", self.stddev),
+            ("min", self.min),
+            ("max", self.max),
+            ("p5", self.p5),
+            ("p25", self.p25),
+            ("p75", self.p75),
+            ("p95", self.p95),
+        ]
+    }
+}
+
+/// PSR and mean PSR of the entries measured with --psr that have them.
+pub struct Psr {
+    pub psr: Distribution,
+    pub psr_avg: Distribution,
+}
+
+impl Psr {
+    /// `None` when no entry has a PSR.
+    pub fn of<'a>(entries: impl IntoIterator<Item = &'a Measurement>) -> Option<Psr> {
+        let (mut psr, mut psr_avg) = (vec![], vec![]);
+        for m in entries {
+            psr.extend(m.psr);
+            psr_avg.extend(m.psr_avg);
+        }
+        (!psr.is_empty()).then(|| Psr {
+            psr: Distribution::of(psr),
+            psr_avg: Distribution::of(psr_avg),
+        })
+    }
+}
+
+struct JsonDistribution<'a>(&'a Distribution);
+
+impl JsonSerialize for JsonDistribution<'_> {
+    fn json_serialize(&self, s: &mut JsonSerializer) {
+        let mut obj = s.write_obj();
+        obj.pair("count", &self.0.count);
+        for (name, value) in self.0.fields() {
+            obj.pair(name, &JsonNumber(value));
+        }
+    }
+}
+
+/// Loudness of a set of entries that accounts for how long each is, where
+/// the plain mean counts a short interlude as much as a long track.
+pub struct Weighted {
+    /// Mean loudness weighted by duration.
+    pub mean: f64,
+    /// Loudness of all of them played back to back, combined from their
+    /// gating energies like an album's; what a listener hears on average.
+    pub combined: f64,
+    /// Entries without a recorded duration, each weighted as one second.
+    pub unweighted: usize,
+}
+
+impl Weighted {
+    /// Of the entries with usable loudness; NaN where there are none.
+    pub fn of<'a>(entries: impl IntoIterator<Item = &'a Measurement>) -> Weighted {
+        let usable: Vec<&Measurement> = entries
+            .into_iter()
+            .filter(|m| m.loudness.is_finite())
+            .collect();
+        let (mut sum, mut weights, mut unweighted) = (0.0, 0.0, 0);
+        for m in &usable {
+            let weight = m.duration.unwrap_or_else(|| {
+                unweighted += 1;
+                1.0
+            });
+            sum += weight * m.loudness;
+            weights += weight;
+        }
+        Weighted {
+            mean: sum / weights,
+            combined: album_loudness(usable).unwrap_or(f64::NAN),
+            unweighted,
+        }
+    }
+}
+
+/// Renders the statistics as a JSON object.
+pub fn json(
+    loudness: &Distribution,
+    excluded: usize,
+    weighted: Option<&Weighted>,
+    psr: Option<&Psr>,
+) -> String {
+    let mut s = JsonSerializer::new();
+    {
+        let mut obj = s.write_obj();
+        obj.pair("count", &loudness.count);
+        obj.pair("excluded", &excluded);
+        for (name, value) in loudness.fields() {
+            obj.pair(name, &JsonNumber(value));
+        }
+        if let Some(weighted) = weighted {
+            obj.pair("weighted_mean", &JsonNumber(weighted.mean));
+            obj.pair("combined", &JsonNumber(weighted.combined));
+            obj.pair("unweighted", &weighted.unweighted);
+        }
+        if let Some(psr) = psr {
+            obj.pair("psr", &JsonDistribution(&psr.psr));
+            obj.pair("psr_avg", &JsonDistribution(&psr.psr_avg));
+        }
+    }
+    String::from_utf8(s.into_inner()).unwrap()
+}
+
+/// Prints the statistics, returning false if the cache couldn't be read.
+pub fn run(args: &StatsArgs) -> io::Result<bool> {
+    let cache = match load_cache(Path::new(&args.cache)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.cache);
+            return Ok(false);
+        }
+    };
+
+    let matching: Vec<&Measurement> = cache
+        .iter()
+        .filter(|(k, _)| args.pattern.as_ref().is_none_or(|p| glob_match(p, k)))
+        .map(|(_, m)| m)
+        .collect();
+    let weighted = args
+        .weighted
+        .then(|| Weighted::of(matching.iter().copied()));
+    let (loudness, excluded) =
+        Distribution::of_loudness(matching.iter().map(|m| m.loudness).collect());
+    let psr = Psr::of(matching.iter().copied());
+
+    if args.json {
+        println!(
+            "{}",
+            json(&loudness, excluded, weighted.as_ref(), psr.as_ref())
+        );
+        return Ok(true);
+    }
+
+    println!("count     {}", loudness.count);
+    if excluded > 0 {
+        eprintln!("excluded  {excluded} without usable loudness");
+    }
+    for (name, value) in loudness.fields() {
+        let unit = if name == "stddev" { "LU" } else { "LUFS" };
+        if value.is_finite() {
+            println!("{name:<9} {value:>7.2} {unit}");
+        } else {
+            println!("{name:<9} {:>7}", "-");
+        }
+    }
+    if let Some(weighted) = weighted {
+        let rows = [
+            ("weighted", weighted.mean, "mean weighted by duration"),
+            ("combined", weighted.combined, "all played back to back"),
+        ];
+        for (name, value, what) in rows {
+            if value.is_finite() {
+                println!("{name:<9} {value:>7.2} LUFS  ({what})");
+            } else {
+                println!("{name:<9} {:>7}", "-");
+            }
+        }
+        if weighted.unweighted > 0 {
+            eprintln!(
+                "{} without a duration, weighted as one second each",
+                weighted.unweighted
+            );
+        }
+    }
+    if let Some(psr) = psr {
+        let cell = |value: f64| match value.is_finite() {
+            true => format!("{value:>7.2}"),
+            false => format!("{:>7}", "-"),
+        };
+        println!("psr count {}", psr.psr.count);
+        println!("{:<9} {:>7} {:>7}", "", "psr", "psr_avg");
+        for ((name, value), (_, avg)) in psr.psr.fields().into_iter().zip(psr.psr_avg.fields()) {
+            println!("{name:<9} {} {} dB", cell(value), cell(avg));
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,104 @@
+//! Which entries of a cache are normalized to a target, and which still
+//! need fixing.
+
+use crate::cli::{StatusArgs, Units};
+use loudness::load_cache;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Ok,
+    TooLoud,
+    TooQuiet,
+    /// No usable loudness, e.g. silent.
+    Unknown,
+}
+
+const CLASSES: &[(Class, &str)] = &[
+    (Class::TooLoud, "TOO LOUD"),
+    (Class::TooQuiet, "TOO QUIET"),
+    (Class::Unknown, "UNKNOWN"),
+    (Class::Ok, "OK"),
+];
+
+fn classify(loudness: f64, target: f64, tolerance: f64) -> Class {
+    let off = loudness - target;
+    if !off.is_finite() {
+        Class::Unknown
+    } else if off > tolerance {
+        Class::TooLoud
+    } else if off < -tolerance {
+        Class::TooQuiet
+    } else {
+        Class::Ok
+    }
+}
+
+/// Prints the entries outside the tolerance by class, returning false if
+/// there were any or the cache couldn't be read.
+pub fn run(args: &StatusArgs) -> io::Result<bool> {
+    let cache = match load_cache(Path::new(&args.cache)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.cache);
+            return Ok(false);
+        }
+    };
+
+    let mut entries: Vec<(&str, f64, Class)> = cache
+        .iter()
+        .map(|(k, m)| {
+            let class = classify(m.loudness, args.target, args.tolerance);
+            (k.as_str(), m.loudness, class)
+        })
+        .collect();
+    // furthest off first, unknown ones by key
+    entries.sort_by(|(ka, a, _), (kb, b, _)| {
+        let off = |l: f64| (l - args.target).abs();
+        let (a, b) = (off(*a), off(*b));
+        b.is_finite()
+            .cmp(&a.is_finite())
+            .then(b.total_cmp(&a))
+            .then(ka.cmp(kb))
+    });
+
+    let width = entries
+        .iter()
+        .filter(|(_, _, c)| *c != Class::Ok)
+        .map(|(k, _, _)| k.chars().count())
+        .max();
+    let mut counts = vec![];
+    for (class, name) in CLASSES {
+        let members: Vec<_> = entries.iter().filter(|(_, _, c)| c == class).collect();
+        counts.push(format!("{name} {}", members.len()));
+        if *class == Class::Ok || members.is_empty() {
+            continue;
+        }
+        println!("{name} ({})", members.len());
+        for (key, loudness, _) in members {
+            let off = match class {
+                Class::Unknown => String::new(),
+                _ => format!("  {:>+7.2} LU", loudness - args.target),
+            };
+            println!(
+                "  {key:<width$}  {:>11}{off}",
+                Units::Lufs.format(*loudness, None),
+                width = width.unwrap_or(0),
+            );
+        }
+    }
+
+    let outside = entries
+        .iter()
+        .filter(|(_, _, c)| matches!(c, Class::TooLoud | Class::TooQuiet))
+        .count();
+    eprintln!(
+        "{} entries against {} LUFS ± {} LU: {}",
+        entries.len(),
+        args.target,
+        args.tolerance,
+        counts.join(", ")
+    );
+    Ok(outside == 0)
+}
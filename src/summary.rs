@@ -0,0 +1,201 @@
+//! Counters collected across the worker threads and the end-of-run report.
+
+use crate::report::Timing;
+use loudness::SHORT_DURATION;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How many failed paths of each kind are listed in the summary.
+const FAILURE_EXAMPLES: usize = 5;
+
+/// A file that failed, for the summary and --failed-out.
+#[derive(Clone)]
+pub struct Failure {
+    pub path: PathBuf,
+    /// The kind of failure, which the summary groups by.
+    pub class: &'static str,
+    /// The step that failed, e.g. "probe".
+    pub stage: &'static str,
+    pub reason: String,
+}
+
+pub struct Summary {
+    started: Instant,
+    /// Directory entries looked at, including filtered ones.
+    pub scanned: usize,
+    /// Entries excluded before measuring, e.g. by extension.
+    pub filtered: usize,
+    measured: AtomicUsize,
+    cached: AtomicUsize,
+    /// Measured files too short for integrated loudness.
+    short: AtomicUsize,
+    failures: Mutex<Vec<Failure>>,
+    /// Files never started, or cancelled part way, because the run was stopped early.
+    not_started: AtomicUsize,
+    /// Total audio duration measured, in milliseconds.
+    audio_ms: AtomicUsize,
+    /// Bytes read by timed files; only recorded under --timings.
+    bytes_read: AtomicU64,
+    timed: AtomicUsize,
+    /// The --limit in effect and how many pending files it left for later.
+    limited: Option<(usize, usize)>,
+}
+
+impl Summary {
+    pub fn new() -> Summary {
+        Summary {
+            started: Instant::now(),
+            scanned: 0,
+            filtered: 0,
+            measured: AtomicUsize::new(0),
+            cached: AtomicUsize::new(0),
+            short: AtomicUsize::new(0),
+            failures: Mutex::new(vec![]),
+            not_started: AtomicUsize::new(0),
+            audio_ms: AtomicUsize::new(0),
+            bytes_read: AtomicU64::new(0),
+            timed: AtomicUsize::new(0),
+            limited: None,
+        }
+    }
+
+    pub fn measured(&self, duration: Option<f64>) {
+        self.measured.fetch_add(1, Ordering::Relaxed);
+        if let Some(secs) = duration {
+            self.audio_ms
+                .fetch_add((secs * 1000.0) as usize, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a measured file as short, on top of `measured`.
+    pub fn short(&self) {
+        self.short.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn timed(&self, timing: &Timing) {
+        self.timed.fetch_add(1, Ordering::Relaxed);
+        self.bytes_read.fetch_add(timing.bytes, Ordering::Relaxed);
+    }
+
+    /// Time since the run started.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn limited(&mut self, limit: usize, remaining: usize) {
+        self.limited = Some((limit, remaining));
+    }
+
+    pub fn cached(&self) {
+        self.cached.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn not_started(&self) {
+        self.not_started.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn failed(&self, failure: Failure) {
+        self.failures.lock().unwrap().push(failure);
+    }
+
+    /// Every failure so far, by path.
+    pub fn failures(&self) -> Vec<Failure> {
+        let mut failures = self.failures.lock().unwrap().clone();
+        failures.sort_by(|a, b| a.path.cmp(&b.path));
+        failures
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.failures.lock().unwrap().len()
+    }
+
+    /// Renders the summary block; `outfile` is the path written and its entry count.
+    pub fn render(&self, outfile: Option<(&Path, usize)>) -> String {
+        let wall = self.started.elapsed();
+        let audio = Duration::from_millis(self.audio_ms.load(Ordering::Relaxed) as u64);
+        let failures = self.failures();
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "scanned:  {} ({} filtered out)",
+            self.scanned, self.filtered
+        );
+        let _ = writeln!(out, "measured: {}", self.measured.load(Ordering::Relaxed));
+        let short = self.short.load(Ordering::Relaxed);
+        if short > 0 {
+            let _ = writeln!(
+                out,
+                "  ({short} under {SHORT_DURATION} s, measured ungated)"
+            );
+        }
+        let _ = writeln!(out, "cached:   {}", self.cached.load(Ordering::Relaxed));
+        let _ = writeln!(out, "failed:   {}", failures.len());
+        // grouped by kind, most common first, with the first few of each
+        let mut classes: Vec<(&str, Vec<&Failure>)> = vec![];
+        for failure in &failures {
+            match classes.iter_mut().find(|(c, _)| *c == failure.class) {
+                Some((_, of_class)) => of_class.push(failure),
+                None => classes.push((failure.class, vec![failure])),
+            }
+        }
+        classes.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(b.0)));
+        for (class, of_class) in &classes {
+            let _ = writeln!(out, "  {class}: {}", of_class.len());
+            for failure in of_class.iter().take(FAILURE_EXAMPLES) {
+                let _ = writeln!(out, "    {}: {}", failure.path.display(), failure.reason);
+            }
+            if of_class.len() > FAILURE_EXAMPLES {
+                let _ = writeln!(
+                    out,
+                    "    ...and {} more (--failed-out lists them all)",
+                    of_class.len() - FAILURE_EXAMPLES
+                );
+            }
+        }
+        let not_started = self.not_started.load(Ordering::Relaxed);
+        if not_started > 0 {
+            let _ = writeln!(out, "stopped:  {not_started} file(s) not measured");
+        }
+        if let Some((limit, remaining)) = self.limited {
+            let _ = writeln!(
+                out,
+                "limited:  --limit {limit}, {remaining} pending file(s) left for later runs"
+            );
+        }
+        let realtime = match wall.as_secs_f64() {
+            0.0 => 0.0,
+            w => audio.as_secs_f64() / w,
+        };
+        let _ = writeln!(
+            out,
+            "audio:    {} in {} ({realtime:.1}x realtime, {} workers)",
+            format_duration(audio),
+            format_duration(wall),
+            rayon::current_num_threads()
+        );
+        if self.timed.load(Ordering::Relaxed) > 0 {
+            let mb = self.bytes_read.load(Ordering::Relaxed) as f64 / 1e6;
+            let rate = match wall.as_secs_f64() {
+                0.0 => 0.0,
+                w => mb / w,
+            };
+            let _ = writeln!(
+                out,
+                "read:     {mb:.1} MB at {rate:.1} MB/s, {realtime:.1} audio hours per wall hour"
+            );
+        }
+        if let Some((path, entries)) = outfile {
+            let _ = writeln!(out, "outfile:  {} ({entries} entries)", path.display());
+        }
+        out
+    }
+}
+
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
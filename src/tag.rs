@@ -0,0 +1,1014 @@
+//! Reading and rewriting ReplayGain tags.
+//!
+//! Only the fields this tool owns are touched; every other frame, comment and
+//! metadata block is copied through byte for byte. mp3 files carry the values
+//! as ID3v2 TXXX frames, with older RVA2 volume adjustments only ever
+//! removed; flac and Ogg Vorbis files as Vorbis comments. MP4 files are
+//! reported as unsupported.
+
+use crate::album::{album_of, albums, linear_peak};
+use crate::cli::{TagArgs, UntagArgs, VerifyTagsArgs};
+use loudness::{cache_key, load_cache};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+pub const TRACK_GAIN: &str = "REPLAYGAIN_TRACK_GAIN";
+pub const ALBUM_GAIN: &str = "REPLAYGAIN_ALBUM_GAIN";
+pub const TRACK_PEAK: &str = "REPLAYGAIN_TRACK_PEAK";
+
+/// Whether a field holds loudness normalization data (ReplayGain, R128 gain
+/// or an RVA2 volume adjustment).
+pub fn is_gain_field(key: &str) -> bool {
+    let key = key.to_ascii_uppercase();
+    key.starts_with("REPLAYGAIN_")
+        || (key.starts_with("R128_") && key.ends_with("_GAIN"))
+        || key.starts_with("RVA2:")
+}
+
+/// Tag container used by a file, determined from its extension.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TagFormat {
+    Id3v2,
+    /// Vorbis comments in a flac metadata block.
+    Vorbis,
+    /// Vorbis comments in the comment header of an Ogg Vorbis stream.
+    Ogg,
+}
+
+impl TagFormat {
+    pub fn for_path(path: &Path) -> Option<TagFormat> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "mp3" => Some(TagFormat::Id3v2),
+            "flac" => Some(TagFormat::Vorbis),
+            "ogg" | "oga" => Some(TagFormat::Ogg),
+            _ => None,
+        }
+    }
+}
+
+/// Extensions of MP4 files, whose tags sit in an atom tree that would need
+/// its chunk offsets rewritten; they're reported and left alone.
+const UNSUPPORTED: &[&str] = &["m4a", "m4b", "mp4"];
+
+/// Formats a gain in dB the way ReplayGain readers expect, e.g. "-3.25 dB".
+pub fn format_gain(gain: f64) -> String {
+    format!("{gain:+.2} dB")
+}
+
+/// Parses a gain value as written by ReplayGain taggers, e.g. "-3.25 dB".
+pub fn parse_gain(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .or_else(|| value.strip_suffix("DB"))
+        .unwrap_or(value);
+    number.trim().parse().ok()
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// The tag section of an audio file, plus where its audio data starts.
+pub struct TaggedFile {
+    path: PathBuf,
+    tags: Tags,
+    audio_offset: u64,
+}
+
+enum Tags {
+    Id3v2 { version: u8, frames: Vec<Id3Frame> },
+    Vorbis(FlacMetadata),
+    Ogg(OggMetadata),
+}
+
+struct Id3Frame {
+    id: [u8; 4],
+    flags: [u8; 2],
+    data: Vec<u8>,
+}
+
+struct FlacMetadata {
+    /// Anything in front of "fLaC", usually an ID3v2 tag some taggers add.
+    prefix: Vec<u8>,
+    /// Non-comment metadata blocks as (type, body).
+    blocks: Vec<(u8, Vec<u8>)>,
+    /// Index into `blocks` the comment block is written at.
+    comment_index: usize,
+    comment: VorbisComment,
+}
+
+struct VorbisComment {
+    vendor: Vec<u8>,
+    comments: Vec<String>,
+}
+
+/// The headers of an Ogg Vorbis stream: its first page, holding the
+/// identification header, then the comment and setup headers, which are
+/// paged again when written.
+struct OggMetadata {
+    first: OggPage,
+    comment: VorbisComment,
+    setup: Vec<u8>,
+    /// Pages the comment and setup headers took up when read.
+    pages: u32,
+}
+
+struct OggPage {
+    flags: u8,
+    granule: u64,
+    serial: u32,
+    sequence: u32,
+    /// Lacing values, the sizes of the packet segments in `data`.
+    segments: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl TaggedFile {
+    pub fn open(path: &Path, format: TagFormat) -> io::Result<TaggedFile> {
+        let mut file = File::open(path)?;
+        let (tags, audio_offset) = match format {
+            TagFormat::Id3v2 => read_id3v2(&mut file)?,
+            TagFormat::Vorbis => read_flac(&mut file)?,
+            TagFormat::Ogg => read_ogg(&mut file)?,
+        };
+        Ok(TaggedFile {
+            path: path.to_path_buf(),
+            tags,
+            audio_offset,
+        })
+    }
+
+    /// All user text fields as (key, value) pairs, in file order. RVA2
+    /// frames are listed as `RVA2:<identification>` with their adjustment.
+    pub fn fields(&self) -> Vec<(String, String)> {
+        match &self.tags {
+            Tags::Id3v2 { frames, .. } => frames.iter().filter_map(Id3Frame::field).collect(),
+            Tags::Vorbis(FlacMetadata { comment, .. }) | Tags::Ogg(OggMetadata { comment, .. }) => {
+                comment
+                    .comments
+                    .iter()
+                    .filter_map(|c| c.split_once('='))
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect()
+            }
+        }
+    }
+
+    /// Values of every field named `key` (compared case-insensitively).
+    pub fn get(&self, key: &str) -> Vec<String> {
+        self.fields()
+            .into_iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    /// Removes every field for which `remove` returns true, returning how many were removed.
+    pub fn remove_where(&mut self, mut remove: impl FnMut(&str) -> bool) -> usize {
+        match &mut self.tags {
+            Tags::Id3v2 { frames, .. } => {
+                let before = frames.len();
+                frames.retain(|f| !f.field().is_some_and(|(k, _)| remove(&k)));
+                before - frames.len()
+            }
+            Tags::Vorbis(FlacMetadata { comment, .. }) | Tags::Ogg(OggMetadata { comment, .. }) => {
+                let before = comment.comments.len();
+                comment
+                    .comments
+                    .retain(|c| !c.split_once('=').is_some_and(|(k, _)| remove(k)));
+                before - comment.comments.len()
+            }
+        }
+    }
+
+    /// Replaces all fields named `key` with a single one holding `value`.
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.remove_where(|k| k.eq_ignore_ascii_case(key));
+        match &mut self.tags {
+            Tags::Id3v2 { version, frames } => frames.push(Id3Frame {
+                id: *b"TXXX",
+                flags: [0, 0],
+                data: encode_txxx(*version, key, value),
+            }),
+            Tags::Vorbis(FlacMetadata { comment, .. }) | Tags::Ogg(OggMetadata { comment, .. }) => {
+                comment.comments.push(format!("{key}={value}"))
+            }
+        }
+    }
+
+    /// Writes the modified tags back, copying the audio data unchanged, but
+    /// for the sequence numbers of Ogg pages after headers that now take up
+    /// a different number of pages.
+    ///
+    /// The new file is written next to the original and renamed over it, so an
+    /// interrupted run never leaves a half-written file behind.
+    pub fn save(&self) -> io::Result<()> {
+        // the serial of the pages to renumber, and by how much
+        let mut renumber = None;
+        let header = match &self.tags {
+            Tags::Id3v2 { version, frames } => write_id3v2(*version, frames)?,
+            Tags::Vorbis(meta) => write_flac(meta)?,
+            Tags::Ogg(meta) => {
+                let (header, pages) = write_ogg(meta)?;
+                if pages != meta.pages {
+                    renumber = Some((meta.first.serial, pages.wrapping_sub(meta.pages)));
+                }
+                header
+            }
+        };
+
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".loudness-tmp");
+        let tmp = self.path.with_file_name(tmp_name);
+
+        let result = (|| {
+            let mut source = File::open(&self.path)?;
+            source.seek(SeekFrom::Start(self.audio_offset))?;
+            let mut out = File::create(&tmp)?;
+            out.write_all(&header)?;
+            match renumber {
+                None => {
+                    io::copy(&mut source, &mut out)?;
+                }
+                Some((serial, by)) => {
+                    let mut pages = io::BufReader::new(&mut source);
+                    let mut to = io::BufWriter::new(&mut out);
+                    while let Some(mut page) = read_ogg_page(&mut pages)? {
+                        if page.serial == serial {
+                            page.sequence = page.sequence.wrapping_add(by);
+                        }
+                        to.write_all(&page.to_bytes())?;
+                    }
+                    to.flush()?;
+                }
+            }
+            out.sync_all()?;
+            std::fs::set_permissions(&tmp, source.metadata()?.permissions())?;
+            std::fs::rename(&tmp, &self.path)
+        })();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        result
+    }
+}
+
+impl Id3Frame {
+    /// The user text field this frame holds, if it's a TXXX or RVA2 frame
+    /// that isn't compressed or encrypted.
+    fn field(&self) -> Option<(String, String)> {
+        if self.flags[1] != 0 {
+            return None;
+        }
+        match &self.id {
+            b"TXXX" => decode_txxx(&self.data),
+            b"RVA2" => decode_rva2(&self.data),
+            _ => None,
+        }
+    }
+}
+
+fn syncsafe(b: &[u8]) -> usize {
+    b.iter().fold(0, |acc, &x| (acc << 7) | (x & 0x7f) as usize)
+}
+
+fn to_syncsafe(n: usize) -> io::Result<[u8; 4]> {
+    if n >= 1 << 28 {
+        return Err(invalid("ID3v2 tag too large"));
+    }
+    Ok([
+        (n >> 21) as u8 & 0x7f,
+        (n >> 14) as u8 & 0x7f,
+        (n >> 7) as u8 & 0x7f,
+        n as u8 & 0x7f,
+    ])
+}
+
+fn read_id3v2(file: &mut File) -> io::Result<(Tags, u64)> {
+    let mut header = [0u8; 10];
+    let len = file.read(&mut header)?;
+    if len < 10 || &header[..3] != b"ID3" {
+        // untagged, a fresh tag is written in front of the audio
+        return Ok((
+            Tags::Id3v2 {
+                version: 3,
+                frames: vec![],
+            },
+            0,
+        ));
+    }
+
+    let version = header[3];
+    let flags = header[5];
+    let size = syncsafe(&header[6..10]);
+    if version != 3 && version != 4 {
+        return Err(invalid(format!("unsupported ID3v2.{version} tag")));
+    }
+    if flags & 0x80 != 0 {
+        return Err(invalid("unsynchronised ID3v2 tags are not supported"));
+    }
+
+    let mut body = vec![0u8; size];
+    file.read_exact(&mut body)?;
+    let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+
+    let mut pos = 0;
+    if flags & 0x40 != 0 {
+        // skip the extended header, it is dropped on rewrite
+        if body.len() < 4 {
+            return Err(invalid("truncated ID3v2 extended header"));
+        }
+        pos = match version {
+            3 => 4 + u32::from_be_bytes(body[..4].try_into().unwrap()) as usize,
+            _ => syncsafe(&body[..4]),
+        };
+    }
+
+    let mut frames = vec![];
+    while pos + 10 <= body.len() && body[pos] != 0 {
+        let id: [u8; 4] = body[pos..pos + 4].try_into().unwrap();
+        let frame_size = match version {
+            3 => u32::from_be_bytes(body[pos + 4..pos + 8].try_into().unwrap()) as usize,
+            _ => syncsafe(&body[pos + 4..pos + 8]),
+        };
+        let flags = [body[pos + 8], body[pos + 9]];
+        let start = pos + 10;
+        if start + frame_size > body.len() {
+            return Err(invalid("ID3v2 frame extends past the end of the tag"));
+        }
+        frames.push(Id3Frame {
+            id,
+            flags,
+            data: body[start..start + frame_size].to_vec(),
+        });
+        pos = start + frame_size;
+    }
+
+    Ok((Tags::Id3v2 { version, frames }, (10 + size + footer) as u64))
+}
+
+fn write_id3v2(version: u8, frames: &[Id3Frame]) -> io::Result<Vec<u8>> {
+    let mut body = vec![];
+    for frame in frames {
+        body.extend_from_slice(&frame.id);
+        match version {
+            3 => body.extend_from_slice(&(frame.data.len() as u32).to_be_bytes()),
+            _ => body.extend_from_slice(&to_syncsafe(frame.data.len())?),
+        }
+        body.extend_from_slice(&frame.flags);
+        body.extend_from_slice(&frame.data);
+    }
+
+    let mut out = Vec::with_capacity(10 + body.len());
+    out.extend_from_slice(b"ID3");
+    out.extend_from_slice(&[version, 0, 0]);
+    out.extend_from_slice(&to_syncsafe(body.len())?);
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+/// Decodes text in one of the four ID3v2 encodings, dropping a trailing terminator.
+fn decode_text(encoding: u8, bytes: &[u8]) -> Option<String> {
+    let text = match encoding {
+        0 => bytes.iter().map(|&b| b as char).collect(),
+        3 => String::from_utf8(bytes.to_vec()).ok()?,
+        1 | 2 => {
+            let mut units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            if encoding == 1 {
+                match units.first() {
+                    Some(0xfeff) => {
+                        units.remove(0);
+                    }
+                    Some(0xfffe) => {
+                        units.remove(0);
+                        units.iter_mut().for_each(|u| *u = u.swap_bytes());
+                    }
+                    _ => {}
+                }
+            }
+            String::from_utf16(&units).ok()?
+        }
+        _ => return None,
+    };
+    Some(text.trim_end_matches('\0').to_string())
+}
+
+fn decode_txxx(data: &[u8]) -> Option<(String, String)> {
+    let (&encoding, rest) = data.split_first()?;
+    let split = match encoding {
+        1 | 2 => rest
+            .chunks_exact(2)
+            .position(|c| c == [0, 0])
+            .map(|i| (i * 2, i * 2 + 2)),
+        _ => rest.iter().position(|&b| b == 0).map(|i| (i, i + 1)),
+    };
+    let (end, value_start) = split?;
+    let key = decode_text(encoding, &rest[..end])?;
+    // a UTF-16 value carries its own byte order mark
+    let value = decode_text(encoding, &rest[value_start..])?;
+    Some((key, value))
+}
+
+/// An RVA2 frame as `RVA2:<identification>` and its adjustment in dB, of
+/// the master volume channel if there's one, else the first.
+fn decode_rva2(data: &[u8]) -> Option<(String, String)> {
+    let end = data.iter().position(|&b| b == 0)?;
+    let key = format!("RVA2:{}", decode_text(0, &data[..end])?);
+    let mut channels = vec![];
+    let mut rest = &data[end + 1..];
+    // channel type, adjustment in 1/512 dB, peak size in bits, then the peak
+    while let [kind, high, low, bits, tail @ ..] = rest {
+        channels.push((*kind, i16::from_be_bytes([*high, *low])));
+        rest = tail.get(usize::from(*bits).div_ceil(8)..)?;
+    }
+    let value = channels
+        .iter()
+        .find(|(kind, _)| *kind == 1)
+        .or(channels.first())
+        .map_or(String::new(), |(_, adjustment)| {
+            format_gain(f64::from(*adjustment) / 512.0)
+        });
+    Some((key, value))
+}
+
+fn encode_txxx(version: u8, key: &str, value: &str) -> Vec<u8> {
+    let text = format!("{key}\0{value}");
+    if text.is_ascii() {
+        let mut data = vec![0];
+        data.extend_from_slice(text.as_bytes());
+        data
+    } else if version == 4 {
+        let mut data = vec![3];
+        data.extend_from_slice(text.as_bytes());
+        data
+    } else {
+        let mut data = vec![1];
+        for part in [key, value] {
+            if data.len() > 1 {
+                data.extend_from_slice(&[0, 0]);
+            }
+            data.extend_from_slice(&[0xff, 0xfe]);
+            data.extend(part.encode_utf16().flat_map(u16::to_le_bytes));
+        }
+        data
+    }
+}
+
+const FLAC_VORBIS_COMMENT: u8 = 4;
+
+fn read_flac(file: &mut File) -> io::Result<(Tags, u64)> {
+    let mut prefix = vec![];
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic)?;
+    if &magic[..3] == b"ID3" {
+        // an ID3v2 tag in front of the stream, kept as-is
+        let mut rest = [0u8; 6];
+        file.read_exact(&mut rest)?;
+        let size = syncsafe(&rest[2..6]) + if rest[1] & 0x10 != 0 { 10 } else { 0 };
+        prefix.extend_from_slice(&magic);
+        prefix.extend_from_slice(&rest);
+        let mut tag = vec![0u8; size];
+        file.read_exact(&mut tag)?;
+        prefix.extend_from_slice(&tag);
+        file.read_exact(&mut magic)?;
+    }
+    if &magic != b"fLaC" {
+        return Err(invalid("not a flac stream"));
+    }
+
+    let mut blocks = vec![];
+    let mut comment = None;
+    loop {
+        let mut header = [0u8; 4];
+        file.read_exact(&mut header)?;
+        let last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7f;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let mut body = vec![0u8; len];
+        file.read_exact(&mut body)?;
+        if kind == FLAC_VORBIS_COMMENT && comment.is_none() {
+            comment = Some((blocks.len(), body));
+        } else {
+            blocks.push((kind, body));
+        }
+        if last {
+            break;
+        }
+    }
+    let audio_offset = file.stream_position()?;
+
+    let (comment_index, comment) = match comment {
+        Some((index, body)) => (index, parse_vorbis_comment(&body)?),
+        // right after STREAMINFO
+        None => (
+            blocks.len().min(1),
+            VorbisComment {
+                vendor: b"loudness".to_vec(),
+                comments: vec![],
+            },
+        ),
+    };
+
+    Ok((
+        Tags::Vorbis(FlacMetadata {
+            prefix,
+            blocks,
+            comment_index,
+            comment,
+        }),
+        audio_offset,
+    ))
+}
+
+fn parse_vorbis_comment(body: &[u8]) -> io::Result<VorbisComment> {
+    let mut pos = 0;
+    let next_u32 = |pos: &mut usize| -> io::Result<usize> {
+        let bytes = body
+            .get(*pos..*pos + 4)
+            .ok_or_else(|| invalid("truncated vorbis comment block"))?;
+        *pos += 4;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    };
+
+    let vendor_len = next_u32(&mut pos)?;
+    let vendor = body
+        .get(pos..pos + vendor_len)
+        .ok_or_else(|| invalid("truncated vorbis comment vendor"))?
+        .to_vec();
+    pos += vendor_len;
+
+    let count = next_u32(&mut pos)?;
+    let mut comments = Vec::with_capacity(count.min(1024));
+    for _ in 0..count {
+        let len = next_u32(&mut pos)?;
+        let bytes = body
+            .get(pos..pos + len)
+            .ok_or_else(|| invalid("truncated vorbis comment"))?;
+        comments.push(String::from_utf8_lossy(bytes).into_owned());
+        pos += len;
+    }
+    Ok(VorbisComment { vendor, comments })
+}
+
+fn write_vorbis_comment(comment: &VorbisComment) -> Vec<u8> {
+    let mut out = vec![];
+    out.extend_from_slice(&(comment.vendor.len() as u32).to_le_bytes());
+    out.extend_from_slice(&comment.vendor);
+    out.extend_from_slice(&(comment.comments.len() as u32).to_le_bytes());
+    for c in &comment.comments {
+        out.extend_from_slice(&(c.len() as u32).to_le_bytes());
+        out.extend_from_slice(c.as_bytes());
+    }
+    out
+}
+
+fn write_flac(meta: &FlacMetadata) -> io::Result<Vec<u8>> {
+    let comment = write_vorbis_comment(&meta.comment);
+
+    let mut blocks: Vec<(u8, &[u8])> = meta.blocks.iter().map(|(k, b)| (*k, &b[..])).collect();
+    blocks.insert(meta.comment_index, (FLAC_VORBIS_COMMENT, &comment));
+
+    let mut out = meta.prefix.clone();
+    out.extend_from_slice(b"fLaC");
+    for (i, (kind, body)) in blocks.iter().enumerate() {
+        if body.len() >= 1 << 24 {
+            return Err(invalid("flac metadata block too large"));
+        }
+        let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+        out.push(last | kind);
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        out.extend_from_slice(body);
+    }
+    Ok(out)
+}
+
+/// CRC-32 of an Ogg page with its checksum field zeroed: polynomial
+/// 0x04c11db7, MSB first, from zero.
+fn ogg_crc(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte) << 24, |crc, _| {
+            (crc << 1)
+                ^ if crc & 0x8000_0000 != 0 {
+                    0x04c1_1db7
+                } else {
+                    0
+                }
+        })
+    })
+}
+
+/// The next page of an Ogg stream, or `None` at its end.
+fn read_ogg_page(from: &mut impl Read) -> io::Result<Option<OggPage>> {
+    let mut header = [0u8; 27];
+    match from.read_exact(&mut header) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    if &header[..4] != b"OggS" || header[4] != 0 {
+        return Err(invalid("not an Ogg page"));
+    }
+    let mut segments = vec![0u8; usize::from(header[26])];
+    from.read_exact(&mut segments)?;
+    let mut data = vec![0u8; segments.iter().map(|&s| usize::from(s)).sum()];
+    from.read_exact(&mut data)?;
+    Ok(Some(OggPage {
+        flags: header[5],
+        granule: u64::from_le_bytes(header[6..14].try_into().unwrap()),
+        serial: u32::from_le_bytes(header[14..18].try_into().unwrap()),
+        sequence: u32::from_le_bytes(header[18..22].try_into().unwrap()),
+        segments,
+        data,
+    }))
+}
+
+impl OggPage {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(27 + self.segments.len() + self.data.len());
+        out.extend_from_slice(b"OggS\0");
+        out.push(self.flags);
+        out.extend_from_slice(&self.granule.to_le_bytes());
+        out.extend_from_slice(&self.serial.to_le_bytes());
+        out.extend_from_slice(&self.sequence.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.push(self.segments.len() as u8);
+        out.extend_from_slice(&self.segments);
+        out.extend_from_slice(&self.data);
+        let crc = ogg_crc(&out);
+        out[22..26].copy_from_slice(&crc.to_le_bytes());
+        out
+    }
+}
+
+fn read_ogg(file: &mut File) -> io::Result<(Tags, u64)> {
+    let mut pages = io::BufReader::new(&mut *file);
+    let first = read_ogg_page(&mut pages)?.ok_or_else(|| invalid("not an Ogg stream"))?;
+    if !first.data.starts_with(b"\x01vorbis") {
+        return Err(invalid("only Vorbis streams in Ogg are supported"));
+    }
+    if first.segments.last().is_none_or(|&s| s == 255) {
+        return Err(invalid(
+            "Vorbis identification header isn't alone on its page",
+        ));
+    }
+    // the comment and setup headers follow on pages of their own, audio
+    // starting on a fresh page after them
+    let (mut packets, mut packet, mut count) = (vec![], vec![], 0);
+    while packets.len() < 2 {
+        let page = read_ogg_page(&mut pages)?
+            .ok_or_else(|| invalid("Ogg stream ends within its headers"))?;
+        if page.serial != first.serial {
+            return Err(invalid("multiplexed Ogg streams are not supported"));
+        }
+        count += 1;
+        let mut pos = 0;
+        for &size in &page.segments {
+            packet.extend_from_slice(&page.data[pos..pos + usize::from(size)]);
+            pos += usize::from(size);
+            if size < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+    }
+    if packets.len() > 2 || !packet.is_empty() {
+        return Err(invalid("Ogg audio starts on a header page"));
+    }
+    let setup = packets.pop().unwrap();
+    let comment = packets.pop().unwrap();
+    let body = comment
+        .strip_prefix(b"\x03vorbis")
+        .ok_or_else(|| invalid("no Vorbis comment header"))?;
+    let comment = parse_vorbis_comment(body)?;
+    // where the reader stopped, not how far it buffered
+    let audio_offset = pages.stream_position()?;
+    Ok((
+        Tags::Ogg(OggMetadata {
+            first,
+            comment,
+            setup,
+            pages: count,
+        }),
+        audio_offset,
+    ))
+}
+
+/// The stream's headers, with the comment and setup headers paged again,
+/// and how many pages those took.
+fn write_ogg(meta: &OggMetadata) -> io::Result<(Vec<u8>, u32)> {
+    let mut comment = b"\x03vorbis".to_vec();
+    comment.extend_from_slice(&write_vorbis_comment(&meta.comment));
+    // the framing bit
+    comment.push(1);
+
+    let mut lacing = vec![];
+    for packet in [&comment, &meta.setup] {
+        lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+        lacing.push((packet.len() % 255) as u8);
+    }
+    let data = [comment.as_slice(), &meta.setup].concat();
+
+    let mut out = meta.first.to_bytes();
+    let (mut pos, mut continued, mut count) = (0, false, 0u32);
+    for segments in lacing.chunks(255) {
+        count += 1;
+        let len: usize = segments.iter().map(|&s| usize::from(s)).sum();
+        let page = OggPage {
+            flags: if continued { 1 } else { 0 },
+            // no packet ends on a page whose granule position is -1
+            granule: if segments.iter().any(|&s| s < 255) {
+                0
+            } else {
+                u64::MAX
+            },
+            serial: meta.first.serial,
+            sequence: meta.first.sequence.wrapping_add(count),
+            segments: segments.to_vec(),
+            data: data[pos..pos + len].to_vec(),
+        };
+        out.extend_from_slice(&page.to_bytes());
+        pos += len;
+        continued = segments.last() == Some(&255);
+    }
+    Ok((out, count))
+}
+
+/// Taggable files in `dir`, sorted by name, and how many files there were
+/// in formats that can't be tagged, each of which is reported.
+pub fn taggable_files(dir: &Path) -> io::Result<(Vec<PathBuf>, usize)> {
+    let (mut files, mut unsupported) = (vec![], vec![]);
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let ext = path.extension().and_then(|e| e.to_str());
+        if TagFormat::for_path(&path).is_some() {
+            files.push(path);
+        } else if ext.is_some_and(|e| UNSUPPORTED.contains(&&*e.to_ascii_lowercase())) {
+            unsupported.push(path);
+        }
+    }
+    files.sort();
+    unsupported.sort();
+    for path in &unsupported {
+        eprintln!("{}: MP4 tags aren't supported, skipping", path.display());
+    }
+    Ok((files, unsupported.len()))
+}
+
+/// Writes tags for every file in the directory from the cache, returning false if any file failed.
+pub fn tag(args: &TagArgs) -> io::Result<bool> {
+    let cache = match load_cache(Path::new(&args.from)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.from);
+            return Ok(false);
+        }
+    };
+
+    let albums = match args.album {
+        true => albums(cache.values()),
+        false => Default::default(),
+    };
+
+    let (mut tagged, mut unchanged, mut missing, mut failed) = (0, 0, 0, 0);
+
+    let (files, unsupported) = taggable_files(Path::new(&args.dir))?;
+    for path in files {
+        let key = cache_key(&path);
+        let Some(measurement) = cache.get(&key) else {
+            eprintln!("{}: not in cache, skipping", path.display());
+            missing += 1;
+            continue;
+        };
+        if !measurement.loudness.is_finite() {
+            eprintln!("{}: no usable loudness in cache, skipping", path.display());
+            missing += 1;
+            continue;
+        }
+
+        let format = TagFormat::for_path(&path).unwrap();
+        let mut file = match TaggedFile::open(&path, format) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("{}: failed to read tags: {e}", path.display());
+                failed += 1;
+                continue;
+            }
+        };
+
+        let mut intended = vec![(TRACK_GAIN, format_gain(args.target - measurement.loudness))];
+        // the track's offset within its album is only shown: album gain
+        // alone is what keeps it
+        let mut offset = String::new();
+        if args.album {
+            match album_of(measurement).and_then(|a| albums.get(&a)) {
+                Some(album) => {
+                    intended.push((ALBUM_GAIN, format_gain(args.target - album.loudness)));
+                    if let Some(delta) = album.track_vs_album(measurement) {
+                        offset = format!(" ({delta:+.2} dB vs album)");
+                    }
+                }
+                None => eprintln!(
+                    "{}: no album in cache, writing track gain only",
+                    path.display()
+                ),
+            }
+        }
+        let current: Vec<Vec<String>> = intended.iter().map(|(key, _)| file.get(key)).collect();
+        let same = intended
+            .iter()
+            .zip(&current)
+            .all(|((_, value), current)| current.len() == 1 && current[0] == *value);
+        if same {
+            unchanged += 1;
+            continue;
+        }
+
+        if args.dry_run {
+            println!("{}{offset}", path.display());
+            for ((key, value), current) in intended.iter().zip(&current) {
+                for old in current {
+                    println!("- {key}={old}");
+                }
+                println!("+ {key}={value}");
+            }
+            tagged += 1;
+            continue;
+        }
+
+        for (key, value) in &intended {
+            file.set(key, value);
+        }
+        match file.save() {
+            Ok(()) => {
+                let fields: Vec<String> =
+                    intended.iter().map(|(k, v)| format!("{k}={v}")).collect();
+                println!("{}: {}{offset}", path.display(), fields.join(", "));
+                tagged += 1;
+            }
+            Err(e) => {
+                eprintln!("{}: failed to write tags: {e}", path.display());
+                failed += 1;
+            }
+        }
+    }
+
+    eprintln!(
+        "{}: {tagged}, unchanged: {unchanged}, missing: {missing}, failed: {failed}, unsupported: {unsupported}",
+        if args.dry_run { "would tag" } else { "tagged" }
+    );
+    Ok(failed == 0)
+}
+
+/// Strips gain fields from every file in the directory, returning false if any file failed.
+pub fn untag(args: &UntagArgs) -> io::Result<bool> {
+    let (mut files, mut removed, mut clean, mut failed) = (0, 0, 0, 0);
+
+    let (taggable, unsupported) = taggable_files(Path::new(&args.dir))?;
+    for path in taggable {
+        let format = TagFormat::for_path(&path).unwrap();
+        let mut file = match TaggedFile::open(&path, format) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("{}: failed to read tags: {e}", path.display());
+                failed += 1;
+                continue;
+            }
+        };
+
+        let fields: Vec<_> = file
+            .fields()
+            .into_iter()
+            .filter(|(k, _)| is_gain_field(k))
+            .collect();
+        if fields.is_empty() {
+            clean += 1;
+            continue;
+        }
+
+        if args.dry_run {
+            println!("{}", path.display());
+            for (key, value) in &fields {
+                println!("- {key}={value}");
+            }
+            files += 1;
+            removed += fields.len();
+            continue;
+        }
+
+        let count = file.remove_where(is_gain_field);
+        match file.save() {
+            Ok(()) => {
+                println!("{}: removed {count} field(s)", path.display());
+                files += 1;
+                removed += count;
+            }
+            Err(e) => {
+                eprintln!("{}: failed to write tags: {e}", path.display());
+                failed += 1;
+            }
+        }
+    }
+
+    eprintln!(
+        "{} {removed} field(s) from {files} file(s), without gain tags: {clean}, failed: {failed}, unsupported: {unsupported}",
+        if args.dry_run {
+            "would remove"
+        } else {
+            "removed"
+        }
+    );
+    Ok(failed == 0)
+}
+
+/// Compares existing gain tags, and peak tags where the cache has a true
+/// peak, against the cache without decoding any audio, returning false if
+/// any tag disagrees or a file couldn't be read.
+pub fn verify(args: &VerifyTagsArgs) -> io::Result<bool> {
+    let cache = match load_cache(Path::new(&args.cache)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.cache);
+            return Ok(false);
+        }
+    };
+
+    let (mut ok, mut mismatched, mut uncached, mut untagged, mut failed) = (0, 0, 0, 0, 0);
+    let mut peak_mismatched = 0;
+
+    let (files, unsupported) = taggable_files(Path::new(&args.dir))?;
+    for path in files {
+        let format = TagFormat::for_path(&path).unwrap();
+        let file = match TaggedFile::open(&path, format) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("{}: failed to read tags: {e}", path.display());
+                failed += 1;
+                continue;
+            }
+        };
+        let entry = cache.get(&cache_key(&path));
+
+        // a peak is only checked when both the tag and the cache have one;
+        // it's compared in dB, as the gain is
+        let mut peak_ok = true;
+        let tagged_peak = file.get(TRACK_PEAK);
+        if let (Some(value), Some(true_peak)) =
+            (tagged_peak.first(), entry.and_then(|m| m.true_peak))
+        {
+            let peak_db = value.trim().parse::<f64>().ok().map(|p| 20.0 * p.log10());
+            if !peak_db.is_some_and(|p| (p - true_peak).abs() <= args.tolerance) {
+                println!(
+                    "{}: tagged peak {value}, cache gives {:.6}",
+                    path.display(),
+                    linear_peak(true_peak)
+                );
+                peak_mismatched += 1;
+                peak_ok = false;
+            }
+        }
+
+        let tagged = file.get(TRACK_GAIN);
+        let expected = entry
+            .map(|m| m.loudness)
+            .filter(|l| l.is_finite())
+            .map(|l| args.target - l);
+
+        match (tagged.first(), expected) {
+            (None, None) => {}
+            (Some(_), None) => {
+                println!("{}: tagged but not in cache", path.display());
+                uncached += 1;
+            }
+            (None, Some(_)) => {
+                println!("{}: in cache but untagged", path.display());
+                untagged += 1;
+            }
+            (Some(value), Some(expected)) => match parse_gain(value) {
+                Some(gain) if (gain - expected).abs() <= args.tolerance => {
+                    ok += usize::from(peak_ok);
+                }
+                _ => {
+                    println!(
+                        "{}: tagged {value}, cache gives {}",
+                        path.display(),
+                        format_gain(expected)
+                    );
+                    mismatched += 1;
+                }
+            },
+        }
+    }
+
+    eprintln!(
+        "ok: {ok}, mismatched: {mismatched}, peak mismatched: {peak_mismatched}, tagged but uncached: {uncached}, cached but untagged: {untagged}, failed: {failed}, unsupported: {unsupported}"
+    );
+    Ok(mismatched == 0 && peak_mismatched == 0 && failed == 0)
+}
@@ -0,0 +1,172 @@
+//! `--format` templates for per-file result lines.
+
+use crate::cli::Units;
+use crate::report::Field;
+use loudness::Measurement;
+
+enum Part {
+    Literal(String),
+    Placeholder {
+        field: Field,
+        /// Minimum width, padded with spaces.
+        width: Option<usize>,
+        /// Pad on the right instead of the left.
+        left: bool,
+        /// Always show the sign of numbers.
+        sign: bool,
+        precision: Option<usize>,
+    },
+}
+
+/// A parsed template such as `{key}\t{loudness:.1}\t{gain:+.1}`.
+///
+/// Placeholders are `{field}` or `{field:spec}` where spec is
+/// `[<|>][+][width][.precision]`. `{{` and `}}` are literal braces, and `\t`,
+/// `\n` and `\\` are escapes. Values are substituted in a single pass, so
+/// braces in keys are printed as they are.
+pub struct Template {
+    /// The template as given.
+    pub source: String,
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template, String> {
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err("unmatched '}' in --format, use '}}' for a literal brace".into()),
+                '{' => {
+                    let mut inner = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => inner.push(c),
+                            None => return Err("unclosed '{' in --format".into()),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_placeholder(&inner)?);
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Template {
+            source: template.to_string(),
+            parts,
+        })
+    }
+
+    /// Renders the line for one measurement; fields that weren't measured are empty.
+    pub fn render(
+        &self,
+        key: &str,
+        m: &Measurement,
+        units: Units,
+        target: Option<f64>,
+        default_precision: usize,
+    ) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            let (field, width, left, sign, precision) = match part {
+                Part::Literal(text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                Part::Placeholder {
+                    field,
+                    width,
+                    left,
+                    sign,
+                    precision,
+                } => (*field, width.unwrap_or(0), *left, *sign, *precision),
+            };
+            let value = match (field, field.value(m, units, target)) {
+                (Field::Key, _) => key.to_string(),
+                (_, None) => String::new(),
+                (_, Some(v)) => {
+                    let p = precision.unwrap_or(default_precision);
+                    match sign {
+                        true => format!("{v:+.p$}"),
+                        false => format!("{v:.p$}"),
+                    }
+                }
+            };
+            if left {
+                out.push_str(&format!("{value:<width$}"));
+            } else {
+                out.push_str(&format!("{value:>width$}"));
+            }
+        }
+        out
+    }
+}
+
+fn parse_placeholder(inner: &str) -> Result<Part, String> {
+    let (name, spec) = inner.split_once(':').unwrap_or((inner, ""));
+    let field = Field::ALL
+        .into_iter()
+        .find(|f| f.name() == name)
+        .ok_or_else(|| {
+            let names: Vec<_> = Field::ALL.iter().map(|f| f.name()).collect();
+            format!(
+                "unknown placeholder '{{{name}}}' in --format, expected one of: {}",
+                names.join(", ")
+            )
+        })?;
+
+    let invalid = || format!("invalid format spec '{spec}' for '{{{name}}}'");
+    let mut rest = spec;
+    // numbers line up on the right unless asked otherwise, text on the left
+    let mut left = field == Field::Key;
+    if let Some(r) = rest.strip_prefix('<') {
+        left = true;
+        rest = r;
+    } else if let Some(r) = rest.strip_prefix('>') {
+        left = false;
+        rest = r;
+    }
+    let sign = rest.starts_with('+');
+    rest = rest.trim_start_matches('+');
+    let (width, precision) = rest.split_once('.').unwrap_or((rest, ""));
+    let width = match width {
+        "" => None,
+        w => Some(w.parse().map_err(|_| invalid())?),
+    };
+    let precision = match (precision, rest.contains('.')) {
+        (_, false) => None,
+        (p, true) => Some(p.parse().map_err(|_| invalid())?),
+    };
+    Ok(Part::Placeholder {
+        field,
+        width,
+        left,
+        sign,
+        precision,
+    })
+}
@@ -0,0 +1,217 @@
+//! The terminal the --tui dashboard takes over: its size, reading keys as
+//! they're pressed, and giving it back as it was, from a signal handler too.
+//!
+//! Keys are read unbuffered and unechoed where the `termios` layout is
+//! known (Linux on x86_64 and aarch64, and macOS); elsewhere the terminal
+//! stays in line mode.
+
+use std::io::{IsTerminal, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// Alternate screen and hidden cursor, and back.
+const TAKE: &str = "\x1b[?1049h\x1b[?25l";
+const GIVE_BACK: &str = "\x1b[?25h\x1b[?1049l";
+
+/// Whether the dashboard has the screen.
+static TAKEN: AtomicBool = AtomicBool::new(false);
+/// Stdin's settings from before it was switched to reading keys, if it was.
+static SAVED: OnceLock<sys::Termios> = OnceLock::new();
+
+#[cfg(any(
+    all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ),
+    target_os = "macos"
+))]
+mod sys {
+    use std::ffi::{c_int, c_ulong, c_void};
+
+    #[cfg(target_os = "linux")]
+    mod layout {
+        pub type Flag = u32;
+        pub const NCCS: usize = 32;
+        pub const ICANON: Flag = 0o2;
+        pub const ECHO: Flag = 0o10;
+        pub const VTIME: usize = 5;
+        pub const VMIN: usize = 6;
+        pub const TIOCGWINSZ: std::ffi::c_ulong = 0x5413;
+    }
+    #[cfg(target_os = "macos")]
+    mod layout {
+        pub type Flag = std::ffi::c_ulong;
+        pub const NCCS: usize = 20;
+        pub const ICANON: Flag = 0x100;
+        pub const ECHO: Flag = 0x8;
+        pub const VMIN: usize = 16;
+        pub const VTIME: usize = 17;
+        pub const TIOCGWINSZ: std::ffi::c_ulong = 0x4008_7468;
+    }
+    pub use layout::*;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct Termios {
+        pub iflag: Flag,
+        pub oflag: Flag,
+        pub cflag: Flag,
+        pub lflag: Flag,
+        #[cfg(target_os = "linux")]
+        pub line: u8,
+        pub cc: [u8; NCCS],
+        pub ispeed: Flag,
+        pub ospeed: Flag,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct WinSize {
+        pub rows: u16,
+        pub cols: u16,
+        pub x_pixels: u16,
+        pub y_pixels: u16,
+    }
+
+    pub const TCSANOW: c_int = 0;
+
+    extern "C" {
+        pub fn tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
+        pub fn tcsetattr(fd: c_int, action: c_int, termios: *const Termios) -> c_int;
+        pub fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+        pub fn write(fd: c_int, buf: *const c_void, count: usize) -> isize;
+    }
+}
+
+#[cfg(not(any(
+    all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ),
+    target_os = "macos"
+)))]
+mod sys {
+    /// Never saved, as it can't be read here.
+    pub struct Termios;
+}
+
+/// Rows and columns of the terminal on stderr, from the terminal itself,
+/// else `$LINES` and `$COLUMNS`, else 24 by 80.
+pub fn size() -> (usize, usize) {
+    #[cfg(any(
+        all(
+            target_os = "linux",
+            any(target_arch = "x86_64", target_arch = "aarch64")
+        ),
+        target_os = "macos"
+    ))]
+    {
+        let mut size = sys::WinSize::default();
+        // SAFETY: TIOCGWINSZ fills in a winsize, which `size` is laid out as
+        let ok = unsafe { sys::ioctl(2, sys::TIOCGWINSZ, &mut size as *mut sys::WinSize) } == 0;
+        if ok && size.rows > 0 && size.cols > 0 {
+            return (usize::from(size.rows), usize::from(size.cols));
+        }
+    }
+    let var = |name: &str| std::env::var(name).ok().and_then(|v| v.parse().ok());
+    (var("LINES").unwrap_or(24), var("COLUMNS").unwrap_or(80))
+}
+
+/// Switches to the alternate screen, and when stdin is the terminal, has
+/// it hand over each key as it's pressed, unechoed. Returns whether keys
+/// can be read that way.
+pub fn take() -> bool {
+    eprint!("{TAKE}");
+    TAKEN.store(true, Ordering::SeqCst);
+    std::io::stdin().is_terminal() && read_keys()
+}
+
+#[cfg(any(
+    all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ),
+    target_os = "macos"
+))]
+fn read_keys() -> bool {
+    let mut termios = std::mem::MaybeUninit::<sys::Termios>::uninit();
+    // SAFETY: tcgetattr fills in the whole struct when it succeeds
+    if unsafe { sys::tcgetattr(0, termios.as_mut_ptr()) } != 0 {
+        return false;
+    }
+    // SAFETY: as above
+    let saved = unsafe { termios.assume_init() };
+    let _ = SAVED.set(saved);
+    let mut keys = saved;
+    keys.lflag &= !(sys::ICANON | sys::ECHO);
+    // each read returns as soon as there's a key
+    keys.cc[sys::VMIN] = 1;
+    keys.cc[sys::VTIME] = 0;
+    // SAFETY: a valid termios, from tcgetattr
+    unsafe { sys::tcsetattr(0, sys::TCSANOW, &keys) == 0 }
+}
+
+#[cfg(not(any(
+    all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ),
+    target_os = "macos"
+)))]
+fn read_keys() -> bool {
+    false
+}
+
+/// Whether the dashboard has the terminal.
+pub fn is_taken() -> bool {
+    TAKEN.load(Ordering::SeqCst)
+}
+
+/// Leaves the alternate screen, shows the cursor and restores stdin's
+/// settings, if the terminal was taken. Only makes async-signal-safe
+/// calls, so the SIGINT handler can use it.
+pub fn give_back() {
+    if !TAKEN.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    #[cfg(any(
+        all(
+            target_os = "linux",
+            any(target_arch = "x86_64", target_arch = "aarch64")
+        ),
+        target_os = "macos"
+    ))]
+    // SAFETY: write and tcsetattr are async-signal-safe, and `saved` came
+    // from tcgetattr
+    unsafe {
+        sys::write(2, GIVE_BACK.as_ptr().cast(), GIVE_BACK.len());
+        if let Some(saved) = SAVED.get() {
+            sys::tcsetattr(0, sys::TCSANOW, saved);
+        }
+    }
+    #[cfg(not(any(
+        all(
+            target_os = "linux",
+            any(target_arch = "x86_64", target_arch = "aarch64")
+        ),
+        target_os = "macos"
+    )))]
+    {
+        let _ = SAVED.get();
+        eprint!("{GIVE_BACK}");
+    }
+}
+
+/// Calls `on_key` with each byte read from stdin, on a thread of its own,
+/// until it returns false or stdin ends.
+pub fn on_keys(mut on_key: impl FnMut(u8) -> bool + Send + 'static) {
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut buf = [0; 16];
+        while let Ok(n @ 1..) = stdin.read(&mut buf) {
+            if !buf[..n].iter().all(|&b| on_key(b)) {
+                break;
+            }
+        }
+    });
+}
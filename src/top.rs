@@ -0,0 +1,88 @@
+//! Loudest/quietest entries of a cache, without touching any audio.
+
+use crate::cli::{TopArgs, Units, DEFAULT_PRECISION};
+use loudness::{load_cache, tsv_field};
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+/// Matches `text` against a shell-style glob supporting `*` and `?`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // position of the last `*` and the text index it was tried at
+    let mut star = None;
+    while ti < t.len() {
+        match p.get(pi) {
+            Some('*') => {
+                star = Some((pi, ti));
+                pi += 1;
+            }
+            Some(&c) if c == '?' || c == t[ti] => {
+                pi += 1;
+                ti += 1;
+            }
+            _ => match star {
+                Some((sp, st)) => {
+                    pi = sp + 1;
+                    ti = st + 1;
+                    star = Some((sp, st + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// Prints the top entries, returning false if the cache couldn't be read.
+pub fn run(args: &TopArgs) -> io::Result<bool> {
+    let cache = match load_cache(Path::new(&args.cache)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("failed to read cache '{}': {e}", args.cache);
+            return Ok(false);
+        }
+    };
+
+    let mut entries: Vec<(&str, f64, String)> = cache
+        .iter()
+        .filter(|(k, _)| args.pattern.as_ref().is_none_or(|p| glob_match(p, k)))
+        // silent or failed measurements can't be ranked, nor ones without
+        // a peak measured by peak or PSR
+        .filter_map(|(k, m)| {
+            let value = args.by.value(m, Units::Lufs, None)?;
+            let shown = args.by.display(k, m, Units::Lufs, None, DEFAULT_PRECISION);
+            Some((k.as_str(), value, shown))
+        })
+        .collect();
+    entries.sort_by(|(ka, a, _), (kb, b, _)| {
+        let order = if args.quietest {
+            a.total_cmp(b)
+        } else {
+            b.total_cmp(a)
+        };
+        order.then_with(|| ka.cmp(kb))
+    });
+    entries.truncate(args.count);
+
+    let mut out = String::new();
+    if io::stdout().is_terminal() {
+        let width = entries.iter().map(|(k, _, _)| k.chars().count()).max();
+        let rank_width = entries.len().to_string().len();
+        for (rank, (key, _, shown)) in entries.iter().enumerate() {
+            out += &format!(
+                "{:>rank_width$}  {key:<width$}  {shown:>11}\n",
+                rank + 1,
+                width = width.unwrap_or(0),
+            );
+        }
+    } else {
+        out += &format!("key\t{}\n", args.by.column(Units::Lufs));
+        for (key, value, _) in &entries {
+            out += &format!("{}\t{value}\n", tsv_field(key));
+        }
+    }
+    print!("{out}");
+    Ok(true)
+}
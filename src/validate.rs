@@ -0,0 +1,258 @@
+//! Measurements checked against known-good reference values, to trust a
+//! build (or catch a regression between versions) before a big run.
+
+use crate::cli::ValidateArgs;
+use crate::report::{loudness_cell, JsonNumber};
+use crate::{measure, AUDIO_EXTENSIONS};
+use log::warn;
+use loudness::{cache_key, normalize_key, split_csv};
+use merde::json::JsonSerializer;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One row of the references file.
+struct Reference {
+    key: String,
+    loudness: f64,
+    /// Whether the row also gives loudness range or peak, which aren't
+    /// measured and so can't be checked.
+    unchecked: bool,
+}
+
+/// A reference and what measuring its file gave.
+struct Check {
+    key: String,
+    expected: f64,
+    measured: Result<f64, String>,
+}
+
+impl Check {
+    /// Measured minus expected; NaN when measuring failed.
+    fn delta(&self) -> f64 {
+        match self.measured {
+            // both silent
+            Ok(m) if m == self.expected => 0.0,
+            Ok(m) => m - self.expected,
+            Err(_) => f64::NAN,
+        }
+    }
+
+    fn passed(&self, tolerance: f64) -> bool {
+        self.delta().abs() <= tolerance
+    }
+
+    /// How far off the check is, failures to measure being the furthest.
+    fn badness(&self) -> f64 {
+        let delta = self.delta().abs();
+        if delta.is_nan() {
+            f64::INFINITY
+        } else {
+            delta
+        }
+    }
+}
+
+/// Reads `key,loudness[,lra[,peak]]` rows, skipping a header line, blank
+/// lines and lines starting with `#`.
+fn read_references(from: &Path) -> io::Result<Vec<Reference>> {
+    let text = std::fs::read_to_string(from)?;
+    let mut references = vec![];
+    let mut first = true;
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let header = std::mem::replace(&mut first, false);
+        let fields = split_csv(line).into_iter().next().unwrap_or_default();
+        let invalid = |why: String| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {why}", n + 1))
+        };
+        let Some(loudness) = fields.get(1).map(|l| l.trim()) else {
+            return Err(invalid("expected key,loudness".to_string()));
+        };
+        let loudness = match loudness.parse::<f64>() {
+            Ok(l) => l,
+            Err(_) if header => continue,
+            Err(_) => return Err(invalid(format!("invalid loudness '{loudness}'"))),
+        };
+        let unchecked = fields[2..].iter().any(|f| !f.trim().is_empty());
+        references.push(Reference {
+            key: normalize_key(fields[0].trim()),
+            loudness,
+            unchecked,
+        });
+    }
+    Ok(references)
+}
+
+/// Audio files in `input` (or `input` itself), by cache key.
+fn files_by_key(input: &Path) -> io::Result<HashMap<String, PathBuf>> {
+    let mut files = if input.is_dir() {
+        let mut files = vec![];
+        for entry in std::fs::read_dir(input)? {
+            let path = entry?.path();
+            let audio = path.extension().is_some_and(|e| {
+                AUDIO_EXTENSIONS.contains(&e.to_string_lossy().to_ascii_lowercase().as_str())
+            });
+            if path.is_file() && audio {
+                files.push(path);
+            }
+        }
+        files
+    } else {
+        vec![input.to_path_buf()]
+    };
+    files.sort();
+    let mut by_key: HashMap<String, PathBuf> = HashMap::new();
+    for file in files {
+        let key = cache_key(&file);
+        if let Some(first) = by_key.get(&key) {
+            warn!(
+                "'{}' and '{}' have the same key '{key}', checking only the first",
+                first.display(),
+                file.display()
+            );
+            continue;
+        }
+        by_key.insert(key, file);
+    }
+    Ok(by_key)
+}
+
+fn json(checks: &[Check], tolerance: f64) -> String {
+    let passed = checks.iter().filter(|c| c.passed(tolerance)).count();
+    let mut s = JsonSerializer::new();
+    {
+        let mut obj = s.write_obj();
+        obj.pair("tolerance", &JsonNumber(tolerance));
+        obj.pair("passed", &passed);
+        obj.pair("failed", &(checks.len() - passed));
+        obj.pair("results", &JsonChecks(checks, tolerance));
+    }
+    String::from_utf8(s.into_inner()).unwrap()
+}
+
+struct JsonChecks<'a>(&'a [Check], f64);
+
+impl merde::json::JsonSerialize for JsonChecks<'_> {
+    fn json_serialize(&self, s: &mut JsonSerializer) {
+        let mut arr = s.write_arr();
+        for check in self.0 {
+            arr.elem(&JsonCheck(check, self.1));
+        }
+    }
+}
+
+struct JsonCheck<'a>(&'a Check, f64);
+
+impl merde::json::JsonSerialize for JsonCheck<'_> {
+    fn json_serialize(&self, s: &mut JsonSerializer) {
+        let check = self.0;
+        let mut obj = s.write_obj();
+        obj.pair("key", &check.key);
+        obj.pair("expected", &JsonNumber(check.expected));
+        match &check.measured {
+            Ok(measured) => {
+                obj.pair("measured", &JsonNumber(*measured));
+                obj.pair("delta", &JsonNumber(check.delta()));
+            }
+            Err(e) => {
+                obj.pair("error", e);
+            }
+        }
+        obj.pair("pass", &check.passed(self.1));
+    }
+}
+
+/// Measures every referenced file and prints how far each is off, worst
+/// first, returning false if any is off by more than the tolerance or
+/// couldn't be measured.
+pub fn run(args: &ValidateArgs) -> io::Result<bool> {
+    let references = match read_references(Path::new(&args.refs)) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("failed to read references '{}': {e}", args.refs);
+            return Ok(false);
+        }
+    };
+    if references.is_empty() {
+        eprintln!("no references in '{}'", args.refs);
+        return Ok(false);
+    }
+    let unchecked = references.iter().filter(|r| r.unchecked).count();
+    if unchecked > 0 {
+        warn!(
+            "{unchecked} reference(s) give loudness range or peak, which aren't measured; \
+             only integrated loudness is checked"
+        );
+    }
+
+    let files = files_by_key(Path::new(&args.input))?;
+    let mut checks: Vec<Check> = references
+        .into_par_iter()
+        .map(|r| {
+            let measured = match files.get(&r.key) {
+                Some(file) => measure(file).map(|m| m.loudness),
+                None => Err(format!("no file for it in '{}'", args.input)),
+            };
+            Check {
+                key: r.key,
+                expected: r.loudness,
+                measured,
+            }
+        })
+        .collect();
+    checks.sort_by(|a, b| {
+        b.badness()
+            .total_cmp(&a.badness())
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    let passed = checks.iter().filter(|c| c.passed(args.tolerance)).count();
+
+    if args.json {
+        println!("{}", json(&checks, args.tolerance));
+    } else {
+        let width = checks
+            .iter()
+            .map(|c| c.key.chars().count())
+            .max()
+            .unwrap_or(0);
+        let width = width.max(3);
+        println!(
+            "{:<width$}  {:>12}  {:>12}  {:>7}",
+            "key", "expected", "measured", "delta"
+        );
+        for check in &checks {
+            let verdict = if check.passed(args.tolerance) {
+                "pass"
+            } else {
+                "FAIL"
+            };
+            match &check.measured {
+                Ok(measured) => println!(
+                    "{:<width$}  {:>12}  {:>12}  {:>+7.2}  {verdict}",
+                    check.key,
+                    loudness_cell(check.expected),
+                    loudness_cell(*measured),
+                    check.delta()
+                ),
+                Err(e) => println!(
+                    "{:<width$}  {:>12}  {:>12}  {:>7}  {verdict}: {e}",
+                    check.key,
+                    loudness_cell(check.expected),
+                    "-",
+                    "-"
+                ),
+            }
+        }
+    }
+    eprintln!(
+        "{passed} of {} within {} LU of the reference",
+        checks.len(),
+        args.tolerance
+    );
+    Ok(passed == checks.len())
+}
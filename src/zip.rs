@@ -0,0 +1,583 @@
+//! Audio files inside zip archives, measured without extracting them.
+//!
+//! An archive's entries are scanned as paths under the archive itself, e.g.
+//! `album.zip/01 Intro.flac`, which can't exist on disk since the archive is
+//! a file. Stored entries are read in place; deflated ones are inflated
+//! into memory first. Encrypted archives and other compression methods
+//! aren't supported.
+
+use loudness::{cache_key, normalize_key};
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use symphonia::core::io::MediaSource;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const ZIP64_END_OF_DIRECTORY: u32 = 0x0606_4b50;
+/// Extra field holding the 64-bit sizes and offset of a zip64 entry.
+const ZIP64_EXTRA: u16 = 0x0001;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// A file in an archive's central directory.
+pub struct Entry {
+    /// Path within the archive, with `/` separators.
+    pub name: String,
+    method: u16,
+    encrypted: bool,
+    crc: u32,
+    compressed_size: u64,
+    /// Uncompressed size in bytes.
+    pub size: u64,
+    /// Offset of the entry's local header.
+    offset: u64,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn read_at(file: &mut File, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Whether `path` is named like a zip archive.
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("zip"))
+}
+
+/// The files in `archive`, from its central directory.
+pub fn entries(archive: &Path) -> io::Result<Vec<Entry>> {
+    let mut file = File::open(archive)?;
+    let len = file.metadata()?.len();
+    // the end record is 22 bytes, followed by a comment of up to 64 KiB
+    let tail_len = len.min(22 + 0xffff);
+    let tail = read_at(&mut file, len - tail_len, tail_len as usize)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(&tail, i) == END_OF_DIRECTORY)
+        .ok_or_else(|| invalid("not a zip archive"))?;
+    let mut count = u64::from(u16_at(&tail, end + 10));
+    let mut directory_len = u64::from(u32_at(&tail, end + 12));
+    let mut directory_offset = u64::from(u32_at(&tail, end + 16));
+
+    // a zip64 archive has its real counts in another record, located just before
+    if end >= 20 && u32_at(&tail, end - 20) == ZIP64_LOCATOR {
+        let offset = u64_at(&tail, end - 20 + 8);
+        let record = read_at(&mut file, offset, 56)?;
+        if u32_at(&record, 0) != ZIP64_END_OF_DIRECTORY {
+            return Err(invalid("bad zip64 end of central directory"));
+        }
+        count = u64_at(&record, 32);
+        directory_len = u64_at(&record, 40);
+        directory_offset = u64_at(&record, 48);
+    }
+    if directory_offset
+        .checked_add(directory_len)
+        .is_none_or(|end| end > len)
+    {
+        return Err(invalid("central directory past the end of the archive"));
+    }
+
+    let directory = read_at(&mut file, directory_offset, directory_len as usize)?;
+    let mut entries = vec![];
+    let mut at = 0;
+    for _ in 0..count {
+        if at + 46 > directory.len() || u32_at(&directory, at) != CENTRAL_HEADER {
+            return Err(invalid("bad central directory entry"));
+        }
+        let flags = u16_at(&directory, at + 8);
+        let name_len = usize::from(u16_at(&directory, at + 28));
+        let extra_len = usize::from(u16_at(&directory, at + 30));
+        let comment_len = usize::from(u16_at(&directory, at + 32));
+        let next = at + 46 + name_len + extra_len + comment_len;
+        if next > directory.len() {
+            return Err(invalid("bad central directory entry"));
+        }
+        let name = String::from_utf8_lossy(&directory[at + 46..at + 46 + name_len]).into_owned();
+        let mut entry = Entry {
+            name,
+            method: u16_at(&directory, at + 10),
+            encrypted: flags & 1 != 0,
+            crc: u32_at(&directory, at + 16),
+            compressed_size: u64::from(u32_at(&directory, at + 20)),
+            size: u64::from(u32_at(&directory, at + 24)),
+            offset: u64::from(u32_at(&directory, at + 42)),
+        };
+        let extra = &directory[at + 46 + name_len..at + 46 + name_len + extra_len];
+        read_zip64_extra(&mut entry, extra);
+        if entry
+            .offset
+            .checked_add(entry.compressed_size)
+            .is_none_or(|end| end > len)
+        {
+            return Err(invalid("entry past the end of the archive"));
+        }
+        entries.push(entry);
+        at = next;
+    }
+    Ok(entries)
+}
+
+/// Replaces the sizes and offset that don't fit in 32 bits with the ones in
+/// the zip64 extra field, which has only those, in this order.
+fn read_zip64_extra(entry: &mut Entry, mut extra: &[u8]) {
+    while extra.len() >= 4 {
+        let (id, len) = (u16_at(extra, 0), usize::from(u16_at(extra, 2)));
+        let Some(data) = extra.get(4..4 + len) else {
+            return;
+        };
+        if id == ZIP64_EXTRA {
+            let mut values = data.chunks_exact(8).map(|v| u64_at(v, 0));
+            for field in [
+                &mut entry.size,
+                &mut entry.compressed_size,
+                &mut entry.offset,
+            ] {
+                if *field == u64::from(u32::MAX) {
+                    match values.next() {
+                        Some(value) => *field = value,
+                        None => return,
+                    }
+                }
+            }
+            return;
+        }
+        extra = &extra[4 + len..];
+    }
+}
+
+/// The audio entries of `archive` as paths under it, for measuring like any
+/// other file. Fails if the archive can't be read or is password-protected.
+pub fn expand(archive: &Path, wanted: impl Fn(&Path) -> bool) -> io::Result<Vec<PathBuf>> {
+    let entries = entries(archive)?;
+    if entries.iter().any(|e| e.encrypted) {
+        return Err(io::Error::other(
+            "the archive is password-protected, which isn't supported",
+        ));
+    }
+    Ok(entries
+        .iter()
+        // directories, and names that would escape the archive's path
+        .filter(|e| {
+            !e.name.ends_with('/')
+                && Path::new(&e.name)
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_)))
+        })
+        .map(|e| archive.join(&e.name))
+        .filter(|p| wanted(p))
+        .collect())
+}
+
+/// The archive `path` is in, and its name within the archive, if it's an
+/// entry as [`expand`] gives them.
+pub fn split(path: &Path) -> Option<(&Path, String)> {
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|a| is_archive(a) && a.is_file())?;
+    let name: Vec<_> = path
+        .strip_prefix(archive)
+        .ok()?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    Some((archive, name.join("/")))
+}
+
+/// Cache key of an archive entry: the archive's key, then the entry's path
+/// without its extension, e.g. "album/01 Intro" for `album.zip/01 Intro.flac`.
+pub fn key(path: &Path) -> Option<String> {
+    let (archive, name) = split(path)?;
+    let name = Path::new(&name).with_extension("");
+    Some(normalize_key(&format!(
+        "{}/{}",
+        cache_key(archive),
+        name.to_string_lossy()
+    )))
+}
+
+/// Uncompressed size of an archive entry.
+pub fn size(path: &Path) -> Option<u64> {
+    let (archive, name) = split(path)?;
+    entries(archive)
+        .ok()?
+        .into_iter()
+        .find(|e| e.name == name)
+        .map(|e| e.size)
+}
+
+/// Opens an archive entry for decoding, or `None` if `path` isn't one. An
+/// archive that couldn't be expanded is scanned as itself, so opening that
+/// gives the reason it couldn't be.
+pub fn open(path: &Path) -> Option<io::Result<Box<dyn MediaSource>>> {
+    if is_archive(path) && path.is_file() {
+        return Some(
+            expand(path, |_| true)
+                .and_then(|_| Err(io::Error::other("the archive has no audio files"))),
+        );
+    }
+    let (archive, name) = split(path)?;
+    Some(open_entry(archive, &name))
+}
+
+fn open_entry(archive: &Path, name: &str) -> io::Result<Box<dyn MediaSource>> {
+    let entry = entries(archive)?
+        .into_iter()
+        .find(|e| e.name == name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file in the archive"))?;
+    if entry.encrypted {
+        return Err(io::Error::other(
+            "the archive is password-protected, which isn't supported",
+        ));
+    }
+    let mut file = File::open(archive)?;
+    let header = read_at(&mut file, entry.offset, 30)?;
+    if u32_at(&header, 0) != LOCAL_HEADER {
+        return Err(invalid("bad local header"));
+    }
+    let start = entry.offset + 30 + u64::from(u16_at(&header, 26)) + u64::from(u16_at(&header, 28));
+    if start + entry.compressed_size > file.metadata()?.len() {
+        return Err(invalid("entry past the end of the archive"));
+    }
+    match entry.method {
+        STORED if entry.size != entry.compressed_size => Err(invalid(
+            "stored entry sizes disagree, the archive is damaged",
+        )),
+        STORED => Ok(Box::new(Window {
+            file,
+            start,
+            len: entry.size,
+            pos: 0,
+        })),
+        DEFLATED => {
+            let compressed = read_at(&mut file, start, entry.compressed_size as usize)?;
+            let data = inflate(&compressed, entry.size as usize)?;
+            if crc32(&data) != entry.crc {
+                return Err(invalid("checksum mismatch, the archive is damaged"));
+            }
+            Ok(Box::new(Cursor::new(data)))
+        }
+        method => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("compression method {method} isn't supported, only stored and deflated"),
+        )),
+    }
+}
+
+/// A stored entry, read in place from the archive.
+struct Window {
+    file: File,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl Read for Window {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // a seek may have gone past the end, which reads nothing
+        let left = self.len.saturating_sub(self.pos).min(buf.len() as u64) as usize;
+        if left == 0 {
+            return Ok(0);
+        }
+        self.file.seek(SeekFrom::Start(self.start + self.pos))?;
+        let n = self.file.read(&mut buf[..left])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Window {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        let pos = match to {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = pos
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+impl MediaSource for Window {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Bits of a deflate stream, least significant first.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn need(&mut self, n: u32) -> io::Result<()> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid("deflate stream cut short"))?;
+            self.buffer |= u64::from(byte) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        Ok(())
+    }
+
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        self.need(n)?;
+        let value = (self.buffer & ((1 << n) - 1)) as u32;
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drops the bits left in the current byte.
+    fn align(&mut self) {
+        let drop = self.count % 8;
+        self.buffer >>= drop;
+        self.count -= drop;
+    }
+}
+
+/// A canonical Huffman code: how many codes there are of each length, and
+/// the symbols in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[usize::from(offsets[usize::from(len)])] = symbol as u16;
+                offsets[usize::from(len)] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = i32::from(self.counts[len]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad deflate code"))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order the code length code lengths of a dynamic block are sent in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Most memory reserved up front for an inflated entry, as `size` comes from
+/// the archive and can't be trusted until the data bears it out.
+const MAX_PREALLOCATION: usize = 64 << 20;
+
+/// Decompresses a raw deflate stream (RFC 1951) of `size` bytes.
+fn inflate(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(size.min(MAX_PREALLOCATION));
+    let mut bits = Bits {
+        data,
+        pos: 0,
+        buffer: 0,
+        count: 0,
+    };
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let len = bits.bits(16)?;
+                let complement = bits.bits(16)?;
+                if len != !complement & 0xffff {
+                    return Err(invalid("bad stored block length"));
+                }
+                if out.len() + len as usize > size {
+                    return Err(too_large());
+                }
+                for _ in 0..len {
+                    out.push(bits.bits(8)? as u8);
+                }
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &mut out, size, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, size, &literals, &distances)?;
+            }
+            _ => return Err(invalid("bad deflate block type")),
+        }
+        if last {
+            break;
+        }
+    }
+    if out.len() != size {
+        return Err(invalid(
+            "inflated to the wrong size, the archive is damaged",
+        ));
+    }
+    Ok(out)
+}
+
+fn too_large() -> io::Error {
+    invalid("inflates past its stated size, the archive is damaged")
+}
+
+/// Reads the literal/length and distance codes of a dynamic block.
+fn dynamic_codes(bits: &mut Bits) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_count = bits.bits(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_count] {
+        code_lengths[i] = bits.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![];
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid("repeated length with nothing before it"))?;
+                (previous, 3 + bits.bits(2)?)
+            }
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(invalid("too many code lengths"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    size: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = usize::from(literals.decode(bits)?);
+        match symbol {
+            0..=255 if out.len() >= size => return Err(too_large()),
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                if i >= LENGTH_BASE.len() {
+                    return Err(invalid("bad deflate length"));
+                }
+                let len =
+                    usize::from(LENGTH_BASE[i]) + bits.bits(u32::from(LENGTH_EXTRA[i]))? as usize;
+                let d = usize::from(distances.decode(bits)?);
+                if d >= DISTANCE_BASE.len() {
+                    return Err(invalid("bad deflate distance"));
+                }
+                let distance = usize::from(DISTANCE_BASE[d])
+                    + bits.bits(u32::from(DISTANCE_EXTRA[d]))? as usize;
+                if distance > out.len() {
+                    return Err(invalid("deflate distance before the start"));
+                }
+                if out.len() + len > size {
+                    return Err(too_large());
+                }
+                let from = out.len() - distance;
+                for k in 0..len {
+                    out.push(out[from + k]);
+                }
+            }
+        }
+    }
+}
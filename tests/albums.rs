@@ -0,0 +1,45 @@
+//! `loudness albums`: tracks grouped by directory.
+
+mod common;
+
+use common::{loudness, sine, temp_dir, wav};
+
+#[test]
+fn tracks_are_grouped_by_directory() {
+    let dir = temp_dir("albums");
+    for (album, track, amplitude) in [("one", "a", 0.1), ("one", "b", 0.2), ("two", "c", 0.1)] {
+        std::fs::create_dir_all(dir.join(album)).unwrap();
+        let samples = sine(1000.0, amplitude, 4.0, 48_000);
+        std::fs::write(
+            dir.join(album).join(format!("{track}.wav")),
+            wav(&samples, 1, 48_000),
+        )
+        .unwrap();
+    }
+    for album in ["one", "two"] {
+        let measured = loudness(&dir)
+            .args([album, "cache.json", "--extensions", "wav", "-q"])
+            .status()
+            .unwrap();
+        assert!(measured.success());
+    }
+
+    let output = loudness(&dir)
+        .args(["albums", "cache.json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let headers: Vec<&str> = stdout
+        .lines()
+        .filter(|line| !line.starts_with(' '))
+        .collect();
+    assert_eq!(headers.len(), 2, "{stdout}");
+    assert!(headers[0].contains("one") && headers[0].ends_with("2 track(s)"));
+    assert!(headers[1].contains("two") && headers[1].ends_with("1 track(s)"));
+    let tracks: Vec<&str> = stdout.lines().filter(|l| l.starts_with(' ')).collect();
+    assert_eq!(tracks.len(), 3, "{stdout}");
+    assert!(tracks[0].ends_with(" LU") && tracks[1].ends_with(" LU"));
+    // a lone track has nothing to be apart from
+    assert!(tracks[2].ends_with("LUFS"), "{stdout}");
+}
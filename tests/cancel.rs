@@ -0,0 +1,114 @@
+//! Cancelling a measurement from another thread.
+
+use loudness::{measure_source, CancelToken, Hint, MeasureOptions, MediaSource};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A 16-bit mono WAV file of a sine hours long, made up as it's read so it
+/// takes no memory or disk.
+struct LongSine {
+    len: u64,
+    pos: u64,
+}
+
+const HEADER_LEN: u64 = 44;
+const RATE: u32 = 48_000;
+
+impl LongSine {
+    fn new(hours: u64) -> LongSine {
+        LongSine {
+            len: HEADER_LEN + hours * 3600 * u64::from(RATE) * 2,
+            pos: 0,
+        }
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let data_len = (self.len - HEADER_LEN) as u32;
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&(data_len + 36).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&RATE.to_le_bytes());
+        out.extend_from_slice(&(RATE * 2).to_le_bytes());
+        out.extend_from_slice(&2u16.to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        out
+    }
+
+    fn byte(&self, at: u64) -> u8 {
+        if at < HEADER_LEN {
+            return self.header()[at as usize];
+        }
+        let frame = (at - HEADER_LEN) / 2;
+        let phase = (frame % 48) as f32 / 48.0;
+        let sample = (3000.0 * (2.0 * std::f32::consts::PI * phase).sin()) as i16;
+        sample.to_le_bytes()[((at - HEADER_LEN) % 2) as usize]
+    }
+}
+
+impl Read for LongSine {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min((self.len - self.pos) as usize);
+        for (i, b) in buf[..n].iter_mut().enumerate() {
+            *b = self.byte(self.pos + i as u64);
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for LongSine {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(n) => n,
+            SeekFrom::End(n) => self.len.saturating_add_signed(n),
+            SeekFrom::Current(n) => self.pos.saturating_add_signed(n),
+        };
+        Ok(self.pos)
+    }
+}
+
+impl MediaSource for LongSine {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+}
+
+#[test]
+fn cancel_stops_a_long_measurement_quickly() {
+    let cancel = CancelToken::new();
+    let options = MeasureOptions::new().with_cancel_token(cancel.clone());
+    let measuring = thread::spawn(move || {
+        let mut hint = Hint::new();
+        hint.with_extension("wav");
+        measure_source(Box::new(LongSine::new(10)), hint, &options)
+    });
+
+    thread::sleep(Duration::from_millis(200));
+    let cancelled_at = Instant::now();
+    cancel.cancel();
+    let result = measuring.join().unwrap();
+    let took = cancelled_at.elapsed();
+
+    let e = result.expect_err("ten hours can't measure in 200 ms");
+    assert!(e.is_cancelled(), "{e}");
+    assert!(took < Duration::from_secs(2), "took {took:?} to stop");
+}
+
+#[test]
+fn cancelled_before_starting_measures_nothing() {
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    let options = MeasureOptions::new().with_cancel_token(cancel);
+    let e = measure_source(Box::new(LongSine::new(10)), Hint::new(), &options).unwrap_err();
+    assert!(e.is_cancelled(), "{e}");
+}
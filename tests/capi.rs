@@ -0,0 +1,49 @@
+//! Builds capi/smoke.c against the header and the cdylib and runs it, when
+//! there's a C compiler to build it with.
+
+#![cfg(all(feature = "capi", unix))]
+
+mod common;
+
+use common::{cdylib, temp_dir};
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn smoke_test_builds_and_passes() {
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    if Command::new(&cc).arg("--version").output().is_err() {
+        eprintln!("no C compiler ('{cc}'), skipping the C smoke test");
+        return;
+    }
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let libraries = cdylib().parent().unwrap().to_path_buf();
+    let smoke = temp_dir("capi").join("smoke");
+
+    let built = Command::new(&cc)
+        .arg(root.join("capi/smoke.c"))
+        .arg("-I")
+        .arg(root.join("capi"))
+        .arg("-L")
+        .arg(&libraries)
+        .args(["-lloudness", "-lm", "-o"])
+        .arg(&smoke)
+        .output()
+        .unwrap();
+    assert!(
+        built.status.success(),
+        "{}",
+        String::from_utf8_lossy(&built.stderr)
+    );
+
+    let ran = Command::new(&smoke)
+        .env("LD_LIBRARY_PATH", &libraries)
+        .env("DYLD_LIBRARY_PATH", &libraries)
+        .output()
+        .unwrap();
+    assert!(
+        ran.status.success(),
+        "{}",
+        String::from_utf8_lossy(&ran.stderr)
+    );
+}
@@ -0,0 +1,155 @@
+//! Chained Ogg streams whose channel count changes between segments, built
+//! here as Ogg FLAC with verbatim subframes.
+
+mod common;
+
+use common::{sine, temp_dir};
+use loudness::{measure_file, MeasureOptions};
+
+const RATE: u32 = 48_000;
+/// Frames per FLAC frame, and so per Ogg page.
+const BLOCK: usize = 4800;
+
+/// CRC-8 of FLAC frame headers (polynomial 0x07).
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |c, _| match c & 0x80 {
+            0 => c << 1,
+            _ => (c << 1) ^ 0x07,
+        })
+    })
+}
+
+/// CRC-16 of whole FLAC frames (polynomial 0x8005).
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ (u16::from(byte) << 8), |c, _| match c & 0x8000 {
+            0 => c << 1,
+            _ => (c << 1) ^ 0x8005,
+        })
+    })
+}
+
+/// CRC-32 of Ogg pages (polynomial 0x04c11db7, unreflected).
+fn ogg_crc(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ (u32::from(byte) << 24), |c, _| {
+            match c & 0x8000_0000 {
+                0 => c << 1,
+                _ => (c << 1) ^ 0x04c1_1db7,
+            }
+        })
+    })
+}
+
+/// The Ogg FLAC identification packet, with the STREAMINFO block.
+fn identification(channels: u8, frames: u64) -> Vec<u8> {
+    let mut out = vec![0x7f];
+    out.extend_from_slice(b"FLAC\x01\x00\x00\x00fLaC");
+    // the last metadata block, STREAMINFO, of 34 bytes
+    out.extend_from_slice(&[0x80, 0, 0, 34]);
+    out.extend_from_slice(&(BLOCK as u16).to_be_bytes());
+    out.extend_from_slice(&(BLOCK as u16).to_be_bytes());
+    out.extend_from_slice(&[0; 6]);
+    // 20 bits of rate, 3 of channels - 1, 5 of bits per sample - 1, 36 of
+    // frames
+    let packed = u64::from(RATE) << 44 | u64::from(channels - 1) << 41 | 15 << 36 | frames;
+    out.extend_from_slice(&packed.to_be_bytes());
+    out.extend_from_slice(&[0; 16]);
+    out
+}
+
+/// A FLAC frame of 16-bit `channels`, each stored verbatim.
+fn flac_frame(number: u8, channels: &[&[f32]]) -> Vec<u8> {
+    let len = channels[0].len();
+    // fixed blocking, the size in 16 bits after the header, 48 kHz,
+    // independent channels of 16 bits
+    let mut out = vec![0xff, 0xf8, 0x7a, ((channels.len() as u8 - 1) << 4) | 0x08];
+    assert!(number < 0x80);
+    out.push(number);
+    out.extend_from_slice(&(len as u16 - 1).to_be_bytes());
+    out.push(crc8(&out));
+    for channel in channels {
+        out.push(0x02);
+        for s in *channel {
+            let s = (s.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            out.extend_from_slice(&s.to_be_bytes());
+        }
+    }
+    let crc = crc16(&out);
+    out.extend_from_slice(&crc.to_be_bytes());
+    out
+}
+
+/// An Ogg page holding the one `packet`.
+fn page(serial: u32, sequence: u32, flags: u8, granule: u64, packet: &[u8]) -> Vec<u8> {
+    let mut lacing = vec![255; packet.len() / 255];
+    lacing.push((packet.len() % 255) as u8);
+    assert!(lacing.len() <= 255);
+    let mut out = b"OggS\x00".to_vec();
+    out.push(flags);
+    out.extend_from_slice(&granule.to_le_bytes());
+    out.extend_from_slice(&serial.to_le_bytes());
+    out.extend_from_slice(&sequence.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.push(lacing.len() as u8);
+    out.extend_from_slice(&lacing);
+    out.extend_from_slice(packet);
+    let crc = ogg_crc(&out);
+    out[22..26].copy_from_slice(&crc.to_le_bytes());
+    out
+}
+
+/// A complete Ogg FLAC stream of `channels`, all of equal length.
+fn ogg_flac(serial: u32, channels: &[Vec<f32>]) -> Vec<u8> {
+    let frames = channels[0].len();
+    let mut out = page(
+        serial,
+        0,
+        0x02,
+        0,
+        &identification(channels.len() as u8, frames as u64),
+    );
+    let blocks = frames.div_ceil(BLOCK);
+    for block in 0..blocks {
+        let range = block * BLOCK..((block + 1) * BLOCK).min(frames);
+        let end = range.end as u64;
+        let slices: Vec<&[f32]> = channels.iter().map(|c| &c[range.clone()]).collect();
+        let flags = if block + 1 == blocks { 0x04 } else { 0 };
+        let packet = flac_frame(block as u8, &slices);
+        out.extend(page(serial, block as u32 + 1, flags, end, &packet));
+    }
+    out
+}
+
+#[test]
+fn counts_a_change_from_stereo_to_mono() {
+    let dir = temp_dir("chained");
+    let path = dir.join("chained.ogg");
+    let tone = sine(1000.0, 0.1, 2.0, RATE);
+    let mut file = ogg_flac(1, &[tone.clone(), tone.clone()]);
+    file.extend(ogg_flac(2, &[tone]));
+    std::fs::write(&path, file).unwrap();
+
+    let m = measure_file(&path, &MeasureOptions::default()).unwrap();
+    assert_eq!(m.channel_changes, Some(1));
+    assert_eq!(m.sample_rate_changes, None);
+    let duration = m.duration.unwrap();
+    assert!((3.5..=4.01).contains(&duration), "{duration}");
+    // two channels of the tone, then one: between the loudness of each
+    assert!((-23.2..=-19.8).contains(&m.loudness), "{}", m.loudness);
+}
+
+#[test]
+fn one_segment_has_no_changes() {
+    let dir = temp_dir("unchained");
+    let path = dir.join("stereo.ogg");
+    let tone = sine(1000.0, 0.1, 2.0, RATE);
+    std::fs::write(&path, ogg_flac(1, &[tone.clone(), tone])).unwrap();
+
+    let m = measure_file(&path, &MeasureOptions::default()).unwrap();
+    assert_eq!(m.channel_changes, None);
+    assert!((m.duration.unwrap() - 2.0).abs() < 1e-3);
+    // the same tone in both channels is 3 dB louder than in one
+    assert!((m.loudness - -20.0).abs() < 0.2, "{}", m.loudness);
+}
@@ -0,0 +1,87 @@
+//! Audio generated for the tests, so there are no binary fixtures to keep.
+
+#![allow(dead_code)]
+
+use std::f32::consts::PI;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// `seconds` of a mono sine at `frequency` Hz and linear `amplitude`.
+pub fn sine(frequency: f32, amplitude: f32, seconds: f32, rate: u32) -> Vec<f32> {
+    let frames = (seconds * rate as f32) as usize;
+    (0..frames)
+        .map(|i| amplitude * (2.0 * PI * frequency * i as f32 / rate as f32).sin())
+        .collect()
+}
+
+/// A 16-bit PCM WAV file of interleaved `samples`.
+pub fn wav(samples: &[f32], channels: u16, rate: u32) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut out = Vec::with_capacity(44 + data_len as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&channels.to_le_bytes());
+    out.extend_from_slice(&rate.to_le_bytes());
+    out.extend_from_slice(&(rate * u32::from(channels) * 2).to_le_bytes());
+    out.extend_from_slice(&(channels * 2).to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    for s in samples {
+        let s = (s.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+        out.extend_from_slice(&s.to_le_bytes());
+    }
+    out
+}
+
+/// An ID3v2.4 tag of `padding` zero bytes, as taggers leave room for edits.
+pub fn id3v2(padding: u32) -> Vec<u8> {
+    let mut out = b"ID3\x04\x00\x00".to_vec();
+    // the size in 7-bit bytes
+    out.extend((0..4).rev().map(|i| (padding >> (7 * i) & 0x7f) as u8));
+    out.resize(out.len() + padding as usize, 0);
+    out
+}
+
+/// An empty directory of its own for the test `name`, under the system's
+/// temporary directory.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("loudness-test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The `loudness` binary, kept from the environment's LOUDNESS_* variables
+/// and the user's config file, and run in `dir`.
+pub fn loudness(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_loudness"));
+    for name in [
+        "LOUDNESS_CACHE",
+        "LOUDNESS_EXTENSIONS",
+        "LOUDNESS_QUIET",
+        "LOUDNESS_TARGET",
+        "LOUDNESS_THREADS",
+    ] {
+        command.env_remove(name);
+    }
+    command
+        .env("XDG_CONFIG_HOME", dir.join(".config"))
+        .env("HOME", dir)
+        .current_dir(dir);
+    command
+}
+
+/// The cdylib cargo built with the tests: in `deps` when building them, or
+/// beside the binary after `cargo build`.
+pub fn cdylib() -> PathBuf {
+    let dir = Path::new(env!("CARGO_BIN_EXE_loudness")).parent().unwrap();
+    [dir.join("deps"), dir.to_path_buf()]
+        .iter()
+        .flat_map(|d| ["libloudness.so", "libloudness.dylib"].map(|name| d.join(name)))
+        .find(|path| path.is_file())
+        .expect("the cdylib wasn't built")
+}
@@ -0,0 +1,110 @@
+//! `loudness daemon` and `loudness client` over a unix socket.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{loudness, sine, temp_dir, wav};
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::process::{Child, Stdio};
+use std::time::{Duration, Instant};
+
+/// A daemon on `dir/sock`, killed when dropped.
+struct Daemon(Child);
+
+impl Daemon {
+    fn start(dir: &Path, env: &[(&str, &str)]) -> Daemon {
+        let child = loudness(dir)
+            .args(["daemon", "--socket", "sock"])
+            .envs(env.iter().copied())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        while !dir.join("sock").exists() {
+            assert!(started.elapsed() < Duration::from_secs(10), "no socket");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        Daemon(child)
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn write_sine(path: &Path) {
+    std::fs::write(path, wav(&sine(1000.0, 0.1, 1.0, 48_000), 1, 48_000)).unwrap();
+}
+
+#[test]
+fn a_panicking_file_fails_without_the_daemon() {
+    if !cfg!(debug_assertions) {
+        // built without the hook, like the binary
+        return;
+    }
+    let dir = temp_dir("daemon-panic");
+    write_sine(&dir.join("boom.wav"));
+    write_sine(&dir.join("good.wav"));
+    let mut daemon = Daemon::start(&dir, &[("LOUDNESS_TEST_PANIC", "boom.wav")]);
+
+    let output = loudness(&dir)
+        .args(["client", "boom.wav", "good.wav", "--socket", "sock"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{stdout}");
+    assert!(lines[0].contains("panicked"), "{stdout}");
+    assert!(!lines[1].contains("\"error\""), "{stdout}");
+    assert!(daemon.0.try_wait().unwrap().is_none(), "the daemon died");
+
+    // and it goes on answering
+    let again = loudness(&dir)
+        .args(["client", "good.wav", "--socket", "sock"])
+        .output()
+        .unwrap();
+    assert!(again.status.success());
+}
+
+#[test]
+fn a_file_in_the_way_of_the_socket_is_kept() {
+    let dir = temp_dir("daemon-file");
+    std::fs::write(dir.join("notes.txt"), "keep me").unwrap();
+    let output = loudness(&dir)
+        .args(["daemon", "--socket", "notes.txt"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("isn't a socket"));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("notes.txt")).unwrap(),
+        "keep me"
+    );
+}
+
+#[test]
+fn unanswered_paths_fail_the_client() {
+    let dir = temp_dir("daemon-gone");
+    write_sine(&dir.join("a.wav"));
+    // a daemon that reads the request and goes away without a reply
+    let listener = UnixListener::bind(dir.join("sock")).unwrap();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let _ = BufReader::new(&stream).lines().next();
+    });
+    let output = loudness(&dir)
+        .args(["client", "a.wav", "--socket", "sock"])
+        .output()
+        .unwrap();
+    server.join().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("answered 0 of 1"));
+}
@@ -0,0 +1,71 @@
+//! The binary's exit codes, as `loudness --help` documents them.
+
+mod common;
+
+use common::{loudness, sine, temp_dir, wav};
+use std::path::Path;
+
+fn write_sine(path: &Path) {
+    std::fs::write(path, wav(&sine(1000.0, 0.1, 1.0, 48_000), 1, 48_000)).unwrap();
+}
+
+fn code(dir: &Path, args: &[&str]) -> i32 {
+    let output = loudness(dir).args(args).output().unwrap();
+    output.status.code().unwrap_or_else(|| {
+        panic!(
+            "killed by a signal: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+    })
+}
+
+#[test]
+fn measured_everything_is_0() {
+    let dir = temp_dir("exit-ok");
+    write_sine(&dir.join("a.wav"));
+    assert_eq!(code(&dir, &["a.wav", "out.json", "--extensions", "wav"]), 0);
+    assert!(dir.join("out.json").is_file());
+}
+
+#[test]
+fn a_failed_file_is_1() {
+    let dir = temp_dir("exit-failed");
+    std::fs::create_dir(dir.join("music")).unwrap();
+    write_sine(&dir.join("music/good.wav"));
+    std::fs::write(dir.join("music/bad.wav"), [0x55; 4096]).unwrap();
+    assert_eq!(code(&dir, &["music", "out.json", "--extensions", "wav"]), 1);
+    // the files that did measure are still saved
+    let cache = loudness::load_cache(&dir.join("out.json")).unwrap();
+    assert!(cache.contains_key("good"));
+    assert!(!cache.contains_key("bad"));
+}
+
+#[test]
+fn bad_usage_is_2() {
+    let dir = temp_dir("exit-usage");
+    write_sine(&dir.join("a.wav"));
+    assert_eq!(code(&dir, &["a.wav", "--no-such-flag"]), 2);
+    assert_eq!(code(&dir, &["a.wav", "--target", "loud"]), 2);
+    assert_eq!(code(&dir, &["missing.wav"]), 2);
+}
+
+#[test]
+fn an_unwritable_outfile_is_3() {
+    let dir = temp_dir("exit-outfile");
+    write_sine(&dir.join("a.wav"));
+    assert_eq!(
+        code(
+            &dir,
+            &["a.wav", "no/such/dir/out.json", "--extensions", "wav"]
+        ),
+        3
+    );
+}
+
+#[test]
+fn no_files_is_4() {
+    let dir = temp_dir("exit-no-files");
+    std::fs::create_dir(dir.join("empty")).unwrap();
+    assert_eq!(code(&dir, &["empty"]), 4);
+    assert_eq!(code(&dir, &["empty", "--allow-empty"]), 0);
+}
@@ -0,0 +1,124 @@
+//! `loudness gains`: rounding, clamping, and entries without a loudness.
+
+mod common;
+
+use common::{loudness, temp_dir};
+use loudness::{save_cache, Measurement};
+use std::collections::HashMap;
+use std::path::Path;
+
+fn entry(loudness: f64) -> Measurement {
+    Measurement {
+        loudness,
+        energy: 0.0,
+        duration: Some(10.0),
+        path: None,
+        decode_errors: None,
+        short: None,
+        duration_mismatch: None,
+        source: None,
+        start: None,
+        end: None,
+        channel_map: None,
+        sample_rate_changes: None,
+        channel_changes: None,
+        true_peak: None,
+        short_term_max: None,
+        psr: None,
+        psr_avg: None,
+        momentary_max: None,
+        momentary_max_at_secs: None,
+    }
+}
+
+/// Runs `gains` on a cache of `entries` with `args`, giving the gains
+/// written and what was said on stderr.
+fn gains(dir: &Path, entries: &[(&str, f64)], args: &[&str]) -> (HashMap<String, f64>, String) {
+    let cache: HashMap<String, Measurement> = entries
+        .iter()
+        .map(|(key, loudness)| (key.to_string(), entry(*loudness)))
+        .collect();
+    save_cache(&cache, &dir.join("cache.json")).unwrap();
+    let output = loudness(dir)
+        .args(["gains", "cache.json", "gains.json"])
+        .args(args)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "{stderr}");
+    let written = std::fs::read_to_string(dir.join("gains.json")).unwrap();
+    (merde::json::from_str(&written).unwrap(), stderr)
+}
+
+#[test]
+fn db_gains_round_to_hundredths() {
+    let dir = temp_dir("gains-db");
+    let (gains, _) = gains(
+        &dir,
+        &[
+            ("a", -20.126),
+            ("b", -15.874),
+            ("c", -18.0),
+            // exact halves round away from zero
+            ("d", -18.125),
+            ("e", -17.875),
+        ],
+        &["--target", "-18", "--db"],
+    );
+    assert_eq!(gains["a"], 2.13);
+    assert_eq!(gains["b"], -2.13);
+    assert_eq!(gains["c"], 0.0);
+    assert_eq!(gains["d"], 0.13);
+    assert_eq!(gains["e"], -0.13);
+}
+
+#[test]
+fn linear_gains_round_to_thousandths() {
+    let dir = temp_dir("gains-linear");
+    let (gains, _) = gains(
+        &dir,
+        &[("louder", -23.0), ("quieter", -3.0), ("same", -18.0)],
+        &["--target", "-18"],
+    );
+    // 10^(5/20) = 1.77827..., 10^(-15/20) = 0.17782...
+    assert_eq!(gains["louder"], 1.778);
+    assert_eq!(gains["quieter"], 0.178);
+    assert_eq!(gains["same"], 1.0);
+}
+
+#[test]
+fn clamp_only_attenuates() {
+    let dir = temp_dir("gains-clamp");
+    let (gains, _) = gains(
+        &dir,
+        &[("quiet", -30.0), ("loud", -8.0)],
+        &["--target", "-18", "--db", "--clamp"],
+    );
+    assert_eq!(gains["quiet"], 0.0);
+    assert_eq!(gains["loud"], -10.0);
+}
+
+#[test]
+fn silent_entries_are_left_out() {
+    let dir = temp_dir("gains-silent");
+    let (gains, stderr) = gains(
+        &dir,
+        &[("silent", f64::NEG_INFINITY), ("tone", -23.0)],
+        &["--target", "-23", "--db"],
+    );
+    assert_eq!(gains.len(), 1);
+    assert_eq!(gains["tone"], 0.0);
+    assert!(stderr.contains("wrote 1 gain(s)"), "{stderr}");
+    assert!(
+        stderr.contains("omitted 1 without usable loudness"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn only_silent_entries_write_an_empty_map() {
+    let dir = temp_dir("gains-all-silent");
+    let (gains, stderr) = gains(&dir, &[("silent", f64::NEG_INFINITY)], &["--target", "-23"]);
+    assert!(gains.is_empty());
+    assert!(stderr.contains("wrote 0 gain(s)"), "{stderr}");
+}
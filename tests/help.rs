@@ -0,0 +1,50 @@
+//! `--help`, in full and for each subcommand.
+
+mod common;
+
+use common::{loudness, temp_dir};
+
+fn help(args: &[&str]) -> String {
+    let dir = temp_dir("help");
+    let output = loudness(&dir).args(args).output().unwrap();
+    assert!(output.status.success(), "{args:?} failed");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn the_full_help_has_every_subcommand() {
+    let full = help(&["--help"]);
+    assert!(full.starts_with("usage: loudness [measure]"));
+    assert!(full.contains("loudness top <cache.json>"));
+    assert!(full.contains("loudness albums <cache.json>"));
+    assert!(full.contains("Exit codes:"));
+    assert_eq!(help(&["help"]), full);
+}
+
+#[test]
+fn a_subcommand_shows_only_its_usage() {
+    let top = help(&["top", "--help"]);
+    assert!(top.starts_with("usage: loudness top <cache.json> --loudest N|--quietest N"));
+    assert!(!top.contains("loudness tag"));
+    assert!(!top.contains("Exit codes:"));
+
+    // continuation lines come along, lined up under the first
+    let hist = help(&["hist", "cache.json", "-h"]);
+    let lines: Vec<&str> = hist.lines().collect();
+    assert!(lines[0].starts_with("usage: loudness hist <cache.json>"));
+    assert!(lines[1]
+        .trim_start()
+        .starts_with("[--metric loudness|gain]"));
+    assert!(!hist.contains("loudness outliers"));
+}
+
+#[test]
+fn measure_and_inputs_show_the_measure_usage() {
+    let measure = help(&["measure", "--help"]);
+    assert!(measure.starts_with("usage: loudness [measure] <file/directory>"));
+    assert!(measure.contains("loudness [measure] --files-from FILE"));
+    assert!(measure.contains("Exit codes:"));
+    assert!(!measure.contains("loudness top"));
+    // an input that isn't a subcommand's name is measure's
+    assert_eq!(help(&["lib", "--help"]), measure);
+}
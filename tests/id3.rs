@@ -0,0 +1,53 @@
+mod common;
+
+use common::{id3v2, sine, temp_dir, wav};
+use loudness::{measure_file, MeasureOptions};
+
+/// A loud first half and a second half 20 dB quieter, so a seek that lands
+/// anywhere but where it should measures something else.
+fn loud_then_quiet() -> Vec<u8> {
+    let mut samples = sine(1000.0, 0.5, 5.0, 48_000);
+    samples.extend(sine(1000.0, 0.05, 5.0, 48_000));
+    wav(&samples, 1, 48_000)
+}
+
+#[test]
+fn start_seeks_the_same_past_id3v2_tags() {
+    let dir = temp_dir("id3-start");
+    let plain = dir.join("plain.wav");
+    let tagged = dir.join("tagged.wav");
+    std::fs::write(&plain, loud_then_quiet()).unwrap();
+    let mut bytes = id3v2(200_000);
+    bytes.extend(loud_then_quiet());
+    std::fs::write(&tagged, bytes).unwrap();
+
+    let options = MeasureOptions::new().with_start(6.0);
+    let plain = measure_file(&plain, &options).unwrap();
+    let tagged = measure_file(&tagged, &options).unwrap();
+    // a mono 1 kHz sine at 0.05 measures 20·log10(0.05) - 3 LUFS
+    assert!((plain.loudness - -29.0).abs() < 0.2, "{}", plain.loudness);
+    assert!(
+        (tagged.loudness - plain.loudness).abs() < 0.01,
+        "tagged {} vs plain {}",
+        tagged.loudness,
+        plain.loudness
+    );
+    assert_eq!(tagged.duration, plain.duration);
+}
+
+#[test]
+fn whole_file_measures_the_same_past_id3v2_tags() {
+    let dir = temp_dir("id3-whole");
+    let tagged = dir.join("tagged.wav");
+    let mut bytes = id3v2(1000);
+    bytes.extend(loud_then_quiet());
+    std::fs::write(&tagged, bytes).unwrap();
+    let plain = loudness::measure_source(
+        Box::new(std::io::Cursor::new(loud_then_quiet())),
+        loudness::Hint::new(),
+        &MeasureOptions::new(),
+    )
+    .unwrap();
+    let tagged = measure_file(&tagged, &MeasureOptions::new()).unwrap();
+    assert!((tagged.loudness - plain.loudness).abs() < 0.01);
+}
@@ -0,0 +1,75 @@
+//! What the binary says about inputs and outfiles it can't use, before
+//! measuring anything.
+
+mod common;
+
+use common::{loudness, sine, temp_dir, wav};
+use std::path::Path;
+
+/// The exit code and standard error of running with `args` in `dir`.
+fn run(dir: &Path, args: &[&str]) -> (Option<i32>, String) {
+    let output = loudness(dir).args(args).output().unwrap();
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn missing_input() {
+    let dir = temp_dir("input-missing");
+    let (code, stderr) = run(&dir, &["nowhere"]);
+    assert_eq!(code, Some(2));
+    assert!(stderr.contains("'nowhere' does not exist"), "{stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn input_that_is_not_a_file() {
+    let dir = temp_dir("input-special");
+    let (code, stderr) = run(&dir, &["/dev/null"]);
+    assert_eq!(code, Some(2));
+    assert!(
+        stderr.contains("'/dev/null' is neither a file nor a directory"),
+        "{stderr}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_input() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = temp_dir("input-unreadable");
+    let path = dir.join("locked.wav");
+    std::fs::write(&path, wav(&sine(1000.0, 0.1, 1.0, 48_000), 1, 48_000)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o000)).unwrap();
+    if std::fs::File::open(&path).is_ok() {
+        // running as root, which reads it anyway
+        return;
+    }
+    let (code, stderr) = run(&dir, &["locked.wav"]);
+    assert_eq!(code, Some(2));
+    assert!(
+        stderr.contains("'locked.wav' can't be read: permission denied"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn unwritable_outfile() {
+    let dir = temp_dir("input-outfile");
+    std::fs::write(
+        dir.join("a.wav"),
+        wav(&sine(1000.0, 0.1, 1.0, 48_000), 1, 48_000),
+    )
+    .unwrap();
+    // a path under a file can't be created, even by root
+    let (code, stderr) = run(&dir, &["a.wav", "a.wav/out.json", "--extensions", "wav"]);
+    assert_eq!(code, Some(3));
+    assert!(
+        stderr.contains("can't write the outfile 'a.wav/out.json'"),
+        "{stderr}"
+    );
+    assert!(!dir.join("out.json").exists());
+}
@@ -0,0 +1,50 @@
+//! `--io-jobs`, which streams files through a shared cap on reads.
+
+mod common;
+
+use common::{loudness, sine, temp_dir, wav};
+use std::path::Path;
+
+/// The CSV report of measuring the directory with `args`.
+fn report(dir: &Path, args: &[&str]) -> String {
+    let output = loudness(dir)
+        .args([".", "--extensions", "wav", "--csv", "--threads", "4"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn one_read_at_a_time_measures_the_same() {
+    let dir = temp_dir("io-jobs");
+    for (i, amplitude) in [0.05, 0.1, 0.2, 0.4].into_iter().enumerate() {
+        let samples = sine(440.0 * (i + 1) as f32, amplitude, 5.0, 48_000);
+        std::fs::write(dir.join(format!("{i}.wav")), wav(&samples, 1, 48_000)).unwrap();
+    }
+    let streamed = report(&dir, &[]);
+    assert_eq!(streamed.lines().count(), 5);
+    // small reads, so the workers take turns many times over
+    let limited = report(&dir, &["--io-jobs", "1", "--read-buffer", "4096"]);
+    assert_eq!(limited, streamed);
+}
+
+#[test]
+fn io_jobs_and_mmap_are_refused_together() {
+    let dir = temp_dir("io-jobs-mmap");
+    std::fs::write(
+        dir.join("a.wav"),
+        wav(&sine(1000.0, 0.1, 1.0, 48_000), 1, 48_000),
+    )
+    .unwrap();
+    let output = loudness(&dir)
+        .args(["a.wav", "--io-jobs", "2", "--mmap"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,181 @@
+//! The library's file and cache functions, on small generated WAV files.
+
+mod common;
+
+use common::{sine, temp_dir, wav};
+use loudness::{
+    csv_field, load_cache, measure_file, save_cache_as, split_csv, tsv_field, BlockHistogram,
+    CacheFormat, ErrorKind, MeasureOptions, Measurement,
+};
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+#[test]
+fn measure_file_measures_a_sine() {
+    let dir = temp_dir("measure-sine");
+    let path = dir.join("sine.wav");
+    std::fs::write(&path, wav(&sine(1000.0, 0.1, 5.0, 48_000), 1, 48_000)).unwrap();
+
+    let m = measure_file(&path, &MeasureOptions::default()).unwrap();
+    // a 1 kHz sine at -20 dBFS peak is about -23 LUFS in one channel
+    assert!((m.loudness - -23.0).abs() < 0.2, "{}", m.loudness);
+    assert!((m.duration.unwrap() - 5.0).abs() < 1e-3);
+    assert_eq!(m.path.as_deref(), path.to_str());
+    assert_eq!(m.short, None);
+    assert_eq!(m.decode_errors, None);
+}
+
+#[test]
+fn measure_file_matches_measure_samples() {
+    let dir = temp_dir("measure-samples");
+    let path = dir.join("stereo.wav");
+    let left = sine(440.0, 0.3, 3.0, 44_100);
+    let right = sine(880.0, 0.2, 3.0, 44_100);
+    let samples: Vec<f32> = left
+        .iter()
+        .zip(&right)
+        .flat_map(|(l, r)| [*l, *r])
+        .collect();
+    std::fs::write(&path, wav(&samples, 2, 44_100)).unwrap();
+
+    let from_file = measure_file(&path, &MeasureOptions::default()).unwrap();
+    let from_samples = loudness::measure_samples(&samples, 2, 44_100).unwrap();
+    // only 16-bit quantization apart
+    assert!((from_file.loudness - from_samples.loudness).abs() < 0.01);
+}
+
+#[test]
+fn measure_file_reports_silence_as_negative_infinity() {
+    let dir = temp_dir("measure-silence");
+    let path = dir.join("silence.wav");
+    std::fs::write(&path, wav(&vec![0.0; 48_000 * 2], 1, 48_000)).unwrap();
+
+    let m = measure_file(&path, &MeasureOptions::default()).unwrap();
+    assert_eq!(m.loudness, f64::NEG_INFINITY);
+}
+
+#[test]
+fn measure_file_errors_carry_the_path() {
+    let dir = temp_dir("measure-errors");
+
+    let missing = dir.join("missing.wav");
+    let e = measure_file(&missing, &MeasureOptions::default()).unwrap_err();
+    assert!(matches!(e.kind(), ErrorKind::Open(_)), "{e}");
+    assert_eq!(e.path(), Some(missing.as_path()));
+
+    let empty = dir.join("empty.wav");
+    std::fs::write(&empty, b"").unwrap();
+    let e = measure_file(&empty, &MeasureOptions::default()).unwrap_err();
+    assert!(matches!(e.kind(), ErrorKind::Empty), "{e}");
+
+    let garbage = dir.join("garbage.wav");
+    std::fs::write(&garbage, [0x55; 4096]).unwrap();
+    let e = measure_file(&garbage, &MeasureOptions::default()).unwrap_err();
+    assert!(matches!(e.kind(), ErrorKind::Probe(_)), "{e}");
+}
+
+fn measured(dir: &std::path::Path) -> HashMap<String, Measurement> {
+    let mut cache = HashMap::new();
+    for (name, amplitude) in [("loud", 0.5), ("quiet", 0.05), ("silent", 0.0)] {
+        let path = dir.join(format!("{name}.wav"));
+        std::fs::write(&path, wav(&sine(1000.0, amplitude, 1.0, 48_000), 1, 48_000)).unwrap();
+        let m = measure_file(&path, &MeasureOptions::default()).unwrap();
+        cache.insert(loudness::cache_key(&path), m);
+    }
+    cache
+}
+
+#[test]
+fn save_cache_as_round_trips_through_load_cache() {
+    let dir = temp_dir("cache-round-trip");
+    let cache = measured(&dir);
+    for format in [
+        CacheFormat::Json,
+        CacheFormat::Ndjson,
+        CacheFormat::Csv,
+        CacheFormat::Tsv,
+    ] {
+        let to = dir.join(format!("cache.{}", format.name()));
+        save_cache_as(&cache, &to, format).unwrap();
+        assert_eq!(loudness::cache_format_of(&to).unwrap(), Some(format));
+
+        let loaded = load_cache(&to).unwrap();
+        assert_eq!(loaded.len(), cache.len(), "{format}");
+        for (key, m) in &cache {
+            let l = &loaded[key];
+            assert_eq!(l.loudness, m.loudness, "{format} {key}");
+            assert_eq!(l.energy, m.energy, "{format} {key}");
+            assert_eq!(l.duration, m.duration, "{format} {key}");
+            assert_eq!(l.path, m.path, "{format} {key}");
+            assert_eq!(l.short, m.short, "{format} {key}");
+        }
+    }
+}
+
+#[test]
+fn csv_fields_split_back_apart() {
+    let values = ["plain", "a,b", "say \"hi\"", "two\nlines", "cr\r", ""];
+    let line: Vec<String> = values.iter().map(|v| csv_field(v)).collect();
+    let text = format!("{}\n\nnext\n", line.join(","));
+    assert_eq!(split_csv(&text), vec![values.to_vec(), vec!["next"]]);
+
+    assert_eq!(tsv_field("a\tb\nc\rd\\e"), "a\\tb\\nc\\rd\\\\e");
+}
+
+#[test]
+fn load_cache_normalizes_keys() {
+    let dir = temp_dir("cache-keys");
+    let m = measure_file(
+        &{
+            let path = dir.join("a.wav");
+            std::fs::write(&path, wav(&sine(1000.0, 0.1, 1.0, 48_000), 1, 48_000)).unwrap();
+            path
+        },
+        &MeasureOptions::default(),
+    )
+    .unwrap();
+    let to = dir.join("cache.json");
+    save_cache_as(
+        &HashMap::from([("Cafe\u{301}".to_string(), m)]),
+        &to,
+        CacheFormat::Json,
+    )
+    .unwrap();
+
+    let loaded = load_cache(&to).unwrap();
+    assert!(loaded.contains_key("Caf\u{e9}"), "{:?}", loaded.keys());
+}
+
+#[test]
+fn load_cache_errors() {
+    let dir = temp_dir("cache-errors");
+    let e = load_cache(&dir.join("missing.json")).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+
+    let malformed = dir.join("malformed.json");
+    std::fs::write(&malformed, "{\"a\": {\"loudness\": ").unwrap();
+    assert!(load_cache(&malformed).is_err());
+}
+
+#[test]
+fn measure_options_debug_shows_every_option() {
+    let options = MeasureOptions {
+        start: Some(1.5),
+        duration: Some(30.0),
+        channel_map: Some("FL,FR".parse().unwrap()),
+        on_block_histogram: Some((BlockHistogram::new(-70.0, 0.0), Arc::new(|_| {}))),
+        psr: true,
+        ..MeasureOptions::default()
+    };
+    let debug = format!("{options:?}");
+    for shown in [
+        "start: Some(1.5)",
+        "duration: Some(30.0)",
+        "channel_map: Some(",
+        "on_block_histogram: Some(\"..\")",
+        "psr: true",
+    ] {
+        assert!(debug.contains(shown), "{shown} missing from {debug}");
+    }
+}
@@ -0,0 +1,54 @@
+//! Cache keys in NFC whichever way the filesystem composed the name.
+
+use loudness::{cache_key, normalize_key};
+use std::path::Path;
+
+/// Decomposed (or otherwise non-NFC) names, and their NFC forms.
+const PAIRS: &[(&str, &str)] = &[
+    ("plain ascii", "plain ascii"),
+    ("Cafe\u{301}", "Caf\u{e9}"),
+    ("Caf\u{e9}", "Caf\u{e9}"),
+    ("u\u{308}ber", "\u{fc}ber"),
+    ("A\u{30a}ngstro\u{308}m", "\u{c5}ngstr\u{f6}m"),
+    // singletons decompose and don't recompose
+    ("\u{212b}", "\u{c5}"),
+    ("\u{212a}elvin", "Kelvin"),
+    // combining marks are put in canonical order before composing
+    ("a\u{323}\u{302}", "\u{1ead}"),
+    ("a\u{302}\u{323}", "\u{1ead}"),
+    // a second mark that can't compose stays after the composed letter
+    ("e\u{301}\u{301}", "\u{e9}\u{301}"),
+    // composition exclusions stay decomposed
+    ("\u{958}", "\u{915}\u{93c}"),
+    // conjoining Hangul jamo, leading + vowel and leading + vowel + trailing
+    ("\u{1100}\u{1161}", "\u{ac00}"),
+    ("\u{1112}\u{1161}\u{11ab}", "\u{d55c}"),
+    ("\u{ac00}\u{11a8}", "\u{ac01}"),
+    (
+        "\u{1112}\u{1161}\u{11ab}\u{1100}\u{1173}\u{11af}",
+        "\u{d55c}\u{ae00}",
+    ),
+    // a syllable that already has a trailing consonant takes no other
+    ("\u{d55c}\u{11ab}", "\u{d55c}\u{11ab}"),
+    (
+        "01 Cafe\u{301} \u{1112}\u{1161}\u{11ab}",
+        "01 Caf\u{e9} \u{d55c}",
+    ),
+];
+
+#[test]
+fn normalize_key_gives_nfc() {
+    for (input, nfc) in PAIRS {
+        assert_eq!(normalize_key(input), *nfc, "{input:?}");
+        // and leaves NFC alone
+        assert_eq!(normalize_key(nfc), *nfc, "{nfc:?}");
+    }
+}
+
+#[test]
+fn cache_key_gives_nfc() {
+    for (input, nfc) in PAIRS {
+        let path = format!("music/{input}.flac");
+        assert_eq!(cache_key(Path::new(&path)), *nfc, "{input:?}");
+    }
+}
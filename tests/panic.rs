@@ -0,0 +1,38 @@
+//! A file whose measurement panics fails alone, without stopping the batch.
+//! Debug builds of the binary panic on the file named by LOUDNESS_TEST_PANIC.
+
+mod common;
+
+use common::{loudness, sine, temp_dir, wav};
+
+#[test]
+fn a_panicking_file_fails_and_the_rest_are_measured() {
+    if !cfg!(debug_assertions) {
+        // built without the hook, like the binary
+        return;
+    }
+    let dir = temp_dir("panic");
+    std::fs::create_dir(dir.join("music")).unwrap();
+    for name in ["a", "b", "c"] {
+        std::fs::write(
+            dir.join(format!("music/{name}.wav")),
+            wav(&sine(1000.0, 0.1, 1.0, 48_000), 1, 48_000),
+        )
+        .unwrap();
+    }
+
+    let output = loudness(&dir)
+        .args(["music", "out.json", "--extensions", "wav", "--threads", "2"])
+        .env("LOUDNESS_TEST_PANIC", "b.wav")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(stderr.contains("b.wav"), "{stderr}");
+    assert!(stderr.contains("LOUDNESS_TEST_PANIC"), "{stderr}");
+    let cache = loudness::load_cache(&dir.join("out.json")).unwrap();
+    let mut keys: Vec<_> = cache.keys().map(String::as_str).collect();
+    keys.sort();
+    assert_eq!(keys, ["a", "c"]);
+}
@@ -0,0 +1,30 @@
+//! Runs the Python bindings' tests against the cdylib, when there's a
+//! python3 to run them with.
+
+#![cfg(all(feature = "capi", unix))]
+
+mod common;
+
+use common::cdylib;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn python_bindings_pass_their_tests() {
+    if Command::new("python3").arg("--version").output().is_err() {
+        eprintln!("no python3, skipping the Python bindings' tests");
+        return;
+    }
+    let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("python/test_loudness.py");
+    let ran = Command::new("python3")
+        .arg(tests)
+        .env("LOUDNESS_LIB", cdylib())
+        .output()
+        .unwrap();
+    assert!(
+        ran.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&ran.stdout),
+        String::from_utf8_lossy(&ran.stderr)
+    );
+}
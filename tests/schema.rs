@@ -0,0 +1,127 @@
+//! Caches written by `save_cache` hold to `cache_schema`, and read back to
+//! the same cache.
+
+mod common;
+
+use common::{sine, temp_dir, wav};
+use loudness::{cache_schema, load_cache, measure_file, save_cache, MeasureOptions};
+use merde::{CowStr, Value};
+use std::collections::HashMap;
+
+fn get<'v, 's>(value: &'v Value<'s>, key: &str) -> &'v Value<'s> {
+    value
+        .as_map()
+        .unwrap()
+        .get(&CowStr::from(key.to_string()))
+        .unwrap_or_else(|| panic!("no {key}"))
+}
+
+/// The schema types a JSON value is one of.
+fn kinds(value: &Value) -> &'static [&'static str] {
+    match value {
+        Value::Null => &["null"],
+        // whole numbers are read back as integers
+        Value::I64(_) | Value::U64(_) => &["integer", "number"],
+        Value::Float(_) => &["number"],
+        Value::Str(_) => &["string"],
+        Value::Bool(_) => &["boolean"],
+        Value::Array(_) => &["array"],
+        Value::Map(_) => &["object"],
+        _ => &[],
+    }
+}
+
+fn allows(schema: &Value, value: &Value) -> bool {
+    let types: Vec<&str> = match get(schema, "type") {
+        Value::Array(types) => types.iter().map(|t| &**t.as_str().unwrap()).collect(),
+        t => vec![&**t.as_str().unwrap()],
+    };
+    kinds(value).iter().any(|k| types.contains(k))
+}
+
+/// Checks `cache` against the parts of JSON Schema that `cache_schema` uses.
+fn validate(schema: &Value, cache: &Value) {
+    assert_eq!(
+        &**get(get(schema, "additionalProperties"), "$ref")
+            .as_str()
+            .unwrap(),
+        "#/$defs/measurement"
+    );
+    assert!(allows(schema, cache));
+    let measurement = get(get(schema, "$defs"), "measurement");
+    let properties = get(measurement, "properties").as_map().unwrap();
+    let Value::Array(required) = get(measurement, "required") else {
+        panic!("required isn't an array");
+    };
+    for (key, entry) in cache.as_map().unwrap().iter() {
+        assert!(allows(measurement, entry), "{key}");
+        let entry = entry.as_map().unwrap();
+        for name in required.iter() {
+            let name = CowStr::from(name.as_str().unwrap().to_string());
+            assert!(entry.contains_key(&name), "{key} has no {name}");
+        }
+        for (name, value) in entry.iter() {
+            let property = properties
+                .get(name)
+                .unwrap_or_else(|| panic!("{key}.{name} isn't in the schema"));
+            assert!(
+                allows(property, value),
+                "{key}.{name} is {value:?}, the schema has {property:?}"
+            );
+        }
+    }
+}
+
+/// Measurements that between them set every field a measurement can.
+fn measured() -> HashMap<String, loudness::Measurement> {
+    let dir = temp_dir("schema-measured");
+    let mut cache = HashMap::new();
+    let write = |name: &str, samples: &[f32]| {
+        let path = dir.join(format!("{name}.wav"));
+        std::fs::write(&path, wav(samples, 1, 48_000)).unwrap();
+        path
+    };
+    let tone = sine(1000.0, 0.1, 8.0, 48_000);
+    let options = [
+        ("whole", MeasureOptions::new()),
+        (
+            "range",
+            MeasureOptions::new().with_start(2.0).with_duration(4.0),
+        ),
+        ("psr", MeasureOptions::new().with_psr(true)),
+    ];
+    for (name, options) in options {
+        let path = write(name, &tone);
+        cache.insert(name.to_string(), measure_file(&path, &options).unwrap());
+    }
+    let short = write("short", &sine(1000.0, 0.1, 1.0, 48_000));
+    // short, as silence long enough to gate fails to measure
+    let silent = write("silent", &vec![0.0; 48_000 * 2]);
+    for path in [short, silent] {
+        let m = measure_file(&path, &MeasureOptions::default()).unwrap();
+        cache.insert(loudness::cache_key(&path), m);
+    }
+    cache
+}
+
+#[test]
+fn saved_caches_hold_to_the_schema_and_read_back() {
+    let schema = cache_schema();
+    let schema: Value = merde::json::from_str(&schema).unwrap();
+    let dir = temp_dir("schema-round-trip");
+    let path = dir.join("cache.json");
+
+    let cache = measured();
+    save_cache(&cache, &path).unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    let value: Value = merde::json::from_str(&written).unwrap();
+    validate(&schema, &value);
+
+    // and what's read back is written the same again
+    let again = dir.join("again.json");
+    save_cache(&load_cache(&path).unwrap(), &again).unwrap();
+    let rewritten = std::fs::read_to_string(&again).unwrap();
+    let rewritten: Value = merde::json::from_str(&rewritten).unwrap();
+    validate(&schema, &rewritten);
+    assert_eq!(rewritten, value);
+}
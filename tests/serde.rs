@@ -0,0 +1,239 @@
+//! The serde impls write the same JSON as the merde ones the cache uses.
+
+#![cfg(feature = "serde")]
+
+use loudness::Measurement;
+use serde::ser::{self, Impossible, Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+#[derive(Debug)]
+struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Error {
+        Error(message.to_string())
+    }
+}
+
+/// Writes the JSON merde would for a struct of scalars and options, which is
+/// all a `Measurement` has.
+struct Json<'a>(&'a mut String);
+
+fn unsupported<T>(what: &str) -> Result<T, Error> {
+    Err(Error(format!("{what} isn't in a Measurement")))
+}
+
+impl<'a> Serializer for Json<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Object<'a>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.0.push_str(&v.to_string());
+        Ok(())
+    }
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.0.push_str(&v.to_string());
+        Ok(())
+    }
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.serialize_u64(v.into())
+    }
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.serialize_u64(v.into())
+    }
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.serialize_u64(v.into())
+    }
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.0.push_str(&v.to_string());
+        Ok(())
+    }
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.serialize_f64(v.into())
+    }
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.0.push_str(&v.to_string());
+        Ok(())
+    }
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.serialize_str(&v.to_string())
+    }
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        // the strings tested need no escaping
+        self.0.push('"');
+        self.0.push_str(v);
+        self.0.push('"');
+        Ok(())
+    }
+    fn serialize_bytes(self, _: &[u8]) -> Result<(), Error> {
+        unsupported("bytes")
+    }
+    fn serialize_none(self) -> Result<(), Error> {
+        self.0.push_str("null");
+        Ok(())
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.serialize_none()
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), Error> {
+        self.serialize_none()
+    }
+    fn serialize_unit_variant(self, _: &'static str, _: u32, _: &'static str) -> Result<(), Error> {
+        unsupported("an enum")
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Error> {
+        unsupported("an enum")
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        unsupported("a sequence")
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Error> {
+        unsupported("a tuple")
+    }
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        unsupported("a tuple")
+    }
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        unsupported("an enum")
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        unsupported("a map")
+    }
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Object<'a>, Error> {
+        self.0.push('{');
+        Ok(Object {
+            out: self.0,
+            first: true,
+        })
+    }
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        unsupported("an enum")
+    }
+}
+
+struct Object<'a> {
+    out: &'a mut String,
+    first: bool,
+}
+
+impl SerializeStruct for Object<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        if !std::mem::take(&mut self.first) {
+            self.out.push(',');
+        }
+        Json(self.out).serialize_str(key)?;
+        self.out.push(':');
+        value.serialize(Json(self.out))
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.out.push('}');
+        Ok(())
+    }
+}
+
+fn serde_json(m: &Measurement) -> String {
+    let mut out = String::new();
+    m.serialize(Json(&mut out)).unwrap();
+    out
+}
+
+fn every_field_set() -> Measurement {
+    Measurement {
+        loudness: -23.5,
+        energy: 1.25,
+        duration: Some(2.5),
+        path: Some("/music/a.flac".to_string()),
+        decode_errors: Some(3),
+        short: Some(true),
+        duration_mismatch: Some(false),
+        source: Some("loudnorm".to_string()),
+        start: Some(30.5),
+        end: Some(90.5),
+        channel_map: Some("FL,FR".to_string()),
+        sample_rate_changes: Some(1),
+        channel_changes: Some(2),
+        true_peak: Some(-1.5),
+        short_term_max: Some(-9.5),
+        psr: Some(8.5),
+        psr_avg: Some(10.5),
+        momentary_max: Some(-8.5),
+        momentary_max_at_secs: Some(61.5),
+    }
+}
+
+#[test]
+fn serde_writes_the_fields_merde_does() {
+    let m = every_field_set();
+    assert_eq!(serde_json(&m), merde::json::to_string(&m));
+}
+
+#[test]
+fn serde_writes_none_as_null_like_merde() {
+    let m = loudness::measure_samples(&[0.25; 48_000 * 4], 1, 48_000).unwrap();
+    assert!(m.duration.is_some() && m.path.is_none());
+    assert_eq!(serde_json(&m), merde::json::to_string(&m));
+}
@@ -0,0 +1,61 @@
+//! The report's `--sort` orders.
+
+mod common;
+
+use common::{loudness, sine, temp_dir, wav};
+use std::path::Path;
+
+/// `a` is a loud sine; `b` a quieter one with a single spike, so it has the
+/// higher peak.
+fn write_files(dir: &Path) {
+    let loud = sine(1000.0, 0.3, 4.0, 48_000);
+    let mut spiky = sine(1000.0, 0.1, 4.0, 48_000);
+    spiky[1000] = 0.9;
+    std::fs::write(dir.join("a.wav"), wav(&loud, 1, 48_000)).unwrap();
+    std::fs::write(dir.join("b.wav"), wav(&spiky, 1, 48_000)).unwrap();
+}
+
+/// The keys of the CSV report, in order.
+fn order(dir: &Path, args: &[&str]) -> Vec<String> {
+    let output = loudness(dir)
+        .args([".", "--extensions", "wav", "--csv"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn peak_sorts_highest_true_peak_first() {
+    let dir = temp_dir("sort-peak");
+    write_files(&dir);
+    assert_eq!(order(&dir, &["--sort", "loudness"]), ["a", "b"]);
+    assert_eq!(order(&dir, &["--sort", "peak", "--psr"]), ["b", "a"]);
+    assert_eq!(
+        order(&dir, &["--sort", "peak", "--psr", "--reverse"]),
+        ["a", "b"]
+    );
+}
+
+#[test]
+fn peak_needs_psr() {
+    let dir = temp_dir("sort-peak-psr");
+    write_files(&dir);
+    let output = loudness(&dir)
+        .args([".", "--extensions", "wav", "--sort", "peak"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--sort peak requires --psr"), "{stderr}");
+}
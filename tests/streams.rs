@@ -0,0 +1,130 @@
+//! With a structured output format, stdout carries only the result records,
+//! however the run goes; notices, warnings and the summary go to stderr.
+
+mod common;
+
+use common::{loudness, sine, temp_dir, wav};
+use loudness::split_csv;
+use merde::{CowStr, Value};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// A directory of a file already in `out.json`, one to measure and one
+/// that fails.
+fn music(name: &str) -> PathBuf {
+    let dir = temp_dir(name);
+    std::fs::create_dir(dir.join("music")).unwrap();
+    let sine = wav(&sine(1000.0, 0.1, 1.0, 48_000), 1, 48_000);
+    std::fs::write(dir.join("music/cached.wav"), &sine).unwrap();
+    std::fs::write(dir.join("music/fresh.wav"), &sine).unwrap();
+    std::fs::write(dir.join("music/broken.wav"), [0x55; 4096]).unwrap();
+    let output = loudness(&dir)
+        .args(["music/cached.wav", "out.json", "--extensions", "wav"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    dir
+}
+
+/// Measures the directory with `format` into a piped stdout, giving its lines.
+fn stdout(dir: &Path, format: &str) -> Vec<String> {
+    let output = loudness(dir)
+        .args(["music", "out.json", "--extensions", "wav", format])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    // what isn't a record still gets said
+    assert!(stderr.contains("broken"), "{stderr}");
+    assert!(stderr.contains("cached:"), "{stderr}");
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+fn keys<'a>(keys: impl IntoIterator<Item = &'a str>) -> BTreeSet<&'a str> {
+    keys.into_iter().collect()
+}
+
+/// Whether a JSON record has a loudness, or failed with an error.
+fn is_result(record: &Value) -> bool {
+    let record = record.as_map().unwrap();
+    let has = |name: &str| record.contains_key(&CowStr::from(name.to_string()));
+    has("loudness") || has("error")
+}
+
+#[test]
+fn json_stdout_is_the_results_object() {
+    let dir = music("streams-json");
+    let lines = stdout(&dir, "--json");
+    assert_eq!(lines.len(), 1, "{lines:?}");
+    let results: Value = merde::json::from_str(&lines[0]).unwrap();
+    let results = results.as_map().unwrap();
+    assert_eq!(
+        keys(results.keys().map(|k| &**k)),
+        keys(["broken", "cached", "fresh"])
+    );
+    assert!(results.values().all(is_result));
+}
+
+#[test]
+fn ndjson_stdout_is_a_record_per_line() {
+    let dir = music("streams-ndjson");
+    let lines = stdout(&dir, "--ndjson");
+    let mut seen = vec![];
+    for line in &lines {
+        let record: Value =
+            merde::json::from_str(line).unwrap_or_else(|e| panic!("{line:?} isn't a record: {e}"));
+        assert!(is_result(&record), "{line}");
+        let key = record
+            .as_map()
+            .unwrap()
+            .get(&CowStr::from("key"))
+            .and_then(|k| k.as_str().ok())
+            .unwrap_or_else(|| panic!("{line} has no key"))
+            .to_string();
+        // the cached file is reported without being measured again
+        let cached = record
+            .as_map()
+            .unwrap()
+            .get(&CowStr::from("cached"))
+            .cloned();
+        assert_eq!(cached == Some(Value::Bool(true)), key == "cached", "{line}");
+        seen.push(key);
+    }
+    assert_eq!(
+        keys(seen.iter().map(String::as_str)),
+        keys(["broken", "cached", "fresh"])
+    );
+}
+
+#[test]
+fn csv_and_tsv_stdout_are_a_header_and_rows() {
+    for (format, delimiter) in [("--csv", ','), ("--tsv", '\t')] {
+        let dir = music(&format!("streams-{}", &format[2..]));
+        let lines = stdout(&dir, format);
+        let rows: Vec<Vec<String>> = match delimiter {
+            ',' => split_csv(&lines.join("\n")),
+            _ => lines
+                .iter()
+                .map(|l| l.split('\t').map(str::to_string).collect())
+                .collect(),
+        };
+        let (header, rows) = rows.split_first().unwrap();
+        assert_eq!(header[..2], ["key", "loudness_lufs"], "{format}");
+        for row in rows {
+            assert_eq!(row.len(), header.len(), "{format} {row:?}");
+            row[1]
+                .parse::<f64>()
+                .unwrap_or_else(|_| panic!("{format} {row:?}"));
+        }
+        // failed files are left out of the table
+        assert_eq!(
+            keys(rows.iter().map(|r| r[0].as_str())),
+            keys(["cached", "fresh"]),
+            "{format}"
+        );
+    }
+}
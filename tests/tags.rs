@@ -0,0 +1,297 @@
+//! `loudness untag` on the tag containers it rewrites: ID3v2 with TXXX and
+//! RVA2 frames, and Vorbis comments in Ogg pages; MP4 is only reported.
+//! And `loudness verify-tags` on the values those tags hold.
+
+mod common;
+
+use common::{loudness, temp_dir};
+use std::path::Path;
+
+fn untag(dir: &Path, args: &[&str]) -> (bool, String, String) {
+    let output = loudness(dir)
+        .arg("untag")
+        .arg(".")
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+/// An ID3v2.3 frame.
+fn frame(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut out = id.to_vec();
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(data);
+    out
+}
+
+/// An ID3v2.3 tag of `frames`.
+fn id3v2(frames: &[Vec<u8>]) -> Vec<u8> {
+    let body = frames.concat();
+    let mut out = b"ID3\x03\x00\x00".to_vec();
+    out.extend((0..4).rev().map(|i| (body.len() >> (7 * i) & 0x7f) as u8));
+    out.extend_from_slice(&body);
+    out
+}
+
+#[test]
+fn untag_removes_txxx_and_rva2_frames_from_mp3() {
+    let dir = temp_dir("untag-mp3");
+    let title = frame(b"TIT2", b"\x00a title");
+    // an RVA2 for the master volume: -3 dB, with a 16-bit peak
+    let rva2 = frame(b"RVA2", b"track\x00\x01\xfa\x00\x10\x7f\xff");
+    let gain = frame(b"TXXX", b"\x00REPLAYGAIN_TRACK_GAIN\x00-3.00 dB");
+    let audio = [0xffu8, 0xfb, 0x90, 0x00].repeat(64);
+    let tag = id3v2(&[title.clone(), gain.clone(), rva2, gain]);
+    std::fs::write(dir.join("a.mp3"), [tag.clone(), audio.clone()].concat()).unwrap();
+
+    let (ok, stdout, _) = untag(&dir, &["--dry-run"]);
+    assert!(ok);
+    assert!(stdout.contains("- RVA2:track=-3.00 dB"), "{stdout}");
+    assert_eq!(
+        stdout.matches("- REPLAYGAIN_TRACK_GAIN=-3.00 dB").count(),
+        2
+    );
+    assert_eq!(
+        std::fs::read(dir.join("a.mp3")).unwrap(),
+        [tag, audio.clone()].concat()
+    );
+
+    let (ok, stdout, stderr) = untag(&dir, &[]);
+    assert!(ok, "{stderr}");
+    assert!(stdout.contains("removed 3 field(s)"), "{stdout}");
+    assert_eq!(
+        std::fs::read(dir.join("a.mp3")).unwrap(),
+        [id3v2(&[title]), audio].concat()
+    );
+}
+
+/// CRC-32 of an Ogg page with its checksum field zeroed.
+fn ogg_crc(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte) << 24, |crc, _| {
+            (crc << 1)
+                ^ if crc & 0x8000_0000 != 0 {
+                    0x04c1_1db7
+                } else {
+                    0
+                }
+        })
+    })
+}
+
+struct Page {
+    flags: u8,
+    granule: u64,
+    sequence: u32,
+    segments: Vec<u8>,
+    data: Vec<u8>,
+}
+
+fn page(page: &Page) -> Vec<u8> {
+    let mut out = b"OggS\x00".to_vec();
+    out.push(page.flags);
+    out.extend_from_slice(&page.granule.to_le_bytes());
+    out.extend_from_slice(&7u32.to_le_bytes());
+    out.extend_from_slice(&page.sequence.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.push(page.segments.len() as u8);
+    out.extend_from_slice(&page.segments);
+    out.extend_from_slice(&page.data);
+    let crc = ogg_crc(&out);
+    out[22..26].copy_from_slice(&crc.to_le_bytes());
+    out
+}
+
+/// The pages of an Ogg stream, checking each one's CRC.
+fn pages(mut bytes: &[u8]) -> Vec<Page> {
+    let mut pages = vec![];
+    while !bytes.is_empty() {
+        assert_eq!(&bytes[..4], b"OggS");
+        let count = usize::from(bytes[26]);
+        let segments = bytes[27..27 + count].to_vec();
+        let len = 27 + count + segments.iter().map(|&s| usize::from(s)).sum::<usize>();
+        let mut zeroed = bytes[..len].to_vec();
+        zeroed[22..26].fill(0);
+        let crc = u32::from_le_bytes(bytes[22..26].try_into().unwrap());
+        assert_eq!(ogg_crc(&zeroed), crc, "page {}", pages.len());
+        pages.push(Page {
+            flags: bytes[5],
+            granule: u64::from_le_bytes(bytes[6..14].try_into().unwrap()),
+            sequence: u32::from_le_bytes(bytes[18..22].try_into().unwrap()),
+            segments,
+            data: bytes[27 + count..len].to_vec(),
+        });
+        bytes = &bytes[len..];
+    }
+    pages
+}
+
+/// `packets` laced onto as many pages as they need, numbered from `sequence`.
+fn paged(packets: &[Vec<u8>], sequence: u32) -> Vec<Page> {
+    let mut lacing = vec![];
+    for packet in packets {
+        lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+        lacing.push((packet.len() % 255) as u8);
+    }
+    let data = packets.concat();
+    let (mut pos, mut continued) = (0, false);
+    let mut pages = vec![];
+    for segments in lacing.chunks(255) {
+        let len: usize = segments.iter().map(|&s| usize::from(s)).sum();
+        let ends = segments.iter().any(|&s| s < 255);
+        pages.push(Page {
+            flags: u8::from(continued),
+            granule: if ends { 0 } else { u64::MAX },
+            sequence: sequence + pages.len() as u32,
+            segments: segments.to_vec(),
+            data: data[pos..pos + len].to_vec(),
+        });
+        pos += len;
+        continued = segments.last() == Some(&255);
+    }
+    pages
+}
+
+fn vorbis_comment(comments: &[String]) -> Vec<u8> {
+    let mut out = b"\x03vorbis".to_vec();
+    out.extend_from_slice(&4u32.to_le_bytes());
+    out.extend_from_slice(b"test");
+    out.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        out.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        out.extend_from_slice(comment.as_bytes());
+    }
+    out.push(1);
+    out
+}
+
+#[test]
+fn untag_rewrites_ogg_headers_and_renumbers_the_pages_after_them() {
+    let dir = temp_dir("untag-ogg");
+    let identification = Page {
+        flags: 2,
+        granule: 0,
+        sequence: 0,
+        segments: vec![30],
+        data: [b"\x01vorbis".as_slice(), &[0; 23]].concat(),
+    };
+    let setup = [b"\x05vorbis".as_slice(), &[0x42; 600]].concat();
+    // some taggers write the same field many times over; here enough for
+    // the headers to take two pages
+    let mut comments = vec!["ARTIST=someone".to_string()];
+    comments.extend(std::iter::repeat_n(
+        "REPLAYGAIN_TRACK_GAIN=-3.00 dB".to_string(),
+        3000,
+    ));
+    let headers = paged(&[vorbis_comment(&comments), setup.clone()], 1);
+    assert_eq!(headers.len(), 2);
+    let audio: Vec<Page> = (0..3)
+        .map(|n| Page {
+            flags: if n == 2 { 4 } else { 0 },
+            granule: 1024 * (n + 1),
+            sequence: 3 + n as u32,
+            segments: vec![100, 50],
+            data: vec![n as u8; 150],
+        })
+        .collect();
+    let stream: Vec<u8> = std::iter::once(&identification)
+        .chain(&headers)
+        .chain(&audio)
+        .flat_map(page)
+        .collect();
+    std::fs::write(dir.join("a.ogg"), &stream).unwrap();
+
+    let (ok, stdout, stderr) = untag(&dir, &[]);
+    assert!(ok, "{stderr}");
+    assert!(stdout.contains("removed 3000 field(s)"), "{stdout}");
+
+    let written = pages(&std::fs::read(dir.join("a.ogg")).unwrap());
+    let expected: Vec<Page> = std::iter::once(identification)
+        .chain(paged(
+            &[vorbis_comment(&["ARTIST=someone".to_string()]), setup],
+            1,
+        ))
+        .chain(audio.into_iter().map(|p| Page {
+            sequence: p.sequence - 1,
+            ..p
+        }))
+        .collect();
+    assert_eq!(written.len(), expected.len());
+    for (n, (written, expected)) in written.iter().zip(&expected).enumerate() {
+        assert_eq!(written.flags, expected.flags, "page {n}");
+        assert_eq!(written.granule, expected.granule, "page {n}");
+        assert_eq!(written.sequence, expected.sequence, "page {n}");
+        assert_eq!(written.segments, expected.segments, "page {n}");
+        assert_eq!(written.data, expected.data, "page {n}");
+    }
+}
+
+#[test]
+fn verify_tags_compares_peaks_where_the_cache_has_them() {
+    let dir = temp_dir("verify-peak");
+    let audio = [0xffu8, 0xfb, 0x90, 0x00].repeat(64);
+    let txxx = |key: &str, value: &str| frame(b"TXXX", format!("\0{key}\0{value}").as_bytes());
+    // -10 LUFS gives -8 dB at the default target; -6 dBTP is 0.501187
+    for (name, peak) in [("good", "0.501187"), ("loud", "0.9"), ("unchecked", "0.9")] {
+        let tag = id3v2(&[
+            txxx("REPLAYGAIN_TRACK_GAIN", "-8.00 dB"),
+            txxx("REPLAYGAIN_TRACK_PEAK", peak),
+        ]);
+        std::fs::write(
+            dir.join(format!("{name}.mp3")),
+            [tag, audio.clone()].concat(),
+        )
+        .unwrap();
+    }
+    let entry =
+        |true_peak: &str| format!(r#"{{"loudness":-10.0,"energy":0.1,"true_peak":{true_peak}}}"#);
+    let cache = format!(
+        r#"{{"good":{},"loud":{},"unchecked":{}}}"#,
+        entry("-6.0"),
+        entry("-6.0"),
+        entry("null")
+    );
+    std::fs::write(dir.join("cache.json"), cache).unwrap();
+
+    let output = loudness(&dir)
+        .args(["verify-tags", "cache.json", "."])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stdout.contains("loud.mp3: tagged peak 0.9, cache gives 0.501187"),
+        "{stdout}"
+    );
+    assert!(
+        !stdout.contains("good.mp3") && !stdout.contains("unchecked.mp3"),
+        "{stdout}"
+    );
+    assert!(
+        stderr.contains("ok: 2, mismatched: 0, peak mismatched: 1,"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn mp4_files_are_reported_as_unsupported() {
+    let dir = temp_dir("untag-mp4");
+    let mp4 = b"\x00\x00\x00\x18ftypM4A \x00\x00\x00\x00M4A mp42".to_vec();
+    std::fs::write(dir.join("a.m4a"), &mp4).unwrap();
+
+    let (ok, _, stderr) = untag(&dir, &[]);
+    assert!(ok, "{stderr}");
+    assert!(
+        stderr.contains("a.m4a: MP4 tags aren't supported"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("unsupported: 1"), "{stderr}");
+    assert_eq!(std::fs::read(dir.join("a.m4a")).unwrap(), mp4);
+}
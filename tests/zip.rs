@@ -0,0 +1,416 @@
+//! Zip archives: entries stored and deflated every way measure as the bare
+//! file does, and archives whose central directory lies about its entries
+//! fail.
+
+mod common;
+
+use common::{loudness, sine, temp_dir, wav};
+use std::collections::HashMap;
+
+/// A zip of one deflated `a.wav`, an empty stored deflate block, whose sizes
+/// are replaced by `size` and `compressed_size` in a zip64 extra field.
+fn zip64(size: u64, compressed_size: u64) -> Vec<u8> {
+    let name = b"a.wav";
+    let data = [0x01, 0x00, 0x00, 0xff, 0xff];
+    let mut out = vec![];
+    out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+    out.extend_from_slice(&[20, 0, 0, 0, 8, 0, 0, 0, 0, 0]);
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(name);
+    out.extend_from_slice(&data);
+
+    let directory_offset = out.len() as u32;
+    out.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+    out.extend_from_slice(&[45, 0, 45, 0, 0, 0, 8, 0, 0, 0, 0, 0]);
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&u32::MAX.to_le_bytes());
+    out.extend_from_slice(&u32::MAX.to_le_bytes());
+    out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    out.extend_from_slice(&20u16.to_le_bytes());
+    out.extend_from_slice(&[0; 6]);
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(name);
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(&compressed_size.to_le_bytes());
+    let directory_len = out.len() as u32 - directory_offset;
+
+    out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    out.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+    out.extend_from_slice(&directory_len.to_le_bytes());
+    out.extend_from_slice(&directory_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out
+}
+
+fn measure(name: &str, archive: Vec<u8>) -> (Option<i32>, String) {
+    let dir = temp_dir(name);
+    std::fs::write(dir.join("a.zip"), archive).unwrap();
+    let output = loudness(&dir)
+        .args(["a.zip", "out.json", "--extensions", "wav"])
+        .output()
+        .unwrap();
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn huge_stated_size_fails_the_file() {
+    let (code, stderr) = measure("zip-huge-size", zip64(1 << 50, 5));
+    assert_eq!(code, Some(1), "{stderr}");
+    assert!(stderr.contains("wrong size"), "{stderr}");
+}
+
+#[test]
+fn compressed_size_past_the_end_fails_the_file() {
+    let (code, stderr) = measure("zip-past-end", zip64(0, 1 << 40));
+    assert_eq!(code, Some(1), "{stderr}");
+    assert!(stderr.contains("past the end of the archive"), "{stderr}");
+}
+
+/// Deflate's length and distance codes: the smallest value of each, and
+/// how many extra bits follow it.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// How a part of an entry is deflated.
+#[derive(Clone, Copy)]
+enum Block {
+    Stored,
+    Fixed,
+    Dynamic,
+}
+
+enum Token {
+    Literal(u8),
+    Match { len: usize, distance: usize },
+}
+
+/// Bits written from the least significant, as deflate packs them.
+#[derive(Default)]
+struct Bits {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl Bits {
+    fn put(&mut self, value: u32, n: u32) {
+        self.buffer |= value << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// A Huffman code, which goes from its most significant bit.
+    fn code(&mut self, (code, len): (u32, u32)) {
+        self.put(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.put(0, 8 - self.count);
+        }
+    }
+}
+
+/// The canonical Huffman codes for symbols of these code lengths.
+fn canonical(lengths: &[u32]) -> Vec<(u32, u32)> {
+    let mut count = [0u32; 16];
+    for &len in lengths {
+        count[len as usize] += 1;
+    }
+    count[0] = 0;
+    let mut next = [0u32; 16];
+    for len in 1..16 {
+        next[len] = (next[len - 1] + count[len - 1]) << 1;
+    }
+    lengths
+        .iter()
+        .map(|&len| {
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            (code, len)
+        })
+        .collect()
+}
+
+/// Greedy LZ77 over `data[from..to]`, matching anywhere in the 32 KiB
+/// before, including earlier blocks.
+fn tokens(data: &[u8], from: usize, to: usize) -> Vec<Token> {
+    let mut last: HashMap<&[u8], usize> = HashMap::new();
+    for i in from.saturating_sub(32 * 1024)..from {
+        if i + 3 <= data.len() {
+            last.insert(&data[i..i + 3], i);
+        }
+    }
+    let mut out = vec![];
+    let mut i = from;
+    while i < to {
+        let found = (i + 3 <= to)
+            .then(|| last.get(&data[i..i + 3]).copied())
+            .flatten()
+            .filter(|&at| i - at <= 32 * 1024);
+        let len = found.map_or(0, |at| {
+            (0..258.min(to - i))
+                .take_while(|&k| data[at + k] == data[i + k])
+                .count()
+        });
+        let step = if len >= 3 {
+            out.push(Token::Match {
+                len,
+                distance: i - found.unwrap(),
+            });
+            len
+        } else {
+            out.push(Token::Literal(data[i]));
+            1
+        };
+        for k in i..i + step {
+            if k + 3 <= data.len() {
+                last.insert(&data[k..k + 3], k);
+            }
+        }
+        i += step;
+    }
+    out
+}
+
+/// The code of the largest `base` at most `value`, and its extra bits.
+fn find(base: &[u16], value: usize) -> (usize, u32) {
+    let i = base.iter().rposition(|&b| usize::from(b) <= value).unwrap();
+    (i, (value - usize::from(base[i])) as u32)
+}
+
+fn write_tokens(
+    bits: &mut Bits,
+    tokens: &[Token],
+    literals: &[(u32, u32)],
+    distances: &[(u32, u32)],
+) {
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => bits.code(literals[usize::from(byte)]),
+            Token::Match { len, distance } => {
+                let (i, extra) = find(&LENGTH_BASE, len);
+                bits.code(literals[257 + i]);
+                bits.put(extra, LENGTH_EXTRA[i]);
+                let (d, extra) = find(&DISTANCE_BASE, distance);
+                bits.code(distances[d]);
+                bits.put(extra, DISTANCE_EXTRA[d]);
+            }
+        }
+    }
+    bits.code(literals[256]);
+}
+
+/// `data` as a raw deflate stream, split evenly into one block of each kind.
+fn deflate(data: &[u8], blocks: &[Block]) -> Vec<u8> {
+    let mut bits = Bits::default();
+    let part = data.len().div_ceil(blocks.len());
+    for (n, &block) in blocks.iter().enumerate() {
+        let (from, to) = (n * part, ((n + 1) * part).min(data.len()));
+        bits.put(u32::from(n + 1 == blocks.len()), 1);
+        match block {
+            Block::Stored => {
+                bits.put(0, 2);
+                bits.align();
+                let len = (to - from) as u32;
+                assert!(len <= 0xffff, "too long for a stored block");
+                bits.put(len, 16);
+                bits.put(!len & 0xffff, 16);
+                for &byte in &data[from..to] {
+                    bits.put(u32::from(byte), 8);
+                }
+            }
+            Block::Fixed => {
+                bits.put(1, 2);
+                let mut lengths = [8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                let literals = canonical(&lengths);
+                let distances = canonical(&[5; 30]);
+                write_tokens(&mut bits, &tokens(data, from, to), &literals, &distances);
+            }
+            Block::Dynamic => {
+                // complete codes of their own: 226 literal/length codes of
+                // 8 bits and 60 of 9, 2 distance codes of 4 bits and 28 of 5
+                let mut lengths = vec![8; 286];
+                lengths[226..].fill(9);
+                let mut distance_lengths = vec![5; 30];
+                distance_lengths[..2].fill(4);
+                let mut code_lengths = [0; 19];
+                for len in [4, 5, 8, 9] {
+                    code_lengths[len] = 2;
+                }
+                let code_length_codes = canonical(&code_lengths);
+                bits.put(2, 2);
+                bits.put(286 - 257, 5);
+                bits.put(30 - 1, 5);
+                bits.put(12 - 4, 4);
+                for &i in &CODE_LENGTH_ORDER[..12] {
+                    bits.put(code_lengths[i], 3);
+                }
+                for &len in lengths.iter().chain(&distance_lengths) {
+                    bits.code(code_length_codes[len as usize]);
+                }
+                let literals = canonical(&lengths);
+                let distances = canonical(&distance_lengths);
+                write_tokens(&mut bits, &tokens(data, from, to), &literals, &distances);
+            }
+        }
+    }
+    bits.align();
+    bits.out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// A zip of `(name, contents, blocks)` entries, stored when `blocks` is
+/// empty and deflated into them otherwise.
+fn zip(entries: &[(&str, &[u8], &[Block])]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut directory = vec![];
+    for &(name, contents, blocks) in entries {
+        let (method, data) = match blocks {
+            [] => (0u16, contents.to_vec()),
+            blocks => (8, deflate(contents, blocks)),
+        };
+        let offset = out.len() as u32;
+        let mut fields = vec![];
+        fields.extend_from_slice(&[0, 0]);
+        fields.extend_from_slice(&method.to_le_bytes());
+        fields.extend_from_slice(&[0, 0, 0, 0]);
+        fields.extend_from_slice(&crc32(contents).to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        out.extend_from_slice(&20u16.to_le_bytes());
+        out.extend_from_slice(&fields);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&data);
+
+        directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        directory.extend_from_slice(&[20, 0, 20, 0]);
+        directory.extend_from_slice(&fields);
+        directory.extend_from_slice(&[0; 10]);
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+    let directory_offset = out.len() as u32;
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    out.extend_from_slice(&[0, 0, 0, 0]);
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    out.extend_from_slice(&directory_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out
+}
+
+#[test]
+fn stored_and_deflated_entries_measure_as_the_bare_file() {
+    let dir = temp_dir("zip-entries");
+    std::fs::create_dir(dir.join("music")).unwrap();
+    // a rising tone, so the data repeats at many distances
+    let samples: Vec<f32> = sine(440.0, 0.2, 2.0, 48_000)
+        .into_iter()
+        .chain(sine(1000.0, 0.1, 2.0, 48_000))
+        .collect();
+    let bare = wav(&samples, 1, 48_000);
+    std::fs::write(dir.join("music/bare.wav"), &bare).unwrap();
+    let head = &bare[..60_000];
+    let mixed = [Block::Stored, Block::Fixed, Block::Dynamic].repeat(3);
+    // back-references, not just literals
+    assert!(deflate(&bare, &[Block::Fixed]).len() < bare.len() * 3 / 4);
+    assert!(deflate(&bare, &[Block::Dynamic]).len() < bare.len() * 3 / 4);
+    std::fs::write(
+        dir.join("music/album.zip"),
+        zip(&[
+            ("stored.wav", &bare, &[]),
+            ("fixed.wav", &bare, &[Block::Fixed]),
+            ("dynamic.wav", &bare, &[Block::Dynamic]),
+            ("mixed.wav", &bare, &mixed),
+            ("disc 2/short.wav", head, &[Block::Stored]),
+        ]),
+    )
+    .unwrap();
+
+    let run = || {
+        let output = loudness(&dir)
+            .args(["music", "out.json", "--extensions", "wav"])
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        assert_eq!(output.status.code(), Some(0), "{stderr}");
+        stderr
+    };
+    let stderr = run();
+    assert!(stderr.contains("measured: 6"), "{stderr}");
+
+    let cache = loudness::load_cache(&dir.join("out.json")).unwrap();
+    let expected = &cache["bare"];
+    for key in [
+        "album/stored",
+        "album/fixed",
+        "album/dynamic",
+        "album/mixed",
+    ] {
+        let m = cache
+            .get(key)
+            .unwrap_or_else(|| panic!("no {key} in {:?}", cache.keys()));
+        assert_eq!(m.loudness.to_bits(), expected.loudness.to_bits(), "{key}");
+        assert_eq!(m.energy.to_bits(), expected.energy.to_bits(), "{key}");
+        assert_eq!(m.duration, expected.duration, "{key}");
+    }
+    assert!(
+        cache.contains_key("album/disc 2/short"),
+        "{:?}",
+        cache.keys()
+    );
+
+    // measured once, so the next run finds every entry cached
+    let stderr = run();
+    assert!(stderr.contains("measured: 0"), "{stderr}");
+    assert!(stderr.contains("cached:   6"), "{stderr}");
+}